@@ -0,0 +1,370 @@
+//! Channel buses and the master bus.
+//!
+//! Sources are summed into one bus per channel. A [`Console`] then runs each
+//! bus through its channel's effect chain and compressor, ducks it under its
+//! trigger channels, applies the channel volume and sums everything into the
+//! master, which ends in the limiter.
+
+use std::collections::HashMap;
+
+use rusqlite::Connection;
+
+use super::dynamics::{Compressor, Ducker, Limiter};
+use super::effects::EffectChain;
+use super::render::{RENDER_CHANNELS, RENDER_SAMPLE_RATE};
+use crate::store::audio_channels::{get_all_audio_channels, ChannelCompressor};
+use crate::store::dynamics::{get_all_duck_rules, get_master_limiter, DuckRule, MasterLimiter};
+use crate::store::effects::{get_all_channel_effects, Effect};
+use crate::AppResult;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelStrip {
+    /// Linear gain, after the compressor and ducking.
+    pub volume: f64,
+    /// Enabled effects in chain order, ahead of the compressor.
+    pub effects: Vec<Effect>,
+    pub compressor: ChannelCompressor,
+}
+
+/// Everything stored about the mix that a [`Console`] applies.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MixSettings {
+    pub channels: HashMap<i64, ChannelStrip>,
+    pub duck_rules: Vec<DuckRule>,
+    /// `None` when the limiter is off.
+    pub limiter: Option<MasterLimiter>,
+}
+
+/// Reads the strips of every channel, every ducking rule and the limiter.
+pub fn load_mix_settings(conn: &Connection) -> AppResult<MixSettings> {
+    let mut effects = get_all_channel_effects(conn)?;
+    let channels = get_all_audio_channels(conn)?
+        .into_iter()
+        .map(|channel| {
+            let chain = effects.remove(&channel.id).unwrap_or_default();
+            (
+                channel.id,
+                ChannelStrip {
+                    volume: channel.volume,
+                    effects: chain
+                        .into_iter()
+                        .filter(|effect| effect.enabled)
+                        .map(|effect| effect.effect)
+                        .collect(),
+                    compressor: channel.compressor,
+                },
+            )
+        })
+        .collect();
+    let limiter = Some(get_master_limiter(conn)?).filter(|limiter| limiter.enabled);
+
+    Ok(MixSettings {
+        channels,
+        duck_rules: get_all_duck_rules(conn)?,
+        limiter,
+    })
+}
+
+/// One block of interleaved stereo per channel. Audio that belongs to no
+/// channel goes to the `None` bus.
+#[derive(Default)]
+pub struct Buses {
+    buffers: HashMap<Option<i64>, Vec<f32>>,
+    frames: usize,
+}
+
+impl Buses {
+    /// Silences every bus and sizes them for `frames` frames.
+    pub fn clear(&mut self, frames: usize) {
+        self.frames = frames;
+        for buffer in self.buffers.values_mut() {
+            buffer.clear();
+            buffer.resize(frames * RENDER_CHANNELS, 0.0);
+        }
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    pub fn add(&mut self, channel: Option<i64>, frame: usize, value: [f32; 2]) {
+        let frames = self.frames;
+        let buffer = self
+            .buffers
+            .entry(channel)
+            .or_insert_with(|| vec![0.0; frames * RENDER_CHANNELS]);
+        buffer[frame * RENDER_CHANNELS] += value[0];
+        buffer[frame * RENDER_CHANNELS + 1] += value[1];
+    }
+
+    fn frame(&self, channel: Option<i64>, frame: usize) -> [f32; 2] {
+        self.buffers.get(&channel).map_or([0.0; 2], |buffer| {
+            [
+                buffer[frame * RENDER_CHANNELS],
+                buffer[frame * RENDER_CHANNELS + 1],
+            ]
+        })
+    }
+}
+
+struct Strip {
+    volume: f32,
+    /// Volume being ramped to, reached after `ramp_frames` more frames.
+    target_volume: f32,
+    ramp_frames: usize,
+    effects: EffectChain,
+    compressor: Option<Compressor>,
+}
+
+impl Strip {
+    /// Volume `frames` frames into the current ramp.
+    fn volume_after(&self, frames: usize) -> f32 {
+        if frames >= self.ramp_frames {
+            return self.target_volume;
+        }
+        let progress = frames as f32 / self.ramp_frames as f32;
+        self.volume + (self.target_volume - self.volume) * progress
+    }
+
+    fn advance(&mut self, frames: usize) {
+        self.volume = self.volume_after(frames);
+        self.ramp_frames = self.ramp_frames.saturating_sub(frames);
+    }
+}
+
+struct DuckPath {
+    target: i64,
+    trigger: i64,
+    ducker: Ducker,
+}
+
+/// Runs a [`MixSettings`] over blocks of [`Buses`], keeping every
+/// processor's state from block to block.
+pub struct Console {
+    strips: HashMap<i64, Strip>,
+    ducks: Vec<DuckPath>,
+    limiter: Option<Limiter>,
+    /// Per-frame ducking gain of each ducked channel, for the current block.
+    duck_gains: HashMap<i64, Vec<f32>>,
+}
+
+impl Console {
+    pub fn new(settings: &MixSettings) -> Self {
+        let sample_rate = RENDER_SAMPLE_RATE as f64;
+        Console {
+            strips: settings
+                .channels
+                .iter()
+                .map(|(id, strip)| {
+                    let compressor = strip
+                        .compressor
+                        .enabled
+                        .then(|| Compressor::new(&strip.compressor, sample_rate));
+                    (
+                        *id,
+                        Strip {
+                            volume: strip.volume as f32,
+                            target_volume: strip.volume as f32,
+                            ramp_frames: 0,
+                            effects: EffectChain::new(&strip.effects, sample_rate),
+                            compressor,
+                        },
+                    )
+                })
+                .collect(),
+            ducks: settings
+                .duck_rules
+                .iter()
+                .map(|rule| DuckPath {
+                    target: rule.target_channel_id,
+                    trigger: rule.trigger_channel_id,
+                    ducker: Ducker::new(rule, sample_rate),
+                })
+                .collect(),
+            limiter: settings
+                .limiter
+                .as_ref()
+                .map(|limiter| Limiter::new(limiter, sample_rate)),
+            duck_gains: HashMap::new(),
+        }
+    }
+
+    /// Moves each channel's volume to its value in `settings` in a straight
+    /// line over `frames` frames, from wherever it is now. Other settings are
+    /// not picked up; build a new console for those.
+    pub fn ramp_volumes(&mut self, settings: &MixSettings, frames: usize) {
+        for (id, strip) in &mut self.strips {
+            if let Some(target) = settings.channels.get(id) {
+                strip.target_volume = target.volume as f32;
+                strip.ramp_frames = frames;
+                if frames == 0 {
+                    strip.volume = strip.target_volume;
+                }
+            }
+        }
+    }
+
+    /// Frames the master output lags behind the buses.
+    pub fn latency(&self) -> usize {
+        self.limiter.as_ref().map_or(0, Limiter::lookahead)
+    }
+
+    /// Mixes `buses` into `out`, scaled by `master_gain` ahead of the limiter.
+    /// Effects and compression are applied to the buses in place.
+    pub fn mix(&mut self, buses: &mut Buses, out: &mut [f32], master_gain: f32) {
+        let frames = out.len() / RENDER_CHANNELS;
+
+        for (channel, buffer) in buses.buffers.iter_mut() {
+            let Some(strip) = channel.and_then(|id| self.strips.get_mut(&id)) else {
+                continue;
+            };
+            if strip.effects.is_empty() && strip.compressor.is_none() {
+                continue;
+            }
+            for frame in buffer.chunks_exact_mut(RENDER_CHANNELS) {
+                let mut stereo = [frame[0], frame[1]];
+                strip.effects.process(&mut stereo);
+                if let Some(compressor) = &mut strip.compressor {
+                    compressor.process(&mut stereo);
+                }
+                frame.copy_from_slice(&stereo);
+            }
+        }
+
+        // Every ducker hears its trigger before any ducking is applied, and a
+        // channel under several triggers follows the deepest.
+        for gains in self.duck_gains.values_mut() {
+            gains.clear();
+            gains.resize(frames, 1.0);
+        }
+        for path in &mut self.ducks {
+            let gains = self
+                .duck_gains
+                .entry(path.target)
+                .or_insert_with(|| vec![1.0; frames]);
+            for (frame, gain) in gains.iter_mut().enumerate() {
+                let ducked = path.ducker.process(buses.frame(Some(path.trigger), frame));
+                *gain = gain.min(ducked);
+            }
+        }
+
+        out.fill(0.0);
+        for (channel, buffer) in &buses.buffers {
+            let strip = channel.and_then(|id| self.strips.get(&id));
+            let ducking = channel.and_then(|id| self.duck_gains.get(&id));
+            for (frame, (source, mixed)) in buffer
+                .chunks_exact(RENDER_CHANNELS)
+                .zip(out.chunks_exact_mut(RENDER_CHANNELS))
+                .enumerate()
+            {
+                let volume = strip.map_or(1.0, |strip| strip.volume_after(frame + 1));
+                let gain = volume * ducking.map_or(1.0, |gains| gains[frame]);
+                mixed[0] += source[0] * gain;
+                mixed[1] += source[1] * gain;
+            }
+        }
+        for strip in self.strips.values_mut() {
+            strip.advance(frames);
+        }
+
+        for frame in out.chunks_exact_mut(RENDER_CHANNELS) {
+            let mut stereo = [frame[0] * master_gain, frame[1] * master_gain];
+            if let Some(limiter) = &mut self.limiter {
+                limiter.process(&mut stereo);
+            }
+            frame.copy_from_slice(&stereo);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MUSIC: i64 = 1;
+    const EFFECTS: i64 = 2;
+
+    fn settings() -> MixSettings {
+        let strip = |volume| ChannelStrip {
+            volume,
+            effects: Vec::new(),
+            compressor: ChannelCompressor::default(),
+        };
+        MixSettings {
+            channels: HashMap::from([(MUSIC, strip(0.5)), (EFFECTS, strip(1.0))]),
+            duck_rules: vec![DuckRule {
+                id: 1,
+                target_channel_id: MUSIC,
+                trigger_channel_id: EFFECTS,
+                depth_db: 6.0206,
+                threshold_db: -40.0,
+                attack_ms: 1.0,
+                release_ms: 1.0,
+            }],
+            limiter: None,
+        }
+    }
+
+    /// Mixes one second of steady levels on each channel, returning the last frame.
+    fn mix(console: &mut Console, music: f32, effects: f32, other: f32) -> f32 {
+        let mut buses = Buses::default();
+        let frames = RENDER_SAMPLE_RATE as usize;
+        buses.clear(frames);
+        for frame in 0..frames {
+            buses.add(Some(MUSIC), frame, [music; 2]);
+            buses.add(Some(EFFECTS), frame, [effects; 2]);
+            buses.add(None, frame, [other; 2]);
+        }
+        let mut out = vec![0.0; frames * RENDER_CHANNELS];
+        console.mix(&mut buses, &mut out, 1.0);
+        out[out.len() - 2]
+    }
+
+    #[test]
+    fn channel_volumes_and_ducking_shape_the_sum() {
+        let mut console = Console::new(&settings());
+        assert!((mix(&mut console, 0.4, 0.0, 0.1) - 0.3).abs() < 1e-4);
+        // Effects pull the music down by half on top of its channel volume.
+        assert!((mix(&mut console, 0.4, 0.2, 0.0) - 0.3).abs() < 1e-3);
+        assert!((mix(&mut console, 0.4, 0.0, 0.0) - 0.2).abs() < 1e-3);
+    }
+
+    #[test]
+    fn volume_ramps_run_across_blocks() {
+        let mut console = Console::new(&settings());
+        let mut louder = settings();
+        louder.duck_rules.clear();
+        louder.channels.get_mut(&MUSIC).unwrap().volume = 1.0;
+        console.ramp_volumes(&louder, 96_000);
+        // Half way through a two-second ramp from 0.5 to 1.0.
+        assert!((mix(&mut console, 1.0, 0.0, 0.0) - 0.75).abs() < 1e-3);
+        assert!((mix(&mut console, 1.0, 0.0, 0.0) - 1.0).abs() < 1e-3);
+        assert!((mix(&mut console, 1.0, 0.0, 0.0) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn compressors_and_the_limiter_apply_when_enabled() {
+        let mut settings = settings();
+        settings.duck_rules.clear();
+        settings.channels.get_mut(&EFFECTS).unwrap().compressor = ChannelCompressor {
+            enabled: true,
+            threshold_db: -20.0,
+            ratio: 20.0,
+            ..ChannelCompressor::default()
+        };
+        let mut console = Console::new(&settings);
+        assert_eq!(console.latency(), 0);
+        // 0 dBFS is 20 dB over, and a 20:1 ratio lets 1 dB of that through.
+        let compressed = mix(&mut console, 0.0, 1.0, 0.0);
+        assert!((20.0 * compressed.log10() + 19.0).abs() < 0.05);
+
+        settings.limiter = Some(MasterLimiter {
+            enabled: true,
+            ceiling_dbtp: -6.0206,
+            release_ms: 50.0,
+        });
+        let mut console = Console::new(&settings);
+        assert_eq!(console.latency(), 72);
+        assert!((mix(&mut console, 0.0, 0.0, 0.9) - 0.5).abs() < 1e-3);
+    }
+}
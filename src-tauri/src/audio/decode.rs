@@ -0,0 +1,106 @@
+//! Decoding whole files to interleaved `f32` samples.
+
+use std::path::Path;
+
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::errors::Error as SymphoniaError;
+
+use super::{open_source, unreadable};
+use crate::AppResult;
+
+/// A file decoded in full. `samples` is interleaved by channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedAudio {
+    pub sample_rate: u32,
+    pub channels: usize,
+    pub samples: Vec<f32>,
+}
+
+impl DecodedAudio {
+    pub fn frame_count(&self) -> usize {
+        self.samples.len() / self.channels.max(1)
+    }
+}
+
+/// Decodes `path` packet by packet, handing each block of interleaved samples
+/// to `visit` together with the sample rate and channel count. Corrupt packets
+/// are skipped, as players do.
+pub fn for_each_block(path: &Path, mut visit: impl FnMut(&[f32], u32, usize)) -> AppResult<()> {
+    let mut source = open_source(path)?;
+    let mut buffer: Option<SampleBuffer<f32>> = None;
+
+    loop {
+        let packet = match source.format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(error))
+                if error.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                break
+            }
+            Err(SymphoniaError::ResetRequired) => break,
+            Err(error) => return Err(unreadable(path, error)),
+        };
+        if packet.track_id() != source.track_id {
+            continue;
+        }
+
+        let decoded = match source.decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(error) => return Err(unreadable(path, error)),
+        };
+        let spec = *decoded.spec();
+        let needed = decoded.capacity() as u64;
+        let buffer = match &mut buffer {
+            Some(buffer) if buffer.capacity() as u64 >= needed * spec.channels.count() as u64 => {
+                buffer
+            }
+            slot => slot.insert(SampleBuffer::new(needed, spec)),
+        };
+        buffer.copy_interleaved_ref(decoded);
+        visit(buffer.samples(), spec.rate, spec.channels.count());
+    }
+
+    Ok(())
+}
+
+pub fn decode_file(path: &Path) -> AppResult<DecodedAudio> {
+    let mut decoded = DecodedAudio {
+        sample_rate: 0,
+        channels: 0,
+        samples: Vec::new(),
+    };
+    for_each_block(path, |block, sample_rate, channels| {
+        decoded.sample_rate = sample_rate;
+        decoded.channels = channels;
+        decoded.samples.extend_from_slice(block);
+    })?;
+
+    if decoded.channels == 0 {
+        return Err(unreadable(path, "no audio could be decoded"));
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{test_dir, write_test_wav};
+
+    #[test]
+    fn decoding_keeps_channels_interleaved() {
+        let dir = test_dir("decode");
+        let path = dir.join("ramp.wav");
+        let samples: Vec<f32> = (0..2000)
+            .map(|i| if i % 2 == 0 { 0.5 } else { -0.25 })
+            .collect();
+        write_test_wav(&path, 44100, 2, &samples);
+
+        let decoded = decode_file(&path).unwrap();
+        assert_eq!(decoded.sample_rate, 44100);
+        assert_eq!(decoded.channels, 2);
+        assert_eq!(decoded.frame_count(), 1000);
+        assert!((decoded.samples[0] - 0.5).abs() < 1e-3);
+        assert!((decoded.samples[1] + 0.25).abs() < 1e-3);
+    }
+}
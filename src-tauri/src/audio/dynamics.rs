@@ -0,0 +1,384 @@
+//! Dynamics processors for the mix: the channel compressor, the sidechain
+//! ducker and the master true-peak limiter.
+//!
+//! Each works on stereo frames at the sample rate it was built for and keeps
+//! its envelope between calls, so a signal can be fed through in any blocking.
+
+use std::collections::VecDeque;
+
+use super::loudness::TruePeakMeter;
+use crate::store::audio_channels::ChannelCompressor;
+use crate::store::dynamics::{DuckRule, MasterLimiter};
+
+/// How far ahead the limiter looks for peaks, which is also its latency.
+pub const LIMITER_LOOKAHEAD_MS: f64 = 1.5;
+
+/// How long a ducking trigger counts as active after its last peak.
+const DUCK_HOLD_MS: f64 = 100.0;
+
+fn db_to_gain(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+fn gain_to_db(gain: f64) -> f64 {
+    20.0 * gain.max(1e-10).log10()
+}
+
+/// Per-sample coefficient of a one-pole smoother settling in about `ms`.
+fn smoothing(ms: f64, sample_rate: f64) -> f64 {
+    (-1.0 / (ms / 1000.0 * sample_rate).max(1.0)).exp()
+}
+
+fn frame_peak(frame: [f32; 2]) -> f64 {
+    frame[0].abs().max(frame[1].abs()) as f64
+}
+
+/// Feed-forward compressor, linked across both channels.
+pub struct Compressor {
+    threshold_db: f64,
+    slope: f64,
+    makeup_db: f64,
+    attack: f64,
+    release: f64,
+    reduction_db: f64,
+}
+
+impl Compressor {
+    pub fn new(settings: &ChannelCompressor, sample_rate: f64) -> Self {
+        Compressor {
+            threshold_db: settings.threshold_db,
+            slope: 1.0 - 1.0 / settings.ratio.max(1.0),
+            makeup_db: settings.makeup_db,
+            attack: smoothing(settings.attack_ms, sample_rate),
+            release: smoothing(settings.release_ms, sample_rate),
+            reduction_db: 0.0,
+        }
+    }
+
+    /// Current gain reduction, not counting makeup gain.
+    pub fn reduction_db(&self) -> f64 {
+        self.reduction_db
+    }
+
+    pub fn process(&mut self, frame: &mut [f32; 2]) {
+        let over_db = gain_to_db(frame_peak(*frame)) - self.threshold_db;
+        let target_db = over_db.max(0.0) * self.slope;
+        let coefficient = if target_db > self.reduction_db {
+            self.attack
+        } else {
+            self.release
+        };
+        self.reduction_db = target_db + coefficient * (self.reduction_db - target_db);
+
+        let gain = db_to_gain(self.makeup_db - self.reduction_db) as f32;
+        frame[0] *= gain;
+        frame[1] *= gain;
+    }
+}
+
+/// Follows a trigger signal and yields the gain for the channel it ducks.
+pub struct Ducker {
+    depth_db: f64,
+    threshold: f64,
+    attack: f64,
+    release: f64,
+    hold: f64,
+    trigger_level: f64,
+    gain_db: f64,
+}
+
+impl Ducker {
+    pub fn new(rule: &DuckRule, sample_rate: f64) -> Self {
+        Ducker {
+            depth_db: rule.depth_db,
+            threshold: db_to_gain(rule.threshold_db),
+            attack: smoothing(rule.attack_ms, sample_rate),
+            release: smoothing(rule.release_ms, sample_rate),
+            hold: smoothing(DUCK_HOLD_MS, sample_rate),
+            trigger_level: 0.0,
+            gain_db: 0.0,
+        }
+    }
+
+    /// Advances one frame of the trigger channel, returning the target's gain.
+    pub fn process(&mut self, trigger: [f32; 2]) -> f32 {
+        self.trigger_level = frame_peak(trigger).max(self.trigger_level * self.hold);
+        let target_db = if self.trigger_level > self.threshold {
+            -self.depth_db
+        } else {
+            0.0
+        };
+        let coefficient = if target_db < self.gain_db {
+            self.attack
+        } else {
+            self.release
+        };
+        self.gain_db = target_db + coefficient * (self.gain_db - target_db);
+        db_to_gain(self.gain_db) as f32
+    }
+}
+
+/// Lookahead limiter holding the true peak of its output under a ceiling.
+///
+/// The gain each frame needs is held over the lookahead window and then
+/// averaged over it, so it has fully come down by the time the frame that
+/// needed it leaves the delay line.
+pub struct Limiter {
+    ceiling: f64,
+    release: f64,
+    lookahead: usize,
+    meter: TruePeakMeter,
+    frame: u64,
+    delay: VecDeque<[f32; 2]>,
+    /// Candidates for the lowest required gain in the window, by frame.
+    minimum: VecDeque<(u64, f64)>,
+    envelope: f64,
+    recent: VecDeque<f64>,
+    recent_sum: f64,
+    /// Values in `recent` below unity.
+    recent_limiting: usize,
+}
+
+impl Limiter {
+    pub fn new(settings: &MasterLimiter, sample_rate: f64) -> Self {
+        let lookahead = (LIMITER_LOOKAHEAD_MS / 1000.0 * sample_rate)
+            .round()
+            .max(1.0) as usize;
+        Limiter {
+            ceiling: db_to_gain(settings.ceiling_dbtp),
+            release: smoothing(settings.release_ms, sample_rate),
+            lookahead,
+            meter: TruePeakMeter::new(2),
+            frame: 0,
+            delay: std::iter::repeat_n([0.0; 2], lookahead).collect(),
+            minimum: VecDeque::new(),
+            envelope: 1.0,
+            recent: std::iter::repeat_n(1.0, lookahead + 1).collect(),
+            recent_sum: (lookahead + 1) as f64,
+            recent_limiting: 0,
+        }
+    }
+
+    /// Latency in frames.
+    pub fn lookahead(&self) -> usize {
+        self.lookahead
+    }
+
+    pub fn process(&mut self, frame: &mut [f32; 2]) {
+        let peak = self
+            .meter
+            .push(0, frame[0] as f64)
+            .max(self.meter.push(1, frame[1] as f64));
+        let required = if peak > self.ceiling {
+            self.ceiling / peak
+        } else {
+            1.0
+        };
+
+        while self
+            .minimum
+            .back()
+            .is_some_and(|(_, gain)| *gain >= required)
+        {
+            self.minimum.pop_back();
+        }
+        self.minimum.push_back((self.frame, required));
+        while self
+            .minimum
+            .front()
+            .is_some_and(|(at, _)| at + (self.lookahead as u64) < self.frame)
+        {
+            self.minimum.pop_front();
+        }
+        let held = self.minimum.front().map_or(1.0, |(_, gain)| *gain);
+
+        let mut recovered = 1.0 - (1.0 - self.envelope) * self.release;
+        if recovered > 1.0 - 1e-9 {
+            recovered = 1.0;
+        }
+        self.envelope = held.min(recovered);
+
+        let oldest = self.recent.pop_front().unwrap_or(1.0);
+        self.recent.push_back(self.envelope);
+        self.recent_sum += self.envelope - oldest;
+        self.recent_limiting += usize::from(self.envelope < 1.0);
+        self.recent_limiting -= usize::from(oldest < 1.0);
+        let gain = if self.recent_limiting == 0 {
+            // Exactly unity while nothing is limited, free of summing error.
+            self.recent_sum = self.recent.len() as f64;
+            1.0
+        } else {
+            self.recent_sum / self.recent.len() as f64
+        };
+
+        self.delay.push_back(*frame);
+        let delayed = self.delay.pop_front().unwrap_or([0.0; 2]);
+        frame[0] = (delayed[0] as f64 * gain) as f32;
+        frame[1] = (delayed[1] as f64 * gain) as f32;
+        self.frame += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    const RATE: f64 = 48_000.0;
+
+    fn compressor(threshold_db: f64, ratio: f64) -> Compressor {
+        Compressor::new(
+            &ChannelCompressor {
+                enabled: true,
+                threshold_db,
+                ratio,
+                attack_ms: 5.0,
+                release_ms: 50.0,
+                makeup_db: 0.0,
+            },
+            RATE,
+        )
+    }
+
+    fn run(frames: usize, level: f32, mut process: impl FnMut(&mut [f32; 2])) -> [f32; 2] {
+        let mut frame = [level; 2];
+        for _ in 0..frames {
+            frame = [level; 2];
+            process(&mut frame);
+        }
+        frame
+    }
+
+    #[test]
+    fn the_compressor_reduces_level_above_threshold_by_its_ratio() {
+        let mut unit = compressor(-18.0, 4.0);
+        // A -6 dBFS level is 12 dB over, and a 4:1 ratio lets 3 dB of that through.
+        let out = run(48_000, 0.5, |frame| unit.process(frame));
+        assert!((gain_to_db(out[0] as f64) + 15.0).abs() < 0.05);
+        assert!((unit.reduction_db() - 9.0).abs() < 0.05);
+
+        // Below the threshold it recovers to unity.
+        let quiet = run(48_000, 0.05, |frame| unit.process(frame));
+        assert!((quiet[0] - 0.05).abs() < 1e-4);
+
+        let mut with_makeup = Compressor::new(
+            &ChannelCompressor {
+                makeup_db: 6.0,
+                ..ChannelCompressor::default()
+            },
+            RATE,
+        );
+        let raised = run(48_000, 0.05, |frame| with_makeup.process(frame));
+        assert!((gain_to_db(raised[0] as f64) - gain_to_db(0.1)).abs() < 0.05);
+    }
+
+    #[test]
+    fn the_compressor_attacks_faster_than_it_releases() {
+        let mut unit = compressor(-20.0, 10.0);
+        run(240, 1.0, |frame| unit.process(frame));
+        let after_attack = unit.reduction_db();
+        assert!(after_attack > 0.6 * 18.0);
+
+        run(240, 0.0, |frame| unit.process(frame));
+        assert!(unit.reduction_db() > 0.8 * after_attack);
+    }
+
+    fn rule(depth_db: f64) -> DuckRule {
+        DuckRule {
+            id: 1,
+            target_channel_id: 1,
+            trigger_channel_id: 2,
+            depth_db,
+            threshold_db: -40.0,
+            attack_ms: 10.0,
+            release_ms: 200.0,
+        }
+    }
+
+    #[test]
+    fn ducking_follows_the_trigger_channel() {
+        let mut ducker = Ducker::new(&rule(6.0), RATE);
+        let mut gain = 1.0;
+        for _ in 0..4800 {
+            gain = ducker.process([0.0; 2]);
+        }
+        assert_eq!(gain, 1.0);
+
+        // A short burst ducks the target by the rule's depth...
+        for i in 0..9600 {
+            let sample = (2.0 * PI * 440.0 * i as f64 / RATE).sin() as f32 * 0.5;
+            gain = ducker.process([sample, sample]);
+        }
+        assert!((gain_to_db(gain as f64) + 6.0).abs() < 0.1);
+
+        // ...which holds through the gaps of a signal, then recovers.
+        for _ in 0..2400 {
+            gain = ducker.process([0.0; 2]);
+        }
+        assert!(gain_to_db(gain as f64) < -5.0);
+        for _ in 0..96_000 {
+            gain = ducker.process([0.0; 2]);
+        }
+        assert!(gain_to_db(gain as f64) > -0.01);
+
+        // A trigger under the threshold does not duck.
+        let mut ducker = Ducker::new(&rule(12.0), RATE);
+        for _ in 0..9600 {
+            gain = ducker.process([0.005, 0.005]);
+        }
+        assert_eq!(gain, 1.0);
+    }
+
+    fn limiter(ceiling_dbtp: f64) -> Limiter {
+        Limiter::new(
+            &MasterLimiter {
+                enabled: true,
+                ceiling_dbtp,
+                release_ms: 50.0,
+            },
+            RATE,
+        )
+    }
+
+    #[test]
+    fn the_limiter_holds_peaks_under_its_ceiling() {
+        let mut unit = limiter(-1.0);
+        let ceiling = db_to_gain(-1.0) as f32;
+        let mut meter = TruePeakMeter::new(2);
+        let mut sample_peak = 0.0f32;
+        for i in 0..48_000 {
+            // A 6 dB over full-scale sine at a frequency that puts peaks between samples.
+            let value = (2.0 * (2.0 * PI * 11_025.0 * i as f64 / RATE + 0.3).sin()) as f32;
+            let mut frame = [value, -value];
+            unit.process(&mut frame);
+            if i > 4800 {
+                sample_peak = sample_peak.max(frame[0].abs()).max(frame[1].abs());
+            }
+            meter.push(0, frame[0] as f64);
+        }
+        assert!(sample_peak <= ceiling + 1e-6);
+        assert!(sample_peak > ceiling * 0.9);
+        assert!(gain_to_db(meter.peak) < -0.9);
+    }
+
+    #[test]
+    fn the_limiter_leaves_quiet_signals_alone_apart_from_its_latency() {
+        let mut unit = limiter(-1.0);
+        let input: Vec<f32> = (0..4800)
+            .map(|i| (0.5 * (2.0 * PI * 1000.0 * i as f64 / RATE).sin()) as f32)
+            .collect();
+        let output: Vec<f32> = input
+            .iter()
+            .map(|sample| {
+                let mut frame = [*sample; 2];
+                unit.process(&mut frame);
+                frame[0]
+            })
+            .collect();
+
+        let latency = unit.lookahead();
+        assert_eq!(latency, 72);
+        assert!(output[..latency].iter().all(|sample| *sample == 0.0));
+        assert_eq!(&output[latency..], &input[..input.len() - latency]);
+    }
+}
@@ -0,0 +1,410 @@
+//! Insert effects for channel buses: RBJ biquad EQ and filters, a
+//! Freeverb-style reverb and a feedback delay.
+//!
+//! An [`EffectChain`] runs a channel's stored [`Effect`]s in order, one stereo
+//! frame at a time, so the renderer and the playback engine process the same
+//! way.
+
+use std::f64::consts::PI;
+
+use crate::store::effects::Effect;
+
+/// A direct form I biquad. Coefficients are normalised so `a[0]` is 1.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    pub(crate) fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        Biquad {
+            b,
+            a,
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    /// Builds a filter from unnormalised cookbook coefficients.
+    fn normalised(b: [f64; 3], a: [f64; 3]) -> Self {
+        Biquad::new(
+            [b[0] / a[0], b[1] / a[0], b[2] / a[0]],
+            [1.0, a[1] / a[0], a[2] / a[0]],
+        )
+    }
+
+    /// Peaking EQ band from the Audio EQ Cookbook.
+    fn peaking(sample_rate: f64, frequency_hz: f64, gain_db: f64, q: f64) -> Self {
+        let (cos, alpha) = cookbook_terms(sample_rate, frequency_hz, q);
+        let amplitude = 10f64.powf(gain_db / 40.0);
+        Biquad::normalised(
+            [1.0 + alpha * amplitude, -2.0 * cos, 1.0 - alpha * amplitude],
+            [1.0 + alpha / amplitude, -2.0 * cos, 1.0 - alpha / amplitude],
+        )
+    }
+
+    fn low_pass(sample_rate: f64, frequency_hz: f64, q: f64) -> Self {
+        let (cos, alpha) = cookbook_terms(sample_rate, frequency_hz, q);
+        Biquad::normalised(
+            [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    fn high_pass(sample_rate: f64, frequency_hz: f64, q: f64) -> Self {
+        let (cos, alpha) = cookbook_terms(sample_rate, frequency_hz, q);
+        Biquad::normalised(
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+            [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        )
+    }
+
+    pub(crate) fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[1] * self.y[0]
+            - self.a[2] * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+}
+
+/// `cos(w0)` and `alpha` for a cookbook filter. The frequency is held below
+/// Nyquist so low sample rates still give a stable filter.
+fn cookbook_terms(sample_rate: f64, frequency_hz: f64, q: f64) -> (f64, f64) {
+    let frequency = frequency_hz.min(sample_rate * 0.49);
+    let w0 = 2.0 * PI * frequency / sample_rate;
+    (w0.cos(), w0.sin() / (2.0 * q))
+}
+
+/// Freeverb's tunings, in samples at 44.1 kHz.
+const COMB_TUNINGS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNINGS: [usize; 4] = [556, 441, 341, 225];
+/// Extra length of the right channel's delays, which decorrelates the sides.
+const STEREO_SPREAD: usize = 23;
+const REVERB_INPUT_GAIN: f64 = 0.015;
+const REVERB_WET_GAIN: f64 = 3.0;
+
+/// A feedback comb with a one-pole low-pass in the loop.
+struct Comb {
+    buffer: Vec<f64>,
+    index: usize,
+    feedback: f64,
+    damping: f64,
+    filtered: f64,
+}
+
+impl Comb {
+    fn new(length: usize, feedback: f64, damping: f64) -> Self {
+        Comb {
+            buffer: vec![0.0; length.max(1)],
+            index: 0,
+            feedback,
+            damping,
+            filtered: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.buffer[self.index];
+        self.filtered = output * (1.0 - self.damping) + self.filtered * self.damping;
+        self.buffer[self.index] = input + self.filtered * self.feedback;
+        self.index = (self.index + 1) % self.buffer.len();
+        output
+    }
+}
+
+/// Schroeder all-pass diffuser.
+struct AllPass {
+    buffer: Vec<f64>,
+    index: usize,
+}
+
+impl AllPass {
+    const FEEDBACK: f64 = 0.5;
+
+    fn new(length: usize) -> Self {
+        AllPass {
+            buffer: vec![0.0; length.max(1)],
+            index: 0,
+        }
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        let delayed = self.buffer[self.index];
+        self.buffer[self.index] = input + delayed * Self::FEEDBACK;
+        self.index = (self.index + 1) % self.buffer.len();
+        delayed - input
+    }
+}
+
+/// One side of the reverb: parallel combs into serial all-passes.
+struct ReverbSide {
+    combs: Vec<Comb>,
+    allpasses: Vec<AllPass>,
+}
+
+impl ReverbSide {
+    fn new(sample_rate: f64, spread: usize, feedback: f64, damping: f64) -> Self {
+        let scale = |tuning: usize| ((tuning + spread) as f64 * sample_rate / 44_100.0) as usize;
+        ReverbSide {
+            combs: COMB_TUNINGS
+                .iter()
+                .map(|tuning| Comb::new(scale(*tuning), feedback, damping))
+                .collect(),
+            allpasses: ALLPASS_TUNINGS
+                .iter()
+                .map(|tuning| AllPass::new(scale(*tuning)))
+                .collect(),
+        }
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        let mut output: f64 = self.combs.iter_mut().map(|comb| comb.process(input)).sum();
+        for allpass in &mut self.allpasses {
+            output = allpass.process(output);
+        }
+        output
+    }
+}
+
+struct Reverb {
+    sides: [ReverbSide; 2],
+    mix: f64,
+}
+
+impl Reverb {
+    fn new(sample_rate: f64, room_size: f64, damping: f64, mix: f64) -> Self {
+        let feedback = 0.7 + room_size * 0.28;
+        let damping = damping * 0.4;
+        Reverb {
+            sides: [
+                ReverbSide::new(sample_rate, 0, feedback, damping),
+                ReverbSide::new(sample_rate, STEREO_SPREAD, feedback, damping),
+            ],
+            mix,
+        }
+    }
+
+    fn process(&mut self, frame: &mut [f64; 2]) {
+        // Both sides hear the mono sum, as in Freeverb.
+        let input = (frame[0] + frame[1]) * REVERB_INPUT_GAIN;
+        for (sample, side) in frame.iter_mut().zip(&mut self.sides) {
+            let wet = side.process(input) * REVERB_WET_GAIN;
+            *sample = *sample * (1.0 - self.mix) + wet * self.mix;
+        }
+    }
+}
+
+struct Delay {
+    buffers: [Vec<f64>; 2],
+    index: usize,
+    feedback: f64,
+    mix: f64,
+}
+
+impl Delay {
+    fn new(sample_rate: f64, time_ms: f64, feedback: f64, mix: f64) -> Self {
+        let length = ((time_ms * sample_rate / 1000.0).round() as usize).max(1);
+        Delay {
+            buffers: [vec![0.0; length], vec![0.0; length]],
+            index: 0,
+            feedback,
+            mix,
+        }
+    }
+
+    fn process(&mut self, frame: &mut [f64; 2]) {
+        for (sample, buffer) in frame.iter_mut().zip(&mut self.buffers) {
+            let echo = buffer[self.index];
+            buffer[self.index] = *sample + echo * self.feedback;
+            *sample = *sample * (1.0 - self.mix) + echo * self.mix;
+        }
+        self.index = (self.index + 1) % self.buffers[0].len();
+    }
+}
+
+enum Processor {
+    Filter([Biquad; 2]),
+    Reverb(Box<Reverb>),
+    Delay(Delay),
+}
+
+impl Processor {
+    fn new(effect: &Effect, sample_rate: f64) -> Self {
+        match *effect {
+            Effect::Eq {
+                frequency_hz,
+                gain_db,
+                q,
+            } => Processor::Filter([Biquad::peaking(sample_rate, frequency_hz, gain_db, q); 2]),
+            Effect::LowPass { frequency_hz, q } => {
+                Processor::Filter([Biquad::low_pass(sample_rate, frequency_hz, q); 2])
+            }
+            Effect::HighPass { frequency_hz, q } => {
+                Processor::Filter([Biquad::high_pass(sample_rate, frequency_hz, q); 2])
+            }
+            Effect::Reverb {
+                room_size,
+                damping,
+                mix,
+            } => Processor::Reverb(Box::new(Reverb::new(sample_rate, room_size, damping, mix))),
+            Effect::Delay {
+                time_ms,
+                feedback,
+                mix,
+            } => Processor::Delay(Delay::new(sample_rate, time_ms, feedback, mix)),
+        }
+    }
+
+    fn process(&mut self, frame: &mut [f64; 2]) {
+        match self {
+            Processor::Filter(filters) => {
+                for (sample, filter) in frame.iter_mut().zip(filters) {
+                    *sample = filter.process(*sample);
+                }
+            }
+            Processor::Reverb(reverb) => reverb.process(frame),
+            Processor::Delay(delay) => delay.process(frame),
+        }
+    }
+}
+
+/// A channel's effects, run in order on each stereo frame.
+pub struct EffectChain {
+    processors: Vec<Processor>,
+}
+
+impl EffectChain {
+    pub fn new(effects: &[Effect], sample_rate: f64) -> Self {
+        EffectChain {
+            processors: effects
+                .iter()
+                .map(|effect| Processor::new(effect, sample_rate))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.processors.is_empty()
+    }
+
+    pub fn process(&mut self, frame: &mut [f32; 2]) {
+        let mut stereo = [frame[0] as f64, frame[1] as f64];
+        for processor in &mut self.processors {
+            processor.process(&mut stereo);
+        }
+        *frame = [stereo[0] as f32, stereo[1] as f32];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f64 = 48_000.0;
+
+    /// Peak level of the second half of a one-second sine through `effects`.
+    fn sine_peak(effects: &[Effect], frequency_hz: f64) -> f32 {
+        let mut chain = EffectChain::new(effects, SAMPLE_RATE);
+        let frames = SAMPLE_RATE as usize;
+        let mut peak = 0.0f32;
+        for frame in 0..frames {
+            let value = (2.0 * PI * frequency_hz * frame as f64 / SAMPLE_RATE).sin() as f32;
+            let mut stereo = [value; 2];
+            chain.process(&mut stereo);
+            if frame > frames / 2 {
+                peak = peak.max(stereo[0].abs());
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn filters_shape_the_spectrum() {
+        let low_pass = [Effect::LowPass {
+            frequency_hz: 500.0,
+            q: 0.707,
+        }];
+        assert!((sine_peak(&low_pass, 100.0) - 1.0).abs() < 0.02);
+        assert!(sine_peak(&low_pass, 8000.0) < 0.01);
+
+        let high_pass = [Effect::HighPass {
+            frequency_hz: 2000.0,
+            q: 0.707,
+        }];
+        assert!(sine_peak(&high_pass, 100.0) < 0.01);
+        assert!((sine_peak(&high_pass, 10_000.0) - 1.0).abs() < 0.02);
+
+        let boost = [Effect::Eq {
+            frequency_hz: 1000.0,
+            gain_db: 6.0206,
+            q: 1.0,
+        }];
+        assert!((sine_peak(&boost, 1000.0) - 2.0).abs() < 0.01);
+        assert!((sine_peak(&boost, 60.0) - 1.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn the_delay_repeats_the_input_with_feedback() {
+        let mut chain = EffectChain::new(
+            &[Effect::Delay {
+                time_ms: 10.0,
+                feedback: 0.5,
+                mix: 0.5,
+            }],
+            SAMPLE_RATE,
+        );
+        let output: Vec<f32> = (0..1000)
+            .map(|frame| {
+                let mut stereo = [if frame == 0 { 1.0 } else { 0.0 }; 2];
+                chain.process(&mut stereo);
+                stereo[1]
+            })
+            .collect();
+        assert_eq!(output[0], 0.5);
+        assert_eq!(output[480], 0.5);
+        assert_eq!(output[960], 0.25);
+        assert_eq!(output[1..480].iter().copied().fold(0.0, f32::max), 0.0);
+    }
+
+    #[test]
+    fn the_reverb_leaves_a_decaying_tail() {
+        let effects = |room_size| {
+            [Effect::Reverb {
+                room_size,
+                damping: 0.5,
+                mix: 1.0,
+            }]
+        };
+        // Energy in each quarter second after a single click.
+        let tail = |room_size| {
+            let mut chain = EffectChain::new(&effects(room_size), SAMPLE_RATE);
+            let mut energy = [0.0f64; 4];
+            for frame in 0..SAMPLE_RATE as usize {
+                let mut stereo = [if frame == 0 { 1.0 } else { 0.0 }; 2];
+                chain.process(&mut stereo);
+                energy[frame / 12_000] += (stereo[0] as f64).powi(2);
+            }
+            energy
+        };
+
+        let small = tail(0.2);
+        assert!(small[0] > 0.0);
+        assert!(small[3] < small[0] / 10.0);
+        let hall = tail(0.95);
+        assert!(hall[3] > small[3]);
+    }
+
+    #[test]
+    fn an_empty_chain_passes_audio_through() {
+        let mut chain = EffectChain::new(&[], SAMPLE_RATE);
+        assert!(chain.is_empty());
+        let mut frame = [0.25, -0.5];
+        chain.process(&mut frame);
+        assert_eq!(frame, [0.25, -0.5]);
+    }
+}
@@ -0,0 +1,683 @@
+//! The backend playback engine, an alternative to mixing in the webview.
+//!
+//! A [`Mixer`] plays a mood's timeline through a [`Renderer`] and lays
+//! one-shots over it, through the same channel buses and [`Console`] as a
+//! render. The [`PlaybackEngine`] pumps the mixer one block at a
+//! time on a clock thread and hands every block to its sinks, so the local
+//! device, the Discord bridge and a recording all get the same master stream.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::bus::{Buses, Console, MixSettings};
+use super::render::{
+    ms_to_frame, Renderer, ScoreSource, Source, RENDER_CHANNELS, RENDER_SAMPLE_RATE,
+};
+use super::sink::AudioSink;
+use crate::store::markers::TransportJump;
+use crate::store::tempo::TempoMap;
+use crate::{AppError, AppResult};
+
+/// Frames per engine block: 40 ms, which is also one Discord voice packet.
+pub const ENGINE_BLOCK_FRAMES: usize = 1920;
+
+/// Loudest master volume the engine accepts, as a linear gain.
+pub const MAX_MASTER_VOLUME: f64 = 2.0;
+
+/// How long channel volumes glide to a value edited during playback, so a
+/// fader move does not click.
+pub const MIX_EDIT_RAMP_MS: i64 = 20;
+
+fn block_duration() -> Duration {
+    Duration::from_secs_f64(ENGINE_BLOCK_FRAMES as f64 / RENDER_SAMPLE_RATE as f64)
+}
+
+/// A decoded one-shot, with the gain and channel it plays at.
+pub struct OneShot {
+    source: Source,
+    gain: f64,
+    channel_id: Option<i64>,
+}
+
+impl OneShot {
+    pub fn load(source: &ScoreSource) -> AppResult<Self> {
+        Ok(OneShot {
+            source: Source::load(Path::new(&source.file_path))?,
+            gain: source.gain,
+            channel_id: source.channel_id,
+        })
+    }
+}
+
+struct PlayingOneShot {
+    shot: OneShot,
+    /// Output frames left before the one-shot starts.
+    delay_frames: u64,
+    /// Read position in source frames.
+    position: f64,
+}
+
+struct PlayingTimeline {
+    mood_id: i64,
+    renderer: Renderer,
+    tempo: Option<TempoMap>,
+}
+
+/// Sums the timeline and every playing one-shot into one stereo stream.
+pub struct Mixer {
+    timeline: Option<PlayingTimeline>,
+    oneshots: Vec<PlayingOneShot>,
+    master_volume: f64,
+    mix: MixSettings,
+    console: Console,
+    buses: Buses,
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        let mix = MixSettings::default();
+        Mixer {
+            timeline: None,
+            oneshots: Vec::new(),
+            master_volume: 1.0,
+            console: Console::new(&mix),
+            mix,
+            buses: Buses::default(),
+        }
+    }
+}
+
+impl Mixer {
+    /// Mixes through `mix` from now on. Unchanged settings keep the
+    /// processors' state, so this can be called before every play.
+    pub fn set_mix(&mut self, mix: MixSettings) {
+        if mix != self.mix {
+            self.console = Console::new(&mix);
+            self.mix = mix;
+        }
+    }
+
+    /// Like [`Mixer::set_mix`], but channel volumes glide to their new values
+    /// over `ramp_ms`. Changes beyond the volumes still apply at once.
+    pub fn ramp_mix(&mut self, mix: MixSettings, ramp_ms: i64) {
+        let mut ramped = self.mix.clone();
+        for (id, strip) in &mut ramped.channels {
+            if let Some(target) = mix.channels.get(id) {
+                strip.volume = target.volume;
+            }
+        }
+        if ramped == mix {
+            self.console
+                .ramp_volumes(&mix, ms_to_frame(ramp_ms) as usize);
+            self.mix = mix;
+        } else {
+            self.set_mix(mix);
+        }
+    }
+
+    /// Starts `renderer` from its current position, replacing any timeline
+    /// already playing. `tempo` is the timeline's tempo map, used to quantize
+    /// one-shots.
+    pub fn play_timeline(&mut self, mood_id: i64, renderer: Renderer, tempo: Option<TempoMap>) {
+        self.set_mix(renderer.score().mix.clone());
+        self.timeline = Some(PlayingTimeline {
+            mood_id,
+            renderer,
+            tempo,
+        });
+    }
+
+    pub fn stop_timeline(&mut self) {
+        self.timeline = None;
+    }
+
+    fn playing_timeline(&mut self) -> AppResult<&mut PlayingTimeline> {
+        self.timeline
+            .as_mut()
+            .ok_or_else(|| AppError::ValidationFailed("No timeline is playing".into()))
+    }
+
+    pub fn seek(&mut self, position_ms: i64) -> AppResult<()> {
+        self.playing_timeline()?.renderer.seek(position_ms);
+        Ok(())
+    }
+
+    /// Moves the playhead to a resolved marker jump on the playing timeline.
+    pub fn jump(&mut self, jump: &TransportJump) -> AppResult<()> {
+        let timeline = self.playing_timeline()?;
+        if timeline.renderer.score().timeline_id != jump.timeline_id {
+            return Err(AppError::ValidationFailed(format!(
+                "Marker {} is not on the playing timeline",
+                jump.marker_id
+            )));
+        }
+        timeline.renderer.seek(jump.position_ms);
+        Ok(())
+    }
+
+    /// Plays `shot`, right away or, with `quantize`, on the next bar line of
+    /// the playing timeline. Without a timeline tempo it plays right away.
+    /// Returns how long until it starts.
+    pub fn play_oneshot(&mut self, shot: OneShot, quantize: bool) -> i64 {
+        let delay_ms = match &self.timeline {
+            Some(PlayingTimeline {
+                renderer,
+                tempo: Some(tempo),
+                ..
+            }) if quantize => {
+                let position_ms = renderer.position_ms();
+                (tempo.next_bar_ms(position_ms) - position_ms)
+                    .round()
+                    .max(0.0) as i64
+            }
+            _ => 0,
+        };
+        self.oneshots.push(PlayingOneShot {
+            shot,
+            delay_frames: ms_to_frame(delay_ms),
+            position: 0.0,
+        });
+        delay_ms
+    }
+
+    pub fn stop_oneshots(&mut self) {
+        self.oneshots.clear();
+    }
+
+    /// Sets the master volume, a linear gain from 0 to [`MAX_MASTER_VOLUME`].
+    pub fn set_master_volume(&mut self, volume: f64) -> AppResult<()> {
+        if !(0.0..=MAX_MASTER_VOLUME).contains(&volume) {
+            return Err(AppError::ValidationFailed(format!(
+                "Master volume must be between 0 and {}, got {}",
+                MAX_MASTER_VOLUME, volume
+            )));
+        }
+        self.master_volume = volume;
+        Ok(())
+    }
+
+    pub fn master_volume(&self) -> f64 {
+        self.master_volume
+    }
+
+    /// Mood whose timeline is playing, and its playhead position.
+    pub fn timeline_position(&self) -> Option<(i64, f64)> {
+        self.timeline
+            .as_ref()
+            .map(|timeline| (timeline.mood_id, timeline.renderer.position_ms()))
+    }
+
+    pub fn active_oneshots(&self) -> usize {
+        self.oneshots.len()
+    }
+
+    /// Fills `out` with the next interleaved stereo frames of the mix.
+    pub fn render(&mut self, out: &mut [f32]) {
+        let frames = out.len() / RENDER_CHANNELS;
+        self.buses.clear(frames);
+        if let Some(timeline) = &mut self.timeline {
+            timeline.renderer.render_buses(&mut self.buses);
+        }
+
+        let step_scale = 1.0 / RENDER_SAMPLE_RATE as f64;
+        for playing in &mut self.oneshots {
+            let step = playing.shot.source.sample_rate * step_scale;
+            let gain = playing.shot.gain as f32;
+            for frame in 0..frames {
+                if playing.delay_frames > 0 {
+                    playing.delay_frames -= 1;
+                    continue;
+                }
+                if playing.position >= playing.shot.source.frames.len() as f64 {
+                    break;
+                }
+                let [left, right] = playing.shot.source.frame_at(playing.position);
+                self.buses
+                    .add(playing.shot.channel_id, frame, [left * gain, right * gain]);
+                playing.position += step;
+            }
+        }
+        self.oneshots.retain(|playing| {
+            playing.delay_frames > 0 || playing.position < playing.shot.source.frames.len() as f64
+        });
+
+        self.console
+            .mix(&mut self.buses, out, self.master_volume as f32);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EngineStatus {
+    pub running: bool,
+    /// Names of the sinks being fed.
+    pub outputs: Vec<String>,
+    pub mood_id: Option<i64>,
+    pub position_ms: Option<f64>,
+    pub active_oneshots: usize,
+    pub master_volume: f64,
+    pub blocks_rendered: u64,
+    /// Blocks the clock produced later than they were due.
+    pub late_blocks: u64,
+    /// Most recent sink failure. A sink that fails is dropped.
+    pub last_error: Option<String>,
+}
+
+#[derive(Default)]
+struct EngineState {
+    mixer: Mixer,
+    /// Names of the sinks being fed, kept here so reading the status never
+    /// waits on a sink.
+    outputs: Vec<String>,
+    blocks_rendered: u64,
+    late_blocks: u64,
+    last_error: Option<String>,
+}
+
+type Sinks = Vec<Box<dyn AudioSink>>;
+
+fn sink_names(sinks: &Sinks) -> Vec<String> {
+    sinks.iter().map(|sink| sink.name().to_string()).collect()
+}
+
+/// What the clock thread shares with commands. Sinks sit behind a lock of
+/// their own, so the state is only held while a block renders and never while
+/// a sink writes. Anything taking both locks takes `sinks` first.
+#[derive(Default)]
+struct Shared {
+    state: Mutex<EngineState>,
+    sinks: Mutex<Sinks>,
+}
+
+impl Shared {
+    /// Renders the next block and hands it to every sink. A sink that fails
+    /// is finished and dropped, and its error becomes the engine's last.
+    fn pump(&self, late: bool) -> AppResult<()> {
+        // Held throughout, so blocks reach the sinks in the order they render.
+        let mut sinks = lock(&self.sinks)?;
+        let block = {
+            let mut state = lock(&self.state)?;
+            if late {
+                state.late_blocks += 1;
+            }
+            let mut block = vec![0.0; ENGINE_BLOCK_FRAMES * RENDER_CHANNELS];
+            state.mixer.render(&mut block);
+            state.blocks_rendered += 1;
+            block
+        };
+
+        let mut last_error = None;
+        sinks.retain_mut(|sink| match sink.write(&block) {
+            Ok(()) => true,
+            Err(error) => {
+                last_error = Some(format!("{}: {}", sink.name(), error));
+                let _ = sink.finish();
+                false
+            }
+        });
+        if last_error.is_some() {
+            let mut state = lock(&self.state)?;
+            state.last_error = last_error;
+            state.outputs = sink_names(&sinks);
+        }
+        Ok(())
+    }
+}
+
+struct Clock {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+/// The mixer and its sinks, shared between commands and the clock thread.
+#[derive(Default)]
+pub struct PlaybackEngine {
+    shared: Arc<Shared>,
+    clock: Mutex<Option<Clock>>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> AppResult<MutexGuard<'_, T>> {
+    mutex
+        .lock()
+        .map_err(|_| AppError::Internal("Failed to lock playback engine".into()))
+}
+
+impl PlaybackEngine {
+    /// Starts feeding `sinks` in real time, replacing any the engine was
+    /// already feeding. The mixer keeps whatever it was playing.
+    pub fn start(&self, sinks: Sinks) -> AppResult<()> {
+        if sinks.is_empty() {
+            return Err(AppError::ValidationFailed(
+                "The playback engine needs at least one output".into(),
+            ));
+        }
+        self.stop()?;
+        self.attach(sinks)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let shared = Arc::clone(&self.shared);
+        let stopping = Arc::clone(&stop);
+        let thread = std::thread::spawn(move || {
+            let block = block_duration();
+            let mut due = Instant::now();
+            while !stopping.load(Ordering::Relaxed) {
+                let now = Instant::now();
+                if now < due {
+                    std::thread::sleep(due - now);
+                    continue;
+                }
+                // Running more than a block behind: count it and pick up from
+                // now instead of bursting to catch up.
+                let late = now - due > block;
+                if late {
+                    due = now;
+                }
+                if shared.pump(late).is_err() {
+                    break;
+                }
+                due += block;
+            }
+        });
+
+        *lock(&self.clock)? = Some(Clock { stop, thread });
+        Ok(())
+    }
+
+    fn attach(&self, sinks: Sinks) -> AppResult<()> {
+        let mut attached = lock(&self.shared.sinks)?;
+        *attached = sinks;
+        lock(&self.shared.state)?.outputs = sink_names(&attached);
+        Ok(())
+    }
+
+    /// Stops the clock and finishes every sink. Playback state is kept, so a
+    /// later [`start`](Self::start) resumes where this left off.
+    pub fn stop(&self) -> AppResult<()> {
+        let clock = lock(&self.clock)?.take();
+        if let Some(clock) = clock {
+            clock.stop.store(true, Ordering::Relaxed);
+            let _ = clock.thread.join();
+        }
+
+        let mut sinks = lock(&self.shared.sinks)?;
+        let mut result = Ok(());
+        for mut sink in sinks.drain(..) {
+            let finished = sink.finish();
+            if result.is_ok() {
+                result = finished;
+            }
+        }
+        lock(&self.shared.state)?.outputs.clear();
+        result
+    }
+
+    /// Renders one block to the sinks right away, outside the clock. Used to
+    /// drive the engine faster than real time, as tests do.
+    pub fn pump(&self) -> AppResult<()> {
+        self.shared.pump(false)
+    }
+
+    /// Runs `f` on the mixer between two blocks.
+    pub fn with_mixer<T>(&self, f: impl FnOnce(&mut Mixer) -> AppResult<T>) -> AppResult<T> {
+        f(&mut lock(&self.shared.state)?.mixer)
+    }
+
+    pub fn status(&self) -> AppResult<EngineStatus> {
+        let running = lock(&self.clock)?.is_some();
+        let state = lock(&self.shared.state)?;
+        let timeline = state.mixer.timeline_position();
+        Ok(EngineStatus {
+            running,
+            outputs: state.outputs.clone(),
+            mood_id: timeline.map(|(mood_id, _)| mood_id),
+            position_ms: timeline.map(|(_, position_ms)| position_ms),
+            active_oneshots: state.mixer.active_oneshots(),
+            master_volume: state.mixer.master_volume(),
+            blocks_rendered: state.blocks_rendered,
+            late_blocks: state.late_blocks,
+            last_error: state.last_error.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::decode::decode_file;
+    use crate::audio::render::load_score;
+    use crate::audio::sink::{NullSink, WavFileSink};
+    use crate::audio::{seed_test_library, test_dir, TestSource};
+    use crate::store::tempo::{load_tempo_map, set_tempo_change};
+    use rusqlite::Connection;
+    use std::sync::mpsc;
+
+    /// Mood 1 with one track holding element 100, a one-second source at 0.5
+    /// placed at 0 ms, and element 101, a one-shot at 0.25.
+    fn seeded(dir: &Path) -> Connection {
+        let sources = [
+            TestSource {
+                id: 100,
+                file_name: "half.wav",
+                channel_type: "music",
+                sample_rate: 8000,
+                channels: 1,
+                samples: vec![0.5; 8000],
+            },
+            TestSource {
+                id: 101,
+                file_name: "quarter.wav",
+                channel_type: "effects",
+                sample_rate: 48_000,
+                channels: 1,
+                samples: vec![0.25; 4800],
+            },
+        ];
+        seed_test_library(
+            dir,
+            &sources,
+            "INSERT INTO timeline_elements (timeline_id, track_id, audio_element_id, start_time_ms, duration_ms) VALUES (1, 5, 100, 0, 1000);",
+        )
+    }
+
+    fn oneshot(conn: &Connection, id: i64) -> OneShot {
+        OneShot::load(&ScoreSource::load(conn, id).unwrap()).unwrap()
+    }
+
+    fn left_at(out: &[f32], ms: i64) -> f32 {
+        out[ms_to_frame(ms) as usize * RENDER_CHANNELS]
+    }
+
+    #[test]
+    fn oneshots_mix_over_the_timeline_and_then_finish() {
+        let dir = test_dir("engine-mix");
+        let conn = seeded(&dir);
+        let mut mixer = Mixer::default();
+        mixer.play_timeline(
+            1,
+            Renderer::new(load_score(&conn, 1).unwrap(), 0).unwrap(),
+            None,
+        );
+        assert_eq!(mixer.play_oneshot(oneshot(&conn, 101), true), 0);
+
+        let mut out = vec![0.0; ms_to_frame(300) as usize * RENDER_CHANNELS];
+        mixer.render(&mut out);
+        assert!((left_at(&out, 50) - 0.75).abs() < 1e-3);
+        assert!((left_at(&out, 200) - 0.5).abs() < 1e-3);
+        assert_eq!(mixer.active_oneshots(), 0);
+
+        mixer.set_master_volume(0.5).unwrap();
+        mixer.seek(0).unwrap();
+        mixer.render(&mut out);
+        assert!((left_at(&out, 50) - 0.25).abs() < 1e-3);
+        assert_eq!(
+            mixer.set_master_volume(3.0).unwrap_err().code(),
+            "ValidationFailed"
+        );
+
+        mixer.stop_timeline();
+        mixer.render(&mut out);
+        // Past the limiter's lookahead, nothing is left playing.
+        assert!(out[RENDER_CHANNELS * 100..]
+            .iter()
+            .all(|sample| *sample == 0.0));
+        assert_eq!(mixer.seek(0).unwrap_err().code(), "ValidationFailed");
+    }
+
+    #[test]
+    fn quantized_oneshots_wait_for_the_next_bar() {
+        let dir = test_dir("engine-quantize");
+        let conn = seeded(&dir);
+        let score = || Renderer::new(load_score(&conn, 1).unwrap(), 0).unwrap();
+        let mut mixer = Mixer::default();
+        mixer.play_timeline(1, score(), None);
+        assert_eq!(mixer.play_oneshot(oneshot(&conn, 101), true), 0);
+        mixer.stop_oneshots();
+
+        set_tempo_change(&conn, 1, 1, 240.0, 2, 4).unwrap();
+        mixer.play_timeline(1, score(), load_tempo_map(&conn, 1).unwrap());
+        mixer.seek(1100).unwrap();
+        // Two beats at 240 bpm make a 500 ms bar, so the next one is at 1500 ms.
+        assert_eq!(mixer.play_oneshot(oneshot(&conn, 101), true), 400);
+        let mut out = vec![0.0; ms_to_frame(500) as usize * RENDER_CHANNELS];
+        mixer.render(&mut out);
+        assert_eq!(left_at(&out, 300), 0.0);
+        assert!((left_at(&out, 450) - 0.25).abs() < 1e-3);
+    }
+
+    #[test]
+    fn the_engine_feeds_every_sink_the_same_master_stream() {
+        let dir = test_dir("engine-sinks");
+        let conn = seeded(&dir);
+        let recording = dir.join("out.wav");
+        let engine = PlaybackEngine::default();
+        engine
+            .with_mixer(|mixer| {
+                mixer.play_timeline(1, Renderer::new(load_score(&conn, 1)?, 0)?, None);
+                Ok(())
+            })
+            .unwrap();
+
+        // Feed the sinks headlessly by pumping instead of running the clock.
+        engine
+            .attach(vec![
+                Box::new(NullSink::default()),
+                Box::new(WavFileSink::create(&recording).unwrap()),
+            ])
+            .unwrap();
+        for _ in 0..25 {
+            engine.pump().unwrap();
+        }
+        let status = engine.status().unwrap();
+        assert!(!status.running);
+        assert_eq!(status.outputs, vec!["null", "file"]);
+        assert_eq!(status.blocks_rendered, 25);
+        assert_eq!(status.mood_id, Some(1));
+        assert!((status.position_ms.unwrap() - 1000.0).abs() < 1e-6);
+        engine.stop().unwrap();
+        assert!(engine.status().unwrap().outputs.is_empty());
+
+        let decoded = decode_file(&recording).unwrap();
+        assert_eq!(decoded.sample_rate, 48_000);
+        assert_eq!(decoded.channels, 2);
+        assert_eq!(decoded.frame_count(), 25 * ENGINE_BLOCK_FRAMES);
+        assert!((decoded.samples[2 * 24_000] - 0.5).abs() < 1e-3);
+
+        assert_eq!(
+            engine.start(Vec::new()).unwrap_err().code(),
+            "ValidationFailed"
+        );
+    }
+
+    /// Fails every write with `error`, or, without one, holds each write
+    /// until the test lets it go.
+    struct TestSink {
+        error: Option<&'static str>,
+        writing: mpsc::Sender<()>,
+        release: mpsc::Receiver<()>,
+    }
+
+    impl AudioSink for TestSink {
+        fn name(&self) -> &str {
+            "test"
+        }
+
+        fn write(&mut self, _block: &[f32]) -> AppResult<()> {
+            if let Some(error) = self.error {
+                return Err(AppError::Io(error.into()));
+            }
+            let _ = self.writing.send(());
+            let _ = self.release.recv();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn a_slow_sink_does_not_hold_up_the_mixer() {
+        let engine = Arc::new(PlaybackEngine::default());
+        let (writing, written) = mpsc::channel();
+        let (release, released) = mpsc::channel();
+        engine
+            .attach(vec![Box::new(TestSink {
+                error: None,
+                writing,
+                release: released,
+            })])
+            .unwrap();
+
+        let pumping = {
+            let engine = Arc::clone(&engine);
+            std::thread::spawn(move || engine.pump())
+        };
+        written.recv().unwrap();
+        // The sink is still writing the first block.
+        engine
+            .with_mixer(|mixer| mixer.set_master_volume(0.5))
+            .unwrap();
+        let status = engine.status().unwrap();
+        assert_eq!(status.blocks_rendered, 1);
+        assert_eq!(status.master_volume, 0.5);
+
+        release.send(()).unwrap();
+        pumping.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn failing_sinks_are_dropped_and_reported() {
+        let engine = PlaybackEngine::default();
+        let (writing, _written) = mpsc::channel();
+        let (_release, released) = mpsc::channel();
+        engine
+            .attach(vec![
+                Box::new(NullSink::default()),
+                Box::new(TestSink {
+                    error: Some("unplugged"),
+                    writing,
+                    release: released,
+                }),
+            ])
+            .unwrap();
+
+        engine.pump().unwrap();
+        engine.pump().unwrap();
+        let status = engine.status().unwrap();
+        assert_eq!(status.outputs, vec!["null"]);
+        assert_eq!(status.blocks_rendered, 2);
+        assert_eq!(status.last_error.as_deref(), Some("test: unplugged"));
+    }
+
+    #[test]
+    fn the_clock_runs_in_real_time_until_stopped() {
+        let engine = PlaybackEngine::default();
+        engine.start(vec![Box::new(NullSink::default())]).unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        let status = engine.status().unwrap();
+        assert!(status.running);
+        assert!(status.blocks_rendered >= 2);
+        engine.stop().unwrap();
+        assert!(!engine.status().unwrap().running);
+    }
+}
@@ -0,0 +1,378 @@
+//! A small FLAC encoder for rendered audio.
+//!
+//! Each channel of each block is coded with whichever fixed predictor (order 0
+//! to 4) leaves the smallest residual, Rice coded with a single partition. That
+//! gets most of the way to the reference encoder on rendered ambience without
+//! the LPC search.
+
+use std::io::{Seek, SeekFrom, Write};
+
+use crate::{AppError, AppResult};
+
+pub const BLOCK_SIZE: usize = 4096;
+
+const STREAMINFO_LENGTH: u32 = 34;
+const MAX_FIXED_ORDER: usize = 4;
+const MAX_RICE_PARAMETER: u32 = 30;
+
+/// Writes bits most significant first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    accumulator: u64,
+    pending: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, bits: u32, value: u64) {
+        debug_assert!(bits <= 32);
+        if bits == 0 {
+            return;
+        }
+        self.accumulator = (self.accumulator << bits) | (value & ((1u64 << bits) - 1));
+        self.pending += bits;
+        while self.pending >= 8 {
+            self.pending -= 8;
+            self.bytes.push((self.accumulator >> self.pending) as u8);
+        }
+    }
+
+    fn write_signed(&mut self, bits: u32, value: i64) {
+        self.write(bits, value as u64);
+    }
+
+    fn write_unary(&mut self, zeros: u64) {
+        let mut zeros = zeros;
+        while zeros >= 32 {
+            self.write(32, 0);
+            zeros -= 32;
+        }
+        self.write(zeros as u32 + 1, 1);
+    }
+
+    fn align(&mut self) {
+        if self.pending > 0 {
+            self.write(8 - self.pending, 0);
+        }
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        self.align();
+        self.bytes
+    }
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |crc, &byte| {
+        (0..8).fold(crc ^ ((byte as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// Residual of the fixed predictor of `order` at every sample after the warm-up.
+fn fixed_residual(samples: &[i64], order: usize) -> Vec<i64> {
+    (order..samples.len())
+        .map(|i| {
+            let s = |back: usize| samples[i - back];
+            match order {
+                0 => s(0),
+                1 => s(0) - s(1),
+                2 => s(0) - 2 * s(1) + s(2),
+                3 => s(0) - 3 * s(1) + 3 * s(2) - s(3),
+                _ => s(0) - 4 * s(1) + 6 * s(2) - 4 * s(3) + s(4),
+            }
+        })
+        .collect()
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Rice parameter with the fewest bits for `residual`, and that bit count.
+fn best_rice_parameter(residual: &[i64]) -> (u32, u64) {
+    let folded: Vec<u64> = residual.iter().map(|&r| zigzag(r)).collect();
+    (0..=MAX_RICE_PARAMETER)
+        .map(|parameter| {
+            let bits = folded
+                .iter()
+                .map(|&u| (u >> parameter) + 1 + parameter as u64)
+                .sum::<u64>();
+            (parameter, bits)
+        })
+        .min_by_key(|&(_, bits)| bits)
+        .unwrap_or((0, 0))
+}
+
+fn write_subframe(out: &mut BitWriter, samples: &[i64], bits_per_sample: u32) {
+    if samples.iter().all(|&sample| sample == samples[0]) {
+        out.write(8, 0b0000_0000);
+        out.write_signed(bits_per_sample, samples[0]);
+        return;
+    }
+
+    let best = (0..=MAX_FIXED_ORDER.min(samples.len() - 1))
+        .map(|order| {
+            let residual = fixed_residual(samples, order);
+            let (parameter, bits) = best_rice_parameter(&residual);
+            (order, residual, parameter, bits)
+        })
+        .min_by_key(|(order, _, _, bits)| bits + (*order as u64) * bits_per_sample as u64);
+    let verbatim_bits = samples.len() as u64 * bits_per_sample as u64;
+
+    match best {
+        Some((order, residual, parameter, bits))
+            if bits + (order as u64) * (bits_per_sample as u64) < verbatim_bits =>
+        {
+            out.write(8, 0b0001_0000 | ((order as u64) << 1));
+            for &sample in &samples[..order] {
+                out.write_signed(bits_per_sample, sample);
+            }
+            // Rice coding with 5-bit parameters, one partition.
+            out.write(2, 0b01);
+            out.write(4, 0);
+            out.write(5, parameter as u64);
+            for value in residual {
+                let folded = zigzag(value);
+                out.write_unary(folded >> parameter);
+                out.write(parameter, folded);
+            }
+        }
+        _ => {
+            out.write(8, 0b0000_0010);
+            for &sample in samples {
+                out.write_signed(bits_per_sample, sample);
+            }
+        }
+    }
+}
+
+/// Frame number in FLAC's extended UTF-8 coding.
+fn write_frame_number(out: &mut BitWriter, number: u64) {
+    if number < 0x80 {
+        out.write(8, number);
+        return;
+    }
+    let continuation_bytes = match number {
+        0..=0x7ff => 1,
+        0x800..=0xffff => 2,
+        0x1_0000..=0x1f_ffff => 3,
+        0x20_0000..=0x3ff_ffff => 4,
+        _ => 5,
+    };
+    let lead_marker = (0xff00u64 >> (continuation_bytes + 1)) & 0xff;
+    out.write(8, lead_marker | (number >> (6 * continuation_bytes)));
+    for index in (0..continuation_bytes).rev() {
+        out.write(8, 0x80 | ((number >> (6 * index)) & 0x3f));
+    }
+}
+
+/// Streams interleaved integer samples into a FLAC file. The stream length is
+/// filled in by [`FlacWriter::finish`].
+pub struct FlacWriter<W: Write + Seek> {
+    writer: W,
+    sample_rate: u32,
+    channels: usize,
+    bits_per_sample: u32,
+    pending: Vec<i32>,
+    frame_number: u64,
+    total_frames: u64,
+}
+
+impl<W: Write + Seek> FlacWriter<W> {
+    pub fn new(
+        mut writer: W,
+        sample_rate: u32,
+        channels: usize,
+        bits_per_sample: u32,
+    ) -> AppResult<Self> {
+        if !(1..=8).contains(&channels) || !(4..=24).contains(&bits_per_sample) {
+            return Err(AppError::ValidationFailed(format!(
+                "FLAC supports 1 to 8 channels of 4 to 24 bits, got {} channels of {} bits",
+                channels, bits_per_sample
+            )));
+        }
+        writer.write_all(b"fLaC")?;
+        let mut flac = FlacWriter {
+            writer,
+            sample_rate,
+            channels,
+            bits_per_sample,
+            pending: Vec::with_capacity(BLOCK_SIZE * channels),
+            frame_number: 0,
+            total_frames: 0,
+        };
+        flac.write_streaminfo()?;
+        Ok(flac)
+    }
+
+    fn write_streaminfo(&mut self) -> AppResult<()> {
+        let mut info = BitWriter::default();
+        // Last metadata block, type STREAMINFO.
+        info.write(8, 0x80);
+        info.write(24, STREAMINFO_LENGTH as u64);
+        info.write(16, BLOCK_SIZE as u64);
+        info.write(16, BLOCK_SIZE as u64);
+        // Frame sizes are unknown.
+        info.write(24, 0);
+        info.write(24, 0);
+        info.write(20, self.sample_rate as u64);
+        info.write(3, self.channels as u64 - 1);
+        info.write(5, self.bits_per_sample as u64 - 1);
+        info.write(4, self.total_frames >> 32);
+        info.write(32, self.total_frames & 0xffff_ffff);
+        // No MD5 signature.
+        info.write(32, 0);
+        info.write(32, 0);
+        info.write(32, 0);
+        info.write(32, 0);
+        self.writer.write_all(&info.into_bytes())?;
+        Ok(())
+    }
+
+    /// Queues interleaved samples, writing every complete block.
+    pub fn write_samples(&mut self, samples: &[i32]) -> AppResult<()> {
+        let block_samples = BLOCK_SIZE * self.channels;
+        for chunk in samples.chunks(block_samples) {
+            let room = block_samples - self.pending.len();
+            let (now, later) = chunk.split_at(chunk.len().min(room));
+            self.pending.extend_from_slice(now);
+            if self.pending.len() == block_samples {
+                self.write_frame()?;
+            }
+            self.pending.extend_from_slice(later);
+        }
+        Ok(())
+    }
+
+    fn write_frame(&mut self) -> AppResult<()> {
+        let frames = self.pending.len() / self.channels;
+        if frames == 0 {
+            return Ok(());
+        }
+
+        let mut out = BitWriter::default();
+        // Sync code, then a reserved zero bit.
+        out.write(15, 0b111_1111_1111_1100);
+        // Fixed block size.
+        out.write(1, 0);
+        let block_size_code = if frames == BLOCK_SIZE { 0b1100 } else { 0b0111 };
+        out.write(4, block_size_code);
+        let sample_rate_code = match self.sample_rate {
+            44_100 => 0b1001,
+            48_000 => 0b1010,
+            96_000 => 0b1011,
+            _ => 0b0000,
+        };
+        out.write(4, sample_rate_code);
+        out.write(4, self.channels as u64 - 1);
+        let sample_size_code = match self.bits_per_sample {
+            8 => 0b001,
+            16 => 0b100,
+            24 => 0b110,
+            _ => 0b000,
+        };
+        out.write(3, sample_size_code);
+        out.write(1, 0);
+        write_frame_number(&mut out, self.frame_number);
+        if block_size_code == 0b0111 {
+            out.write(16, frames as u64 - 1);
+        }
+        let header_crc = crc8(&out.bytes);
+        out.write(8, header_crc as u64);
+
+        for channel in 0..self.channels {
+            let samples: Vec<i64> = self
+                .pending
+                .iter()
+                .skip(channel)
+                .step_by(self.channels)
+                .map(|&sample| sample as i64)
+                .collect();
+            write_subframe(&mut out, &samples, self.bits_per_sample);
+        }
+
+        let mut bytes = out.into_bytes();
+        let frame_crc = crc16(&bytes);
+        bytes.extend_from_slice(&frame_crc.to_be_bytes());
+        self.writer.write_all(&bytes)?;
+
+        self.frame_number += 1;
+        self.total_frames += frames as u64;
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Writes the last, possibly short, block and the final stream length.
+    pub fn finish(mut self) -> AppResult<W> {
+        self.write_frame()?;
+        self.writer.flush()?;
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.write_streaminfo()?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::decode::decode_file;
+    use crate::audio::test_dir;
+    use std::fs::File;
+    use std::io::BufWriter;
+
+    #[test]
+    fn encoded_files_decode_back_to_the_same_samples() {
+        let dir = test_dir("flac-roundtrip");
+        let path = dir.join("tone.flac");
+        // A ramp on the left, a tone on the right, silence, and a short last block.
+        let frames = BLOCK_SIZE * 2 + 1000;
+        let samples: Vec<i32> = (0..frames)
+            .flat_map(|i| {
+                let left = if i < BLOCK_SIZE {
+                    0
+                } else {
+                    (i as i32 * 37) % 8_000_000 - 4_000_000
+                };
+                let right = (4_000_000.0 * (i as f64 * 0.05).sin()) as i32;
+                [left, right]
+            })
+            .collect();
+
+        let mut writer =
+            FlacWriter::new(BufWriter::new(File::create(&path).unwrap()), 48_000, 2, 24).unwrap();
+        writer.write_samples(&samples[..3333]).unwrap();
+        writer.write_samples(&samples[3333..]).unwrap();
+        writer.finish().unwrap();
+
+        let decoded = decode_file(&path).unwrap();
+        assert_eq!(decoded.sample_rate, 48_000);
+        assert_eq!(decoded.channels, 2);
+        assert_eq!(decoded.frame_count(), frames);
+        let scale = (1 << 23) as f32;
+        for (decoded, original) in decoded.samples.iter().zip(&samples) {
+            assert_eq!((decoded * scale).round() as i32, *original);
+        }
+        // Smaller than the 24-bit samples stored raw.
+        assert!(std::fs::metadata(&path).unwrap().len() < (samples.len() * 3) as u64);
+    }
+}
@@ -0,0 +1,174 @@
+//! Finding the silence around a file's content and where it loops cleanly.
+//!
+//! The loop start is the first rising zero crossing of the content. The loop
+//! end is searched for near the end of the content: the candidate whose
+//! following audio best matches the audio after the loop start wins, so
+//! jumping from end back to start continues the sound instead of cutting it.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::decode::decode_file;
+use crate::AppResult;
+
+/// Samples quieter than -60 dBFS count as silence.
+const SILENCE_THRESHOLD: f32 = 0.001;
+/// Length of audio compared when matching the loop end to the loop start.
+const MATCH_WINDOW_MS: usize = 50;
+/// How far back from the end of the content the loop end may move.
+const SEARCH_RANGE_MS: usize = 1000;
+/// How far a point may move to land on a zero crossing.
+const ZERO_CROSSING_RANGE_MS: usize = 5;
+/// The coarse search looks at every this many frames before refining.
+const COARSE_STEP: usize = 16;
+
+/// Where the content of a file starts and ends and where it loops.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LoopPoints {
+    pub leading_silence_ms: i64,
+    pub trailing_silence_ms: i64,
+    pub loop_start_ms: i64,
+    pub loop_end_ms: i64,
+}
+
+/// Index of the rising zero crossing nearest to `frame` within `range`
+/// frames, or `frame` itself when there is none.
+fn nearest_zero_crossing(mono: &[f32], frame: usize, range: usize) -> usize {
+    let from = frame.saturating_sub(range).max(1);
+    let to = (frame + range).min(mono.len().saturating_sub(1));
+    (from..=to)
+        .filter(|&i| mono[i - 1] < 0.0 && mono[i] >= 0.0)
+        .min_by_key(|&i| i.abs_diff(frame))
+        .unwrap_or(frame)
+}
+
+/// Normalised cross-correlation of two equally long windows.
+fn correlation(a: &[f32], b: &[f32]) -> f64 {
+    let (mut ab, mut aa, mut bb) = (0.0f64, 0.0f64, 0.0f64);
+    for (&x, &y) in a.iter().zip(b) {
+        ab += x as f64 * y as f64;
+        aa += x as f64 * x as f64;
+        bb += y as f64 * y as f64;
+    }
+    if aa == 0.0 || bb == 0.0 {
+        return 0.0;
+    }
+    ab / (aa * bb).sqrt()
+}
+
+/// Finds the loop end in `[first, last]` whose following `window` frames
+/// best match those after `start`.
+fn best_loop_end(mono: &[f32], start: usize, window: usize, first: usize, last: usize) -> usize {
+    let reference = &mono[start..start + window];
+    let score = |end: usize| correlation(reference, &mono[end..end + window]);
+    let best_of = |candidates: &mut dyn Iterator<Item = usize>| {
+        candidates
+            .map(|end| (end, score(end)))
+            .max_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)))
+            .map(|(end, _)| end)
+            .unwrap_or(last)
+    };
+
+    let coarse = best_of(&mut (first..=last).step_by(COARSE_STEP).chain([last]));
+    let refine_from = coarse.saturating_sub(COARSE_STEP).max(first);
+    let refine_to = (coarse + COARSE_STEP).min(last);
+    best_of(&mut (refine_from..=refine_to))
+}
+
+/// Decodes `path` and finds its silence and loop points. A silent file is
+/// reported as all leading silence with an empty loop.
+pub fn detect_loop_points(path: &Path) -> AppResult<LoopPoints> {
+    let decoded = decode_file(path)?;
+    let channels = decoded.channels;
+    let mono: Vec<f32> = decoded
+        .samples
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+    let frames_per_ms = decoded.sample_rate as usize / 1000;
+    let to_ms =
+        |frames: usize| (frames as f64 * 1000.0 / decoded.sample_rate as f64).round() as i64;
+    let audible = |frame: &[f32]| frame.iter().any(|sample| sample.abs() > SILENCE_THRESHOLD);
+
+    let frames: Vec<&[f32]> = decoded.samples.chunks_exact(channels).collect();
+    let Some(content_start) = frames.iter().position(|frame| audible(frame)) else {
+        let length_ms = to_ms(mono.len());
+        return Ok(LoopPoints {
+            leading_silence_ms: length_ms,
+            trailing_silence_ms: 0,
+            loop_start_ms: length_ms,
+            loop_end_ms: length_ms,
+        });
+    };
+    let content_end = frames
+        .iter()
+        .rposition(|frame| audible(frame))
+        .map_or(mono.len(), |last| last + 1);
+
+    let zero_range = ZERO_CROSSING_RANGE_MS * frames_per_ms;
+    let loop_start = nearest_zero_crossing(&mono, content_start, zero_range).max(content_start);
+    let window = MATCH_WINDOW_MS * frames_per_ms;
+
+    // Matching needs a window after the start and after every candidate end,
+    // with the candidates staying clear of the start's own window.
+    let last = content_end.saturating_sub(window);
+    let first = last
+        .saturating_sub(SEARCH_RANGE_MS * frames_per_ms)
+        .max(loop_start + 2 * window);
+    let loop_end = if window == 0 || first > last {
+        nearest_zero_crossing(&mono, content_end, zero_range).min(content_end)
+    } else {
+        let end = best_loop_end(&mono, loop_start, window, first, last);
+        nearest_zero_crossing(&mono, end, zero_range).min(content_end)
+    };
+
+    Ok(LoopPoints {
+        leading_silence_ms: to_ms(content_start),
+        trailing_silence_ms: to_ms(mono.len() - content_end),
+        loop_start_ms: to_ms(loop_start),
+        loop_end_ms: to_ms(loop_end),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{test_dir, write_test_wav};
+    use std::f64::consts::PI;
+
+    #[test]
+    fn silence_is_trimmed_and_the_loop_lands_on_a_period() {
+        let dir = test_dir("loop-points");
+        let path = dir.join("hum.wav");
+        // 0.25 s of silence, 2 s of a 100 Hz tone, 0.5 s of silence at 8 kHz.
+        let rate = 8000;
+        let mut samples = vec![0.0f32; 2000];
+        samples.extend(
+            (0..16000).map(|i| (0.5 * (2.0 * PI * 100.0 * i as f64 / rate as f64).sin()) as f32),
+        );
+        samples.extend(vec![0.0f32; 4000]);
+        write_test_wav(&path, rate, 1, &samples);
+
+        let points = detect_loop_points(&path).unwrap();
+        assert_eq!(points.leading_silence_ms, 250);
+        assert!((points.trailing_silence_ms - 500).abs() <= 1);
+        assert!((points.loop_start_ms - 250).abs() <= 1);
+
+        // The loop must span whole 10 ms periods of the tone to be seamless.
+        let length = points.loop_end_ms - points.loop_start_ms;
+        assert!(length > 1000 && length <= 2000, "length {}", length);
+        assert_eq!(length % 10, 0, "length {}", length);
+    }
+
+    #[test]
+    fn silent_files_have_an_empty_loop() {
+        let dir = test_dir("loop-silent");
+        let path = dir.join("silence.wav");
+        write_test_wav(&path, 8000, 2, &vec![0.0; 16000]);
+
+        let points = detect_loop_points(&path).unwrap();
+        assert_eq!(points.leading_silence_ms, 1000);
+        assert_eq!(points.loop_start_ms, points.loop_end_ms);
+    }
+}
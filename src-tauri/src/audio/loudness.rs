@@ -0,0 +1,286 @@
+//! Loudness measurement after ITU-R BS.1770-4 and EBU R128.
+//!
+//! The signal is K-weighted and summed into 100 ms slices of mean-square
+//! energy. Gated 400 ms blocks over those slices give the integrated loudness,
+//! 3 s windows give the loudness range, and a 4x oversampled copy of the input
+//! gives the true peak.
+
+use std::f64::consts::PI;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::decode::for_each_block;
+use super::effects::Biquad;
+use crate::AppResult;
+
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+const RANGE_RELATIVE_GATE_LU: f64 = -20.0;
+const SLICE_MS: u32 = 100;
+const BLOCK_SLICES: usize = 4;
+const SHORT_TERM_SLICES: usize = 30;
+const OVERSAMPLING: usize = 4;
+const TAPS_PER_PHASE: usize = 12;
+
+/// Loudness of one file. Values are `None` when the file is too short or too
+/// quiet to measure.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LoudnessReport {
+    pub integrated_lufs: Option<f64>,
+    pub loudness_range_lu: Option<f64>,
+    pub true_peak_dbtp: Option<f64>,
+}
+
+/// The two K-weighting stages, recomputed for `sample_rate` the way
+/// libebur128 does so rates other than 48 kHz measure the same.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let rate = sample_rate as f64;
+
+    let f0 = 1681.974450955533;
+    let gain_db = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    [shelf, high_pass]
+}
+
+/// BS.1770 channel weight, assuming the usual L R C LFE Ls Rs order for
+/// six channels. The LFE channel does not count.
+fn channel_weight(channels: usize, index: usize) -> f64 {
+    match (channels, index) {
+        (6, 3) => 0.0,
+        (6, 4) | (6, 5) => 1.41,
+        _ => 1.0,
+    }
+}
+
+/// Interpolates each channel to four times its rate to catch peaks between samples.
+pub(crate) struct TruePeakMeter {
+    phases: Vec<[f64; TAPS_PER_PHASE]>,
+    history: Vec<[f64; TAPS_PER_PHASE]>,
+    pub peak: f64,
+}
+
+impl TruePeakMeter {
+    pub fn new(channels: usize) -> Self {
+        let taps = OVERSAMPLING * TAPS_PER_PHASE;
+        let center = (taps - 1) as f64 / 2.0;
+        let mut phases = vec![[0.0; TAPS_PER_PHASE]; OVERSAMPLING];
+        for tap in 0..taps {
+            let t = (tap as f64 - center) / OVERSAMPLING as f64;
+            let sinc = if t == 0.0 {
+                1.0
+            } else {
+                (PI * t).sin() / (PI * t)
+            };
+            let window = 0.5 - 0.5 * (2.0 * PI * (tap as f64 + 0.5) / taps as f64).cos();
+            phases[tap % OVERSAMPLING][tap / OVERSAMPLING] = sinc * window;
+        }
+        for phase in &mut phases {
+            let sum: f64 = phase.iter().sum();
+            phase.iter_mut().for_each(|tap| *tap /= sum);
+        }
+
+        TruePeakMeter {
+            phases,
+            history: vec![[0.0; TAPS_PER_PHASE]; channels],
+            peak: 0.0,
+        }
+    }
+
+    /// Adds the next sample of `channel`, returning the highest peak between
+    /// it and the previous one.
+    pub fn push(&mut self, channel: usize, sample: f64) -> f64 {
+        let mut local = sample.abs();
+        let history = &mut self.history[channel];
+        history.rotate_right(1);
+        history[0] = sample;
+        for phase in &self.phases {
+            let interpolated: f64 = phase.iter().zip(history.iter()).map(|(h, x)| h * x).sum();
+            local = local.max(interpolated.abs());
+        }
+        self.peak = self.peak.max(local);
+        local
+    }
+}
+
+fn energy_to_lufs(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0usize), |(sum, count), value| {
+        (sum + value, count + 1)
+    });
+    (count > 0).then(|| sum / count as f64)
+}
+
+/// Energies of windows of `width` slices, advancing one slice at a time.
+fn window_energies(slices: &[f64], width: usize) -> Vec<f64> {
+    if slices.len() < width {
+        return Vec::new();
+    }
+    slices
+        .windows(width)
+        .map(|window| window.iter().sum::<f64>() / width as f64)
+        .collect()
+}
+
+fn integrated_loudness(slices: &[f64]) -> Option<f64> {
+    let blocks: Vec<f64> = window_energies(slices, BLOCK_SLICES)
+        .into_iter()
+        .filter(|energy| energy_to_lufs(*energy) > ABSOLUTE_GATE_LUFS)
+        .collect();
+    let relative_gate = energy_to_lufs(mean(blocks.iter().copied())?) + RELATIVE_GATE_LU;
+
+    mean(
+        blocks
+            .into_iter()
+            .filter(|energy| energy_to_lufs(*energy) > relative_gate),
+    )
+    .map(energy_to_lufs)
+}
+
+/// Loudness range after EBU Tech 3342: the spread between the 10th and 95th
+/// percentiles of gated short-term loudness.
+fn loudness_range(slices: &[f64]) -> Option<f64> {
+    let windows: Vec<f64> = window_energies(slices, SHORT_TERM_SLICES)
+        .into_iter()
+        .filter(|energy| energy_to_lufs(*energy) > ABSOLUTE_GATE_LUFS)
+        .collect();
+    let relative_gate = energy_to_lufs(mean(windows.iter().copied())?) + RANGE_RELATIVE_GATE_LU;
+
+    let mut loudness: Vec<f64> = windows
+        .into_iter()
+        .map(energy_to_lufs)
+        .filter(|lufs| *lufs > relative_gate)
+        .collect();
+    loudness.sort_by(f64::total_cmp);
+    let percentile = |fraction: f64| {
+        let index = ((loudness.len() - 1) as f64 * fraction).round() as usize;
+        loudness[index]
+    };
+
+    Some(percentile(0.95) - percentile(0.10))
+}
+
+/// Measures a whole file.
+pub fn measure_file(path: &Path) -> AppResult<LoudnessReport> {
+    let mut filters: Vec<[Biquad; 2]> = Vec::new();
+    let mut true_peak: Option<TruePeakMeter> = None;
+    let mut slices = Vec::new();
+    let mut slice_energy = 0.0;
+    let mut slice_frames = 0usize;
+
+    for_each_block(path, |block, sample_rate, channels| {
+        if filters.len() != channels {
+            filters = vec![k_weighting(sample_rate); channels];
+            true_peak = Some(TruePeakMeter::new(channels));
+        }
+        let frames_per_slice = (sample_rate * SLICE_MS / 1000) as usize;
+        let meter = true_peak.as_mut().expect("created with the filters");
+
+        for frame in block.chunks_exact(channels) {
+            for (channel, &sample) in frame.iter().enumerate() {
+                let sample = sample as f64;
+                meter.push(channel, sample);
+                let [shelf, high_pass] = &mut filters[channel];
+                let weighted = high_pass.process(shelf.process(sample));
+                slice_energy += channel_weight(channels, channel) * weighted * weighted;
+            }
+            slice_frames += 1;
+            if slice_frames == frames_per_slice {
+                slices.push(slice_energy / frames_per_slice as f64);
+                slice_energy = 0.0;
+                slice_frames = 0;
+            }
+        }
+    })?;
+
+    let true_peak_dbtp = true_peak
+        .map(|meter| meter.peak)
+        .filter(|peak| *peak > 0.0)
+        .map(|peak| 20.0 * peak.log10());
+
+    Ok(LoudnessReport {
+        integrated_lufs: integrated_loudness(&slices),
+        loudness_range_lu: loudness_range(&slices),
+        true_peak_dbtp,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{test_dir, write_test_wav};
+
+    fn sine(sample_rate: u32, frequency: f64, amplitude: f64, seconds: f64) -> Vec<f32> {
+        (0..(sample_rate as f64 * seconds) as usize)
+            .map(|i| {
+                (amplitude * (2.0 * PI * frequency * i as f64 / sample_rate as f64).sin()) as f32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_full_scale_sine_reads_as_in_the_specification() {
+        // BS.1770: a 0 dBFS 997 Hz sine in one channel of a stereo pair is -3.01 LKFS.
+        let dir = test_dir("loudness-sine");
+        let path = dir.join("sine.wav");
+        let left = sine(48000, 997.0, 1.0, 5.0);
+        let samples: Vec<f32> = left.iter().flat_map(|&sample| [sample, 0.0]).collect();
+        write_test_wav(&path, 48000, 2, &samples);
+
+        let report = measure_file(&path).unwrap();
+        let integrated = report.integrated_lufs.unwrap();
+        assert!((integrated + 3.01).abs() < 0.1, "integrated {}", integrated);
+        assert!(report.loudness_range_lu.unwrap() < 0.5);
+        assert!(report.true_peak_dbtp.unwrap().abs() < 0.3);
+    }
+
+    #[test]
+    fn steps_in_level_widen_the_loudness_range() {
+        let dir = test_dir("loudness-range");
+        let path = dir.join("steps.wav");
+        let mut samples = sine(48000, 1000.0, 0.5, 10.0);
+        samples.extend(sine(48000, 1000.0, 0.05, 10.0));
+        write_test_wav(&path, 48000, 1, &samples);
+
+        let report = measure_file(&path).unwrap();
+        let range = report.loudness_range_lu.unwrap();
+        assert!((range - 20.0).abs() < 1.5, "range {}", range);
+    }
+
+    #[test]
+    fn silence_and_short_files_have_no_loudness() {
+        let dir = test_dir("loudness-silence");
+        let path = dir.join("silence.wav");
+        write_test_wav(&path, 48000, 1, &vec![0.0; 48000]);
+
+        let report = measure_file(&path).unwrap();
+        assert_eq!(report.integrated_lufs, None);
+        assert_eq!(report.loudness_range_lu, None);
+        assert_eq!(report.true_peak_dbtp, None);
+    }
+}
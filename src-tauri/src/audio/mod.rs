@@ -0,0 +1,178 @@
+//! Decoding and analysis of the library's audio files.
+//!
+//! Everything here works on files on disk through symphonia, so the frontend
+//! never has to decode a file just to learn about it.
+
+pub mod bus;
+pub mod decode;
+pub mod dynamics;
+pub mod effects;
+pub mod engine;
+pub mod flac;
+pub mod loops;
+pub mod loudness;
+pub mod probe;
+pub mod render;
+pub mod sink;
+pub mod waveform;
+
+use std::fs::File;
+use std::path::Path;
+
+use symphonia::core::codecs::{CodecParameters, Decoder, DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use crate::{AppError, AppResult};
+
+/// An opened audio file, positioned before its first packet.
+pub(crate) struct AudioSource {
+    pub format: Box<dyn FormatReader>,
+    pub decoder: Box<dyn Decoder>,
+    pub track_id: u32,
+    pub params: CodecParameters,
+}
+
+fn unreadable(path: &Path, reason: impl std::fmt::Display) -> AppError {
+    AppError::ValidationFailed(format!(
+        "'{}' is not a readable audio file: {}",
+        path.display(),
+        reason
+    ))
+}
+
+/// Opens the first decodable track of `path`. Files symphonia cannot read
+/// fail with `ValidationFailed`.
+pub(crate) fn open_source(path: &Path) -> AppResult<AudioSource> {
+    let file = File::open(path)?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|e| unreadable(path, e))?;
+    let format = probed.format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| unreadable(path, "no audio track"))?;
+    let params = track.codec_params.clone();
+    let track_id = track.id;
+
+    let decoder = symphonia::default::get_codecs()
+        .make(&params, &DecoderOptions::default())
+        .map_err(|e| unreadable(path, e))?;
+
+    Ok(AudioSource {
+        format,
+        decoder,
+        track_id,
+        params,
+    })
+}
+
+/// Writes a 16-bit PCM WAV file holding `samples`, interleaved by channel.
+#[cfg(test)]
+pub(crate) fn write_test_wav(path: &Path, sample_rate: u32, channels: u16, samples: &[f32]) {
+    let data_len = (samples.len() * 2) as u32;
+    let mut bytes = Vec::with_capacity(44 + data_len as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&channels.to_le_bytes());
+    bytes.extend_from_slice(&sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&(sample_rate * channels as u32 * 2).to_le_bytes());
+    bytes.extend_from_slice(&(channels * 2).to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    std::fs::write(path, bytes).unwrap();
+}
+
+/// A WAV file [`seed_test_library`] writes and stores as audio element `id`.
+#[cfg(test)]
+pub(crate) struct TestSource {
+    pub id: i64,
+    pub file_name: &'static str,
+    pub channel_type: &'static str,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples: Vec<f32>,
+}
+
+/// A library with sound set 1 holding `sources`, written to `dir`, and mood 1
+/// whose timeline 1 has an empty track 5. `extra_sql` then adds whatever
+/// rows the test needs.
+#[cfg(test)]
+pub(crate) fn seed_test_library(
+    dir: &Path,
+    sources: &[TestSource],
+    extra_sql: &str,
+) -> rusqlite::Connection {
+    let conn = crate::store::test_connection();
+    conn.execute(
+        "INSERT INTO sound_sets (id, name, description) VALUES (1, 'S', 'D')",
+        [],
+    )
+    .unwrap();
+    for source in sources {
+        let path = dir.join(source.file_name);
+        write_test_wav(&path, source.sample_rate, source.channels, &source.samples);
+        conn.execute(
+            "INSERT INTO audio_elements (id, sound_set_id, file_path, file_name, channel_type)
+             VALUES (?1, 1, ?2, ?3, ?4)",
+            rusqlite::params![
+                source.id,
+                path.to_string_lossy(),
+                source.file_name,
+                source.channel_type
+            ],
+        )
+        .unwrap();
+    }
+    conn.execute_batch(
+        "INSERT INTO moods (id, name) VALUES (1, 'M');
+         INSERT INTO timelines (id, mood_id, name) VALUES (1, 1, 'T');
+         INSERT INTO timeline_tracks (id, timeline_id, name, is_looping) VALUES (5, 1, 'Trk', 0);",
+    )
+    .unwrap();
+    conn.execute_batch(extra_sql).unwrap();
+    conn
+}
+
+/// A fresh scratch directory for a test's files, unique to the call and the
+/// process, so tests running in parallel never share one.
+#[cfg(test)]
+pub(crate) fn test_dir(name: &str) -> std::path::PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "immersive-scene-{}-{}-{}",
+        name,
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
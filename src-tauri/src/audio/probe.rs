@@ -0,0 +1,133 @@
+//! Reading an audio file's length and format without playing it.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use symphonia::core::errors::Error as SymphoniaError;
+
+use super::{open_source, unreadable};
+use crate::AppResult;
+
+/// What probing a file found out about it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioInfo {
+    pub duration_ms: i64,
+    pub sample_rate: i64,
+    pub channel_count: i64,
+    /// Symphonia's short codec name, such as `vorbis`, `mp3` or `pcm_s16le`.
+    pub codec: String,
+    pub file_size_bytes: i64,
+}
+
+/// Opens `path` and decodes its first packet, so only files that will play
+/// are accepted. The length comes from the container when it knows it, and
+/// from walking every packet otherwise.
+pub fn probe_file(path: &Path) -> AppResult<AudioInfo> {
+    let file_size_bytes = std::fs::metadata(path)?.len() as i64;
+    let mut source = open_source(path)?;
+    let params = source.params.clone();
+
+    let codec = symphonia::default::get_codecs()
+        .get_codec(params.codec)
+        .map(|descriptor| descriptor.short_name)
+        .unwrap_or("unknown")
+        .to_string();
+
+    let mut decoded_channels = None;
+    let mut decoded_rate = None;
+    let mut counted_ts: u64 = 0;
+    loop {
+        let packet = match source.format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(error))
+                if error.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                break
+            }
+            Err(error) => return Err(unreadable(path, error)),
+        };
+        if packet.track_id() != source.track_id {
+            continue;
+        }
+        counted_ts += packet.dur;
+
+        if decoded_channels.is_none() {
+            let decoded = source
+                .decoder
+                .decode(&packet)
+                .map_err(|e| unreadable(path, e))?;
+            decoded_channels = Some(decoded.spec().channels.count() as i64);
+            decoded_rate = Some(decoded.spec().rate as i64);
+
+            if params.n_frames.is_some() {
+                break;
+            }
+        }
+    }
+
+    let channel_count = params
+        .channels
+        .map(|channels| channels.count() as i64)
+        .or(decoded_channels)
+        .ok_or_else(|| unreadable(path, "no audio could be decoded"))?;
+    let sample_rate = params
+        .sample_rate
+        .map(i64::from)
+        .or(decoded_rate)
+        .ok_or_else(|| unreadable(path, "unknown sample rate"))?;
+
+    let length_ts = params
+        .n_frames
+        .map(|frames| frames + params.start_ts)
+        .unwrap_or(counted_ts);
+    let duration_ms = match params.time_base {
+        Some(time_base) => {
+            let time = time_base.calc_time(length_ts);
+            (time.seconds as f64 * 1000.0 + time.frac * 1000.0).round() as i64
+        }
+        None => (length_ts as f64 * 1000.0 / sample_rate as f64).round() as i64,
+    };
+
+    Ok(AudioInfo {
+        duration_ms,
+        sample_rate,
+        channel_count,
+        codec,
+        file_size_bytes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{test_dir, write_test_wav};
+
+    #[test]
+    fn wav_files_report_their_format_and_length() {
+        let dir = test_dir("probe-wav");
+        let path = dir.join("tone.wav");
+        // 1.5 s of stereo silence at 8 kHz.
+        write_test_wav(&path, 8000, 2, &vec![0.0; 8000 * 2 * 3 / 2]);
+
+        let info = probe_file(&path).unwrap();
+        assert_eq!(info.duration_ms, 1500);
+        assert_eq!(info.sample_rate, 8000);
+        assert_eq!(info.channel_count, 2);
+        assert_eq!(info.codec, "pcm_s16le");
+        assert_eq!(info.file_size_bytes, 44 + 8000 * 2 * 3 / 2 * 2);
+    }
+
+    #[test]
+    fn unreadable_files_are_rejected() {
+        let dir = test_dir("probe-garbage");
+        let path = dir.join("notes.ogg");
+        std::fs::write(&path, b"definitely not audio").unwrap();
+
+        let error = probe_file(&path).unwrap_err();
+        assert_eq!(error.code(), "ValidationFailed");
+        assert!(error.to_string().contains("not a readable audio file"));
+
+        let missing = probe_file(&dir.join("missing.ogg")).unwrap_err();
+        assert_eq!(missing.code(), "Io");
+    }
+}
@@ -0,0 +1,678 @@
+//! Mixing a mood's timeline into audio outside the webview.
+//!
+//! [`load_score`] reads everything a mix depends on from the database once,
+//! and a [`Renderer`] turns it into 48 kHz stereo block by block, following
+//! the same rules as live playback: each element plays its source once from
+//! its trim offset, looping tracks repeat their content, a looping timeline
+//! wraps at its loop region, and element groups pick a member per play.
+//! Tracks are summed into their sources' channel buses and mixed through a
+//! [`Console`], so channel dynamics and the master limiter apply as well.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use super::bus::{load_mix_settings, Buses, Console, MixSettings};
+use super::decode::decode_file;
+use super::flac::FlacWriter;
+use crate::store::audio_elements::get_audio_element;
+use crate::store::automation::{db_to_amplitude, gain_db_at, get_track_gain_points, GainPoint};
+use crate::store::element_groups::{
+    get_element_group, get_group_members, ElementGroup, ElementGroupMember,
+};
+use crate::store::group_playback::pick_member;
+use crate::store::markers::get_timeline_marker;
+use crate::store::timelines::{
+    get_timeline_tracks, get_timelines, get_track_elements, TimelineElement,
+};
+use crate::{AppError, AppResult};
+
+pub const RENDER_SAMPLE_RATE: u32 = 48_000;
+pub const RENDER_CHANNELS: usize = 2;
+/// Longest render accepted, three hours.
+pub const MAX_RENDER_MS: i64 = 3 * 60 * 60 * 1000;
+
+const RENDER_BITS: u32 = 24;
+const BLOCK_FRAMES: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderFormat {
+    Wav,
+    Flac,
+}
+
+impl RenderFormat {
+    /// The format named by `path`'s extension.
+    pub fn from_path(path: &Path) -> AppResult<Self> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("wav") => Ok(RenderFormat::Wav),
+            Some("flac") => Ok(RenderFormat::Flac),
+            _ => Err(AppError::ValidationFailed(format!(
+                "Render output '{}' must end in .wav or .flac",
+                path.display()
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RenderSummary {
+    pub output_path: String,
+    pub format: RenderFormat,
+    pub length_ms: i64,
+    pub sample_rate: u32,
+    /// Loudest sample before clipping; `None` for a silent render.
+    pub peak_dbfs: Option<f64>,
+    /// Samples that went past full scale and were clipped.
+    pub clipped_samples: u64,
+}
+
+/// File, fixed gain and channel of an audio element a score can play.
+#[derive(Debug, Clone)]
+pub struct ScoreSource {
+    pub file_path: String,
+    /// The element's `volume_db` as an amplitude factor.
+    pub gain: f64,
+    /// Bus the element plays through.
+    pub channel_id: Option<i64>,
+}
+
+impl ScoreSource {
+    pub fn load(conn: &Connection, audio_element_id: i64) -> AppResult<Self> {
+        let element = get_audio_element(conn, audio_element_id)?;
+        Ok(ScoreSource {
+            file_path: element.file_path,
+            gain: db_to_amplitude(element.volume_db),
+            channel_id: element.channel_id,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ScoreTrack {
+    pub id: i64,
+    pub is_looping: bool,
+    /// End of the track's last element; a looping track repeats with this period.
+    pub length_ms: i64,
+    /// Playable elements, by start time.
+    pub elements: Vec<TimelineElement>,
+    pub gain_points: Vec<GainPoint>,
+}
+
+/// Everything about a timeline that affects how it sounds.
+#[derive(Debug)]
+pub struct TimelineScore {
+    pub timeline_id: i64,
+    pub tracks: Vec<ScoreTrack>,
+    /// End of the last element on any track.
+    pub length_ms: i64,
+    /// Range the transport wraps around when the timeline loops.
+    pub loop_range: Option<(i64, i64)>,
+    pub groups: HashMap<i64, (ElementGroup, Vec<ElementGroupMember>)>,
+    pub sources: HashMap<i64, ScoreSource>,
+    pub mix: MixSettings,
+}
+
+/// Reads the timeline of `mood_id`. Elements from disabled sound sets are
+/// left out, as they are in live playback.
+pub fn load_score(conn: &Connection, mood_id: i64) -> AppResult<TimelineScore> {
+    let timeline = get_timelines(conn, mood_id)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| {
+            AppError::ValidationFailed(format!("Mood {} has no timeline to render", mood_id))
+        })?;
+
+    let mut tracks = Vec::new();
+    let mut groups = HashMap::new();
+    let mut source_ids = Vec::new();
+    for track in get_timeline_tracks(conn, timeline.id)? {
+        let elements: Vec<TimelineElement> = get_track_elements(conn, track.id)?
+            .into_iter()
+            .filter(|element| element.is_available)
+            .collect();
+
+        for element in &elements {
+            source_ids.extend(element.audio_element_id);
+            if let Some(group_id) = element.element_group_id {
+                if let Entry::Vacant(entry) = groups.entry(group_id) {
+                    let members = get_group_members(conn, group_id)?;
+                    source_ids.extend(members.iter().map(|member| member.audio_element_id));
+                    entry.insert((get_element_group(conn, group_id)?, members));
+                }
+            }
+        }
+
+        tracks.push(ScoreTrack {
+            id: track.id,
+            is_looping: track.is_looping,
+            length_ms: elements
+                .iter()
+                .map(|element| element.start_time_ms + element.duration_ms)
+                .max()
+                .unwrap_or(0),
+            gain_points: get_track_gain_points(conn, track.id)?,
+            elements,
+        });
+    }
+
+    let mut sources = HashMap::new();
+    for id in source_ids {
+        if sources.contains_key(&id) {
+            continue;
+        }
+        sources.insert(id, ScoreSource::load(conn, id)?);
+    }
+
+    let length_ms = tracks
+        .iter()
+        .map(|track| track.length_ms)
+        .max()
+        .unwrap_or(0);
+    let loop_range = match (timeline.is_looping, timeline.loop_marker_id) {
+        (false, _) => None,
+        (true, Some(marker_id)) => {
+            let marker = get_timeline_marker(conn, marker_id)?;
+            marker.end_ms.map(|end_ms| (marker.position_ms, end_ms))
+        }
+        (true, None) => Some((0, length_ms)),
+    }
+    .filter(|(start_ms, end_ms)| end_ms > start_ms);
+
+    Ok(TimelineScore {
+        timeline_id: timeline.id,
+        tracks,
+        length_ms,
+        loop_range,
+        groups,
+        sources,
+        mix: load_mix_settings(conn)?,
+    })
+}
+
+/// A decoded source, reduced to stereo at its own sample rate.
+pub(crate) struct Source {
+    pub sample_rate: f64,
+    pub frames: Vec<[f32; 2]>,
+}
+
+impl Source {
+    pub fn load(path: &Path) -> AppResult<Self> {
+        let decoded = decode_file(path)?;
+        let frames = decoded
+            .samples
+            .chunks_exact(decoded.channels)
+            .map(|frame| match frame {
+                [mono] => [*mono, *mono],
+                [left, right, ..] => [*left, *right],
+                [] => [0.0, 0.0],
+            })
+            .collect();
+        Ok(Source {
+            sample_rate: decoded.sample_rate as f64,
+            frames,
+        })
+    }
+
+    /// Cubic (Catmull-Rom) interpolation between frames at fractional `position`.
+    pub fn frame_at(&self, position: f64) -> [f32; 2] {
+        let index = position.floor() as isize;
+        let t = (position - index as f64) as f32;
+        let at = |offset: isize| {
+            let i = (index + offset).clamp(0, self.frames.len() as isize - 1) as usize;
+            self.frames[i]
+        };
+        let (p0, p1, p2, p3) = (at(-1), at(0), at(1), at(2));
+        let mut out = [0.0; 2];
+        for channel in 0..2 {
+            let (a, b, c, d) = (p0[channel], p1[channel], p2[channel], p3[channel]);
+            out[channel] = b + 0.5
+                * t
+                * (c - a + t * (2.0 * a - 5.0 * b + 4.0 * c - d + t * (3.0 * (b - c) + d - a)));
+        }
+        out
+    }
+}
+
+/// One play of one timeline element.
+struct Voice {
+    /// Element index, transport pass and track repeat that started this play.
+    key: (usize, u64, i64),
+    audio_element_id: Option<i64>,
+    gain: f64,
+    pitch_ratio: f64,
+}
+
+/// Streams a score as interleaved stereo at [`RENDER_SAMPLE_RATE`].
+pub struct Renderer {
+    score: TimelineScore,
+    sources: HashMap<i64, Source>,
+    seed: u64,
+    frame: u64,
+    /// Bumped every time the transport wraps or seeks, so elements under the
+    /// playhead start a new play.
+    pass: u64,
+    voices: Vec<Option<Voice>>,
+    /// Plays so far and last member played, per element group.
+    group_plays: HashMap<i64, (u64, Option<i64>)>,
+    buses: Buses,
+    console: Console,
+}
+
+impl Renderer {
+    /// Decodes every source the score can play. Group picks are seeded with
+    /// `seed`, so the same seed renders the same mix.
+    pub fn new(score: TimelineScore, seed: u64) -> AppResult<Self> {
+        let mut sources = HashMap::new();
+        for (id, source) in &score.sources {
+            sources.insert(*id, Source::load(Path::new(&source.file_path))?);
+        }
+
+        Ok(Renderer {
+            voices: score.tracks.iter().map(|_| None).collect(),
+            console: Console::new(&score.mix),
+            score,
+            sources,
+            seed,
+            frame: 0,
+            pass: 0,
+            group_plays: HashMap::new(),
+            buses: Buses::default(),
+        })
+    }
+
+    pub fn score(&self) -> &TimelineScore {
+        &self.score
+    }
+
+    /// Playhead position on the timeline.
+    pub fn position_ms(&self) -> f64 {
+        self.frame as f64 * 1000.0 / RENDER_SAMPLE_RATE as f64
+    }
+
+    /// Moves the playhead; elements under it start over.
+    pub fn seek(&mut self, position_ms: i64) {
+        self.frame = ms_to_frame(position_ms);
+        self.pass += 1;
+    }
+
+    /// Frames the output of [`render`](Self::render) lags behind the playhead.
+    pub fn latency(&self) -> usize {
+        self.console.latency()
+    }
+
+    /// Fills `out` with the mix as interleaved stereo and advances the playhead.
+    pub fn render(&mut self, out: &mut [f32]) {
+        let mut buses = std::mem::take(&mut self.buses);
+        buses.clear(out.len() / RENDER_CHANNELS);
+        self.render_buses(&mut buses);
+        self.console.mix(&mut buses, out, 1.0);
+        self.buses = buses;
+    }
+
+    /// Adds the next frames of every track to `buses`, as many as they were
+    /// last cleared for, and advances the playhead.
+    pub fn render_buses(&mut self, buses: &mut Buses) {
+        let loop_frames = self
+            .score
+            .loop_range
+            .map(|(start_ms, end_ms)| (ms_to_frame(start_ms), ms_to_frame(end_ms)));
+
+        for frame in 0..buses.frames() {
+            if let Some((start, end)) = loop_frames {
+                if self.frame >= end {
+                    self.frame = start;
+                    self.pass += 1;
+                }
+            }
+            let time_ms = self.position_ms();
+
+            for track_index in 0..self.score.tracks.len() {
+                if let Some((channel, [left, right])) = self.track_frame(track_index, time_ms) {
+                    buses.add(channel, frame, [left as f32, right as f32]);
+                }
+            }
+            self.frame += 1;
+        }
+    }
+
+    /// The output of one track at timeline `time_ms` and the channel it plays
+    /// through, or `None` when it is silent.
+    fn track_frame(&mut self, track_index: usize, time_ms: f64) -> Option<(Option<i64>, [f64; 2])> {
+        let track = &self.score.tracks[track_index];
+        let (repeat, local_ms) = if track.is_looping && track.length_ms > 0 {
+            let length = track.length_ms as f64;
+            ((time_ms / length).floor() as i64, time_ms % length)
+        } else {
+            (0, time_ms)
+        };
+
+        let index = track
+            .elements
+            .partition_point(|element| element.start_time_ms as f64 <= local_ms);
+        let element = index.checked_sub(1).map(|index| &track.elements[index]);
+        let Some(element) = element
+            .filter(|element| local_ms < (element.start_time_ms + element.duration_ms) as f64)
+        else {
+            self.voices[track_index] = None;
+            return None;
+        };
+
+        let key = (index - 1, self.pass, repeat);
+        if self.voices[track_index]
+            .as_ref()
+            .is_none_or(|voice| voice.key != key)
+        {
+            let voice = start_voice(&self.score, &mut self.group_plays, self.seed, key, element);
+            self.voices[track_index] = Some(voice);
+        }
+        let voice = self.voices[track_index].as_ref()?;
+        let audio_element_id = voice.audio_element_id?;
+        let source = self.sources.get(&audio_element_id)?;
+        let channel = self
+            .score
+            .sources
+            .get(&audio_element_id)
+            .and_then(|source| source.channel_id);
+
+        let elapsed_ms = local_ms - element.start_time_ms as f64;
+        let position = (element.source_offset_ms as f64 + elapsed_ms * voice.pitch_ratio) / 1000.0
+            * source.sample_rate;
+        if position >= source.frames.len() as f64 {
+            return None;
+        }
+
+        let gain = voice.gain
+            * element.fade_gain(elapsed_ms as i64)
+            * db_to_amplitude(gain_db_at(&track.gain_points, local_ms));
+        let [left, right] = source.frame_at(position);
+        Some((channel, [left as f64 * gain, right as f64 * gain]))
+    }
+}
+
+/// Starts a play of `element`, picking a member when it is an element group.
+fn start_voice(
+    score: &TimelineScore,
+    group_plays: &mut HashMap<i64, (u64, Option<i64>)>,
+    seed: u64,
+    key: (usize, u64, i64),
+    element: &TimelineElement,
+) -> Voice {
+    let silent = Voice {
+        key,
+        audio_element_id: None,
+        gain: 0.0,
+        pitch_ratio: 1.0,
+    };
+    let source_gain = |id: i64| score.sources.get(&id).map(|source| source.gain);
+
+    if let Some(id) = element.audio_element_id {
+        return Voice {
+            audio_element_id: Some(id),
+            gain: source_gain(id).unwrap_or(0.0),
+            ..silent
+        };
+    }
+    let Some((group, members)) = element
+        .element_group_id
+        .and_then(|group_id| score.groups.get(&group_id))
+    else {
+        return silent;
+    };
+
+    let (plays, previous) = group_plays.entry(group.id).or_default();
+    let Some(pick) = pick_member(group, members, seed, *plays, *previous) else {
+        return silent;
+    };
+    *plays += 1;
+    *previous = Some(pick.member_id);
+
+    Voice {
+        audio_element_id: Some(pick.audio_element_id),
+        gain: source_gain(pick.audio_element_id).unwrap_or(0.0) * db_to_amplitude(pick.volume_db),
+        pitch_ratio: 2f64.powf(pick.pitch_semitones / 12.0),
+        ..silent
+    }
+}
+
+/// Negative times, which nothing in the schema rules out, count as 0.
+pub(crate) fn ms_to_frame(ms: i64) -> u64 {
+    (ms.max(0) as u64 * RENDER_SAMPLE_RATE as u64) / 1000
+}
+
+enum RenderWriter {
+    Wav(hound::WavWriter<BufWriter<File>>),
+    Flac(FlacWriter<BufWriter<File>>),
+}
+
+pub(crate) fn wav_error(error: hound::Error) -> AppError {
+    AppError::Io(format!("Failed to write WAV file: {}", error))
+}
+
+/// Renders the first `length_ms` of `score` to a 24-bit file at `output`,
+/// WAV or FLAC by its extension. Samples past full scale are clipped.
+pub fn render_to_file(
+    score: TimelineScore,
+    seed: u64,
+    length_ms: i64,
+    output: &Path,
+) -> AppResult<RenderSummary> {
+    if !(1..=MAX_RENDER_MS).contains(&length_ms) {
+        return Err(AppError::ValidationFailed(format!(
+            "Render length must be between 1 ms and {} ms, got {} ms",
+            MAX_RENDER_MS, length_ms
+        )));
+    }
+    let format = RenderFormat::from_path(output)?;
+    let mut renderer = Renderer::new(score, seed)?;
+
+    let mut writer = match format {
+        RenderFormat::Wav => {
+            let spec = hound::WavSpec {
+                channels: RENDER_CHANNELS as u16,
+                sample_rate: RENDER_SAMPLE_RATE,
+                bits_per_sample: RENDER_BITS as u16,
+                sample_format: hound::SampleFormat::Int,
+            };
+            RenderWriter::Wav(hound::WavWriter::create(output, spec).map_err(wav_error)?)
+        }
+        RenderFormat::Flac => RenderWriter::Flac(FlacWriter::new(
+            BufWriter::new(File::create(output)?),
+            RENDER_SAMPLE_RATE,
+            RENDER_CHANNELS,
+            RENDER_BITS,
+        )?),
+    };
+
+    let full_scale = ((1 << (RENDER_BITS - 1)) - 1) as f32;
+    let mut remaining = ms_to_frame(length_ms) as usize;
+    let mut block = vec![0.0f32; BLOCK_FRAMES * RENDER_CHANNELS];
+    // Line the file up with the timeline by dropping the limiter's lookahead.
+    let latency = renderer.latency();
+    renderer.render(&mut block[..latency * RENDER_CHANNELS]);
+    let mut samples = Vec::with_capacity(block.len());
+    let mut peak = 0.0f32;
+    let mut clipped_samples = 0;
+    while remaining > 0 {
+        let frames = remaining.min(BLOCK_FRAMES);
+        let block = &mut block[..frames * RENDER_CHANNELS];
+        renderer.render(block);
+
+        samples.clear();
+        for &sample in block.iter() {
+            peak = peak.max(sample.abs());
+            if sample.abs() > 1.0 {
+                clipped_samples += 1;
+            }
+            samples.push((sample.clamp(-1.0, 1.0) * full_scale).round() as i32);
+        }
+        match &mut writer {
+            RenderWriter::Wav(wav) => {
+                for &sample in &samples {
+                    wav.write_sample(sample).map_err(wav_error)?;
+                }
+            }
+            RenderWriter::Flac(flac) => flac.write_samples(&samples)?,
+        }
+        remaining -= frames;
+    }
+    match writer {
+        RenderWriter::Wav(wav) => wav.finalize().map_err(wav_error)?,
+        RenderWriter::Flac(flac) => {
+            flac.finish()?;
+        }
+    }
+
+    Ok(RenderSummary {
+        output_path: output.to_string_lossy().to_string(),
+        format,
+        length_ms,
+        sample_rate: RENDER_SAMPLE_RATE,
+        peak_dbfs: (peak > 0.0).then(|| 20.0 * (peak as f64).log10()),
+        clipped_samples,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{seed_test_library, test_dir, TestSource};
+
+    /// Mood 1 with a timeline of one track (id 5) and two sources: element 100
+    /// holds 0.5 and element 101 holds -0.25, each for one second.
+    fn seeded(dir: &Path) -> Connection {
+        let sources = [
+            TestSource {
+                id: 100,
+                file_name: "half.wav",
+                channel_type: "music",
+                sample_rate: 8000,
+                channels: 1,
+                samples: vec![0.5; 8000],
+            },
+            TestSource {
+                id: 101,
+                file_name: "quarter.wav",
+                channel_type: "effects",
+                sample_rate: 8000,
+                channels: 2,
+                samples: vec![-0.25; 16000],
+            },
+        ];
+        seed_test_library(
+            dir,
+            &sources,
+            "INSERT INTO audio_channels (id, sound_set_id, name, icon, volume, order_index) VALUES (7, 1, 'Fx', 'sfx', 0.5, 0);
+             UPDATE audio_elements SET channel_id = 7 WHERE id = 101;",
+        )
+    }
+
+    fn render(conn: &Connection, length_ms: i64) -> Vec<f32> {
+        let mut renderer = Renderer::new(load_score(conn, 1).unwrap(), 0).unwrap();
+        let mut out = vec![0.0; ms_to_frame(length_ms) as usize * RENDER_CHANNELS];
+        renderer.render(&mut out);
+        out
+    }
+
+    fn left_at(out: &[f32], ms: i64) -> f32 {
+        out[ms_to_frame(ms) as usize * RENDER_CHANNELS]
+    }
+
+    #[test]
+    fn elements_play_at_their_place_with_their_gain() {
+        let dir = test_dir("render-gain");
+        let conn = seeded(&dir);
+        conn.execute_batch(
+            "UPDATE audio_elements SET volume_db = -6.0206 WHERE id = 100;
+             INSERT INTO timeline_elements (timeline_id, track_id, audio_element_id, start_time_ms, duration_ms) VALUES (1, 5, 100, 100, 300);
+             INSERT INTO timeline_elements (timeline_id, track_id, audio_element_id, start_time_ms, duration_ms) VALUES (1, 5, 101, 600, 2000);",
+        )
+        .unwrap();
+
+        let out = render(&conn, 3000);
+        assert_eq!(left_at(&out, 50), 0.0);
+        assert!((left_at(&out, 200) - 0.25).abs() < 1e-3);
+        assert_eq!(left_at(&out, 450), 0.0);
+        // Channel volume halves the second source, which ends after one second.
+        assert!((left_at(&out, 1000) + 0.125).abs() < 1e-3);
+        assert_eq!(left_at(&out, 1700), 0.0);
+    }
+
+    #[test]
+    fn looping_tracks_repeat_and_disabled_sound_sets_are_silent() {
+        let dir = test_dir("render-loop");
+        let conn = seeded(&dir);
+        conn.execute_batch(
+            "UPDATE timeline_tracks SET is_looping = 1 WHERE id = 5;
+             INSERT INTO timeline_elements (timeline_id, track_id, audio_element_id, start_time_ms, duration_ms) VALUES (1, 5, 100, 200, 200);",
+        )
+        .unwrap();
+
+        let out = render(&conn, 1000);
+        // The track is 400 ms long, so the element plays at 200 and 600 ms.
+        assert!((left_at(&out, 300) - 0.5).abs() < 1e-3);
+        assert_eq!(left_at(&out, 500), 0.0);
+        assert!((left_at(&out, 700) - 0.5).abs() < 1e-3);
+
+        conn.execute("UPDATE sound_sets SET is_enabled = 0", [])
+            .unwrap();
+        assert!(render(&conn, 1000).iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn loop_regions_starting_before_zero_loop_from_the_start() {
+        let dir = test_dir("render-negative-loop");
+        let conn = seeded(&dir);
+        conn.execute_batch(
+            "INSERT INTO timeline_markers (id, timeline_id, name, position_ms, end_ms) VALUES (9, 1, 'L', -500, 400);
+             UPDATE timelines SET is_looping = 1, loop_marker_id = 9 WHERE id = 1;
+             INSERT INTO timeline_elements (timeline_id, track_id, audio_element_id, start_time_ms, duration_ms) VALUES (1, 5, 100, 200, 200);",
+        )
+        .unwrap();
+
+        let out = render(&conn, 1000);
+        assert!((left_at(&out, 300) - 0.5).abs() < 1e-3);
+        assert_eq!(left_at(&out, 500), 0.0);
+        assert!((left_at(&out, 700) - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn renders_are_written_as_48k_stereo_files() {
+        let dir = test_dir("render-files");
+        let conn = seeded(&dir);
+        conn.execute(
+            "INSERT INTO timeline_elements (timeline_id, track_id, audio_element_id, start_time_ms, duration_ms) VALUES (1, 5, 100, 0, 500)",
+            [],
+        )
+        .unwrap();
+
+        for name in ["mix.wav", "mix.flac"] {
+            let output = dir.join(name);
+            let summary = render_to_file(load_score(&conn, 1).unwrap(), 0, 1000, &output).unwrap();
+            assert!((summary.peak_dbfs.unwrap() + 6.02).abs() < 0.01);
+            assert_eq!(summary.clipped_samples, 0);
+
+            let decoded = decode_file(&output).unwrap();
+            assert_eq!(decoded.sample_rate, 48_000);
+            assert_eq!(decoded.channels, 2);
+            assert_eq!(decoded.frame_count(), 48_000);
+            assert!((decoded.samples[2 * 12_000] - 0.5).abs() < 1e-3);
+            assert_eq!(decoded.samples[2 * 36_000], 0.0);
+        }
+
+        let wrong = render_to_file(load_score(&conn, 1).unwrap(), 0, 1000, &dir.join("mix.mp3"));
+        assert_eq!(wrong.unwrap_err().code(), "ValidationFailed");
+        let empty = render_to_file(load_score(&conn, 1).unwrap(), 0, 0, &dir.join("mix.wav"));
+        assert_eq!(empty.unwrap_err().code(), "ValidationFailed");
+    }
+}
@@ -0,0 +1,374 @@
+//! Destinations for the playback engine's master output.
+//!
+//! Every sink takes blocks of interleaved stereo at
+//! [`RENDER_SAMPLE_RATE`](super::render::RENDER_SAMPLE_RATE). The Discord sink
+//! lives next to the bridge in `discord.rs`.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use super::render::{wav_error, RENDER_CHANNELS, RENDER_SAMPLE_RATE};
+use crate::{AppError, AppResult};
+
+pub trait AudioSink: Send {
+    /// Short name reported in the engine status, such as `file` or `discord`.
+    fn name(&self) -> &str;
+
+    fn write(&mut self, block: &[f32]) -> AppResult<()>;
+
+    /// Flushes whatever the sink buffers. Called once, when the engine stops.
+    fn finish(&mut self) -> AppResult<()> {
+        Ok(())
+    }
+}
+
+/// Converts a block to 16-bit PCM, clipping at full scale.
+pub fn to_pcm16(block: &[f32]) -> Vec<i16> {
+    block
+        .iter()
+        .map(|sample| (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16)
+        .collect()
+}
+
+/// Discards everything, counting what it was given.
+#[derive(Debug, Default)]
+pub struct NullSink {
+    pub frames_written: u64,
+}
+
+impl AudioSink for NullSink {
+    fn name(&self) -> &str {
+        "null"
+    }
+
+    fn write(&mut self, block: &[f32]) -> AppResult<()> {
+        self.frames_written += (block.len() / RENDER_CHANNELS) as u64;
+        Ok(())
+    }
+}
+
+/// Records the output to a 16-bit WAV file.
+pub struct WavFileSink {
+    writer: Option<hound::WavWriter<BufWriter<File>>>,
+}
+
+impl WavFileSink {
+    pub fn create(path: &Path) -> AppResult<Self> {
+        let spec = hound::WavSpec {
+            channels: RENDER_CHANNELS as u16,
+            sample_rate: RENDER_SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        Ok(WavFileSink {
+            writer: Some(hound::WavWriter::create(path, spec).map_err(wav_error)?),
+        })
+    }
+}
+
+impl AudioSink for WavFileSink {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    fn write(&mut self, block: &[f32]) -> AppResult<()> {
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| AppError::Internal("The recording was already finished".into()))?;
+        let mut samples = writer.get_i16_writer(block.len() as u32);
+        for sample in to_pcm16(block) {
+            samples.write_sample(sample);
+        }
+        samples.flush().map_err(wav_error)
+    }
+
+    fn finish(&mut self) -> AppResult<()> {
+        match self.writer.take() {
+            Some(writer) => writer.finalize().map_err(wav_error),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Plays the output on the system's default device.
+///
+/// cpal streams cannot move between threads on every platform, so the stream
+/// lives on a thread of its own and pulls from a queue this sink fills. Errors
+/// the stream reports are kept until the next write, which fails with them.
+#[cfg(feature = "native-output")]
+pub struct DeviceSink {
+    queue: DeviceQueue,
+    failure: DeviceFailure,
+    stop: std::sync::mpsc::Sender<()>,
+}
+
+#[cfg(feature = "native-output")]
+type DeviceQueue = std::sync::Arc<std::sync::Mutex<std::collections::VecDeque<f32>>>;
+
+/// The first error the stream reported, waiting for the next write.
+#[cfg(feature = "native-output")]
+type DeviceFailure = std::sync::Arc<std::sync::Mutex<Option<String>>>;
+
+/// Most audio the device queue holds before the oldest is dropped, 200 ms.
+#[cfg(feature = "native-output")]
+const DEVICE_QUEUE_SAMPLES: usize = RENDER_SAMPLE_RATE as usize / 5 * RENDER_CHANNELS;
+
+#[cfg(feature = "native-output")]
+impl DeviceSink {
+    pub fn open() -> AppResult<Self> {
+        use cpal::traits::{HostTrait, StreamTrait};
+        use cpal::SampleFormat;
+        use std::collections::VecDeque;
+        use std::sync::{mpsc, Arc, Mutex};
+
+        let queue = Arc::new(Mutex::new(VecDeque::<f32>::new()));
+        let failure = Arc::new(Mutex::new(None::<String>));
+        let (stop, stopped) = mpsc::channel::<()>();
+        let (ready, opened) = mpsc::channel::<AppResult<()>>();
+
+        let source = Arc::clone(&queue);
+        let failing = Arc::clone(&failure);
+        std::thread::spawn(move || {
+            let device_error = |error: &dyn std::fmt::Display| {
+                AppError::Io(format!("Failed to open the audio device: {}", error))
+            };
+            let stream = (|| {
+                let device = cpal::default_host()
+                    .default_output_device()
+                    .ok_or_else(|| AppError::Io("No audio output device is available".into()))?;
+                let supported = device_config(&device).map_err(|e| device_error(&e))?;
+                let config = supported.config();
+                let output = DeviceOutput {
+                    feed: DeviceFeed::new(config.channels, config.sample_rate.0),
+                    queue: source,
+                    failure: failing,
+                };
+                let stream = match supported.sample_format() {
+                    SampleFormat::F32 => output.build::<f32>(&device, &config),
+                    SampleFormat::F64 => output.build::<f64>(&device, &config),
+                    SampleFormat::I8 => output.build::<i8>(&device, &config),
+                    SampleFormat::I16 => output.build::<i16>(&device, &config),
+                    SampleFormat::I32 => output.build::<i32>(&device, &config),
+                    SampleFormat::I64 => output.build::<i64>(&device, &config),
+                    SampleFormat::U8 => output.build::<u8>(&device, &config),
+                    SampleFormat::U16 => output.build::<u16>(&device, &config),
+                    SampleFormat::U32 => output.build::<u32>(&device, &config),
+                    SampleFormat::U64 => output.build::<u64>(&device, &config),
+                    other => {
+                        let unsupported = format!("{} samples are not supported", other);
+                        return Err(device_error(&unsupported));
+                    }
+                }
+                .map_err(|e| device_error(&e))?;
+                stream.play().map_err(|e| device_error(&e))?;
+                Ok(stream)
+            })();
+
+            match stream {
+                Ok(stream) => {
+                    let _ = ready.send(Ok(()));
+                    // Keep the stream alive until the sink is dropped.
+                    let _ = stopped.recv();
+                    drop(stream);
+                }
+                Err(error) => {
+                    let _ = ready.send(Err(error));
+                }
+            }
+        });
+
+        opened
+            .recv()
+            .map_err(|_| AppError::Internal("The audio device thread stopped".into()))??;
+        Ok(DeviceSink {
+            queue,
+            failure,
+            stop,
+        })
+    }
+}
+
+/// The device's own 48 kHz stereo float config when it has one, so nothing
+/// needs converting, and otherwise its default config.
+#[cfg(feature = "native-output")]
+fn device_config(
+    device: &cpal::Device,
+) -> Result<cpal::SupportedStreamConfig, cpal::DefaultStreamConfigError> {
+    use cpal::traits::DeviceTrait;
+
+    let rate = cpal::SampleRate(RENDER_SAMPLE_RATE);
+    let native = device
+        .supported_output_configs()
+        .ok()
+        .and_then(|mut configs| {
+            configs.find(|range| {
+                range.channels() as usize == RENDER_CHANNELS
+                    && range.sample_format() == cpal::SampleFormat::F32
+                    && (range.min_sample_rate()..=range.max_sample_rate()).contains(&rate)
+            })
+        });
+    match native {
+        Some(range) => Ok(range.with_sample_rate(rate)),
+        None => device.default_output_config(),
+    }
+}
+
+/// What the device callback owns: the feed and the sink's shared state.
+#[cfg(feature = "native-output")]
+struct DeviceOutput {
+    feed: DeviceFeed,
+    queue: DeviceQueue,
+    failure: DeviceFailure,
+}
+
+#[cfg(feature = "native-output")]
+impl DeviceOutput {
+    fn build<T>(
+        self,
+        device: &cpal::Device,
+        config: &cpal::StreamConfig,
+    ) -> Result<cpal::Stream, cpal::BuildStreamError>
+    where
+        T: cpal::SizedSample + cpal::FromSample<f32>,
+    {
+        use cpal::traits::DeviceTrait;
+
+        let DeviceOutput {
+            mut feed,
+            queue,
+            failure,
+        } = self;
+        device.build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                let mut queue = queue.lock().unwrap_or_else(|e| e.into_inner());
+                feed.fill(&mut queue, data);
+            },
+            move |error| {
+                let mut failure = failure.lock().unwrap_or_else(|e| e.into_inner());
+                failure.get_or_insert_with(|| error.to_string());
+            },
+            None,
+        )
+    }
+}
+
+/// Converts the queued 48 kHz stereo to the device's rate and channel count,
+/// interpolating linearly between frames. Mono devices get both channels
+/// averaged, and channels past the first two stay silent.
+#[cfg(feature = "native-output")]
+struct DeviceFeed {
+    channels: usize,
+    /// Queued frames per device frame.
+    step: f64,
+    /// How far the next device frame lies from `previous` towards `next`.
+    position: f64,
+    previous: [f32; 2],
+    next: [f32; 2],
+}
+
+#[cfg(feature = "native-output")]
+impl DeviceFeed {
+    fn new(channels: u16, sample_rate: u32) -> Self {
+        DeviceFeed {
+            channels: usize::from(channels.max(1)),
+            step: RENDER_SAMPLE_RATE as f64 / sample_rate as f64,
+            // Pulls the first two frames before anything plays.
+            position: 2.0,
+            previous: [0.0; 2],
+            next: [0.0; 2],
+        }
+    }
+
+    /// Fills `data` from `queue`, playing silence once the queue runs dry.
+    fn fill<T: cpal::Sample + cpal::FromSample<f32>>(
+        &mut self,
+        queue: &mut std::collections::VecDeque<f32>,
+        data: &mut [T],
+    ) {
+        for frame in data.chunks_mut(self.channels) {
+            while self.position >= 1.0 {
+                self.previous = self.next;
+                self.next = match (queue.pop_front(), queue.pop_front()) {
+                    (Some(left), Some(right)) => [left, right],
+                    _ => [0.0; 2],
+                };
+                self.position -= 1.0;
+            }
+            let t = self.position as f32;
+            let [left, right] =
+                [0, 1].map(|c| self.previous[c] + (self.next[c] - self.previous[c]) * t);
+            self.position += self.step;
+
+            match frame {
+                [mono] => *mono = T::from_sample((left + right) / 2.0),
+                [first, second, rest @ ..] => {
+                    *first = T::from_sample(left);
+                    *second = T::from_sample(right);
+                    rest.fill(T::EQUILIBRIUM);
+                }
+                [] => {}
+            }
+        }
+    }
+}
+
+#[cfg(feature = "native-output")]
+impl AudioSink for DeviceSink {
+    fn name(&self) -> &str {
+        "device"
+    }
+
+    fn write(&mut self, block: &[f32]) -> AppResult<()> {
+        if let Some(error) = self
+            .failure
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+        {
+            return Err(AppError::Io(format!("Audio device error: {}", error)));
+        }
+        let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+        queue.extend(block.iter().copied());
+        let excess = queue.len().saturating_sub(DEVICE_QUEUE_SAMPLES);
+        queue.drain(..excess);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> AppResult<()> {
+        let _ = self.stop.send(());
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "native-output"))]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn the_device_feed_resamples_between_frames() {
+        let mut queue = VecDeque::from(vec![0.0, 0.0, 1.0, -1.0]);
+        let mut feed = DeviceFeed::new(2, 96_000);
+        let mut data = [9.0f32; 8];
+        feed.fill(&mut queue, &mut data);
+        assert_eq!(data, [0.0, 0.0, 0.5, -0.5, 1.0, -1.0, 0.5, -0.5]);
+
+        // Past the queue the feed fades to silence rather than stopping.
+        feed.fill(&mut queue, &mut data);
+        assert!(data.iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn the_device_feed_fits_the_device_channels_and_format() {
+        let mut mono = [0i16; 1];
+        DeviceFeed::new(1, 48_000).fill(&mut VecDeque::from(vec![0.5, 0.0]), &mut mono);
+        assert_eq!(mono, [i16::MAX / 4 + 1]);
+
+        let mut surround = [0u16; 4];
+        DeviceFeed::new(4, 48_000).fill(&mut VecDeque::from(vec![-1.0, 0.0]), &mut surround);
+        assert_eq!(surround, [0, 32_768, 32_768, 32_768]);
+    }
+}
@@ -0,0 +1,415 @@
+//! Min/max peaks for drawing waveforms at any zoom.
+//!
+//! Peaks are computed once per file content and cached on disk under the file's
+//! SHA-256, at several resolutions. A request is answered from the coarsest
+//! resolution that still gives every bucket at least one peak, so a zoomed-out
+//! view of a long file reads few values and a zoomed-in one stays precise.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::decode::for_each_block;
+use crate::{AppError, AppResult};
+
+/// Frames summarised by one peak, finest first. Each level is four times
+/// coarser than the one before.
+pub const PEAK_RESOLUTIONS: [u32; 4] = [128, 512, 2048, 8192];
+pub const MAX_BUCKETS: i64 = 16_384;
+
+const CACHE_MAGIC: &[u8; 4] = b"ISPK";
+const CACHE_VERSION: u32 = 1;
+
+/// Peaks of `[start_ms, end_ms)` split into equal buckets, mixed down to mono.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WaveformPeaks {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub mins: Vec<f32>,
+    pub maxs: Vec<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PeakLevel {
+    frames_per_peak: u32,
+    mins: Vec<f32>,
+    maxs: Vec<f32>,
+}
+
+/// Every resolution of one file's peaks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeakCache {
+    sample_rate: u32,
+    frame_count: u64,
+    levels: Vec<PeakLevel>,
+}
+
+/// Size and modification time of a file. A stored content hash is trusted
+/// only while the file's stamp still matches the one taken with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    pub size: i64,
+    pub modified_ms: i64,
+}
+
+pub fn file_stamp(path: &Path) -> AppResult<FileStamp> {
+    let metadata = std::fs::metadata(path)?;
+    let modified_ms = metadata
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as i64);
+
+    Ok(FileStamp {
+        size: metadata.len() as i64,
+        modified_ms,
+    })
+}
+
+/// Hex SHA-256 of the file's bytes.
+pub fn hash_file(path: &Path) -> AppResult<String> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = Sha256::new();
+    let mut chunk = [0u8; 64 * 1024];
+    loop {
+        let read = reader.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&chunk[..read]);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+impl PeakCache {
+    /// Decodes `path` and summarises it at every resolution in [`PEAK_RESOLUTIONS`].
+    pub fn build(path: &Path) -> AppResult<Self> {
+        let finest = PEAK_RESOLUTIONS[0] as u64;
+        let mut sample_rate = 0;
+        let mut frame_count: u64 = 0;
+        let mut mins = Vec::new();
+        let mut maxs = Vec::new();
+
+        for_each_block(path, |block, rate, channels| {
+            sample_rate = rate;
+            for frame in block.chunks_exact(channels) {
+                if frame_count % finest == 0 {
+                    mins.push(f32::MAX);
+                    maxs.push(f32::MIN);
+                }
+                let last = mins.len() - 1;
+                for &sample in frame {
+                    mins[last] = mins[last].min(sample);
+                    maxs[last] = maxs[last].max(sample);
+                }
+                frame_count += 1;
+            }
+        })?;
+
+        let mut levels = vec![PeakLevel {
+            frames_per_peak: PEAK_RESOLUTIONS[0],
+            mins,
+            maxs,
+        }];
+        for &frames_per_peak in &PEAK_RESOLUTIONS[1..] {
+            let previous = levels.last().expect("the finest level exists");
+            let factor = (frames_per_peak / previous.frames_per_peak) as usize;
+            let mins = previous
+                .mins
+                .chunks(factor)
+                .map(|chunk| chunk.iter().copied().fold(f32::MAX, f32::min))
+                .collect();
+            let maxs = previous
+                .maxs
+                .chunks(factor)
+                .map(|chunk| chunk.iter().copied().fold(f32::MIN, f32::max))
+                .collect();
+            levels.push(PeakLevel {
+                frames_per_peak,
+                mins,
+                maxs,
+            });
+        }
+
+        Ok(PeakCache {
+            sample_rate,
+            frame_count,
+            levels,
+        })
+    }
+
+    pub fn duration_ms(&self) -> i64 {
+        if self.sample_rate == 0 {
+            return 0;
+        }
+        (self.frame_count as f64 * 1000.0 / self.sample_rate as f64).round() as i64
+    }
+
+    /// Splits `[start_ms, end_ms)` into `buckets` and reports the lowest and
+    /// highest sample in each. Buckets past the end of the file are silent.
+    pub fn peaks(&self, start_ms: i64, end_ms: i64, buckets: i64) -> AppResult<WaveformPeaks> {
+        if start_ms < 0 || end_ms <= start_ms {
+            return Err(AppError::ValidationFailed(format!(
+                "Waveform range must start at 0 ms or later and end after it, got {} to {} ms",
+                start_ms, end_ms
+            )));
+        }
+        if !(1..=MAX_BUCKETS).contains(&buckets) {
+            return Err(AppError::ValidationFailed(format!(
+                "Bucket count must be between 1 and {}, got {}",
+                MAX_BUCKETS, buckets
+            )));
+        }
+
+        let ms_to_frame = self.sample_rate as f64 / 1000.0;
+        let start_frame = start_ms as f64 * ms_to_frame;
+        let frames_per_bucket = (end_ms - start_ms) as f64 * ms_to_frame / buckets as f64;
+        let level = self
+            .levels
+            .iter()
+            .rev()
+            .find(|level| level.frames_per_peak as f64 <= frames_per_bucket)
+            .unwrap_or(&self.levels[0]);
+
+        let mut mins = Vec::with_capacity(buckets as usize);
+        let mut maxs = Vec::with_capacity(buckets as usize);
+        for bucket in 0..buckets {
+            let from = start_frame + bucket as f64 * frames_per_bucket;
+            let to = from + frames_per_bucket;
+            let first = (from / level.frames_per_peak as f64).floor() as usize;
+            let last = ((to / level.frames_per_peak as f64).ceil() as usize).max(first + 1);
+
+            if first >= level.mins.len() {
+                mins.push(0.0);
+                maxs.push(0.0);
+                continue;
+            }
+            let last = last.min(level.mins.len());
+            mins.push(
+                level.mins[first..last]
+                    .iter()
+                    .copied()
+                    .fold(f32::MAX, f32::min),
+            );
+            maxs.push(
+                level.maxs[first..last]
+                    .iter()
+                    .copied()
+                    .fold(f32::MIN, f32::max),
+            );
+        }
+
+        Ok(WaveformPeaks {
+            start_ms,
+            end_ms,
+            mins,
+            maxs,
+        })
+    }
+
+    fn write_to(&self, path: &Path) -> AppResult<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(CACHE_MAGIC)?;
+        writer.write_all(&CACHE_VERSION.to_le_bytes())?;
+        writer.write_all(&self.sample_rate.to_le_bytes())?;
+        writer.write_all(&self.frame_count.to_le_bytes())?;
+        writer.write_all(&(self.levels.len() as u32).to_le_bytes())?;
+        for level in &self.levels {
+            writer.write_all(&level.frames_per_peak.to_le_bytes())?;
+            writer.write_all(&(level.mins.len() as u32).to_le_bytes())?;
+            for (min, max) in level.mins.iter().zip(&level.maxs) {
+                writer.write_all(&min.to_le_bytes())?;
+                writer.write_all(&max.to_le_bytes())?;
+            }
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Reads a cache file, or `None` when it is missing, truncated, corrupt or
+    /// from another version. Counts in the header are checked against the
+    /// file's length before anything is allocated.
+    fn read_from(path: &Path) -> Option<Self> {
+        let file = File::open(path).ok()?;
+        let file_len = file.metadata().ok()?.len();
+        let mut reader = BufReader::new(file);
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic).ok()?;
+        if &magic != CACHE_MAGIC || read_u32(&mut reader)? != CACHE_VERSION {
+            return None;
+        }
+
+        let sample_rate = read_u32(&mut reader)?;
+        let mut frame_count = [0u8; 8];
+        reader.read_exact(&mut frame_count).ok()?;
+        let frame_count = u64::from_le_bytes(frame_count);
+        let level_count = read_u32(&mut reader)?;
+        if level_count as usize > PEAK_RESOLUTIONS.len() {
+            return None;
+        }
+
+        let mut levels = Vec::with_capacity(level_count as usize);
+        for _ in 0..level_count {
+            let frames_per_peak = read_u32(&mut reader)?;
+            let count = read_u32(&mut reader)? as u64;
+            // Each peak is a min and a max of four bytes each.
+            if frames_per_peak == 0
+                || count != frame_count.div_ceil(frames_per_peak as u64)
+                || count * 8 > file_len
+            {
+                return None;
+            }
+            let count = count as usize;
+            let mut mins = Vec::with_capacity(count);
+            let mut maxs = Vec::with_capacity(count);
+            for _ in 0..count {
+                mins.push(f32::from_bits(read_u32(&mut reader)?));
+                maxs.push(f32::from_bits(read_u32(&mut reader)?));
+            }
+            levels.push(PeakLevel {
+                frames_per_peak,
+                mins,
+                maxs,
+            });
+        }
+        if levels.is_empty() {
+            return None;
+        }
+
+        Some(PeakCache {
+            sample_rate,
+            frame_count,
+            levels,
+        })
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> Option<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes).ok()?;
+    Some(u32::from_le_bytes(bytes))
+}
+
+fn cache_path(cache_dir: &Path, content_hash: &str) -> PathBuf {
+    cache_dir.join(format!("{}.peaks", content_hash))
+}
+
+/// Returns the cached peaks of the file with `content_hash`, building and
+/// caching them from `audio_path` on a miss.
+pub fn load_or_build(
+    cache_dir: &Path,
+    content_hash: &str,
+    audio_path: &Path,
+) -> AppResult<PeakCache> {
+    let path = cache_path(cache_dir, content_hash);
+    if let Some(cache) = PeakCache::read_from(&path) {
+        return Ok(cache);
+    }
+
+    let cache = PeakCache::build(audio_path)?;
+    std::fs::create_dir_all(cache_dir)?;
+    // Write under a temporary name first so a reader never sees half a file.
+    let partial = path.with_extension("partial");
+    cache.write_to(&partial)?;
+    std::fs::rename(&partial, &path)?;
+
+    Ok(cache)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{test_dir, write_test_wav};
+
+    /// One second of mono audio at 8192 Hz, so the halves line up with every
+    /// peak resolution: silence, then a half second at ±0.5.
+    fn half_loud(dir: &Path) -> PathBuf {
+        let path = dir.join("half.wav");
+        let samples: Vec<f32> = (0..8192)
+            .map(|i| match i {
+                0..=4095 => 0.0,
+                _ if i % 2 == 0 => 0.5,
+                _ => -0.5,
+            })
+            .collect();
+        write_test_wav(&path, 8192, 1, &samples);
+        path
+    }
+
+    #[test]
+    fn peaks_follow_the_signal_at_any_zoom() {
+        let dir = test_dir("waveform-peaks");
+        let cache = PeakCache::build(&half_loud(&dir)).unwrap();
+        assert_eq!(cache.duration_ms(), 1000);
+
+        let overview = cache.peaks(0, 1000, 2).unwrap();
+        assert_eq!(overview.maxs[0], 0.0);
+        assert!((overview.maxs[1] - 0.5).abs() < 1e-3);
+        assert!((overview.mins[1] + 0.5).abs() < 1e-3);
+
+        // Zoomed into the loud half, with more buckets than finest peaks.
+        let zoomed = cache.peaks(600, 610, 20).unwrap();
+        assert_eq!(zoomed.maxs.len(), 20);
+        assert!(zoomed.maxs.iter().all(|max| (max - 0.5).abs() < 1e-3));
+
+        let past_the_end = cache.peaks(2000, 3000, 4).unwrap();
+        assert!(past_the_end.maxs.iter().all(|max| *max == 0.0));
+
+        assert_eq!(
+            cache.peaks(0, 1000, 0).unwrap_err().code(),
+            "ValidationFailed"
+        );
+        assert_eq!(
+            cache.peaks(500, 500, 10).unwrap_err().code(),
+            "ValidationFailed"
+        );
+    }
+
+    #[test]
+    fn caches_are_keyed_by_content() {
+        let dir = test_dir("waveform-cache");
+        let audio = half_loud(&dir);
+        let copy = dir.join("copy.wav");
+        std::fs::copy(&audio, &copy).unwrap();
+
+        let hash = hash_file(&audio).unwrap();
+        assert_eq!(hash, hash_file(&copy).unwrap());
+        assert_eq!(hash.len(), 64);
+
+        let cache_dir = dir.join("peaks");
+        let built = load_or_build(&cache_dir, &hash, &audio).unwrap();
+        std::fs::remove_file(&audio).unwrap();
+        // The original is gone, so this can only come from the cache.
+        let cached = load_or_build(&cache_dir, &hash, &audio).unwrap();
+        assert_eq!(built, cached);
+    }
+
+    #[test]
+    fn corrupt_cache_headers_are_rejected_before_allocating() {
+        let dir = test_dir("waveform-corrupt");
+        let path = dir.join("corrupt.peaks");
+        let header = |frame_count: u64, level_count: u32, count: u32| {
+            let mut bytes = CACHE_MAGIC.to_vec();
+            bytes.extend_from_slice(&CACHE_VERSION.to_le_bytes());
+            bytes.extend_from_slice(&48_000u32.to_le_bytes());
+            bytes.extend_from_slice(&frame_count.to_le_bytes());
+            bytes.extend_from_slice(&level_count.to_le_bytes());
+            bytes.extend_from_slice(&128u32.to_le_bytes());
+            bytes.extend_from_slice(&count.to_le_bytes());
+            bytes
+        };
+
+        std::fs::write(&path, header(128, u32::MAX, 1)).unwrap();
+        assert!(PeakCache::read_from(&path).is_none());
+        // A count that matches a huge frame count, in a file far too short for it.
+        let frames = u32::MAX as u64 * 128;
+        std::fs::write(&path, header(frames, 1, u32::MAX)).unwrap();
+        assert!(PeakCache::read_from(&path).is_none());
+        std::fs::write(&path, header(128, 1, 7)).unwrap();
+        assert!(PeakCache::read_from(&path).is_none());
+    }
+}
@@ -0,0 +1,82 @@
+use std::path::PathBuf;
+
+use immersive_scene_lib::audio::render::{load_score, render_to_file};
+use immersive_scene_lib::Database;
+
+fn print_help() {
+    println!(
+        "timeline-render\n\nUSAGE:\n  timeline_render <database> <mood-id> <length-seconds> <output> [--seed <n>]\n\nARGS:\n  <database>         Path to immersive_scene.db\n  <mood-id>          Mood whose timeline is rendered\n  <length-seconds>   Length of the render, in seconds\n  <output>           Destination .wav or .flac file (48 kHz stereo, 24-bit)\n\nOPTIONS:\n  --seed <n>         Seed for element group picks (default 0)\n\nEXAMPLES:\n  timeline_render ./immersive_scene.db 3 120 ./tavern.flac\n  timeline_render ./immersive_scene.db 3 30 ./golden.wav --seed 7"
+    );
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    print_help();
+    std::process::exit(1);
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.is_empty()
+        || args
+            .iter()
+            .any(|argument| argument == "-h" || argument == "--help")
+    {
+        print_help();
+        if args.is_empty() {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let mut seed = 0;
+    if let Some(index) = args.iter().position(|argument| argument == "--seed") {
+        let Some(value) = args.get(index + 1) else {
+            fail("--seed needs a value.");
+        };
+        seed = value
+            .parse()
+            .unwrap_or_else(|_| fail("--seed must be a non-negative integer."));
+        args.drain(index..=index + 1);
+    }
+
+    if args.len() != 4 {
+        fail("Invalid number of arguments.");
+    }
+    let database = PathBuf::from(&args[0]);
+    let mood_id: i64 = args[1]
+        .parse()
+        .unwrap_or_else(|_| fail("<mood-id> must be an integer."));
+    let length_seconds: f64 = args[2]
+        .parse()
+        .unwrap_or_else(|_| fail("<length-seconds> must be a number."));
+    let output = PathBuf::from(&args[3]);
+
+    // The database is only read, so a typo fails instead of creating a new
+    // file and an outdated one is left for the app to migrate.
+    let result = Database::open_read_only(&database).and_then(|db| {
+        let score = load_score(&*db.connection()?, mood_id)?;
+        render_to_file(
+            score,
+            seed,
+            (length_seconds * 1000.0).round() as i64,
+            &output,
+        )
+    });
+    match result {
+        Ok(summary) => {
+            let peak = summary.peak_dbfs.map_or("silent".to_string(), |peak| {
+                format!("peak {:.1} dBFS", peak)
+            });
+            println!("Rendered {} ({})", summary.output_path, peak);
+            if summary.clipped_samples > 0 {
+                println!("Warning: {} samples clipped", summary.clipped_samples);
+            }
+        }
+        Err(error) => {
+            eprintln!("Failed to render timeline: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
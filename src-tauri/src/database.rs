@@ -0,0 +1,201 @@
+use rusqlite::{Connection, OpenFlags};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::error::{AppError, AppResult};
+use crate::migrations;
+
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The application's single SQLite connection, held in Tauri managed state.
+///
+/// Every command borrows it through `State<'_, Database>` instead of opening
+/// the file again, so connection-level settings such as `foreign_keys` apply
+/// to every statement the app runs.
+pub struct Database {
+    conn: Mutex<Connection>,
+}
+
+impl Database {
+    pub fn open(path: &Path) -> AppResult<Self> {
+        let conn = Connection::open(path).map_err(|e| {
+            AppError::Database(format!(
+                "Failed to open database '{}': {}",
+                path.display(),
+                e
+            ))
+        })?;
+        Self::from_connection(conn)
+    }
+
+    /// Opens an existing database without changing it: no migrations, no
+    /// journal mode switch, and writes fail. Fails unless the file is there
+    /// and already at the schema version this build expects.
+    pub fn open_read_only(path: &Path) -> AppResult<Self> {
+        if !path.is_file() {
+            return Err(AppError::NotFound(format!(
+                "Database '{}' does not exist",
+                path.display()
+            )));
+        }
+        let conn = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .map_err(|e| {
+            AppError::Database(format!(
+                "Failed to open database '{}': {}",
+                path.display(),
+                e
+            ))
+        })?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+
+        let version = migrations::schema_version(&conn)?;
+        if version != migrations::latest_version() {
+            return Err(AppError::ValidationFailed(format!(
+                "Database '{}' is at schema version {} but this build expects {}; open it in the app once to upgrade it",
+                path.display(),
+                version,
+                migrations::latest_version()
+            )));
+        }
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub fn from_connection(conn: Connection) -> AppResult<Self> {
+        configure_connection(&conn)
+            .map_err(|e| AppError::Database(format!("Failed to configure database: {}", e)))?;
+        migrations::run_migrations(&conn)?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub fn connection(&self) -> AppResult<MutexGuard<'_, Connection>> {
+        self.conn
+            .lock()
+            .map_err(|_| AppError::Internal("Failed to lock database connection".into()))
+    }
+}
+
+fn configure_connection(conn: &Connection) -> rusqlite::Result<()> {
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    // journal_mode reports the resulting mode as a row, which `execute` rejects
+    let _: String = conn.query_row("PRAGMA journal_mode = WAL", [], |row| row.get(0))?;
+    conn.execute_batch("PRAGMA foreign_keys = ON;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(conn: &Connection, table: &str) -> i64 {
+        conn.query_row(&format!("SELECT count(*) FROM {}", table), [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn read_only_opens_never_create_or_change_the_file() {
+        let dir = crate::audio::test_dir("database-read-only");
+        let missing = dir.join("typo.db");
+        let error = Database::open_read_only(&missing).err().unwrap();
+        assert_eq!(error.code(), "NotFound");
+        assert!(!missing.exists());
+
+        let stale = dir.join("stale.db");
+        Connection::open(&stale)
+            .unwrap()
+            .execute_batch("CREATE TABLE sound_sets (id INTEGER PRIMARY KEY);")
+            .unwrap();
+        let error = Database::open_read_only(&stale).err().unwrap();
+        assert_eq!(error.code(), "ValidationFailed");
+        let version: i64 = Connection::open(&stale)
+            .unwrap()
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, 0);
+
+        let current = dir.join("current.db");
+        migrations::run_migrations(&Connection::open(&current).unwrap()).unwrap();
+        let db = Database::open_read_only(&current).unwrap();
+        let conn = db.connection().unwrap();
+        let journal_mode: String = conn
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(journal_mode, "delete");
+        assert!(conn
+            .execute("INSERT INTO moods (name) VALUES ('M')", [])
+            .is_err());
+    }
+
+    #[test]
+    fn enables_foreign_keys() {
+        let db = Database::from_connection(Connection::open_in_memory().unwrap()).unwrap();
+        let conn = db.connection().unwrap();
+
+        let foreign_keys: bool = conn
+            .query_row("PRAGMA foreign_keys", [], |row| row.get(0))
+            .unwrap();
+        assert!(foreign_keys);
+    }
+
+    #[test]
+    fn deleting_a_mood_cascades_to_its_timeline() {
+        let db = Database::from_connection(Connection::open_in_memory().unwrap()).unwrap();
+        let conn = db.connection().unwrap();
+        conn.execute_batch(
+            "INSERT INTO sound_sets (id, name, description) VALUES (1, 'S', 'D');
+             INSERT INTO audio_elements (id, sound_set_id, file_path, file_name) VALUES (10, 1, 'p', 'f');
+             INSERT INTO moods (id, name) VALUES (1, 'M');
+             INSERT INTO timelines (id, mood_id, name) VALUES (1, 1, 'T');
+             INSERT INTO timeline_tracks (id, timeline_id, name) VALUES (1, 1, 'Trk');
+             INSERT INTO timeline_elements (id, timeline_id, track_id, audio_element_id, start_time_ms, duration_ms)
+                 VALUES (1, 1, 1, 10, 0, 1000);",
+        )
+        .unwrap();
+
+        conn.execute("DELETE FROM moods WHERE id = 1", []).unwrap();
+
+        assert_eq!(count(&conn, "timelines"), 0);
+        assert_eq!(count(&conn, "timeline_tracks"), 0);
+        assert_eq!(count(&conn, "timeline_elements"), 0);
+        assert_eq!(count(&conn, "audio_elements"), 1);
+    }
+
+    #[test]
+    fn deleting_a_sound_set_cascades_to_its_library() {
+        let db = Database::from_connection(Connection::open_in_memory().unwrap()).unwrap();
+        let conn = db.connection().unwrap();
+        conn.execute_batch(
+            "INSERT INTO sound_sets (id, name, description) VALUES (1, 'S', 'D');
+             INSERT INTO audio_channels (id, sound_set_id, name) VALUES (1, 1, 'Music');
+             INSERT INTO audio_elements (id, sound_set_id, channel_id, file_path, file_name) VALUES (10, 1, 1, 'p', 'f');
+             INSERT INTO element_groups (id, sound_set_id, name) VALUES (20, 1, 'G');
+             INSERT INTO element_group_members (group_id, audio_element_id) VALUES (20, 10);
+             INSERT INTO moods (id, name) VALUES (1, 'M');
+             INSERT INTO timelines (id, mood_id, name) VALUES (1, 1, 'T');
+             INSERT INTO timeline_tracks (id, timeline_id, name) VALUES (1, 1, 'Trk');
+             INSERT INTO timeline_elements (track_id, audio_element_id, start_time_ms, duration_ms) VALUES (1, 10, 0, 1000);
+             INSERT INTO timeline_elements (track_id, element_group_id, start_time_ms, duration_ms) VALUES (1, 20, 1000, 1000);",
+        )
+        .unwrap();
+
+        conn.execute("DELETE FROM sound_sets WHERE id = 1", [])
+            .unwrap();
+
+        assert_eq!(count(&conn, "audio_channels"), 0);
+        assert_eq!(count(&conn, "audio_elements"), 0);
+        assert_eq!(count(&conn, "element_groups"), 0);
+        assert_eq!(count(&conn, "element_group_members"), 0);
+        assert_eq!(count(&conn, "timeline_elements"), 0);
+        assert_eq!(count(&conn, "timeline_tracks"), 1);
+    }
+}
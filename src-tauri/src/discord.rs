@@ -6,10 +6,15 @@ use std::env;
 use std::io::{BufRead, BufReader, Write};
 use std::path::PathBuf;
 use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
-use std::sync::Mutex;
+use std::sync::{mpsc, Mutex};
 use tauri::{AppHandle, Manager};
 
+use crate::audio::sink::{to_pcm16, AudioSink};
+use crate::{AppError, AppResult};
+
 const DISCORD_API_BASE: &str = "https://discord.com/api/v10";
+/// Samples per `sendPcm` packet: 40 ms of 48 kHz stereo. The sidecar drops any other size.
+const DISCORD_PACKET_SAMPLES: usize = 960 * 2 * 2;
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "snake_case")]
@@ -79,14 +84,14 @@ impl SidecarState {
 
 static SIDECAR_STATE: Lazy<Mutex<SidecarState>> = Lazy::new(|| Mutex::new(SidecarState::new()));
 
-fn discord_http_client() -> Result<Client, String> {
+fn discord_http_client() -> AppResult<Client> {
     Client::builder()
         .user_agent("immersive-scene-discord/1.0")
         .build()
-        .map_err(|err| format!("Failed to build HTTP client: {err}"))
+        .map_err(|err| AppError::Network(format!("Failed to build HTTP client: {err}")))
 }
 
-fn resolve_sidecar_script_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
+fn resolve_sidecar_script_path(app_handle: &AppHandle) -> AppResult<PathBuf> {
     let dev_candidate = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
         .join("..")
         .join("sidecar")
@@ -99,7 +104,7 @@ fn resolve_sidecar_script_path(app_handle: &AppHandle) -> Result<PathBuf, String
     let resource_candidate = app_handle
         .path()
         .resource_dir()
-        .map_err(|err| format!("Failed to resolve resource dir: {err}"))?
+        .map_err(|err| AppError::Sidecar(format!("Failed to resolve resource dir: {err}")))?
         .join("sidecar")
         .join("discord-voice-sidecar.cjs");
 
@@ -110,17 +115,19 @@ fn resolve_sidecar_script_path(app_handle: &AppHandle) -> Result<PathBuf, String
     let flat_resource_candidate = app_handle
         .path()
         .resource_dir()
-        .map_err(|err| format!("Failed to resolve resource dir: {err}"))?
+        .map_err(|err| AppError::Sidecar(format!("Failed to resolve resource dir: {err}")))?
         .join("discord-voice-sidecar.cjs");
 
     if flat_resource_candidate.exists() {
         return Ok(flat_resource_candidate);
     }
 
-    Err("Discord sidecar script not found".to_string())
+    Err(AppError::Sidecar(
+        "Discord sidecar script not found".to_string(),
+    ))
 }
 
-fn spawn_sidecar(app_handle: &AppHandle) -> Result<SidecarProcess, String> {
+fn spawn_sidecar(app_handle: &AppHandle) -> AppResult<SidecarProcess> {
     let script_path = resolve_sidecar_script_path(app_handle)?;
     let node_binary = env::var("IMMERSIVE_SCENE_NODE_BIN").unwrap_or_else(|_| "node".to_string());
 
@@ -137,16 +144,16 @@ fn spawn_sidecar(app_handle: &AppHandle) -> Result<SidecarProcess, String> {
 
     let mut child = command
         .spawn()
-        .map_err(|err| format!("Failed to start Discord sidecar: {err}"))?;
+        .map_err(|err| AppError::Sidecar(format!("Failed to start Discord sidecar: {err}")))?;
 
     let stdin = child
         .stdin
         .take()
-        .ok_or_else(|| "Failed to open sidecar stdin".to_string())?;
+        .ok_or_else(|| AppError::Sidecar("Failed to open sidecar stdin".to_string()))?;
     let stdout = child
         .stdout
         .take()
-        .ok_or_else(|| "Failed to open sidecar stdout".to_string())?;
+        .ok_or_else(|| AppError::Sidecar("Failed to open sidecar stdout".to_string()))?;
 
     Ok(SidecarProcess {
         child,
@@ -159,12 +166,14 @@ fn spawn_sidecar(app_handle: &AppHandle) -> Result<SidecarProcess, String> {
 fn ensure_sidecar_started<'a>(
     app_handle: &AppHandle,
     state: &'a mut SidecarState,
-) -> Result<&'a mut SidecarProcess, String> {
+) -> AppResult<&'a mut SidecarProcess> {
     let should_spawn = match state.process.as_mut() {
         Some(process) => process
             .child
             .try_wait()
-            .map_err(|err| format!("Failed to query sidecar process state: {err}"))?
+            .map_err(|err| {
+                AppError::Sidecar(format!("Failed to query sidecar process state: {err}"))
+            })?
             .is_some(),
         None => true,
     };
@@ -176,13 +185,13 @@ fn ensure_sidecar_started<'a>(
     state
         .process
         .as_mut()
-        .ok_or_else(|| "Discord sidecar process is unavailable".to_string())
+        .ok_or_else(|| AppError::Sidecar("Discord sidecar process is unavailable".to_string()))
 }
 
-fn send_sidecar_request(app_handle: &AppHandle, command: &str, payload: Value) -> Result<Value, String> {
+fn send_sidecar_request(app_handle: &AppHandle, command: &str, payload: Value) -> AppResult<Value> {
     let mut state = SIDECAR_STATE
         .lock()
-        .map_err(|_| "Failed to lock Discord sidecar state".to_string())?;
+        .map_err(|_| AppError::Sidecar("Failed to lock Discord sidecar state".to_string()))?;
 
     let process = ensure_sidecar_started(app_handle, &mut state)?;
 
@@ -196,49 +205,50 @@ fn send_sidecar_request(app_handle: &AppHandle, command: &str, payload: Value) -
     };
 
     let json_line = serde_json::to_string(&request)
-        .map_err(|err| format!("Failed to serialize sidecar request: {err}"))?;
+        .map_err(|err| AppError::Sidecar(format!("Failed to serialize sidecar request: {err}")))?;
 
     process
         .stdin
         .write_all(json_line.as_bytes())
-        .map_err(|err| format!("Failed to write sidecar request: {err}"))?;
+        .map_err(|err| AppError::Sidecar(format!("Failed to write sidecar request: {err}")))?;
     process
         .stdin
         .write_all(b"\n")
-        .map_err(|err| format!("Failed to terminate sidecar request: {err}"))?;
+        .map_err(|err| AppError::Sidecar(format!("Failed to terminate sidecar request: {err}")))?;
     process
         .stdin
         .flush()
-        .map_err(|err| format!("Failed to flush sidecar request: {err}"))?;
+        .map_err(|err| AppError::Sidecar(format!("Failed to flush sidecar request: {err}")))?;
 
     let mut response_line = String::new();
     let bytes = process
         .stdout
         .read_line(&mut response_line)
-        .map_err(|err| format!("Failed to read sidecar response: {err}"))?;
+        .map_err(|err| AppError::Sidecar(format!("Failed to read sidecar response: {err}")))?;
 
     if bytes == 0 {
-        let exit_state = process
-            .child
-            .try_wait()
-            .map_err(|err| format!("Failed to inspect sidecar exit state: {err}"))?;
-        return Err(match exit_state {
+        let exit_state = process.child.try_wait().map_err(|err| {
+            AppError::Sidecar(format!("Failed to inspect sidecar exit state: {err}"))
+        })?;
+        return Err(AppError::Sidecar(match exit_state {
             Some(status) => format!("Discord sidecar closed unexpectedly (status: {status})"),
             None => "Discord sidecar closed unexpectedly".to_string(),
-        });
+        }));
     }
 
     let response: SidecarResponse = serde_json::from_str(response_line.trim())
-        .map_err(|err| format!("Failed to parse sidecar response: {err}"))?;
+        .map_err(|err| AppError::Sidecar(format!("Failed to parse sidecar response: {err}")))?;
 
     if response.id != request_id {
-        return Err("Discord sidecar response id mismatch".to_string());
+        return Err(AppError::Sidecar(
+            "Discord sidecar response id mismatch".to_string(),
+        ));
     }
 
     if !response.ok {
-        return Err(response
-            .error
-            .unwrap_or_else(|| "Discord sidecar returned an unknown error".to_string()));
+        return Err(AppError::Sidecar(response.error.unwrap_or_else(|| {
+            "Discord sidecar returned an unknown error".to_string()
+        })));
     }
 
     Ok(response.result.unwrap_or(Value::Null))
@@ -263,66 +273,72 @@ fn map_sidecar_telemetry(raw: SidecarTelemetry) -> DiscordStreamTelemetry {
 }
 
 #[tauri::command]
-pub async fn discord_validate_token(token: String) -> Result<Value, String> {
+pub async fn discord_validate_token(token: String) -> AppResult<Value> {
     let client = discord_http_client()?;
     let response = client
         .get(format!("{DISCORD_API_BASE}/users/@me"))
         .header("Authorization", format!("Bot {token}"))
         .send()
         .await
-        .map_err(|err| format!("Failed to validate token: {err}"))?;
+        .map_err(|err| AppError::Network(format!("Failed to validate token: {err}")))?;
 
     if !response.status().is_success() {
-        return Err(format!("Token validation failed with status {}", response.status()));
+        return Err(AppError::Network(format!(
+            "Token validation failed with status {}",
+            response.status()
+        )));
     }
 
     response
         .json::<Value>()
         .await
-        .map_err(|err| format!("Failed to parse Discord user response: {err}"))
+        .map_err(|err| AppError::Network(format!("Failed to parse Discord user response: {err}")))
 }
 
 #[tauri::command]
-pub async fn discord_list_guilds(token: String) -> Result<Vec<Value>, String> {
+pub async fn discord_list_guilds(token: String) -> AppResult<Vec<Value>> {
     let client = discord_http_client()?;
     let response = client
         .get(format!("{DISCORD_API_BASE}/users/@me/guilds"))
         .header("Authorization", format!("Bot {token}"))
         .send()
         .await
-        .map_err(|err| format!("Failed to list guilds: {err}"))?;
+        .map_err(|err| AppError::Network(format!("Failed to list guilds: {err}")))?;
 
     if !response.status().is_success() {
-        return Err(format!("Failed to list guilds with status {}", response.status()));
+        return Err(AppError::Network(format!(
+            "Failed to list guilds with status {}",
+            response.status()
+        )));
     }
 
     response
         .json::<Vec<Value>>()
         .await
-        .map_err(|err| format!("Failed to parse guild list: {err}"))
+        .map_err(|err| AppError::Network(format!("Failed to parse guild list: {err}")))
 }
 
 #[tauri::command]
-pub async fn discord_list_voice_channels(token: String, guild_id: String) -> Result<Vec<Value>, String> {
+pub async fn discord_list_voice_channels(token: String, guild_id: String) -> AppResult<Vec<Value>> {
     let client = discord_http_client()?;
     let response = client
         .get(format!("{DISCORD_API_BASE}/guilds/{guild_id}/channels"))
         .header("Authorization", format!("Bot {token}"))
         .send()
         .await
-        .map_err(|err| format!("Failed to list channels: {err}"))?;
+        .map_err(|err| AppError::Network(format!("Failed to list channels: {err}")))?;
 
     if !response.status().is_success() {
-        return Err(format!(
+        return Err(AppError::Network(format!(
             "Failed to list channels for guild {guild_id} with status {}",
             response.status()
-        ));
+        )));
     }
 
     let all_channels = response
         .json::<Vec<Value>>()
         .await
-        .map_err(|err| format!("Failed to parse channel list: {err}"))?;
+        .map_err(|err| AppError::Network(format!("Failed to parse channel list: {err}")))?;
 
     Ok(all_channels
         .into_iter()
@@ -336,7 +352,7 @@ pub async fn discord_connect(
     token: String,
     guild_id: String,
     channel_id: String,
-) -> Result<(), String> {
+) -> AppResult<()> {
     send_sidecar_request(
         &app_handle,
         "connect",
@@ -351,19 +367,23 @@ pub async fn discord_connect(
 }
 
 #[tauri::command]
-pub async fn discord_disconnect(app_handle: AppHandle) -> Result<(), String> {
+pub async fn discord_disconnect(app_handle: AppHandle) -> AppResult<()> {
     send_sidecar_request(&app_handle, "disconnect", json!({}))?;
     Ok(())
 }
 
 #[tauri::command]
-pub async fn discord_send_audio(app_handle: AppHandle, pcm_data: Vec<i16>) -> Result<(), String> {
+pub async fn discord_send_audio(app_handle: AppHandle, pcm_data: Vec<i16>) -> AppResult<()> {
     if pcm_data.is_empty() {
         return Ok(());
     }
 
+    send_pcm(&app_handle, &pcm_data)
+}
+
+fn send_pcm(app_handle: &AppHandle, pcm_data: &[i16]) -> AppResult<()> {
     send_sidecar_request(
-        &app_handle,
+        app_handle,
         "sendPcm",
         json!({
             "pcmData": pcm_data,
@@ -373,12 +393,89 @@ pub async fn discord_send_audio(app_handle: AppHandle, pcm_data: Vec<i16>) -> Re
     Ok(())
 }
 
+/// Most packets waiting for the voice bridge before new ones are dropped.
+const DISCORD_QUEUE_PACKETS: usize = 8;
+
+/// Feeds the playback engine's master output straight to the voice bridge.
+///
+/// Talking to the sidecar blocks, so packets go to a sender thread of their
+/// own and are dropped while it falls behind. The thread stops at the first
+/// failed send and hands the error back, so the next write fails with it.
+pub struct DiscordSink {
+    packets: Option<mpsc::SyncSender<Vec<i16>>>,
+    failure: mpsc::Receiver<AppError>,
+    pending: Vec<i16>,
+}
+
+impl DiscordSink {
+    pub fn new(app_handle: AppHandle) -> Self {
+        let (packets, outgoing) = mpsc::sync_channel::<Vec<i16>>(DISCORD_QUEUE_PACKETS);
+        let (failed, failure) = mpsc::channel::<AppError>();
+        std::thread::spawn(move || {
+            // Ends once the sink is finished or dropped, or a send fails.
+            for packet in outgoing {
+                if let Err(error) = send_pcm(&app_handle, &packet) {
+                    let _ = failed.send(error);
+                    break;
+                }
+            }
+        });
+
+        Self {
+            packets: Some(packets),
+            failure,
+            pending: Vec::with_capacity(DISCORD_PACKET_SAMPLES * 2),
+        }
+    }
+
+    fn stopped_error(&self) -> AppError {
+        self.failure
+            .try_recv()
+            .unwrap_or_else(|_| AppError::Sidecar("The Discord sender thread stopped".into()))
+    }
+}
+
+impl AudioSink for DiscordSink {
+    fn name(&self) -> &str {
+        "discord"
+    }
+
+    fn write(&mut self, block: &[f32]) -> AppResult<()> {
+        if let Ok(error) = self.failure.try_recv() {
+            return Err(error);
+        }
+        self.pending.extend(to_pcm16(block));
+
+        let full_packets = self.pending.len() / DISCORD_PACKET_SAMPLES * DISCORD_PACKET_SAMPLES;
+        if let Some(packets) = &self.packets {
+            for packet in self.pending[..full_packets].chunks_exact(DISCORD_PACKET_SAMPLES) {
+                match packets.try_send(packet.to_vec()) {
+                    Ok(()) | Err(mpsc::TrySendError::Full(_)) => {}
+                    Err(mpsc::TrySendError::Disconnected(_)) => {
+                        return Err(self.stopped_error());
+                    }
+                }
+            }
+        }
+        self.pending.drain(..full_packets);
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> AppResult<()> {
+        self.packets = None;
+        Ok(())
+    }
+}
+
 #[tauri::command]
-pub async fn discord_get_stream_telemetry(app_handle: AppHandle) -> Result<DiscordStreamTelemetry, String> {
+pub async fn discord_get_stream_telemetry(
+    app_handle: AppHandle,
+) -> AppResult<DiscordStreamTelemetry> {
     let result = send_sidecar_request(&app_handle, "getTelemetry", json!({}))?;
 
     let raw = serde_json::from_value::<SidecarTelemetry>(result)
-        .map_err(|err| format!("Failed to decode sidecar telemetry: {err}"))?;
+        .map_err(|err| AppError::Sidecar(format!("Failed to decode sidecar telemetry: {err}")))?;
 
     Ok(map_sidecar_telemetry(raw))
 }
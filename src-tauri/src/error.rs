@@ -0,0 +1,146 @@
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use serde_json::{json, Value};
+use std::fmt;
+
+/// Error returned by every Tauri command.
+///
+/// It reaches the frontend as `{ code, message, details }`, where `code` is one
+/// of the variant names below and never changes once shipped, `message` is the
+/// human readable text, and `details` carries structured data for the codes
+/// that have any (for example the conflicting element of an `Overlap`).
+#[derive(Debug)]
+pub enum AppError {
+    NotFound(String),
+    Overlap {
+        track_id: i64,
+        conflicting_element_id: i64,
+    },
+    ValidationFailed(String),
+    Io(String),
+    Database(String),
+    Sidecar(String),
+    Network(String),
+    ManifestInvalid(String),
+    Internal(String),
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+impl AppError {
+    pub fn not_found(entity: &str, id: i64) -> Self {
+        AppError::NotFound(format!("{} {} not found", entity, id))
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "NotFound",
+            AppError::Overlap { .. } => "Overlap",
+            AppError::ValidationFailed(_) => "ValidationFailed",
+            AppError::Io(_) => "Io",
+            AppError::Database(_) => "Database",
+            AppError::Sidecar(_) => "Sidecar",
+            AppError::Network(_) => "Network",
+            AppError::ManifestInvalid(_) => "ManifestInvalid",
+            AppError::Internal(_) => "Internal",
+        }
+    }
+
+    pub fn details(&self) -> Value {
+        match self {
+            AppError::Overlap {
+                track_id,
+                conflicting_element_id,
+            } => json!({
+                "track_id": track_id,
+                "conflicting_element_id": conflicting_element_id,
+            }),
+            _ => Value::Null,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Overlap { .. } => {
+                write!(f, "Element overlaps with existing element in track")
+            }
+            AppError::NotFound(message)
+            | AppError::ValidationFailed(message)
+            | AppError::Io(message)
+            | AppError::Database(message)
+            | AppError::Sidecar(message)
+            | AppError::Network(message)
+            | AppError::ManifestInvalid(message)
+            | AppError::Internal(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AppError", 3)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("details", &self.details())?;
+        state.end()
+    }
+}
+
+impl From<rusqlite::Error> for AppError {
+    fn from(error: rusqlite::Error) -> Self {
+        match error {
+            rusqlite::Error::QueryReturnedNoRows => AppError::NotFound("Record not found".into()),
+            other => AppError::Database(other.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(error: std::io::Error) -> Self {
+        AppError::Io(error.to_string())
+    }
+}
+
+impl From<crate::migrations::MigrationError> for AppError {
+    fn from(error: crate::migrations::MigrationError) -> Self {
+        AppError::Database(error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_code_message_and_details() {
+        let error = AppError::Overlap {
+            track_id: 5,
+            conflicting_element_id: 10,
+        };
+
+        let value = serde_json::to_value(&error).unwrap();
+
+        assert_eq!(
+            value,
+            json!({
+                "code": "Overlap",
+                "message": "Element overlaps with existing element in track",
+                "details": { "track_id": 5, "conflicting_element_id": 10 },
+            })
+        );
+    }
+
+    #[test]
+    fn missing_rows_map_to_not_found() {
+        let error: AppError = rusqlite::Error::QueryReturnedNoRows.into();
+        assert_eq!(error.code(), "NotFound");
+
+        let value = serde_json::to_value(AppError::not_found("Timeline track", 7)).unwrap();
+        assert_eq!(value["message"], "Timeline track 7 not found");
+        assert_eq!(value["details"], Value::Null);
+    }
+}
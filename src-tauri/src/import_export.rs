@@ -1,15 +1,22 @@
-use rusqlite::Connection;
+use rusqlite::{Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
 use std::io::{Read, Seek, Write};
 use std::path::{Component, Path, PathBuf};
-use tauri::AppHandle;
+use tauri::{AppHandle, State};
 use zip::write::SimpleFileOptions;
 use zip::ZipWriter;
 
-use crate::get_db_path;
+use crate::audio::probe::{probe_file, AudioInfo};
+use crate::store::audio_elements::update_audio_element_info;
+use crate::store::effects::{append_channel_effect, get_channel_effects, Effect};
+use crate::store::element_groups::validate_variation;
+use crate::store::group_playback::PlaybackMode;
+use crate::store::history::{self, Scope};
+use crate::store::tags::{add_tags, split_tag_names, tag_names_column, validate_rating, TagTarget};
+use crate::{AppError, AppResult, Database};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ExportManifest {
@@ -33,6 +40,15 @@ pub struct ExportChannel {
     pub icon: String,
     pub volume: f64,
     pub order_index: i64,
+    /// Insert effects in chain order.
+    #[serde(default)]
+    pub effects: Vec<ExportEffect>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportEffect {
+    pub enabled: bool,
+    pub effect: Effect,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -42,36 +58,60 @@ pub struct ExportElement {
     pub channel_name: Option<String>,
     pub channel_type: String,
     pub volume_db: f64,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub rating: Option<i64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ExportGroup {
     pub name: String,
     pub members: Vec<ExportGroupMember>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub rating: Option<i64>,
+    #[serde(default)]
+    pub playback_mode: PlaybackMode,
+    #[serde(default)]
+    pub pitch_variation_semitones: f64,
+    #[serde(default)]
+    pub volume_variation_db: f64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ExportGroupMember {
     pub element_file_name: String,
     pub order_index: i64,
+    #[serde(default = "default_member_weight")]
+    pub weight: f64,
+}
+
+fn default_member_weight() -> f64 {
+    1.0
 }
 
 pub fn package_sound_set_folder(
     source_folder: &Path,
     output_path: Option<&Path>,
-) -> Result<PathBuf, String> {
+) -> AppResult<PathBuf> {
     if !source_folder.exists() {
-        return Err(format!(
+        return Err(AppError::NotFound(format!(
             "Source folder does not exist: {}",
             source_folder.display()
-        ));
+        )));
     }
 
     if !source_folder.is_dir() {
-        return Err(format!(
+        return Err(AppError::ValidationFailed(format!(
             "Source path is not a folder: {}",
             source_folder.display()
-        ));
+        )));
     }
 
     let manifest = load_manifest_from_folder(source_folder)?;
@@ -80,27 +120,27 @@ pub fn package_sound_set_folder(
     let mut files_to_package: Vec<(PathBuf, String)> = Vec::new();
     for element in &manifest.elements {
         validate_archive_path(&element.archive_path).map_err(|error| {
-            format!(
+            AppError::ManifestInvalid(format!(
                 "Invalid elements[].archive_path '{}': {}",
                 element.archive_path, error
-            )
+            ))
         })?;
 
         let source_path = source_folder.join(&element.archive_path);
         if !source_path.exists() {
-            return Err(format!(
+            return Err(AppError::NotFound(format!(
                 "Missing referenced audio file for elements[].archive_path '{}': {}",
                 element.archive_path,
                 source_path.display()
-            ));
+            )));
         }
 
         if !source_path.is_file() {
-            return Err(format!(
+            return Err(AppError::ValidationFailed(format!(
                 "Referenced path is not a file for elements[].archive_path '{}': {}",
                 element.archive_path,
                 source_path.display()
-            ));
+            )));
         }
 
         files_to_package.push((source_path, element.archive_path.clone()));
@@ -113,129 +153,135 @@ pub fn package_sound_set_folder(
         None => {
             let safe_name = sanitize_archive_name(&manifest.soundset.name);
             std::env::current_dir()
-                .map_err(|error| format!("Failed to read current directory: {}", error))?
+                .map_err(|error| {
+                    AppError::Io(format!("Failed to read current directory: {}", error))
+                })?
                 .join(format!("{}.zip", safe_name))
         }
     };
 
     if let Some(parent) = output.parent() {
         if !parent.as_os_str().is_empty() {
-            fs::create_dir_all(parent)
-                .map_err(|error| format!("Failed to create output directory: {}", error))?;
+            fs::create_dir_all(parent).map_err(|error| {
+                AppError::Io(format!("Failed to create output directory: {}", error))
+            })?;
         }
     }
 
     let file = File::create(&output).map_err(|error| {
-        format!(
+        AppError::Io(format!(
             "Failed to create zip file '{}': {}",
             output.display(),
             error
-        )
+        ))
     })?;
     let mut zip = ZipWriter::new(file);
     let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
 
-    zip.start_file("manifest.json", options.clone())
-        .map_err(|error| format!("Failed to write manifest.json: {}", error))?;
+    zip.start_file("manifest.json", options)
+        .map_err(|error| AppError::Io(format!("Failed to write manifest.json: {}", error)))?;
     let manifest_json = serde_json::to_string_pretty(&manifest)
-        .map_err(|error| format!("Failed to serialize manifest: {}", error))?;
-    zip.write_all(manifest_json.as_bytes())
-        .map_err(|error| format!("Failed to write manifest.json contents: {}", error))?;
+        .map_err(|error| AppError::Internal(format!("Failed to serialize manifest: {}", error)))?;
+    zip.write_all(manifest_json.as_bytes()).map_err(|error| {
+        AppError::Io(format!("Failed to write manifest.json contents: {}", error))
+    })?;
 
     for (source_path, archive_path) in files_to_package {
-        zip.start_file(&archive_path, options.clone())
-            .map_err(|error| {
-                format!(
-                    "Failed to create archive entry '{}': {}",
-                    archive_path, error
-                )
-            })?;
+        zip.start_file(&archive_path, options).map_err(|error| {
+            AppError::Io(format!(
+                "Failed to create archive entry '{}': {}",
+                archive_path, error
+            ))
+        })?;
         let data = fs::read(&source_path).map_err(|error| {
-            format!(
+            AppError::Io(format!(
                 "Failed to read source audio file '{}': {}",
                 source_path.display(),
                 error
-            )
+            ))
         })?;
         zip.write_all(&data).map_err(|error| {
-            format!(
+            AppError::Io(format!(
                 "Failed to write archive entry '{}': {}",
                 archive_path, error
-            )
+            ))
         })?;
     }
 
     zip.finish()
-        .map_err(|error| format!("Failed to finish zip archive: {}", error))?;
+        .map_err(|error| AppError::Io(format!("Failed to finish zip archive: {}", error)))?;
 
     Ok(output)
 }
 
 pub(crate) fn read_manifest_from_zip<R: Read + Seek>(
     archive: &mut zip::ZipArchive<R>,
-) -> Result<ExportManifest, String> {
+) -> AppResult<ExportManifest> {
     let mut manifest_json = String::new();
     {
-        let mut manifest_file = archive
-            .by_name("manifest.json")
-            .map_err(|error| format!("Manifest not found in zip: {}", error))?;
+        let mut manifest_file = archive.by_name("manifest.json").map_err(|error| {
+            AppError::ManifestInvalid(format!("Manifest not found in zip: {}", error))
+        })?;
         manifest_file
             .read_to_string(&mut manifest_json)
-            .map_err(|error| format!("Failed to read manifest.json: {}", error))?;
+            .map_err(|error| AppError::Io(format!("Failed to read manifest.json: {}", error)))?;
     }
 
-    let manifest: ExportManifest = serde_json::from_str(&manifest_json)
-        .map_err(|error| format!("Failed to parse manifest: {}", error))?;
+    let manifest: ExportManifest = serde_json::from_str(&manifest_json).map_err(|error| {
+        AppError::ManifestInvalid(format!("Failed to parse manifest: {}", error))
+    })?;
     validate_manifest_for_packaging(&manifest)?;
     Ok(manifest)
 }
 
-fn load_manifest_from_folder(source_folder: &Path) -> Result<ExportManifest, String> {
+fn load_manifest_from_folder(source_folder: &Path) -> AppResult<ExportManifest> {
     let manifest_path = source_folder.join("manifest.json");
     if !manifest_path.exists() {
-        return Err(format!(
+        return Err(AppError::NotFound(format!(
             "manifest.json not found in source folder: {}",
             manifest_path.display()
-        ));
+        )));
     }
 
     let manifest_json = fs::read_to_string(&manifest_path).map_err(|error| {
-        format!(
+        AppError::Io(format!(
             "Failed to read manifest.json '{}': {}",
             manifest_path.display(),
             error
-        )
+        ))
     })?;
     let manifest: ExportManifest = serde_json::from_str(&manifest_json)
-        .map_err(|error| format!("Invalid manifest.json: {}", error))?;
+        .map_err(|error| AppError::ManifestInvalid(format!("Invalid manifest.json: {}", error)))?;
     Ok(manifest)
 }
 
-fn validate_manifest_for_packaging(manifest: &ExportManifest) -> Result<(), String> {
+fn validate_manifest_for_packaging(manifest: &ExportManifest) -> AppResult<()> {
     if manifest.format_version != 1 && manifest.format_version != 2 {
-        return Err(format!(
+        return Err(AppError::ManifestInvalid(format!(
             "Unsupported format_version {}. Supported versions: [1, 2]",
             manifest.format_version
-        ));
+        )));
     }
 
     if manifest.soundset.name.trim().is_empty() {
-        return Err("Invalid manifest.soundset.name: value must not be empty".to_string());
+        return Err(AppError::ManifestInvalid(
+            "Invalid manifest.soundset.name: value must not be empty".to_string(),
+        ));
     }
 
     for (index, element) in manifest.elements.iter().enumerate() {
         if element.archive_path.trim().is_empty() {
-            return Err(format!(
+            return Err(AppError::ManifestInvalid(format!(
                 "Invalid manifest.elements[{}].archive_path: value must not be empty",
                 index
-            ));
+            )));
         }
 
         if element.file_name.trim().is_empty() {
-            return Err(format!(
+            return Err(AppError::ManifestInvalid(format!(
                 "Invalid manifest.elements[{}].file_name: value must not be empty",
                 index
-            ));
+            )));
         }
     }
 
@@ -284,19 +330,14 @@ fn sanitize_archive_name(name: &str) -> String {
     }
 }
 
-#[tauri::command]
-pub async fn export_sound_set(
-    app_handle: AppHandle,
+fn build_export_manifest(
+    conn: &Connection,
     sound_set_id: i64,
-    destination_path: String,
-) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
+) -> AppResult<(ExportManifest, Vec<(String, String)>)> {
     // 1. SoundSet
     let soundset = conn
         .query_row(
-            "SELECT name, description FROM sound_sets WHERE id = ?1",
+            "SELECT name, description FROM sound_sets WHERE id = ?1 AND deleted_at IS NULL",
             [&sound_set_id],
             |row| {
                 Ok(ExportSoundSet {
@@ -305,11 +346,14 @@ pub async fn export_sound_set(
                 })
             },
         )
-        .map_err(|e| format!("Failed to find soundset: {}", e))?;
+        .optional()?
+        .ok_or_else(|| AppError::not_found("Sound set", sound_set_id))?;
 
     // 2. Channels
-    let mut stmt = conn.prepare("SELECT id, name, icon, volume, order_index FROM audio_channels WHERE sound_set_id = ?1").map_err(|e| e.to_string())?;
-    let channels_data: Vec<(i64, ExportChannel)> = stmt
+    let mut stmt = conn.prepare(
+        "SELECT id, name, icon, volume, order_index FROM audio_channels WHERE sound_set_id = ?1",
+    )?;
+    let mut channels_data: Vec<(i64, ExportChannel)> = stmt
         .query_map([sound_set_id], |row| {
             Ok((
                 row.get(0)?,
@@ -318,12 +362,21 @@ pub async fn export_sound_set(
                     icon: row.get(2)?,
                     volume: row.get(3)?,
                     order_index: row.get(4)?,
+                    effects: Vec::new(),
                 },
             ))
-        })
-        .map_err(|e| e.to_string())?
+        })?
         .map(|r| r.unwrap())
         .collect();
+    for (id, channel) in &mut channels_data {
+        channel.effects = get_channel_effects(conn, *id)?
+            .into_iter()
+            .map(|effect| ExportEffect {
+                enabled: effect.enabled,
+                effect: effect.effect,
+            })
+            .collect();
+    }
 
     let channel_map: HashMap<i64, String> = channels_data
         .iter()
@@ -332,7 +385,10 @@ pub async fn export_sound_set(
     let export_channels: Vec<ExportChannel> = channels_data.into_iter().map(|(_, c)| c).collect();
 
     // 3. Elements (excluding global oneshots because sound_set_id filters them)
-    let mut stmt = conn.prepare("SELECT id, channel_id, file_path, file_name, channel_type, volume_db FROM audio_elements WHERE sound_set_id = ?1").map_err(|e| e.to_string())?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT e.id, e.channel_id, e.file_path, e.file_name, e.channel_type, e.volume_db, e.notes, e.rating, {} FROM audio_elements e WHERE e.sound_set_id = ?1 AND e.deleted_at IS NULL",
+        tag_names_column(TagTarget::AudioElement, "e.id")
+    ))?;
     let elements_data: Vec<(i64, ExportElement, String)> = stmt
         .query_map([sound_set_id], |row| {
             let id: i64 = row.get(0)?;
@@ -341,6 +397,9 @@ pub async fn export_sound_set(
             let file_name: String = row.get(3)?;
             let channel_type: String = row.get(4)?;
             let volume_db: f64 = row.get(5)?;
+            let notes: String = row.get(6)?;
+            let rating: Option<i64> = row.get(7)?;
+            let tags = split_tag_names(row.get(8)?);
 
             let channel_name = channel_id.and_then(|cid| channel_map.get(&cid).cloned());
             let archive_path = format!("audio/{}", file_name);
@@ -353,11 +412,13 @@ pub async fn export_sound_set(
                     channel_name,
                     channel_type,
                     volume_db,
+                    tags,
+                    notes,
+                    rating,
                 },
                 file_path,
             ))
-        })
-        .map_err(|e| e.to_string())?
+        })?
         .map(|r| r.unwrap())
         .collect();
 
@@ -374,21 +435,35 @@ pub async fn export_sound_set(
     }
 
     // 4. Groups
-    let mut stmt = conn
-        .prepare("SELECT id, name FROM element_groups WHERE sound_set_id = ?1")
-        .map_err(|e| e.to_string())?;
-    let groups_data: Vec<(i64, String)> = stmt
-        .query_map([sound_set_id], |row| Ok((row.get(0)?, row.get(1)?)))
-        .map_err(|e| e.to_string())?
+    let mut stmt = conn.prepare(&format!(
+        "SELECT g.id, g.name, g.notes, g.rating, {}, g.playback_mode, g.pitch_variation_semitones, g.volume_variation_db FROM element_groups g WHERE g.sound_set_id = ?1",
+        tag_names_column(TagTarget::ElementGroup, "g.id")
+    ))?;
+    let groups_data: Vec<(i64, ExportGroup)> = stmt
+        .query_map([sound_set_id], |row| {
+            Ok((
+                row.get(0)?,
+                ExportGroup {
+                    name: row.get(1)?,
+                    members: Vec::new(),
+                    tags: split_tag_names(row.get(4)?),
+                    notes: row.get(2)?,
+                    rating: row.get(3)?,
+                    playback_mode: PlaybackMode::from_name(&row.get::<_, String>(5)?),
+                    pitch_variation_semitones: row.get(6)?,
+                    volume_variation_db: row.get(7)?,
+                },
+            ))
+        })?
         .map(|r| r.unwrap())
         .collect();
 
     let mut export_groups = Vec::new();
 
-    for (group_id, name) in groups_data {
-        let mut member_stmt = conn.prepare("SELECT audio_element_id, order_index FROM element_group_members WHERE group_id = ?1 ORDER BY order_index ASC").map_err(|e| e.to_string())?;
+    for (group_id, mut group) in groups_data {
+        let mut member_stmt = conn.prepare("SELECT m.audio_element_id, m.order_index, m.weight FROM element_group_members m JOIN audio_elements e ON e.id = m.audio_element_id WHERE m.group_id = ?1 AND e.deleted_at IS NULL ORDER BY m.order_index ASC")?;
 
-        let members: Vec<ExportGroupMember> = member_stmt
+        group.members = member_stmt
             .query_map([group_id], |row| {
                 let element_id: i64 = row.get(0)?;
                 let file_name = element_name_map
@@ -398,13 +473,13 @@ pub async fn export_sound_set(
                 Ok(ExportGroupMember {
                     element_file_name: file_name,
                     order_index: row.get(1)?,
+                    weight: row.get(2)?,
                 })
-            })
-            .map_err(|e| e.to_string())?
+            })?
             .map(|r| r.unwrap())
             .collect();
 
-        export_groups.push(ExportGroup { name, members });
+        export_groups.push(group);
     }
 
     let manifest = ExportManifest {
@@ -415,56 +490,239 @@ pub async fn export_sound_set(
         groups: export_groups,
     };
 
+    Ok((manifest, files_to_copy))
+}
+
+#[tauri::command]
+pub async fn export_sound_set(
+    db: State<'_, Database>,
+    sound_set_id: i64,
+    destination_path: String,
+) -> AppResult<()> {
+    // Release the shared connection before copying audio into the archive
+    let (manifest, files_to_copy) = {
+        let conn = db.connection()?;
+        build_export_manifest(&conn, sound_set_id)?
+    };
+
     // 5. Create ZIP archive
-    let file =
-        File::create(&destination_path).map_err(|e| format!("Failed to create zip file: {}", e))?;
+    let file = File::create(&destination_path)
+        .map_err(|e| AppError::Io(format!("Failed to create zip file: {}", e)))?;
     let mut zip = ZipWriter::new(file);
     let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
 
     // Write manifest
-    zip.start_file("manifest.json", options.clone())
-        .map_err(|e| format!("Failed to create manifest in zip: {}", e))?;
-    let manifest_json = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
-    zip.write_all(manifest_json.as_bytes())
-        .map_err(|e| e.to_string())?;
+    zip.start_file("manifest.json", options)
+        .map_err(|e| AppError::Io(format!("Failed to create manifest in zip: {}", e)))?;
+    let manifest_json =
+        serde_json::to_string_pretty(&manifest).map_err(|e| AppError::Internal(e.to_string()))?;
+    zip.write_all(manifest_json.as_bytes())?;
 
     // Create audio dir implicitly or by adding files
     for (src_path, archive_path) in files_to_copy {
-        zip.start_file(&archive_path, options.clone())
-            .map_err(|e| format!("Failed to start file in zip: {}", e))?;
+        zip.start_file(&archive_path, options)
+            .map_err(|e| AppError::Io(format!("Failed to start file in zip: {}", e)))?;
         if let Ok(mut src_file) = File::open(&src_path) {
             let mut buffer = Vec::new();
             src_file
                 .read_to_end(&mut buffer)
-                .map_err(|e| format!("Failed to read source file: {}", e))?;
+                .map_err(|e| AppError::Io(format!("Failed to read source file: {}", e)))?;
             zip.write_all(&buffer)
-                .map_err(|e| format!("Failed to write file to zip: {}", e))?;
+                .map_err(|e| AppError::Io(format!("Failed to write file to zip: {}", e)))?;
         }
     }
 
     zip.finish()
-        .map_err(|e| format!("Failed to finish zip: {}", e))?;
+        .map_err(|e| AppError::Io(format!("Failed to finish zip: {}", e)))?;
 
     Ok(())
 }
 
 #[tauri::command]
-pub async fn import_sound_set(app_handle: AppHandle, source_path: String) -> Result<(), String> {
-    let db_path = crate::get_db_path(&app_handle);
-    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+pub async fn import_sound_set(
+    app_handle: AppHandle,
+    db: State<'_, Database>,
+    source_path: String,
+) -> AppResult<()> {
+    let settings = crate::read_app_settings(&app_handle);
+    let library_dir = if settings.library_path.trim().is_empty() {
+        crate::get_default_library_path(&app_handle)
+    } else {
+        PathBuf::from(&settings.library_path)
+    };
 
-    let file = File::open(&source_path).map_err(|e| format!("Failed to open zip file: {}", e))?;
-    let mut archive =
-        zip::ZipArchive::new(file).map_err(|e| format!("Failed to read zip archive: {}", e))?;
+    let imported_element_ids =
+        import_sound_set_archive(&db, Path::new(&source_path), &library_dir)?;
+
+    for element_id in imported_element_ids {
+        crate::warm_waveform(&app_handle, &db, element_id);
+    }
+
+    Ok(())
+}
+
+/// Imports the archive at `source_path`, extracting its audio into
+/// `library_dir`, and returns the new element ids.
+///
+/// The manifest is checked in full before anything is extracted, and the
+/// extracted files are removed again if probing them or writing the database
+/// fails, so a rejected archive leaves the library as it was.
+pub(crate) fn import_sound_set_archive(
+    db: &Database,
+    source_path: &Path,
+    library_dir: &Path,
+) -> AppResult<Vec<i64>> {
+    let file = File::open(source_path)
+        .map_err(|e| AppError::Io(format!("Failed to open zip file: {}", e)))?;
+    let mut archive = zip::ZipArchive::new(file)
+        .map_err(|e| AppError::Io(format!("Failed to read zip archive: {}", e)))?;
 
     let manifest = read_manifest_from_zip(&mut archive)?;
+    validate_import(&manifest, &archive)?;
+
+    if !library_dir.exists() {
+        std::fs::create_dir_all(library_dir)?;
+    }
+
+    // Extracting and probing decodes every file, so it happens before the
+    // database is locked.
+    let mut extracted_files = ExtractedFiles::default();
+    let mut extracted = Vec::with_capacity(manifest.elements.len());
+    for element in &manifest.elements {
+        let mut zipped_file = archive.by_name(&element.archive_path).map_err(|_| {
+            AppError::ManifestInvalid(format!(
+                "Audio file not found in archive: {}",
+                element.archive_path
+            ))
+        })?;
+
+        let dest_path = library_dir.join(&element.file_name);
+        let mut actual_dest = dest_path.clone();
+        let mut f_suffix = 1;
+
+        while actual_dest.exists() {
+            let file_stem = dest_path.file_stem().unwrap_or_default().to_string_lossy();
+            let extension = dest_path.extension().unwrap_or_default().to_string_lossy();
+            actual_dest = library_dir.join(format!("{}-{}.{}", file_stem, f_suffix, extension));
+            f_suffix += 1;
+        }
 
-    let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let mut out_file = File::create(&actual_dest)?;
+        extracted_files.0.push(actual_dest.clone());
+        std::io::copy(&mut zipped_file, &mut out_file)?;
 
+        let info = probe_file(&actual_dest)?;
+        extracted.push((actual_dest.to_string_lossy().to_string(), info));
+    }
+
+    let imported_element_ids = {
+        let conn = db.connection()?;
+        let scopes = import_scopes(&conn)?;
+        history::record(&conn, "Import sound set", &scopes, |conn| {
+            insert_imported_sound_set(conn, manifest, extracted)
+        })?
+    };
+    extracted_files.keep();
+
+    Ok(imported_element_ids)
+}
+
+/// Files an import extracted into the library, deleted again on drop unless
+/// the import went through.
+#[derive(Default)]
+struct ExtractedFiles(Vec<PathBuf>);
+
+impl ExtractedFiles {
+    fn keep(mut self) {
+        self.0.clear();
+    }
+}
+
+impl Drop for ExtractedFiles {
+    fn drop(&mut self) {
+        for path in &self.0 {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Rejects a manifest the database would refuse, or that names audio the
+/// archive lacks, before any file is extracted.
+fn validate_import<R: Read + Seek>(
+    manifest: &ExportManifest,
+    archive: &zip::ZipArchive<R>,
+) -> AppResult<()> {
+    for element in &manifest.elements {
+        if archive.index_for_name(&element.archive_path).is_none() {
+            return Err(AppError::ManifestInvalid(format!(
+                "Audio file not found in archive: {}",
+                element.archive_path
+            )));
+        }
+        validate_rating(element.rating)?;
+    }
+    for group in &manifest.groups {
+        validate_rating(group.rating)?;
+        validate_variation(group.pitch_variation_semitones, group.volume_variation_db)?;
+    }
+
+    Ok(())
+}
+
+/// Every row an import can create, for journaling it: rows get ids past the
+/// current ones, and tag links belong to new elements and groups.
+fn import_scopes(conn: &Connection) -> AppResult<Vec<Scope>> {
+    let first_element_id = history::next_id(conn, "audio_elements")?;
+    let first_group_id = history::next_id(conn, "element_groups")?;
+
+    Ok(vec![
+        Scope::new(
+            "sound_sets",
+            "id >= ?1",
+            history::next_id(conn, "sound_sets")?,
+        ),
+        Scope::new("tags", "id >= ?1", history::next_id(conn, "tags")?),
+        Scope::new(
+            "audio_channels",
+            "id >= ?1",
+            history::next_id(conn, "audio_channels")?,
+        ),
+        Scope::new(
+            "channel_effects",
+            "id >= ?1",
+            history::next_id(conn, "channel_effects")?,
+        ),
+        Scope::new("audio_elements", "id >= ?1", first_element_id),
+        Scope::new(
+            "audio_element_tags",
+            "audio_element_id >= ?1",
+            first_element_id,
+        ),
+        Scope::new("element_groups", "id >= ?1", first_group_id),
+        Scope::new(
+            "element_group_tags",
+            "element_group_id >= ?1",
+            first_group_id,
+        ),
+        Scope::new(
+            "element_group_members",
+            "id >= ?1",
+            history::next_id(conn, "element_group_members")?,
+        ),
+    ])
+}
+
+/// Inserts the manifest's sound set with the already extracted and probed
+/// files, in order of `manifest.elements`, and returns the new element ids.
+fn insert_imported_sound_set(
+    conn: &Connection,
+    manifest: ExportManifest,
+    extracted: Vec<(String, AudioInfo)>,
+) -> AppResult<Vec<i64>> {
     let mut actual_name = manifest.soundset.name.clone();
     let mut suffix = 1;
     loop {
-        let count: i64 = tx
+        let count: i64 = conn
             .query_row(
                 "SELECT count(*) FROM sound_sets WHERE name = ?1",
                 [&actual_name],
@@ -479,112 +737,92 @@ pub async fn import_sound_set(app_handle: AppHandle, source_path: String) -> Res
         suffix += 1;
     }
 
-    tx.execute(
+    conn.execute(
         "INSERT INTO sound_sets (name, description) VALUES (?1, ?2)",
         [&actual_name, &manifest.soundset.description],
-    )
-    .map_err(|e| e.to_string())?;
+    )?;
 
-    let sound_set_id = tx.last_insert_rowid();
+    let sound_set_id = conn.last_insert_rowid();
 
     let mut channel_id_map: HashMap<String, i64> = HashMap::new();
     for channel in manifest.channels {
-        tx.execute(
+        conn.execute(
             "INSERT INTO audio_channels (sound_set_id, name, icon, volume, order_index) VALUES (?1, ?2, ?3, ?4, ?5)",
             (sound_set_id, &channel.name, &channel.icon, channel.volume, channel.order_index),
-        ).map_err(|e| e.to_string())?;
-        channel_id_map.insert(channel.name.clone(), tx.last_insert_rowid());
-    }
-
-    let settings = crate::read_app_settings(&app_handle);
-    let library_dir = if settings.library_path.trim().is_empty() {
-        crate::get_default_library_path(&app_handle)
-    } else {
-        PathBuf::from(&settings.library_path)
-    };
-
-    if !library_dir.exists() {
-        std::fs::create_dir_all(&library_dir).map_err(|e| e.to_string())?;
+        )?;
+        let channel_id = conn.last_insert_rowid();
+        for effect in &channel.effects {
+            append_channel_effect(conn, channel_id, effect.enabled, &effect.effect)?;
+        }
+        channel_id_map.insert(channel.name.clone(), channel_id);
     }
 
     let mut element_id_map: HashMap<String, i64> = HashMap::new();
+    let mut imported_element_ids = Vec::new();
 
-    for element in manifest.elements {
-        let final_file_path = if let Ok(mut zipped_file) = archive.by_name(&element.archive_path) {
-            let dest_path = library_dir.join(&element.file_name);
-            let mut actual_dest = dest_path.clone();
-            let mut f_suffix = 1;
-
-            while actual_dest.exists() {
-                let file_stem = dest_path.file_stem().unwrap_or_default().to_string_lossy();
-                let extension = dest_path.extension().unwrap_or_default().to_string_lossy();
-                actual_dest = library_dir.join(format!("{}-{}.{}", file_stem, f_suffix, extension));
-                f_suffix += 1;
-            }
-
-            let mut out_file = File::create(&actual_dest).map_err(|e| e.to_string())?;
-            std::io::copy(&mut zipped_file, &mut out_file).map_err(|e| e.to_string())?;
-            actual_dest.to_string_lossy().to_string()
-        } else {
-            return Err(format!(
-                "Audio file not found in archive: {}",
-                element.archive_path
-            ));
-        };
-
+    for (element, (final_file_path, info)) in manifest.elements.into_iter().zip(extracted) {
         let channel_id = element
             .channel_name
             .and_then(|name| channel_id_map.get(&name).copied());
 
-        tx.execute(
-            "INSERT INTO audio_elements (sound_set_id, channel_id, file_path, file_name, channel_type, volume_db) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            (sound_set_id, channel_id, &final_file_path, &element.file_name, &element.channel_type, element.volume_db),
-        ).map_err(|e| e.to_string())?;
+        conn.execute(
+            "INSERT INTO audio_elements (sound_set_id, channel_id, file_path, file_name, channel_type, volume_db, notes, rating) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            (sound_set_id, channel_id, &final_file_path, &element.file_name, &element.channel_type, element.volume_db, &element.notes, element.rating),
+        )?;
+        let element_id = conn.last_insert_rowid();
+        update_audio_element_info(conn, element_id, &info)?;
+        add_tags(conn, TagTarget::AudioElement, element_id, &element.tags)?;
 
-        element_id_map.insert(element.file_name.clone(), tx.last_insert_rowid());
+        element_id_map.insert(element.file_name.clone(), element_id);
+        imported_element_ids.push(element_id);
     }
 
     for group in manifest.groups {
-        tx.execute(
-            "INSERT INTO element_groups (sound_set_id, name) VALUES (?1, ?2)",
-            (sound_set_id, &group.name),
-        )
-        .map_err(|e| e.to_string())?;
-        let group_id = tx.last_insert_rowid();
+        conn.execute(
+            "INSERT INTO element_groups (sound_set_id, name, notes, rating, playback_mode, pitch_variation_semitones, volume_variation_db) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params![
+                sound_set_id,
+                group.name,
+                group.notes,
+                group.rating,
+                group.playback_mode.as_str(),
+                group.pitch_variation_semitones,
+                group.volume_variation_db
+            ],
+        )?;
+        let group_id = conn.last_insert_rowid();
+        add_tags(conn, TagTarget::ElementGroup, group_id, &group.tags)?;
 
         for member in group.members {
             if let Some(&audio_element_id) = element_id_map.get(&member.element_file_name) {
-                tx.execute(
-                    "INSERT INTO element_group_members (group_id, audio_element_id, order_index) VALUES (?1, ?2, ?3)",
-                    (group_id, audio_element_id, member.order_index),
-                ).map_err(|e| e.to_string())?;
+                conn.execute(
+                    "INSERT INTO element_group_members (group_id, audio_element_id, order_index, weight) VALUES (?1, ?2, ?3, ?4)",
+                    (group_id, audio_element_id, member.order_index, member.weight.max(0.0)),
+                )?;
             }
         }
     }
 
-    tx.commit().map_err(|e| e.to_string())?;
-
-    Ok(())
+    Ok(imported_element_ids)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{package_sound_set_folder, read_manifest_from_zip, ExportManifest};
+    use super::{
+        build_export_manifest, import_scopes, import_sound_set_archive, insert_imported_sound_set,
+        package_sound_set_folder, read_manifest_from_zip, ExportManifest,
+    };
+    use crate::audio::probe::AudioInfo;
+    use crate::audio::test_dir;
+    use crate::store::effects::{append_channel_effect, Effect};
+    use crate::store::group_playback::PlaybackMode;
+    use crate::store::history;
+    use crate::store::tags::{tag_items, TagTarget};
+    use crate::store::test_connection;
+    use crate::{AppError, Database};
     use std::fs;
     use std::fs::File;
     use std::path::{Path, PathBuf};
-    use std::time::{SystemTime, UNIX_EPOCH};
-
-    fn test_dir(name: &str) -> PathBuf {
-        let mut path = std::env::temp_dir();
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("clock should be valid")
-            .as_nanos();
-        path.push(format!("immersive-scene-{}-{}", name, timestamp));
-        fs::create_dir_all(&path).expect("should create test directory");
-        path
-    }
 
     fn write_valid_package_folder(base: &Path) {
         fs::create_dir_all(base.join("audio")).expect("should create audio directory");
@@ -658,7 +896,7 @@ mod tests {
 
         let error = package_sound_set_folder(&source, Some(&output))
             .expect_err("packaging should fail without manifest");
-        assert!(error.contains("manifest.json not found"));
+        assert!(error.to_string().contains("manifest.json not found"));
         assert!(!output.exists());
     }
 
@@ -690,7 +928,7 @@ mod tests {
         let output = test_dir("unsupported-version-output").join("out.zip");
         let error = package_sound_set_folder(&source, Some(&output))
             .expect_err("packaging should fail with unsupported version");
-        assert!(error.contains("Unsupported format_version"));
+        assert!(error.to_string().contains("Unsupported format_version"));
         assert!(!output.exists());
     }
 
@@ -720,8 +958,8 @@ mod tests {
         let output = test_dir("missing-file-output").join("out.zip");
         let error = package_sound_set_folder(&source, Some(&output))
             .expect_err("packaging should fail with missing referenced file");
-        assert!(error.contains("Missing referenced audio file"));
-        assert!(error.contains("audio/rain.wav"));
+        assert!(error.to_string().contains("Missing referenced audio file"));
+        assert!(error.to_string().contains("audio/rain.wav"));
         assert!(!output.exists());
     }
 
@@ -751,8 +989,10 @@ mod tests {
         let output = test_dir("unsafe-path-output").join("out.zip");
         let error = package_sound_set_folder(&source, Some(&output))
             .expect_err("packaging should fail with unsafe archive path");
-        assert!(error.contains("Invalid elements[].archive_path"));
-        assert!(error.contains(".."));
+        assert!(error
+            .to_string()
+            .contains("Invalid elements[].archive_path"));
+        assert!(error.to_string().contains(".."));
         assert!(!output.exists());
     }
 
@@ -798,4 +1038,199 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn export_carries_group_and_element_metadata() {
+        let conn = test_connection();
+        conn.execute_batch(
+            "INSERT INTO sound_sets (id, name, description) VALUES (1, 'Forest Set', '');
+             INSERT INTO audio_channels (id, sound_set_id, name, icon, volume, order_index)
+                 VALUES (5, 1, 'Ambience', 'ambient', 0.8, 0);
+             INSERT INTO audio_elements (id, sound_set_id, file_path, file_name, notes, rating)
+                 VALUES (10, 1, '/audio/rain.wav', 'rain.wav', 'Loops cleanly', 5);
+             INSERT INTO element_groups (id, sound_set_id, name, notes, playback_mode, pitch_variation_semitones)
+                 VALUES (20, 1, 'Birds', 'Dawn only', 'weighted', 1.5);
+             INSERT INTO element_group_members (group_id, audio_element_id, order_index, weight) VALUES (20, 10, 0, 2.5);",
+        )
+        .unwrap();
+        tag_items(
+            &conn,
+            TagTarget::AudioElement,
+            &[10],
+            &["weather".into(), "Calm".into()],
+        )
+        .unwrap();
+        tag_items(&conn, TagTarget::ElementGroup, &[20], &["dawn".into()]).unwrap();
+        let muffle = Effect::LowPass {
+            frequency_hz: 900.0,
+            q: 0.707,
+        };
+        let echo = Effect::Delay {
+            time_ms: 250.0,
+            feedback: 0.3,
+            mix: 0.2,
+        };
+        append_channel_effect(&conn, 5, true, &muffle).unwrap();
+        append_channel_effect(&conn, 5, false, &echo).unwrap();
+
+        let (manifest, _) = build_export_manifest(&conn, 1).unwrap();
+        let json = serde_json::to_string(&manifest).unwrap();
+        let parsed: ExportManifest = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed.elements[0].tags, ["Calm", "weather"]);
+        assert_eq!(parsed.elements[0].notes, "Loops cleanly");
+        assert_eq!(parsed.elements[0].rating, Some(5));
+        assert_eq!(parsed.groups[0].tags, ["dawn"]);
+        assert_eq!(parsed.groups[0].notes, "Dawn only");
+        assert_eq!(parsed.groups[0].rating, None);
+        assert_eq!(parsed.groups[0].playback_mode, PlaybackMode::Weighted);
+        assert_eq!(parsed.groups[0].pitch_variation_semitones, 1.5);
+        assert_eq!(parsed.groups[0].members[0].weight, 2.5);
+        let effects = &parsed.channels[0].effects;
+        assert_eq!(effects.len(), 2);
+        assert!(effects[0].enabled && effects[0].effect == muffle);
+        assert!(!effects[1].enabled && effects[1].effect == echo);
+    }
+
+    /// Packages the valid folder with `edit` applied to its manifest and
+    /// imports it into a fresh database, extracting into `library`.
+    fn import_edited_package(
+        name: &str,
+        library: &Path,
+        edit: impl Fn(String) -> String,
+    ) -> AppError {
+        let source = test_dir(&format!("{}-source", name));
+        write_valid_package_folder(&source);
+        let manifest_path = source.join("manifest.json");
+        let manifest = fs::read_to_string(&manifest_path).expect("should read manifest");
+        fs::write(&manifest_path, edit(manifest)).expect("should write manifest");
+        let archive = package_sound_set_folder(
+            &source,
+            Some(&test_dir(&format!("{}-output", name)).join("set.zip")),
+        )
+        .expect("packaging should succeed");
+
+        let db =
+            Database::from_connection(rusqlite::Connection::open_in_memory().unwrap()).unwrap();
+        let error = import_sound_set_archive(&db, &archive, library)
+            .expect_err("import should be rejected");
+        let sound_sets: i64 = db
+            .connection()
+            .unwrap()
+            .query_row("SELECT count(*) FROM sound_sets", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(sound_sets, 0);
+        error
+    }
+
+    fn library_files(library: &Path) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = fs::read_dir(library)
+            .expect("library should exist")
+            .map(|entry| entry.expect("entry should be readable").path())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn invalid_ratings_are_rejected_before_extracting() {
+        let library = test_dir("import-invalid-rating-library");
+        fs::write(library.join("rain.wav"), b"existing").expect("should write library file");
+        let before = library_files(&library);
+
+        let error = import_edited_package("import-invalid-rating", &library, |manifest| {
+            manifest.replacen(
+                "\"volume_db\": -1.0",
+                "\"volume_db\": -1.0, \"rating\": 9",
+                1,
+            )
+        });
+
+        assert_eq!(error.code(), "ValidationFailed");
+        assert!(error.to_string().contains("Rating"));
+        assert_eq!(library_files(&library), before);
+    }
+
+    #[test]
+    fn failed_imports_remove_the_files_they_extracted() {
+        let library = test_dir("import-unplayable-library");
+
+        // The packaged files are not audio, so probing the first one fails.
+        let error = import_edited_package("import-unplayable", &library, |manifest| manifest);
+
+        assert!(error.to_string().contains("not a readable audio file"));
+        assert!(library_files(&library).is_empty());
+    }
+
+    #[test]
+    fn undoing_an_import_removes_everything_it_added() {
+        let conn = test_connection();
+        conn.execute_batch(
+            "INSERT INTO sound_sets (id, name, description) VALUES (1, 'Forest Set', '');
+             INSERT INTO audio_channels (id, sound_set_id, name, icon, volume, order_index)
+                 VALUES (5, 1, 'Ambience', 'ambient', 0.8, 0);
+             INSERT INTO audio_elements (id, sound_set_id, channel_id, file_path, file_name)
+                 VALUES (10, 1, 5, '/audio/rain.wav', 'rain.wav');
+             INSERT INTO element_groups (id, sound_set_id, name) VALUES (20, 1, 'Birds');
+             INSERT INTO element_group_members (group_id, audio_element_id, order_index) VALUES (20, 10, 0);",
+        )
+        .unwrap();
+        tag_items(&conn, TagTarget::AudioElement, &[10], &["weather".into()]).unwrap();
+        tag_items(&conn, TagTarget::ElementGroup, &[20], &["dawn".into()]).unwrap();
+        append_channel_effect(
+            &conn,
+            5,
+            true,
+            &Effect::LowPass {
+                frequency_hz: 900.0,
+                q: 0.707,
+            },
+        )
+        .unwrap();
+        let (mut manifest, _) = build_export_manifest(&conn, 1).unwrap();
+        manifest.elements[0].tags.push("imported".into());
+
+        let extracted = vec![(
+            "/library/rain.wav".to_string(),
+            AudioInfo {
+                duration_ms: 1000,
+                sample_rate: 48_000,
+                channel_count: 2,
+                codec: "pcm_s16le".into(),
+                file_size_bytes: 4,
+            },
+        )];
+        let scopes = import_scopes(&conn).unwrap();
+        history::record(&conn, "Import sound set", &scopes, |conn| {
+            insert_imported_sound_set(conn, manifest, extracted)
+        })
+        .unwrap();
+
+        let totals = || {
+            [
+                "sound_sets",
+                "audio_channels",
+                "channel_effects",
+                "audio_elements",
+                "element_groups",
+                "element_group_members",
+                "tags",
+                "audio_element_tags",
+                "element_group_tags",
+            ]
+            .map(|table| {
+                conn.query_row(&format!("SELECT count(*) FROM {}", table), [], |row| {
+                    row.get::<_, i64>(0)
+                })
+                .unwrap()
+            })
+        };
+        assert_eq!(totals(), [2, 2, 2, 2, 2, 2, 3, 3, 2]);
+
+        history::undo(&conn).unwrap().unwrap();
+        assert_eq!(totals(), [1, 1, 1, 1, 1, 1, 2, 1, 1]);
+
+        history::redo(&conn).unwrap().unwrap();
+        assert_eq!(totals(), [2, 2, 2, 2, 2, 2, 3, 3, 2]);
+    }
 }
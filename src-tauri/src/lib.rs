@@ -1,23 +1,51 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
-use rusqlite::{Connection, Result as SqliteResult};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tauri::AppHandle;
 use tauri::Manager;
+use tauri::State;
 
+pub mod audio;
+pub mod database;
+pub mod error;
 pub mod import_export;
+pub use database::Database;
+pub use error::{AppError, AppResult};
 pub use import_export::*;
 pub mod discord;
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SoundSet {
-    pub id: i64,
-    pub name: String,
-    pub description: String,
-    pub is_enabled: bool,
-    pub created_at: String,
-}
+mod migrations;
+pub mod store;
+pub use audio::engine::{EngineStatus, PlaybackEngine};
+pub use audio::loops::LoopPoints;
+pub use audio::loudness::LoudnessReport;
+pub use audio::probe::AudioInfo;
+pub use audio::render::{RenderFormat, RenderSummary};
+use audio::sink::AudioSink;
+pub use audio::waveform::WaveformPeaks;
+use store::history::{self, Scope};
+pub use store::{
+    audio_channels::{AudioChannel, ChannelCompressor},
+    audio_elements::AudioElement,
+    automation::{AutomationCurve, GainPoint},
+    dynamics::{DuckRule, MasterLimiter},
+    effects::{ChannelEffect, Effect},
+    element_groups::{ElementGroup, ElementGroupMember},
+    group_playback::{GroupPick, PlaybackMode},
+    markers::{TimelineMarker, TransportJump},
+    moods::Mood,
+    search::{SearchHit, SearchHitKind},
+    snapshots::{ChannelToggles, MixerSnapshot, SnapshotChannel, SnapshotSoundSet},
+    sound_sets::SoundSet,
+    tags::{Tag, TagTarget},
+    tempo::{MusicalPosition, SnapMode, TempoChange},
+    timeline_edits::ElementEdit,
+    timeline_templates::TimelineTemplate,
+    timelines::{FadeCurve, Timeline, TimelineElement, TimelineTrack},
+    transitions::{MoodTransition, TrackFadeOut, TransitionPlan, TransitionSync},
+    trash::{TrashItem, TrashKind},
+};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AppSettings {
@@ -31,6 +59,12 @@ pub struct AppSettings {
     pub discord_guild_id: String,
     #[serde(default)]
     pub discord_channel_id: String,
+    #[serde(default = "default_trash_retention_days")]
+    pub trash_retention_days: u32,
+}
+
+fn default_trash_retention_days() -> u32 {
+    store::trash::DEFAULT_RETENTION_DAYS
 }
 
 pub(crate) fn get_settings_path(app_handle: &AppHandle) -> PathBuf {
@@ -68,100 +102,72 @@ pub(crate) fn read_app_settings(app_handle: &AppHandle) -> AppSettings {
         discord_bot_token: "".to_string(),
         discord_guild_id: "".to_string(),
         discord_channel_id: "".to_string(),
+        trash_retention_days: default_trash_retention_days(),
     }
 }
 
 #[tauri::command]
-async fn get_app_settings(app_handle: AppHandle) -> Result<AppSettings, String> {
+async fn get_app_settings(app_handle: AppHandle) -> AppResult<AppSettings> {
     Ok(read_app_settings(&app_handle))
 }
 
 #[tauri::command]
-async fn update_app_settings(app_handle: AppHandle, settings: AppSettings) -> Result<(), String> {
+async fn update_app_settings(app_handle: AppHandle, settings: AppSettings) -> AppResult<()> {
     let settings_path = get_settings_path(&app_handle);
 
-    let json = serde_json::to_string_pretty(&settings).map_err(|e| e.to_string())?;
-    fs::write(settings_path, json).map_err(|e| e.to_string())?;
+    let json =
+        serde_json::to_string_pretty(&settings).map_err(|e| AppError::Internal(e.to_string()))?;
+    fs::write(settings_path, json)?;
 
     Ok(())
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Mood {
-    pub id: i64,
-    pub name: String,
-    pub description: String,
-    pub created_at: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct AudioChannel {
-    pub id: i64,
-    pub sound_set_id: i64,
-    pub name: String,
-    pub icon: String,
-    pub volume: f64,
-    pub order_index: i64,
-    pub created_at: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct AudioElement {
-    pub id: i64,
-    pub sound_set_id: Option<i64>,
-    pub channel_id: Option<i64>,
-    pub file_path: String,
-    pub file_name: String,
-    pub channel_type: String,
-    pub volume_db: f64,
-    pub created_at: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Timeline {
-    pub id: i64,
-    pub mood_id: i64,
-    pub name: String,
-    pub order_index: i64,
-    pub is_looping: bool,
-    pub created_at: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct TimelineTrack {
-    pub id: i64,
-    pub timeline_id: i64,
-    pub name: String,
-    pub order_index: i64,
-    pub is_looping: bool,
-    pub created_at: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ElementGroup {
-    pub id: i64,
-    pub name: String,
-    pub sound_set_id: Option<i64>,
-    pub created_at: String,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ElementGroupMember {
-    pub id: i64,
-    pub group_id: i64,
-    pub audio_element_id: i64,
-    pub order_index: i64,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct TimelineElement {
-    pub id: i64,
-    pub track_id: i64,
-    pub audio_element_id: Option<i64>,
-    pub element_group_id: Option<i64>,
-    pub start_time_ms: i64,
-    pub duration_ms: i64,
-    pub is_available: bool,
+fn get_waveform_cache_dir(app_handle: &AppHandle) -> PathBuf {
+    let cache_dir = app_handle.path().app_cache_dir().unwrap();
+    cache_dir.join("waveforms")
+}
+
+/// Loads an element's waveform peaks, hashing its file first if that has not
+/// happened yet or the file changed since, and building the peaks on a cache
+/// miss. The database is only
+/// locked to read the element and to store its hash, never while the file is
+/// hashed or decoded.
+pub(crate) fn load_waveform(
+    app_handle: &AppHandle,
+    db: &Database,
+    element_id: i64,
+) -> AppResult<audio::waveform::PeakCache> {
+    let (file_path, hashed) = {
+        let conn = db.connection()?;
+        store::audio_elements::get_audio_element_source(&conn, element_id)?
+    };
+    let stamp = audio::waveform::file_stamp(Path::new(&file_path))?;
+    let content_hash = match hashed {
+        Some((content_hash, hashed_at)) if hashed_at == stamp => content_hash,
+        _ => {
+            let content_hash = audio::waveform::hash_file(Path::new(&file_path))?;
+            let conn = db.connection()?;
+            store::audio_elements::set_audio_element_content_hash(
+                &conn,
+                element_id,
+                &content_hash,
+                stamp,
+            )?;
+            content_hash
+        }
+    };
+
+    audio::waveform::load_or_build(
+        &get_waveform_cache_dir(app_handle),
+        &content_hash,
+        Path::new(&file_path),
+    )
+}
+
+/// Builds a new element's peaks ahead of the first request. Peaks are only a
+/// cache; if building them fails, `get_waveform_peaks` reports why.
+pub(crate) fn warm_waveform(app_handle: &AppHandle, db: &Database, element_id: i64) {
+    let _ = load_waveform(app_handle, db, element_id);
 }
 
 fn get_db_path(app_handle: &AppHandle) -> PathBuf {
@@ -170,1594 +176,1982 @@ fn get_db_path(app_handle: &AppHandle) -> PathBuf {
     app_dir.join("immersive_scene.db")
 }
 
-fn init_database(conn: &Connection) -> SqliteResult<()> {
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS sound_sets (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT NOT NULL,
-            description TEXT,
-            is_enabled INTEGER DEFAULT 1,
-            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS moods (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT NOT NULL,
-            description TEXT,
-            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS audio_channels (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            sound_set_id INTEGER NOT NULL,
-            name TEXT NOT NULL,
-            icon TEXT NOT NULL DEFAULT 'generic',
-            volume REAL NOT NULL DEFAULT 1.0,
-            order_index INTEGER NOT NULL DEFAULT 0,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (sound_set_id) REFERENCES sound_sets(id) ON DELETE CASCADE
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS audio_elements (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            sound_set_id INTEGER,
-            channel_id INTEGER,
-            file_path TEXT NOT NULL,
-            file_name TEXT NOT NULL,
-            channel_type TEXT DEFAULT 'ambient',
-            volume_db REAL DEFAULT 0.0,
-            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (sound_set_id) REFERENCES sound_sets(id) ON DELETE CASCADE,
-            FOREIGN KEY (channel_id) REFERENCES audio_channels(id) ON DELETE SET NULL
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS timelines (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            mood_id INTEGER NOT NULL,
-            name TEXT NOT NULL,
-            order_index INTEGER DEFAULT 0,
-            is_looping INTEGER DEFAULT 0,
-            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (mood_id) REFERENCES moods(id) ON DELETE CASCADE
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS timeline_tracks (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            timeline_id INTEGER NOT NULL,
-            name TEXT NOT NULL,
-            order_index INTEGER DEFAULT 0,
-            is_looping INTEGER DEFAULT 0,
-            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (timeline_id) REFERENCES timelines(id) ON DELETE CASCADE
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS element_groups (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            name TEXT NOT NULL,
-            sound_set_id INTEGER,
-            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (sound_set_id) REFERENCES sound_sets(id) ON DELETE CASCADE
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS element_group_members (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            group_id INTEGER NOT NULL,
-            audio_element_id INTEGER NOT NULL,
-            order_index INTEGER DEFAULT 0,
-            FOREIGN KEY (group_id) REFERENCES element_groups(id) ON DELETE CASCADE,
-            FOREIGN KEY (audio_element_id) REFERENCES audio_elements(id) ON DELETE CASCADE
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS timeline_elements (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            timeline_id INTEGER,
-            track_id INTEGER,
-            audio_element_id INTEGER,
-            element_group_id INTEGER,
-            start_time_ms INTEGER DEFAULT 0,
-            duration_ms INTEGER DEFAULT 0,
-            FOREIGN KEY (timeline_id) REFERENCES timelines(id) ON DELETE CASCADE,
-            FOREIGN KEY (track_id) REFERENCES timeline_tracks(id) ON DELETE CASCADE,
-            FOREIGN KEY (audio_element_id) REFERENCES audio_elements(id) ON DELETE CASCADE,
-            FOREIGN KEY (element_group_id) REFERENCES element_groups(id) ON DELETE CASCADE
-        )",
-        [],
-    )?;
-
-    // Migration
-    let mut stmt = conn.prepare("PRAGMA table_info(audio_elements)")?;
-    let has_mood_id = stmt
-        .query_map([], |row| {
-            let name: String = row.get(1)?;
-            Ok(name == "mood_id")
-        })?
-        .any(|r| r.unwrap_or(false));
-
-    let mut stmt2 = conn.prepare("PRAGMA table_info(audio_elements)")?;
-    let has_sound_set_id = stmt2
-        .query_map([], |row| {
-            let name: String = row.get(1)?;
-            Ok(name == "sound_set_id")
-        })?
-        .any(|r| r.unwrap_or(false));
-
-    if has_mood_id && !has_sound_set_id {
-        conn.execute(
-            "ALTER TABLE audio_elements ADD COLUMN sound_set_id INTEGER REFERENCES sound_sets(id)",
-            [],
-        )?;
-        conn.execute("UPDATE audio_elements SET sound_set_id = (SELECT sound_set_id FROM moods WHERE moods.id = audio_elements.mood_id)", [])?;
+/// Copies the file into the library when the "copy" strategy is active and
+/// returns the path the element should reference.
+fn resolve_audio_file_path(
+    app_handle: &AppHandle,
+    file_path: String,
+    file_name: &str,
+) -> AppResult<String> {
+    let settings = read_app_settings(app_handle);
+    if settings.audio_file_strategy != "copy" {
+        return Ok(file_path);
     }
 
-    // SoundSets migration for is_enabled
-    let mut stmt_ss = conn.prepare("PRAGMA table_info(sound_sets)")?;
-    let has_is_enabled = stmt_ss
-        .query_map([], |row| {
-            let name: String = row.get(1)?;
-            Ok(name == "is_enabled")
-        })?
-        .any(|r| r.unwrap_or(false));
-
-    if !has_is_enabled {
-        conn.execute(
-            "ALTER TABLE sound_sets ADD COLUMN is_enabled INTEGER DEFAULT 1",
-            [],
-        )?;
+    let library_dir = if settings.library_path.trim().is_empty() {
+        get_default_library_path(app_handle)
+    } else {
+        PathBuf::from(&settings.library_path)
+    };
+    if !library_dir.exists() {
+        fs::create_dir_all(&library_dir)?;
     }
 
-    // Mood global migration
-    let mut stmt_moods = conn.prepare("PRAGMA table_info(moods)")?;
-    let has_mood_sound_set_id = stmt_moods
-        .query_map([], |row| {
-            let name: String = row.get(1)?;
-            Ok(name == "sound_set_id")
-        })?
-        .any(|r| r.unwrap_or(false));
-
-    if has_mood_sound_set_id {
-        conn.execute("PRAGMA foreign_keys=off;", [])?;
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS moods_new (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                name TEXT NOT NULL,
-                description TEXT,
-                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-            )",
-            [],
-        )?;
-
-        conn.execute(
-            "INSERT INTO moods_new (id, name, description, created_at)
-             SELECT id, name, description, created_at FROM moods",
-            [],
-        )?;
-        conn.execute("DROP TABLE moods", [])?;
-        conn.execute("ALTER TABLE moods_new RENAME TO moods", [])?;
-        conn.execute("PRAGMA foreign_keys=on;", [])?;
-    }
+    let destination = library_dir.join(file_name);
+    fs::copy(&file_path, &destination)?;
+    Ok(destination.to_string_lossy().to_string())
+}
 
-    // Timelines migration for is_looping
-    let mut stmt = conn.prepare("PRAGMA table_info(timelines)")?;
-    let has_is_looping = stmt
-        .query_map([], |row| {
-            let name: String = row.get(1)?;
-            Ok(name == "is_looping")
-        })?
-        .any(|r| r.unwrap_or(false));
+#[tauri::command]
+async fn create_sound_set(
+    db: State<'_, Database>,
+    name: String,
+    description: String,
+) -> AppResult<SoundSet> {
+    let conn = db.connection()?;
+    let first_id = history::next_id(&conn, "sound_sets")?;
+    history::record(
+        &conn,
+        "Add sound set",
+        &[Scope::new("sound_sets", "id >= ?1", first_id)],
+        |conn| store::sound_sets::create_sound_set(conn, name, description),
+    )
+}
 
-    if !has_is_looping {
-        conn.execute(
-            "ALTER TABLE timelines ADD COLUMN is_looping INTEGER DEFAULT 0",
-            [],
-        )?;
-    }
+#[tauri::command]
+async fn get_sound_sets(db: State<'_, Database>) -> AppResult<Vec<SoundSet>> {
+    let conn = db.connection()?;
+    store::sound_sets::get_sound_sets(&conn)
+}
 
-    // Timeline tracks migration for is_looping
-    let mut stmt = conn.prepare("PRAGMA table_info(timeline_tracks)")?;
-    let track_has_is_looping = stmt
-        .query_map([], |row| {
-            let name: String = row.get(1)?;
-            Ok(name == "is_looping")
-        })?
-        .any(|r| r.unwrap_or(false));
+#[tauri::command]
+async fn delete_sound_set(db: State<'_, Database>, id: i64) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Delete sound set",
+        &[Scope::new("sound_sets", "id = ?1", id)],
+        |conn| store::sound_sets::delete_sound_set(conn, id),
+    )
+}
 
-    if !track_has_is_looping {
-        conn.execute(
-            "ALTER TABLE timeline_tracks ADD COLUMN is_looping INTEGER DEFAULT 0",
-            [],
-        )?;
-    }
+#[tauri::command]
+async fn update_sound_set_enabled(
+    db: State<'_, Database>,
+    id: i64,
+    is_enabled: bool,
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        if is_enabled {
+            "Enable sound set"
+        } else {
+            "Disable sound set"
+        },
+        &[Scope::new("sound_sets", "id = ?1", id)],
+        |conn| store::sound_sets::update_sound_set_enabled(conn, id, is_enabled),
+    )
+}
 
-    // Audio channels migration
-    let mut stmt = conn.prepare("PRAGMA table_info(audio_elements)")?;
-    let has_channel_id = stmt
-        .query_map([], |row| {
-            let name: String = row.get(1)?;
-            Ok(name == "channel_id")
-        })?
-        .any(|r| r.unwrap_or(false));
+#[tauri::command]
+async fn create_mood(
+    db: State<'_, Database>,
+    name: String,
+    description: String,
+) -> AppResult<Mood> {
+    let conn = db.connection()?;
+    let first_id = history::next_id(&conn, "moods")?;
+    history::record(
+        &conn,
+        "Add mood",
+        &[Scope::new("moods", "id >= ?1", first_id)],
+        |conn| store::moods::create_mood(conn, name, description),
+    )
+}
 
-    if !has_channel_id {
-        conn.execute(
-            "ALTER TABLE audio_elements ADD COLUMN channel_id INTEGER REFERENCES audio_channels(id)",
-            [],
-        )?;
+#[tauri::command]
+async fn get_moods(db: State<'_, Database>) -> AppResult<Vec<Mood>> {
+    let conn = db.connection()?;
+    store::moods::get_moods(&conn)
+}
 
-        // Ensure default Music channel exists for all soundsets that have elements before we update
-        conn.execute(
-            "INSERT INTO audio_channels (sound_set_id, name, icon, volume, order_index)
-             SELECT DISTINCT sound_set_id, 'Music', 'music', 1.0, 0 
-             FROM audio_elements WHERE sound_set_id NOT IN (SELECT sound_set_id FROM audio_channels WHERE name = 'Music')",
-            [],
-        )?;
+#[tauri::command]
+async fn delete_mood(db: State<'_, Database>, id: i64) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Delete mood",
+        &[Scope::new("moods", "id = ?1", id)],
+        |conn| store::moods::delete_mood(conn, id),
+    )
+}
 
-        // Assign existing elements to the Music channel of their sound set
-        conn.execute(
-            "UPDATE audio_elements SET channel_id = (
-                SELECT id FROM audio_channels 
-                WHERE audio_channels.sound_set_id = audio_elements.sound_set_id 
-                AND audio_channels.name = 'Music' LIMIT 1
-            )",
-            [],
-        )?;
-    }
+#[tauri::command]
+async fn duplicate_mood(db: State<'_, Database>, id: i64, name: String) -> AppResult<Mood> {
+    let conn = db.connection()?;
+    let first_id = history::next_id(&conn, "moods")?;
+    history::record(
+        &conn,
+        "Duplicate mood",
+        &[
+            Scope::new("moods", "id >= ?1", first_id),
+            Scope::new("timelines", "mood_id >= ?1", first_id),
+            Scope::new("timeline_markers", "timeline_id IN (SELECT id FROM timelines WHERE mood_id >= ?1)", first_id),
+            Scope::new("timeline_tempo_changes", "timeline_id IN (SELECT id FROM timelines WHERE mood_id >= ?1)", first_id),
+            Scope::new("timeline_tracks", "timeline_id IN (SELECT id FROM timelines WHERE mood_id >= ?1)", first_id),
+            Scope::new("timeline_elements", "track_id IN (SELECT tt.id FROM timeline_tracks tt JOIN timelines t ON t.id = tt.timeline_id WHERE t.mood_id >= ?1)", first_id),
+            Scope::new("track_gain_points", "track_id IN (SELECT tt.id FROM timeline_tracks tt JOIN timelines t ON t.id = tt.timeline_id WHERE t.mood_id >= ?1)", first_id),
+        ],
+        |conn| store::timeline_templates::duplicate_mood(conn, id, name),
+    )
+}
 
-    // Timeline elements migration for tracks and duration + element groups
-    let mut stmt = conn.prepare("PRAGMA table_info(timeline_elements)")?;
-    let has_track_id = stmt
-        .query_map([], |row| {
-            let name: String = row.get(1)?;
-            Ok(name == "track_id")
-        })?
-        .any(|r| r.unwrap_or(false));
-
-    let mut stmt_eg = conn.prepare("PRAGMA table_info(timeline_elements)")?;
-    let has_element_group_id = stmt_eg
-        .query_map([], |row| {
-            let name: String = row.get(1)?;
-            Ok(name == "element_group_id")
-        })?
-        .any(|r| r.unwrap_or(false));
-
-    if !has_track_id || !has_element_group_id {
-        conn.execute("PRAGMA foreign_keys=off;", [])?;
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS timeline_elements_new (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                timeline_id INTEGER,
-                track_id INTEGER,
-                audio_element_id INTEGER,
-                element_group_id INTEGER,
-                start_time_ms INTEGER DEFAULT 0,
-                duration_ms INTEGER DEFAULT 0,
-                FOREIGN KEY (timeline_id) REFERENCES timelines(id) ON DELETE CASCADE,
-                FOREIGN KEY (track_id) REFERENCES timeline_tracks(id) ON DELETE CASCADE,
-                FOREIGN KEY (audio_element_id) REFERENCES audio_elements(id) ON DELETE CASCADE,
-                FOREIGN KEY (element_group_id) REFERENCES element_groups(id) ON DELETE CASCADE
-            )",
-            [],
-        )?;
+#[tauri::command]
+async fn copy_track_to_mood(
+    db: State<'_, Database>,
+    track_id: i64,
+    mood_id: i64,
+) -> AppResult<TimelineTrack> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Copy track",
+        &[
+            Scope::new("timelines", "mood_id = ?1", mood_id),
+            Scope::new("timeline_tracks", "timeline_id IN (SELECT id FROM timelines WHERE mood_id = ?1)", mood_id),
+            Scope::new("timeline_elements", "track_id IN (SELECT tt.id FROM timeline_tracks tt JOIN timelines t ON t.id = tt.timeline_id WHERE t.mood_id = ?1)", mood_id),
+            Scope::new("track_gain_points", "track_id IN (SELECT tt.id FROM timeline_tracks tt JOIN timelines t ON t.id = tt.timeline_id WHERE t.mood_id = ?1)", mood_id),
+        ],
+        |conn| store::timeline_templates::copy_track_to_mood(conn, track_id, mood_id),
+    )
+}
 
-        // Assume columns match original except potentially missing track_id, duration_ms, element_group_id
-        if !has_track_id {
-            // Need to create default master track and copy over
-            conn.execute("INSERT INTO timeline_tracks (timeline_id, name, order_index) SELECT id, 'Master Track', 0 FROM timelines", [])?;
-            conn.execute(
-                "INSERT INTO timeline_elements_new (id, timeline_id, track_id, audio_element_id, start_time_ms, duration_ms)
-                 SELECT e.id, e.timeline_id, t.id, e.audio_element_id, COALESCE(e.start_time_ms, 0), 0 
-                 FROM timeline_elements e 
-                 LEFT JOIN timeline_tracks t ON t.timeline_id = e.timeline_id",
-                [],
-            )?;
-        } else {
-            conn.execute(
-                "INSERT INTO timeline_elements_new (id, timeline_id, track_id, audio_element_id, start_time_ms, duration_ms)
-                 SELECT id, timeline_id, track_id, audio_element_id, start_time_ms, duration_ms FROM timeline_elements",
-                [],
-            )?;
-        }
+#[tauri::command]
+async fn get_timeline_templates(db: State<'_, Database>) -> AppResult<Vec<TimelineTemplate>> {
+    let conn = db.connection()?;
+    store::timeline_templates::get_timeline_templates(&conn)
+}
 
-        conn.execute("DROP TABLE timeline_elements", [])?;
-        conn.execute(
-            "ALTER TABLE timeline_elements_new RENAME TO timeline_elements",
-            [],
-        )?;
-        conn.execute("PRAGMA foreign_keys=on;", [])?;
-    }
+#[tauri::command]
+async fn save_timeline_template(
+    db: State<'_, Database>,
+    timeline_id: i64,
+    name: String,
+) -> AppResult<TimelineTemplate> {
+    let conn = db.connection()?;
+    let first_id = history::next_id(&conn, "timeline_templates")?;
+    history::record(
+        &conn,
+        "Save timeline template",
+        &[Scope::new("timeline_templates", "id >= ?1", first_id)],
+        |conn| store::timeline_templates::save_timeline_template(conn, timeline_id, name),
+    )
+}
 
-    // Timeline deduplication migration
-    let mut stmt = conn.prepare(
-        "SELECT count(*) FROM sqlite_master WHERE type='index' AND name='ux_timelines_mood_id'",
-    )?;
-    let has_unique_timeline_index = stmt
-        .query_row([], |row| {
-            let count: i64 = row.get(0)?;
-            Ok(count > 0)
-        })
-        .unwrap_or(false);
+#[tauri::command]
+async fn delete_timeline_template(db: State<'_, Database>, id: i64) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Delete timeline template",
+        &[Scope::new("timeline_templates", "id = ?1", id)],
+        |conn| store::timeline_templates::delete_timeline_template(conn, id),
+    )
+}
 
-    if !has_unique_timeline_index {
-        // Keep the oldest timeline per mood, delete the rest
-        conn.execute(
-            "DELETE FROM timelines WHERE id NOT IN (
-                SELECT MIN(id) FROM timelines GROUP BY mood_id
-            )",
-            [],
-        )?;
+#[tauri::command]
+async fn instantiate_timeline_template(
+    db: State<'_, Database>,
+    template_id: i64,
+    mood_id: i64,
+) -> AppResult<Timeline> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Create timeline from template",
+        &[
+            Scope::new("timelines", "mood_id = ?1", mood_id),
+            Scope::new("timeline_markers", "timeline_id IN (SELECT id FROM timelines WHERE mood_id = ?1)", mood_id),
+            Scope::new("timeline_tempo_changes", "timeline_id IN (SELECT id FROM timelines WHERE mood_id = ?1)", mood_id),
+            Scope::new("timeline_tracks", "timeline_id IN (SELECT id FROM timelines WHERE mood_id = ?1)", mood_id),
+            Scope::new("timeline_elements", "track_id IN (SELECT tt.id FROM timeline_tracks tt JOIN timelines t ON t.id = tt.timeline_id WHERE t.mood_id = ?1)", mood_id),
+            Scope::new("track_gain_points", "track_id IN (SELECT tt.id FROM timeline_tracks tt JOIN timelines t ON t.id = tt.timeline_id WHERE t.mood_id = ?1)", mood_id),
+        ],
+        |conn| store::timeline_templates::instantiate_timeline_template(conn, template_id, mood_id),
+    )
+}
 
-        // Create unique index
-        conn.execute(
-            "CREATE UNIQUE INDEX ux_timelines_mood_id ON timelines(mood_id)",
-            [],
-        )?;
-    }
+#[tauri::command]
+async fn get_mood_transitions(db: State<'_, Database>) -> AppResult<Vec<MoodTransition>> {
+    let conn = db.connection()?;
+    store::transitions::get_mood_transitions(&conn)
+}
 
-    // Global one-shots migration (drop legacy mood_id and allow global rows)
-    let mut stmt = conn.prepare("PRAGMA table_info(audio_elements)")?;
-    let is_sound_set_id_not_null = stmt
-        .query_map([], |row| {
-            let name: String = row.get(1)?;
-            let notnull: bool = row.get(3)?;
-            Ok(name == "sound_set_id" && notnull)
-        })?
-        .any(|r| r.unwrap_or(false));
+#[tauri::command]
+async fn set_mood_transition(
+    db: State<'_, Database>,
+    from_mood_id: Option<i64>,
+    to_mood_id: Option<i64>,
+    crossfade_ms: i64,
+    curve: FadeCurve,
+    sync: TransitionSync,
+) -> AppResult<MoodTransition> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Change mood transition",
+        &[Scope::new(
+            "mood_transitions",
+            "from_mood_id IS ?1",
+            from_mood_id,
+        )],
+        |conn| {
+            store::transitions::set_mood_transition(
+                conn,
+                from_mood_id,
+                to_mood_id,
+                crossfade_ms,
+                curve,
+                sync,
+            )
+        },
+    )
+}
 
-    let needs_audio_elements_rebuild = has_mood_id || is_sound_set_id_not_null;
+#[tauri::command]
+async fn delete_mood_transition(db: State<'_, Database>, id: i64) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Delete mood transition",
+        &[Scope::new("mood_transitions", "id = ?1", id)],
+        |conn| store::transitions::delete_mood_transition(conn, id),
+    )
+}
 
-    if needs_audio_elements_rebuild {
-        conn.execute("PRAGMA foreign_keys=off;", [])?;
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS audio_elements_new (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                sound_set_id INTEGER,
-                channel_id INTEGER,
-                file_path TEXT NOT NULL,
-                file_name TEXT NOT NULL,
-                channel_type TEXT DEFAULT 'ambient',
-                volume_db REAL DEFAULT 0.0,
-                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-                FOREIGN KEY (sound_set_id) REFERENCES sound_sets(id) ON DELETE CASCADE,
-                FOREIGN KEY (channel_id) REFERENCES audio_channels(id) ON DELETE SET NULL
-            )",
-            [],
-        )?;
+#[tauri::command]
+async fn plan_mood_transition(
+    db: State<'_, Database>,
+    from_mood_id: Option<i64>,
+    to_mood_id: i64,
+    position_ms: i64,
+) -> AppResult<TransitionPlan> {
+    let conn = db.connection()?;
+    store::transitions::plan_mood_transition(&conn, from_mood_id, to_mood_id, position_ms)
+}
 
-        if has_mood_id {
-            conn.execute(
-                "INSERT INTO audio_elements_new (id, sound_set_id, channel_id, file_path, file_name, channel_type, volume_db, created_at)
-                 SELECT
-                    id,
-                    COALESCE(
-                        sound_set_id,
-                        (SELECT sound_set_id FROM moods WHERE moods.id = audio_elements.mood_id)
-                    ),
-                    channel_id,
+#[tauri::command]
+async fn create_audio_element(
+    app_handle: AppHandle,
+    db: State<'_, Database>,
+    sound_set_id: i64,
+    file_path: String,
+    file_name: String,
+    channel_type: String,
+    channel_id: Option<i64>,
+) -> AppResult<AudioElement> {
+    let info = audio::probe::probe_file(Path::new(&file_path))?;
+    let file_path = resolve_audio_file_path(&app_handle, file_path, &file_name)?;
+
+    let element = {
+        let conn = db.connection()?;
+        history::record(
+            &conn,
+            "Add audio element",
+            &[Scope::new(
+                "audio_elements",
+                "sound_set_id IS ?1",
+                Some(sound_set_id),
+            )],
+            |conn| {
+                store::audio_elements::create_audio_element(
+                    conn,
+                    Some(sound_set_id),
                     file_path,
                     file_name,
                     channel_type,
-                    volume_db,
-                    created_at
-                 FROM audio_elements",
-                [],
-            )?;
-        } else {
-            conn.execute(
-                "INSERT INTO audio_elements_new (id, sound_set_id, channel_id, file_path, file_name, channel_type, volume_db, created_at)
-                 SELECT
-                    id,
-                    sound_set_id,
                     channel_id,
-                    file_path,
-                    file_name,
-                    channel_type,
-                    volume_db,
-                    created_at
-                 FROM audio_elements",
-                [],
-            )?;
-        }
-
-        conn.execute("DROP TABLE audio_elements", [])?;
-        conn.execute(
-            "ALTER TABLE audio_elements_new RENAME TO audio_elements",
-            [],
-        )?;
-        conn.execute("PRAGMA foreign_keys=on;", [])?;
-    }
+                    Some(info),
+                )
+            },
+        )?
+    };
 
-    Ok(())
+    warm_waveform(&app_handle, &db, element.id);
+    Ok(element)
 }
 
-fn check_element_overlap(
-    conn: &Connection,
-    track_id: i64,
-    start_time_ms: i64,
-    duration_ms: i64,
-    exclude_id: Option<i64>,
-) -> Result<bool, String> {
-    let end_time_ms = start_time_ms + duration_ms;
-
-    let query = match exclude_id {
-        Some(_) => "SELECT count(*) FROM timeline_elements WHERE track_id = ?1 AND id != ?2 AND start_time_ms < ?3 AND (start_time_ms + duration_ms) > ?4",
-        None => "SELECT count(*) FROM timeline_elements WHERE track_id = ?1 AND start_time_ms < ?2 AND (start_time_ms + duration_ms) > ?3",
+/// Re-reads an element's format details and content hash. The file is read
+/// with the database unlocked.
+#[tauri::command]
+async fn probe_audio_element(db: State<'_, Database>, id: i64) -> AppResult<AudioElement> {
+    let file_path = {
+        let conn = db.connection()?;
+        store::audio_elements::get_audio_element(&conn, id)?.file_path
     };
+    let info = audio::probe::probe_file(Path::new(&file_path))?;
+    let stamp = audio::waveform::file_stamp(Path::new(&file_path))?;
+    let content_hash = audio::waveform::hash_file(Path::new(&file_path))?;
+
+    let conn = db.connection()?;
+    store::audio_elements::set_audio_element_content_hash(&conn, id, &content_hash, stamp)?;
+
+    history::record(
+        &conn,
+        "Probe audio file",
+        &[Scope::new("audio_elements", "id = ?1", id)],
+        |conn| store::audio_elements::update_audio_element_info(conn, id, &info),
+    )?;
+    store::audio_elements::get_audio_element(&conn, id)
+}
 
-    let mut stmt = conn.prepare(query).map_err(|e| e.to_string())?;
-
-    let count: i64 = match exclude_id {
-        Some(id) => stmt
-            .query_row([&track_id, &id, &end_time_ms, &start_time_ms], |row| {
-                row.get(0)
-            })
-            .map_err(|e| e.to_string())?,
-        None => stmt
-            .query_row([&track_id, &end_time_ms, &start_time_ms], |row| row.get(0))
-            .map_err(|e| e.to_string())?,
+/// Measures an element's loudness with the database unlocked.
+#[tauri::command]
+async fn analyze_audio_element(db: State<'_, Database>, id: i64) -> AppResult<AudioElement> {
+    let file_path = {
+        let conn = db.connection()?;
+        store::audio_elements::get_audio_element(&conn, id)?.file_path
     };
+    let report = audio::loudness::measure_file(Path::new(&file_path))?;
+
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Analyze loudness",
+        &[Scope::new("audio_elements", "id = ?1", id)],
+        |conn| store::audio_elements::update_audio_element_loudness(conn, id, &report),
+    )?;
+    store::audio_elements::get_audio_element(&conn, id)
+}
 
-    Ok(count > 0)
+/// Finds an element's silences and loop points. The file is decoded with the
+/// database unlocked.
+#[tauri::command]
+async fn detect_loop_points(db: State<'_, Database>, id: i64) -> AppResult<AudioElement> {
+    let file_path = {
+        let conn = db.connection()?;
+        store::audio_elements::get_audio_element(&conn, id)?.file_path
+    };
+    let points = audio::loops::detect_loop_points(Path::new(&file_path))?;
+
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Detect loop points",
+        &[Scope::new("audio_elements", "id = ?1", id)],
+        |conn| store::audio_elements::update_audio_element_loop_points(conn, id, &points),
+    )?;
+    store::audio_elements::get_audio_element(&conn, id)
 }
 
+/// Measures any elements of the sound set that have not been analysed yet,
+/// then sets every measured element's volume to its channel type's target.
+/// Files are measured with the database unlocked.
 #[tauri::command]
-async fn create_sound_set(
-    app_handle: AppHandle,
-    name: String,
-    description: String,
-) -> Result<SoundSet, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+async fn normalize_sound_set_loudness(
+    db: State<'_, Database>,
+    sound_set_id: i64,
+    targets: Option<HashMap<String, f64>>,
+) -> AppResult<Vec<AudioElement>> {
+    let unmeasured: Vec<(i64, String)> = {
+        let conn = db.connection()?;
+        store::audio_elements::get_audio_elements(&conn, sound_set_id, None)?
+            .into_iter()
+            .filter(|element| element.loudness_lufs.is_none() && element.true_peak_dbtp.is_none())
+            .map(|element| (element.id, element.file_path))
+            .collect()
+    };
+    let mut reports = Vec::new();
+    for (id, file_path) in unmeasured {
+        reports.push((id, audio::loudness::measure_file(Path::new(&file_path))?));
+    }
 
-    conn.execute(
-        "INSERT INTO sound_sets (name, description) VALUES (?1, ?2)",
-        [&name, &description],
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Normalize loudness",
+        &[Scope::new(
+            "audio_elements",
+            "sound_set_id IS ?1",
+            Some(sound_set_id),
+        )],
+        |conn| {
+            for (id, report) in &reports {
+                store::audio_elements::update_audio_element_loudness(conn, *id, report)?;
+            }
+            store::audio_elements::normalize_sound_set_loudness(
+                conn,
+                sound_set_id,
+                &targets.unwrap_or_default(),
+            )
+        },
     )
-    .map_err(|e| e.to_string())?;
-
-    let id = conn.last_insert_rowid();
-
-    Ok(SoundSet {
-        id,
-        name,
-        description,
-        is_enabled: true,
-        created_at: chrono::Local::now().to_rfc3339(),
-    })
 }
 
 #[tauri::command]
-async fn get_sound_sets(app_handle: AppHandle) -> Result<Vec<SoundSet>, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let mut stmt = conn
-        .prepare(
-            "SELECT id, name, description, is_enabled, created_at FROM sound_sets ORDER BY created_at DESC",
-        )
-        .map_err(|e| e.to_string())?;
-
-    let sound_sets = stmt
-        .query_map([], |row| {
-            Ok(SoundSet {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                description: row.get(2)?,
-                is_enabled: row.get(3)?,
-                created_at: row.get(4)?,
-            })
-        })
-        .map_err(|e| e.to_string())?;
-
-    let result: Result<Vec<_>, _> = sound_sets.collect();
-    result.map_err(|e| e.to_string())
+async fn get_waveform_peaks(
+    app_handle: AppHandle,
+    db: State<'_, Database>,
+    element_id: i64,
+    start_ms: i64,
+    end_ms: i64,
+    buckets: i64,
+) -> AppResult<WaveformPeaks> {
+    load_waveform(&app_handle, &db, element_id)?.peaks(start_ms, end_ms, buckets)
 }
 
+/// Renders the mood's timeline to a WAV or FLAC file. The score is read up
+/// front so the database is free again while the audio is mixed.
 #[tauri::command]
-async fn delete_sound_set(app_handle: AppHandle, id: i64) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    conn.execute("DELETE FROM sound_sets WHERE id = ?1", [id])
-        .map_err(|e| e.to_string())?;
+async fn render_timeline(
+    db: State<'_, Database>,
+    mood_id: i64,
+    output_path: String,
+    length_ms: i64,
+    seed: Option<u64>,
+) -> AppResult<RenderSummary> {
+    let score = {
+        let conn = db.connection()?;
+        audio::render::load_score(&conn, mood_id)?
+    };
+    audio::render::render_to_file(score, seed.unwrap_or(0), length_ms, Path::new(&output_path))
+}
 
-    Ok(())
+/// Where the playback engine sends its master output.
+#[derive(Debug, Default, Deserialize)]
+pub struct EngineOutputs {
+    #[serde(default)]
+    pub device: bool,
+    #[serde(default)]
+    pub discord: bool,
+    /// Records the output to a WAV file at this path.
+    #[serde(default)]
+    pub file_path: Option<String>,
 }
 
 #[tauri::command]
-async fn update_sound_set_enabled(
+async fn engine_start(
     app_handle: AppHandle,
-    id: i64,
-    is_enabled: bool,
-) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    conn.execute(
-        "UPDATE sound_sets SET is_enabled = ?1 WHERE id = ?2",
-        (&is_enabled, &id),
-    )
-    .map_err(|e| e.to_string())?;
-
-    Ok(())
+    engine: State<'_, PlaybackEngine>,
+    outputs: EngineOutputs,
+) -> AppResult<EngineStatus> {
+    let mut sinks: Vec<Box<dyn AudioSink>> = Vec::new();
+    if outputs.device {
+        #[cfg(feature = "native-output")]
+        sinks.push(Box::new(audio::sink::DeviceSink::open()?));
+        #[cfg(not(feature = "native-output"))]
+        return Err(AppError::ValidationFailed(
+            "This build has no native audio output".into(),
+        ));
+    }
+    if outputs.discord {
+        sinks.push(Box::new(discord::DiscordSink::new(app_handle)));
+    }
+    if let Some(file_path) = &outputs.file_path {
+        sinks.push(Box::new(audio::sink::WavFileSink::create(Path::new(
+            file_path,
+        ))?));
+    }
+    engine.start(sinks)?;
+    engine.status()
 }
 
 #[tauri::command]
-async fn create_mood(
-    app_handle: AppHandle,
-    name: String,
-    description: String,
-) -> Result<Mood, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    conn.execute(
-        "INSERT INTO moods (name, description) VALUES (?1, ?2)",
-        [&name, &description],
-    )
-    .map_err(|e| e.to_string())?;
+async fn engine_stop(engine: State<'_, PlaybackEngine>) -> AppResult<EngineStatus> {
+    engine.stop()?;
+    engine.status()
+}
 
-    let id = conn.last_insert_rowid();
+#[tauri::command]
+async fn engine_status(engine: State<'_, PlaybackEngine>) -> AppResult<EngineStatus> {
+    engine.status()
+}
 
-    Ok(Mood {
-        id,
-        name,
-        description,
-        created_at: chrono::Local::now().to_rfc3339(),
+#[tauri::command]
+async fn engine_play_timeline(
+    db: State<'_, Database>,
+    engine: State<'_, PlaybackEngine>,
+    mood_id: i64,
+    seed: Option<u64>,
+) -> AppResult<()> {
+    let (score, tempo) = {
+        let conn = db.connection()?;
+        let score = audio::render::load_score(&conn, mood_id)?;
+        let tempo = store::tempo::load_tempo_map(&conn, score.timeline_id)?;
+        (score, tempo)
+    };
+    let renderer = audio::render::Renderer::new(score, seed.unwrap_or_else(rand::random))?;
+    engine.with_mixer(|mixer| {
+        mixer.play_timeline(mood_id, renderer, tempo);
+        Ok(())
     })
 }
 
 #[tauri::command]
-async fn get_moods(app_handle: AppHandle) -> Result<Vec<Mood>, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let mut stmt = conn
-        .prepare("SELECT id, name, description, created_at FROM moods ORDER BY created_at DESC")
-        .map_err(|e| e.to_string())?;
-
-    let moods = stmt
-        .query_map([], |row| {
-            Ok(Mood {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                description: row.get(2)?,
-                created_at: row.get(3)?,
-            })
-        })
-        .map_err(|e| e.to_string())?;
-
-    let result: Result<Vec<_>, _> = moods.collect();
-    result.map_err(|e| e.to_string())
+async fn engine_stop_timeline(engine: State<'_, PlaybackEngine>) -> AppResult<()> {
+    engine.with_mixer(|mixer| {
+        mixer.stop_timeline();
+        Ok(())
+    })
 }
 
 #[tauri::command]
-async fn delete_mood(app_handle: AppHandle, id: i64) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    conn.execute("DELETE FROM moods WHERE id = ?1", [id])
-        .map_err(|e| e.to_string())?;
-
-    Ok(())
+async fn engine_seek(engine: State<'_, PlaybackEngine>, position_ms: i64) -> AppResult<()> {
+    engine.with_mixer(|mixer| mixer.seek(position_ms))
 }
 
 #[tauri::command]
-async fn create_audio_element(
-    app_handle: AppHandle,
-    sound_set_id: i64,
-    file_path: String,
-    file_name: String,
-    channel_type: String,
-    channel_id: Option<i64>,
-) -> Result<AudioElement, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let settings = get_app_settings(app_handle.clone()).await?;
-    let mut final_file_path = file_path.clone();
+async fn engine_jump_to_marker(
+    db: State<'_, Database>,
+    engine: State<'_, PlaybackEngine>,
+    marker_id: i64,
+) -> AppResult<TransportJump> {
+    let jump = {
+        let conn = db.connection()?;
+        store::markers::jump_to_marker(&conn, marker_id)?
+    };
+    engine.with_mixer(|mixer| mixer.jump(&jump))?;
+    Ok(jump)
+}
 
-    if settings.audio_file_strategy == "copy" {
-        let library_dir = if settings.library_path.trim().is_empty() {
-            get_default_library_path(&app_handle)
-        } else {
-            PathBuf::from(&settings.library_path)
-        };
-        if !library_dir.exists() {
-            fs::create_dir_all(&library_dir).map_err(|e| e.to_string())?;
-        }
+/// Plays an audio element over the engine's mix. Returns the delay in ms
+/// before it starts, which is non-zero when quantized to the next bar.
+#[tauri::command]
+async fn engine_play_oneshot(
+    db: State<'_, Database>,
+    engine: State<'_, PlaybackEngine>,
+    audio_element_id: i64,
+    quantize: Option<bool>,
+) -> AppResult<i64> {
+    let (source, mix) = {
+        let conn = db.connection()?;
+        (
+            audio::render::ScoreSource::load(&conn, audio_element_id)?,
+            audio::bus::load_mix_settings(&conn)?,
+        )
+    };
+    let shot = audio::engine::OneShot::load(&source)?;
+    engine.with_mixer(|mixer| {
+        mixer.set_mix(mix);
+        Ok(mixer.play_oneshot(shot, quantize.unwrap_or(false)))
+    })
+}
 
-        let destination = library_dir.join(&file_name);
-        fs::copy(&file_path, &destination).map_err(|e| e.to_string())?;
-        final_file_path = destination.to_string_lossy().to_string();
-    }
+#[tauri::command]
+async fn engine_stop_oneshots(engine: State<'_, PlaybackEngine>) -> AppResult<()> {
+    engine.with_mixer(|mixer| {
+        mixer.stop_oneshots();
+        Ok(())
+    })
+}
 
-    conn.execute(
-        "INSERT INTO audio_elements (sound_set_id, file_path, file_name, channel_type, channel_id) VALUES (?1, ?2, ?3, ?4, ?5)",
-        (&sound_set_id, &final_file_path, &file_name, &channel_type, &channel_id),
-    ).map_err(|e| e.to_string())?;
-
-    let id = conn.last_insert_rowid();
-
-    Ok(AudioElement {
-        id,
-        sound_set_id: Some(sound_set_id),
-        channel_id,
-        file_path: final_file_path,
-        file_name,
-        channel_type,
-        volume_db: 0.0,
-        created_at: chrono::Local::now().to_rfc3339(),
+/// Hands the engine the mix as it now stands in the database, so edits to
+/// channels, effects and dynamics are heard while it plays.
+fn refresh_engine_mix(conn: &rusqlite::Connection, engine: &PlaybackEngine) -> AppResult<()> {
+    let mix = audio::bus::load_mix_settings(conn)?;
+    engine.with_mixer(|mixer| {
+        mixer.ramp_mix(mix, audio::engine::MIX_EDIT_RAMP_MS);
+        Ok(())
     })
 }
 
+#[tauri::command]
+async fn engine_set_master_volume(engine: State<'_, PlaybackEngine>, volume: f64) -> AppResult<()> {
+    engine.with_mixer(|mixer| mixer.set_master_volume(volume))
+}
+
 #[tauri::command]
 async fn get_audio_elements(
-    app_handle: AppHandle,
+    db: State<'_, Database>,
     sound_set_id: i64,
-) -> Result<Vec<AudioElement>, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let mut stmt = conn.prepare(
-        "SELECT id, sound_set_id, file_path, file_name, channel_type, volume_db, created_at, channel_id FROM audio_elements WHERE sound_set_id = ?1 ORDER BY created_at DESC"
-    ).map_err(|e| e.to_string())?;
-
-    let elements = stmt
-        .query_map([sound_set_id], |row| {
-            Ok(AudioElement {
-                id: row.get(0)?,
-                sound_set_id: row.get(1)?,
-                file_path: row.get(2)?,
-                file_name: row.get(3)?,
-                channel_type: row.get(4)?,
-                volume_db: row.get(5)?,
-                created_at: row.get(6)?,
-                channel_id: row.get(7)?,
-            })
-        })
-        .map_err(|e| e.to_string())?;
-
-    let result: Result<Vec<_>, _> = elements.collect();
-    result.map_err(|e| e.to_string())
+    tag: Option<String>,
+) -> AppResult<Vec<AudioElement>> {
+    let conn = db.connection()?;
+    store::audio_elements::get_audio_elements(&conn, sound_set_id, tag.as_deref())
 }
 
 #[tauri::command]
-async fn get_all_available_audio_elements(
-    app_handle: AppHandle,
-) -> Result<Vec<AudioElement>, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let mut stmt = conn.prepare(
-        "SELECT e.id, e.sound_set_id, e.file_path, e.file_name, e.channel_type, e.volume_db, e.created_at, e.channel_id 
-         FROM audio_elements e
-         LEFT JOIN sound_sets s ON e.sound_set_id = s.id
-         WHERE e.sound_set_id IS NULL OR s.is_enabled = 1
-         ORDER BY e.created_at DESC"
-    ).map_err(|e| e.to_string())?;
-
-    let elements = stmt
-        .query_map([], |row| {
-            Ok(AudioElement {
-                id: row.get(0)?,
-                sound_set_id: row.get(1)?,
-                file_path: row.get(2)?,
-                file_name: row.get(3)?,
-                channel_type: row.get(4)?,
-                volume_db: row.get(5)?,
-                created_at: row.get(6)?,
-                channel_id: row.get(7)?,
-            })
-        })
-        .map_err(|e| e.to_string())?;
-
-    let result: Result<Vec<_>, _> = elements.collect();
-    result.map_err(|e| e.to_string())
+async fn get_all_available_audio_elements(db: State<'_, Database>) -> AppResult<Vec<AudioElement>> {
+    let conn = db.connection()?;
+    store::audio_elements::get_all_available_audio_elements(&conn)
 }
 
 #[tauri::command]
-async fn delete_audio_element(app_handle: AppHandle, id: i64) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    conn.execute("DELETE FROM audio_elements WHERE id = ?1", [id])
-        .map_err(|e| e.to_string())?;
-
-    Ok(())
+async fn delete_audio_element(db: State<'_, Database>, id: i64) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Delete audio element",
+        &[
+            Scope::new("audio_elements", "id = ?1", id),
+            Scope::new("element_group_members", "audio_element_id = ?1", id),
+            Scope::new("timeline_elements", "audio_element_id = ?1", id),
+        ],
+        |conn| store::audio_elements::delete_audio_element(conn, id),
+    )
 }
 
 #[tauri::command]
 async fn update_audio_element_channel(
-    app_handle: AppHandle,
+    db: State<'_, Database>,
     id: i64,
     channel_type: String,
-) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    conn.execute(
-        "UPDATE audio_elements SET channel_type = ?1 WHERE id = ?2",
-        (&channel_type, &id),
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Change element channel",
+        &[Scope::new("audio_elements", "id = ?1", id)],
+        |conn| store::audio_elements::update_audio_element_channel(conn, id, &channel_type),
     )
-    .map_err(|e| e.to_string())?;
-
-    Ok(())
 }
 
 #[tauri::command]
 async fn update_audio_element_channel_id(
-    app_handle: AppHandle,
+    db: State<'_, Database>,
     id: i64,
     channel_id: Option<i64>,
-) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    conn.execute(
-        "UPDATE audio_elements SET channel_id = ?1 WHERE id = ?2",
-        (&channel_id, &id),
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Change element channel",
+        &[Scope::new("audio_elements", "id = ?1", id)],
+        |conn| store::audio_elements::update_audio_element_channel_id(conn, id, channel_id),
     )
-    .map_err(|e| e.to_string())?;
+}
 
-    Ok(())
+#[tauri::command]
+async fn update_audio_element_metadata(
+    db: State<'_, Database>,
+    id: i64,
+    notes: String,
+    rating: Option<i64>,
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Edit element notes",
+        &[Scope::new("audio_elements", "id = ?1", id)],
+        |conn| store::audio_elements::update_audio_element_metadata(conn, id, &notes, rating),
+    )
 }
 
 #[tauri::command]
 async fn create_timeline(
-    app_handle: AppHandle,
+    db: State<'_, Database>,
     mood_id: i64,
     name: String,
-) -> Result<Timeline, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    // Check if one already exists
-    let mut stmt = conn.prepare("SELECT id, name, order_index, is_looping, created_at FROM timelines WHERE mood_id = ?1").map_err(|e| e.to_string())?;
-
-    let existing = stmt.query_row([&mood_id], |row| {
-        Ok(Timeline {
-            id: row.get(0)?,
-            mood_id,
-            name: row.get(1)?,
-            order_index: row.get(2)?,
-            is_looping: row.get::<_, i64>(3)? != 0,
-            created_at: row.get(4)?,
-        })
-    });
+) -> AppResult<Timeline> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Create timeline",
+        &[
+            Scope::new("timelines", "mood_id = ?1", mood_id),
+            Scope::new(
+                "timeline_tracks",
+                "timeline_id IN (SELECT id FROM timelines WHERE mood_id = ?1)",
+                mood_id,
+            ),
+        ],
+        |conn| store::timelines::create_timeline(conn, mood_id, name),
+    )
+}
 
-    if let Ok(timeline) = existing {
-        return Ok(timeline);
-    }
+#[tauri::command]
+async fn get_timelines(db: State<'_, Database>, mood_id: i64) -> AppResult<Vec<Timeline>> {
+    let conn = db.connection()?;
+    store::timelines::get_timelines(&conn, mood_id)
+}
 
-    conn.execute(
-        "INSERT INTO timelines (mood_id, name, order_index, is_looping) VALUES (?1, ?2, 0, 0)",
-        [&mood_id.to_string(), &name],
+#[tauri::command]
+async fn delete_timeline(db: State<'_, Database>, id: i64) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Delete timeline",
+        &[
+            Scope::new("timelines", "id = ?1", id),
+            Scope::new("timeline_markers", "timeline_id = ?1", id),
+            Scope::new("timeline_tempo_changes", "timeline_id = ?1", id),
+            Scope::new("timeline_tracks", "timeline_id = ?1", id),
+            Scope::new("timeline_elements", "timeline_id = ?1 OR track_id IN (SELECT id FROM timeline_tracks WHERE timeline_id = ?1)", id),
+            Scope::new("track_gain_points", "track_id IN (SELECT id FROM timeline_tracks WHERE timeline_id = ?1)", id),
+        ],
+        |conn| store::timelines::delete_timeline(conn, id),
     )
-    .map_err(|e| e.to_string())?;
-
-    let id = conn.last_insert_rowid();
+}
 
-    // Automatically create a default track for new timelines
-    conn.execute(
-        "INSERT INTO timeline_tracks (timeline_id, name, order_index, is_looping) VALUES (?1, 'Track 1', 0, 0)",
-        [&id.to_string()],
+#[tauri::command]
+async fn update_timeline_loop(db: State<'_, Database>, id: i64, is_looping: bool) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Toggle timeline loop",
+        &[
+            Scope::new("timelines", "id = ?1", id),
+            Scope::new("timeline_tracks", "timeline_id = ?1", id),
+        ],
+        |conn| store::timelines::update_timeline_loop(conn, id, is_looping),
     )
-    .map_err(|e| e.to_string())?;
-
-    Ok(Timeline {
-        id,
-        mood_id,
-        name,
-        order_index: 0,
-        is_looping: false,
-        created_at: chrono::Local::now().to_rfc3339(),
-    })
 }
 
 #[tauri::command]
-async fn get_timelines(app_handle: AppHandle, mood_id: i64) -> Result<Vec<Timeline>, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+async fn get_timeline_markers(
+    db: State<'_, Database>,
+    timeline_id: i64,
+) -> AppResult<Vec<TimelineMarker>> {
+    let conn = db.connection()?;
+    store::markers::get_timeline_markers(&conn, timeline_id)
+}
 
-    let mut stmt = conn.prepare(
-        "SELECT id, mood_id, name, order_index, is_looping, created_at FROM timelines WHERE mood_id = ?1 ORDER BY order_index ASC"
-    ).map_err(|e| e.to_string())?;
+#[tauri::command]
+async fn create_timeline_marker(
+    db: State<'_, Database>,
+    timeline_id: i64,
+    name: String,
+    position_ms: i64,
+    end_ms: Option<i64>,
+    color: Option<String>,
+) -> AppResult<TimelineMarker> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Add marker",
+        &[Scope::new(
+            "timeline_markers",
+            "timeline_id = ?1",
+            timeline_id,
+        )],
+        |conn| {
+            store::markers::create_timeline_marker(
+                conn,
+                timeline_id,
+                name,
+                position_ms,
+                end_ms,
+                color,
+            )
+        },
+    )
+}
 
-    let timelines = stmt
-        .query_map([mood_id], |row| {
-            Ok(Timeline {
-                id: row.get(0)?,
-                mood_id: row.get(1)?,
-                name: row.get(2)?,
-                order_index: row.get(3)?,
-                is_looping: row.get::<_, i64>(4)? != 0,
-                created_at: row.get(5)?,
-            })
-        })
-        .map_err(|e| e.to_string())?;
+#[tauri::command]
+async fn update_timeline_marker(
+    db: State<'_, Database>,
+    id: i64,
+    name: String,
+    position_ms: i64,
+    end_ms: Option<i64>,
+    color: Option<String>,
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Edit marker",
+        &[
+            Scope::new("timeline_markers", "id = ?1", id),
+            Scope::new("timelines", "loop_marker_id = ?1", id),
+        ],
+        |conn| {
+            store::markers::update_timeline_marker(
+                conn,
+                id,
+                &name,
+                position_ms,
+                end_ms,
+                color.as_deref(),
+            )
+        },
+    )
+}
 
-    let result: Result<Vec<_>, _> = timelines.collect();
-    result.map_err(|e| e.to_string())
+#[tauri::command]
+async fn delete_timeline_marker(db: State<'_, Database>, id: i64) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Delete marker",
+        &[
+            Scope::new("timeline_markers", "id = ?1", id),
+            Scope::new("timelines", "loop_marker_id = ?1", id),
+        ],
+        |conn| store::markers::delete_timeline_marker(conn, id),
+    )
 }
 
 #[tauri::command]
-async fn delete_timeline(app_handle: AppHandle, id: i64) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+async fn set_timeline_loop_region(
+    db: State<'_, Database>,
+    timeline_id: i64,
+    marker_id: Option<i64>,
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Change loop region",
+        &[Scope::new("timelines", "id = ?1", timeline_id)],
+        |conn| store::markers::set_timeline_loop_region(conn, timeline_id, marker_id),
+    )
+}
 
-    conn.execute("DELETE FROM timelines WHERE id = ?1", [id])
-        .map_err(|e| e.to_string())?;
+#[tauri::command]
+async fn jump_to_marker(db: State<'_, Database>, marker_id: i64) -> AppResult<TransportJump> {
+    let conn = db.connection()?;
+    store::markers::jump_to_marker(&conn, marker_id)
+}
 
-    Ok(())
+#[tauri::command]
+async fn get_tempo_changes(
+    db: State<'_, Database>,
+    timeline_id: i64,
+) -> AppResult<Vec<TempoChange>> {
+    let conn = db.connection()?;
+    store::tempo::get_tempo_changes(&conn, timeline_id)
 }
 
 #[tauri::command]
-async fn update_timeline_loop(
-    app_handle: AppHandle,
-    id: i64,
-    is_looping: bool,
-) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let loop_int = if is_looping { 1 } else { 0 };
-    conn.execute(
-        "UPDATE timelines SET is_looping = ?1 WHERE id = ?2",
-        [&loop_int.to_string(), &id.to_string()],
+async fn set_tempo_change(
+    db: State<'_, Database>,
+    timeline_id: i64,
+    bar: i64,
+    bpm: f64,
+    beats_per_bar: i64,
+    beat_unit: i64,
+) -> AppResult<TempoChange> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Change tempo",
+        &[Scope::new(
+            "timeline_tempo_changes",
+            "timeline_id = ?1",
+            timeline_id,
+        )],
+        |conn| {
+            store::tempo::set_tempo_change(conn, timeline_id, bar, bpm, beats_per_bar, beat_unit)
+        },
     )
-    .map_err(|e| e.to_string())?;
+}
 
-    conn.execute(
-        "UPDATE timeline_tracks SET is_looping = ?1 WHERE timeline_id = ?2",
-        [&loop_int.to_string(), &id.to_string()],
+#[tauri::command]
+async fn delete_tempo_change(db: State<'_, Database>, id: i64) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Delete tempo change",
+        &[Scope::new("timeline_tempo_changes", "id = ?1", id)],
+        |conn| store::tempo::delete_tempo_change(conn, id),
     )
-    .map_err(|e| e.to_string())?;
+}
 
-    Ok(())
+#[tauri::command]
+async fn ms_to_bars(
+    db: State<'_, Database>,
+    timeline_id: i64,
+    position_ms: i64,
+) -> AppResult<MusicalPosition> {
+    let conn = db.connection()?;
+    store::tempo::ms_to_musical(&conn, timeline_id, position_ms)
+}
+
+#[tauri::command]
+async fn bars_to_ms(
+    db: State<'_, Database>,
+    timeline_id: i64,
+    bar: i64,
+    beat: f64,
+) -> AppResult<i64> {
+    let conn = db.connection()?;
+    store::tempo::musical_to_ms(&conn, timeline_id, MusicalPosition { bar, beat })
 }
 
 #[tauri::command]
 async fn create_timeline_track(
-    app_handle: AppHandle,
+    db: State<'_, Database>,
     timeline_id: i64,
     name: String,
-) -> Result<TimelineTrack, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let mut stmt = conn
-        .prepare(
-            "SELECT COALESCE(MAX(order_index), -1) + 1 FROM timeline_tracks WHERE timeline_id = ?1",
-        )
-        .map_err(|e| e.to_string())?;
-    let order_index: i64 = stmt
-        .query_row([&timeline_id], |row| row.get(0))
-        .map_err(|e| e.to_string())?;
-
-    conn.execute(
-        "INSERT INTO timeline_tracks (timeline_id, name, order_index, is_looping) VALUES (?1, ?2, ?3, 0)",
-        [&timeline_id.to_string(), &name, &order_index.to_string()],
+) -> AppResult<TimelineTrack> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Add track",
+        &[Scope::new(
+            "timeline_tracks",
+            "timeline_id = ?1",
+            timeline_id,
+        )],
+        |conn| store::timelines::create_timeline_track(conn, timeline_id, name),
     )
-    .map_err(|e| e.to_string())?;
-
-    let id = conn.last_insert_rowid();
-
-    Ok(TimelineTrack {
-        id,
-        timeline_id,
-        name,
-        order_index,
-        is_looping: false,
-        created_at: chrono::Local::now().to_rfc3339(),
-    })
 }
 
 #[tauri::command]
 async fn get_timeline_tracks(
-    app_handle: AppHandle,
+    db: State<'_, Database>,
     timeline_id: i64,
-) -> Result<Vec<TimelineTrack>, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let mut stmt = conn.prepare(
-        "SELECT id, timeline_id, name, order_index, is_looping, created_at FROM timeline_tracks WHERE timeline_id = ?1 ORDER BY order_index ASC"
-    ).map_err(|e| e.to_string())?;
-
-    let tracks = stmt
-        .query_map([timeline_id], |row| {
-            Ok(TimelineTrack {
-                id: row.get(0)?,
-                timeline_id: row.get(1)?,
-                name: row.get(2)?,
-                order_index: row.get(3)?,
-                is_looping: row.get::<_, i64>(4)? != 0,
-                created_at: row.get(5)?,
-            })
-        })
-        .map_err(|e| e.to_string())?;
-
-    let result: Result<Vec<_>, _> = tracks.collect();
-    result.map_err(|e| e.to_string())
+) -> AppResult<Vec<TimelineTrack>> {
+    let conn = db.connection()?;
+    store::timelines::get_timeline_tracks(&conn, timeline_id)
 }
 
 #[tauri::command]
 async fn update_timeline_track_looping(
-    app_handle: AppHandle,
+    db: State<'_, Database>,
     id: i64,
     is_looping: bool,
-) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let loop_int = if is_looping { 1 } else { 0 };
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Toggle track loop",
+        &[Scope::new("timeline_tracks", "id = ?1", id)],
+        |conn| store::timelines::update_timeline_track_looping(conn, id, is_looping),
+    )
+}
 
-    conn.execute(
-        "UPDATE timeline_tracks SET is_looping = ?1 WHERE id = ?2",
-        [&loop_int.to_string(), &id.to_string()],
+#[tauri::command]
+async fn delete_timeline_track(db: State<'_, Database>, id: i64) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Delete track",
+        &[
+            Scope::new("timeline_tracks", "id = ?1", id),
+            Scope::new("timeline_elements", "track_id = ?1", id),
+            Scope::new("track_gain_points", "track_id = ?1", id),
+        ],
+        |conn| store::timelines::delete_timeline_track(conn, id),
     )
-    .map_err(|e| e.to_string())?;
+}
 
-    Ok(())
+#[tauri::command]
+async fn update_timeline_track_order(
+    db: State<'_, Database>,
+    id: i64,
+    order_index: i64,
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Reorder track",
+        &[Scope::new("timeline_tracks", "id = ?1", id)],
+        |conn| store::timelines::update_timeline_track_order(conn, id, order_index),
+    )
 }
 
 #[tauri::command]
-async fn delete_timeline_track(app_handle: AppHandle, id: i64) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+async fn get_track_gain_points(
+    db: State<'_, Database>,
+    track_id: i64,
+) -> AppResult<Vec<GainPoint>> {
+    let conn = db.connection()?;
+    store::automation::get_track_gain_points(&conn, track_id)
+}
 
-    conn.execute("DELETE FROM timeline_tracks WHERE id = ?1", [id])
-        .map_err(|e| e.to_string())?;
+#[tauri::command]
+async fn get_track_gain_at(db: State<'_, Database>, track_id: i64, time_ms: f64) -> AppResult<f64> {
+    let conn = db.connection()?;
+    store::automation::get_track_gain_at(&conn, track_id, time_ms)
+}
 
-    Ok(())
+#[tauri::command]
+async fn add_track_gain_point(
+    db: State<'_, Database>,
+    track_id: i64,
+    time_ms: i64,
+    gain_db: f64,
+    curve: Option<AutomationCurve>,
+) -> AppResult<GainPoint> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Add automation point",
+        &[Scope::new("track_gain_points", "track_id = ?1", track_id)],
+        |conn| {
+            store::automation::add_track_gain_point(
+                conn,
+                track_id,
+                time_ms,
+                gain_db,
+                curve.unwrap_or_default(),
+            )
+        },
+    )
 }
 
 #[tauri::command]
-async fn update_timeline_track_order(
-    app_handle: AppHandle,
+async fn move_track_gain_point(
+    db: State<'_, Database>,
     id: i64,
-    order_index: i64,
-) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    conn.execute(
-        "UPDATE timeline_tracks SET order_index = ?1 WHERE id = ?2",
-        [&order_index.to_string(), &id.to_string()],
+    time_ms: i64,
+    gain_db: f64,
+    curve: AutomationCurve,
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Move automation point",
+        &[Scope::new("track_gain_points", "id = ?1", id)],
+        |conn| store::automation::move_track_gain_point(conn, id, time_ms, gain_db, curve),
     )
-    .map_err(|e| e.to_string())?;
+}
 
-    Ok(())
+#[tauri::command]
+async fn delete_track_gain_point(db: State<'_, Database>, id: i64) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Delete automation point",
+        &[Scope::new("track_gain_points", "id = ?1", id)],
+        |conn| store::automation::delete_track_gain_point(conn, id),
+    )
 }
 
 #[tauri::command]
 async fn add_element_to_track(
-    app_handle: AppHandle,
+    db: State<'_, Database>,
     track_id: i64,
     audio_element_id: Option<i64>,
     element_group_id: Option<i64>,
     start_time_ms: i64,
     duration_ms: i64,
-) -> Result<TimelineElement, String> {
-    if audio_element_id.is_none() && element_group_id.is_none() {
-        return Err("Must provide either audio_element_id or element_group_id".into());
-    }
+    snap: Option<SnapMode>,
+) -> AppResult<TimelineElement> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Add element to track",
+        &[Scope::new("timeline_elements", "track_id = ?1", track_id)],
+        |conn| {
+            store::timelines::add_element_to_track(
+                conn,
+                track_id,
+                audio_element_id,
+                element_group_id,
+                start_time_ms,
+                duration_ms,
+                snap,
+            )
+        },
+    )
+}
 
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+#[tauri::command]
+async fn get_track_elements(
+    db: State<'_, Database>,
+    track_id: i64,
+) -> AppResult<Vec<TimelineElement>> {
+    let conn = db.connection()?;
+    store::timelines::get_track_elements(&conn, track_id)
+}
 
-    if check_element_overlap(&conn, track_id, start_time_ms, duration_ms, None)? {
-        return Err("Element overlaps with existing element in track".to_string());
-    }
+#[tauri::command]
+async fn update_element_time_and_duration(
+    db: State<'_, Database>,
+    id: i64,
+    start_time_ms: i64,
+    duration_ms: i64,
+    snap: Option<SnapMode>,
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Move element",
+        &[Scope::new("timeline_elements", "id = ?1", id)],
+        |conn| {
+            store::timelines::update_element_time_and_duration(
+                conn,
+                id,
+                start_time_ms,
+                duration_ms,
+                snap,
+            )
+        },
+    )
+}
 
-    let timeline_id: i64 = conn
-        .query_row(
-            "SELECT timeline_id FROM timeline_tracks WHERE id = ?1",
-            [track_id],
-            |row| row.get(0),
-        )
-        .map_err(|e| e.to_string())?;
+#[tauri::command]
+async fn apply_element_edits(
+    db: State<'_, Database>,
+    timeline_id: i64,
+    edits: Vec<ElementEdit>,
+    snap: Option<SnapMode>,
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Edit elements",
+        &[Scope::new(
+            "timeline_elements",
+            "track_id IN (SELECT id FROM timeline_tracks WHERE timeline_id = ?1)",
+            timeline_id,
+        )],
+        |conn| store::timeline_edits::apply_element_edits(conn, timeline_id, &edits, snap),
+    )
+}
 
-    conn.execute(
-        "INSERT INTO timeline_elements (timeline_id, track_id, audio_element_id, element_group_id, start_time_ms, duration_ms) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        (
-            &timeline_id,
-            &track_id,
-            &audio_element_id,
-            &element_group_id,
-            &start_time_ms,
-            &duration_ms,
-        ),
-    ).map_err(|e| e.to_string())?;
-
-    let id = conn.last_insert_rowid();
-
-    // The created element is by definition from the selected library, so we assume it is available
-    Ok(TimelineElement {
-        id,
-        track_id,
-        audio_element_id,
-        element_group_id,
-        start_time_ms,
-        duration_ms,
-        is_available: true,
-    })
+#[tauri::command]
+async fn move_element_to_track(
+    db: State<'_, Database>,
+    id: i64,
+    track_id: i64,
+    start_time_ms: i64,
+    snap: Option<SnapMode>,
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Move element to track",
+        &[Scope::new("timeline_elements", "id = ?1", id)],
+        |conn| {
+            store::timeline_edits::move_element_to_track(conn, id, track_id, start_time_ms, snap)
+        },
+    )
+}
+
+#[tauri::command]
+async fn ripple_move(
+    db: State<'_, Database>,
+    timeline_id: i64,
+    track_id: Option<i64>,
+    from_ms: i64,
+    delta_ms: i64,
+) -> AppResult<usize> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Ripple move",
+        &[Scope::new(
+            "timeline_elements",
+            "track_id IN (SELECT id FROM timeline_tracks WHERE timeline_id = ?1)",
+            timeline_id,
+        )],
+        |conn| store::timeline_edits::ripple_move(conn, timeline_id, track_id, from_ms, delta_ms),
+    )
+}
+
+#[tauri::command]
+async fn update_element_fades(
+    db: State<'_, Database>,
+    id: i64,
+    source_offset_ms: i64,
+    fade_in_ms: i64,
+    fade_out_ms: i64,
+    fade_curve: FadeCurve,
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Edit element fades",
+        &[Scope::new("timeline_elements", "id = ?1", id)],
+        |conn| {
+            store::timelines::update_element_fades(
+                conn,
+                id,
+                source_offset_ms,
+                fade_in_ms,
+                fade_out_ms,
+                fade_curve,
+            )
+        },
+    )
+}
+
+#[tauri::command]
+async fn delete_timeline_element(db: State<'_, Database>, id: i64) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Remove element from track",
+        &[Scope::new("timeline_elements", "id = ?1", id)],
+        |conn| store::timelines::delete_timeline_element(conn, id),
+    )
+}
+
+#[tauri::command]
+async fn create_audio_channel(
+    db: State<'_, Database>,
+    engine: State<'_, PlaybackEngine>,
+    sound_set_id: i64,
+    name: String,
+    icon: String,
+    volume: f64,
+) -> AppResult<AudioChannel> {
+    let conn = db.connection()?;
+    let channel = history::record(
+        &conn,
+        "Add channel",
+        &[Scope::new(
+            "audio_channels",
+            "sound_set_id = ?1",
+            sound_set_id,
+        )],
+        |conn| store::audio_channels::create_audio_channel(conn, sound_set_id, name, icon, volume),
+    )?;
+    refresh_engine_mix(&conn, &engine)?;
+    Ok(channel)
+}
+
+#[tauri::command]
+async fn get_audio_channels(
+    db: State<'_, Database>,
+    sound_set_id: i64,
+) -> AppResult<Vec<AudioChannel>> {
+    let conn = db.connection()?;
+    store::audio_channels::get_audio_channels(&conn, sound_set_id)
+}
+
+#[tauri::command]
+async fn update_audio_channel(
+    db: State<'_, Database>,
+    engine: State<'_, PlaybackEngine>,
+    id: i64,
+    name: String,
+    icon: String,
+    volume: f64,
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Edit channel",
+        &[Scope::new("audio_channels", "id = ?1", id)],
+        |conn| store::audio_channels::update_audio_channel(conn, id, &name, &icon, volume),
+    )?;
+    refresh_engine_mix(&conn, &engine)
+}
+
+#[tauri::command]
+async fn delete_audio_channel(
+    db: State<'_, Database>,
+    engine: State<'_, PlaybackEngine>,
+    id: i64,
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Delete channel",
+        &[
+            Scope::new("audio_channels", "id = ?1", id),
+            Scope::new("audio_elements", "channel_id = ?1", id),
+            Scope::new(
+                "channel_duck_rules",
+                "target_channel_id = ?1 OR trigger_channel_id = ?1",
+                id,
+            ),
+            Scope::new("channel_effects", "channel_id = ?1", id),
+        ],
+        |conn| store::audio_channels::delete_audio_channel(conn, id),
+    )?;
+    refresh_engine_mix(&conn, &engine)
+}
+
+#[tauri::command]
+async fn set_channel_compressor(
+    db: State<'_, Database>,
+    engine: State<'_, PlaybackEngine>,
+    id: i64,
+    compressor: ChannelCompressor,
+) -> AppResult<AudioChannel> {
+    let conn = db.connection()?;
+    let channel = history::record(
+        &conn,
+        "Edit channel compressor",
+        &[Scope::new("audio_channels", "id = ?1", id)],
+        |conn| store::audio_channels::set_channel_compressor(conn, id, &compressor),
+    )?;
+    refresh_engine_mix(&conn, &engine)?;
+    Ok(channel)
+}
+
+#[tauri::command]
+async fn get_duck_rules(db: State<'_, Database>, sound_set_id: i64) -> AppResult<Vec<DuckRule>> {
+    let conn = db.connection()?;
+    store::dynamics::get_duck_rules(&conn, sound_set_id)
+}
+
+/// Ducks `target_channel_id` under `trigger_channel_id`; unset settings take
+/// the `DEFAULT_DUCK_*` values.
+// Tauri commands take their arguments by name, one per frontend field.
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+async fn create_duck_rule(
+    db: State<'_, Database>,
+    engine: State<'_, PlaybackEngine>,
+    target_channel_id: i64,
+    trigger_channel_id: i64,
+    depth_db: Option<f64>,
+    threshold_db: Option<f64>,
+    attack_ms: Option<f64>,
+    release_ms: Option<f64>,
+) -> AppResult<DuckRule> {
+    use store::dynamics::{
+        DEFAULT_DUCK_ATTACK_MS, DEFAULT_DUCK_DEPTH_DB, DEFAULT_DUCK_RELEASE_MS,
+        DEFAULT_DUCK_THRESHOLD_DB,
+    };
+    let conn = db.connection()?;
+    let rule = history::record(
+        &conn,
+        "Add ducking",
+        &[Scope::new(
+            "channel_duck_rules",
+            "target_channel_id = ?1",
+            target_channel_id,
+        )],
+        |conn| {
+            store::dynamics::create_duck_rule(
+                conn,
+                target_channel_id,
+                trigger_channel_id,
+                depth_db.unwrap_or(DEFAULT_DUCK_DEPTH_DB),
+                threshold_db.unwrap_or(DEFAULT_DUCK_THRESHOLD_DB),
+                attack_ms.unwrap_or(DEFAULT_DUCK_ATTACK_MS),
+                release_ms.unwrap_or(DEFAULT_DUCK_RELEASE_MS),
+            )
+        },
+    )?;
+    refresh_engine_mix(&conn, &engine)?;
+    Ok(rule)
 }
 
 #[tauri::command]
-async fn get_track_elements(
-    app_handle: AppHandle,
-    track_id: i64,
-) -> Result<Vec<TimelineElement>, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let mut stmt = conn.prepare(
-        "
-        SELECT 
-            te.id, 
-            te.track_id, 
-            te.audio_element_id, 
-            te.element_group_id, 
-            te.start_time_ms, 
-            te.duration_ms,
-            COALESCE(
-                (SELECT ss.is_enabled FROM sound_sets ss JOIN audio_elements ae ON ae.sound_set_id = ss.id WHERE ae.id = te.audio_element_id),
-                (SELECT ss.is_enabled FROM sound_sets ss JOIN element_groups eg ON eg.sound_set_id = ss.id WHERE eg.id = te.element_group_id),
-                1
-            ) as is_available
-        FROM timeline_elements te 
-        WHERE te.track_id = ?1 
-        ORDER BY te.start_time_ms ASC
-        "
-    ).map_err(|e| e.to_string())?;
-
-    let elements = stmt
-        .query_map([track_id], |row| {
-            Ok(TimelineElement {
-                id: row.get(0)?,
-                track_id: row.get(1)?,
-                audio_element_id: row.get(2)?,
-                element_group_id: row.get(3)?,
-                start_time_ms: row.get(4)?,
-                duration_ms: row.get(5)?,
-                is_available: row.get::<_, Option<bool>>(6)?.unwrap_or(true),
-            })
-        })
-        .map_err(|e| e.to_string())?;
-
-    let result: Result<Vec<_>, _> = elements.collect();
-    result.map_err(|e| e.to_string())
+async fn update_duck_rule(
+    db: State<'_, Database>,
+    engine: State<'_, PlaybackEngine>,
+    id: i64,
+    depth_db: f64,
+    threshold_db: f64,
+    attack_ms: f64,
+    release_ms: f64,
+) -> AppResult<DuckRule> {
+    let conn = db.connection()?;
+    let rule = history::record(
+        &conn,
+        "Edit ducking",
+        &[Scope::new("channel_duck_rules", "id = ?1", id)],
+        |conn| {
+            store::dynamics::update_duck_rule(
+                conn,
+                id,
+                depth_db,
+                threshold_db,
+                attack_ms,
+                release_ms,
+            )
+        },
+    )?;
+    refresh_engine_mix(&conn, &engine)?;
+    Ok(rule)
 }
 
 #[tauri::command]
-async fn update_element_time_and_duration(
-    app_handle: AppHandle,
+async fn delete_duck_rule(
+    db: State<'_, Database>,
+    engine: State<'_, PlaybackEngine>,
     id: i64,
-    start_time_ms: i64,
-    duration_ms: i64,
-) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let track_id: i64 = conn
-        .query_row(
-            "SELECT track_id FROM timeline_elements WHERE id = ?1",
-            [id],
-            |row| row.get(0),
-        )
-        .map_err(|e| e.to_string())?;
-
-    if check_element_overlap(&conn, track_id, start_time_ms, duration_ms, Some(id))? {
-        return Err("Element overlaps with existing element in track".to_string());
-    }
-
-    conn.execute(
-        "UPDATE timeline_elements SET start_time_ms = ?1, duration_ms = ?2 WHERE id = ?3",
-        [
-            &start_time_ms.to_string(),
-            &duration_ms.to_string(),
-            &id.to_string(),
-        ],
-    )
-    .map_err(|e| e.to_string())?;
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Remove ducking",
+        &[Scope::new("channel_duck_rules", "id = ?1", id)],
+        |conn| store::dynamics::delete_duck_rule(conn, id),
+    )?;
+    refresh_engine_mix(&conn, &engine)
+}
 
-    Ok(())
+#[tauri::command]
+async fn get_master_limiter(db: State<'_, Database>) -> AppResult<MasterLimiter> {
+    let conn = db.connection()?;
+    store::dynamics::get_master_limiter(&conn)
 }
 
 #[tauri::command]
-async fn delete_timeline_element(app_handle: AppHandle, id: i64) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+async fn set_master_limiter(
+    db: State<'_, Database>,
+    engine: State<'_, PlaybackEngine>,
+    limiter: MasterLimiter,
+) -> AppResult<MasterLimiter> {
+    let conn = db.connection()?;
+    let limiter = history::record(
+        &conn,
+        "Edit master limiter",
+        &[Scope::new("master_bus", "id = ?1", 1)],
+        |conn| store::dynamics::set_master_limiter(conn, &limiter),
+    )?;
+    refresh_engine_mix(&conn, &engine)?;
+    Ok(limiter)
+}
 
-    conn.execute("DELETE FROM timeline_elements WHERE id = ?1", [id])
-        .map_err(|e| e.to_string())?;
+#[tauri::command]
+async fn get_channel_effects(
+    db: State<'_, Database>,
+    channel_id: i64,
+) -> AppResult<Vec<ChannelEffect>> {
+    let conn = db.connection()?;
+    store::effects::get_channel_effects(&conn, channel_id)
+}
 
-    Ok(())
+#[tauri::command]
+async fn add_channel_effect(
+    db: State<'_, Database>,
+    engine: State<'_, PlaybackEngine>,
+    channel_id: i64,
+    effect: Effect,
+) -> AppResult<ChannelEffect> {
+    let conn = db.connection()?;
+    let effect = history::record(
+        &conn,
+        "Add effect",
+        &[Scope::new("channel_effects", "channel_id = ?1", channel_id)],
+        |conn| store::effects::add_channel_effect(conn, channel_id, &effect),
+    )?;
+    refresh_engine_mix(&conn, &engine)?;
+    Ok(effect)
 }
 
 #[tauri::command]
-async fn create_audio_channel(
-    app_handle: AppHandle,
-    sound_set_id: i64,
-    name: String,
-    icon: String,
-    volume: f64,
-) -> Result<AudioChannel, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+async fn update_channel_effect(
+    db: State<'_, Database>,
+    engine: State<'_, PlaybackEngine>,
+    id: i64,
+    enabled: bool,
+    effect: Effect,
+) -> AppResult<ChannelEffect> {
+    let conn = db.connection()?;
+    let effect = history::record(
+        &conn,
+        "Edit effect",
+        &[Scope::new("channel_effects", "id = ?1", id)],
+        |conn| store::effects::update_channel_effect(conn, id, enabled, &effect),
+    )?;
+    refresh_engine_mix(&conn, &engine)?;
+    Ok(effect)
+}
 
-    let mut stmt = conn
-        .prepare(
-            "SELECT COALESCE(MAX(order_index), -1) + 1 FROM audio_channels WHERE sound_set_id = ?1",
-        )
-        .map_err(|e| e.to_string())?;
-    let order_index: i64 = stmt
-        .query_row([&sound_set_id], |row| row.get(0))
-        .map_err(|e| e.to_string())?;
-
-    conn.execute(
-        "INSERT INTO audio_channels (sound_set_id, name, icon, volume, order_index) VALUES (?1, ?2, ?3, ?4, ?5)",
-        (&sound_set_id, &name, &icon, &volume, &order_index),
-    ).map_err(|e| e.to_string())?;
-
-    let id = conn.last_insert_rowid();
-
-    Ok(AudioChannel {
-        id,
-        sound_set_id,
-        name,
-        icon,
-        volume,
-        order_index,
-        created_at: chrono::Local::now().to_rfc3339(),
-    })
+/// Moves an effect within its channel's chain and returns the reordered chain.
+#[tauri::command]
+async fn move_channel_effect(
+    db: State<'_, Database>,
+    engine: State<'_, PlaybackEngine>,
+    id: i64,
+    order_index: i64,
+) -> AppResult<Vec<ChannelEffect>> {
+    let conn = db.connection()?;
+    let channel_id = store::effects::get_channel_effect(&conn, id)?.channel_id;
+    let chain = history::record(
+        &conn,
+        "Reorder effect",
+        &[Scope::new("channel_effects", "channel_id = ?1", channel_id)],
+        |conn| store::effects::move_channel_effect(conn, id, order_index),
+    )?;
+    refresh_engine_mix(&conn, &engine)?;
+    Ok(chain)
 }
 
 #[tauri::command]
-async fn get_audio_channels(
-    app_handle: AppHandle,
-    sound_set_id: i64,
-) -> Result<Vec<AudioChannel>, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let mut stmt = conn.prepare(
-        "SELECT id, sound_set_id, name, icon, volume, order_index, created_at FROM audio_channels WHERE sound_set_id = ?1 ORDER BY order_index ASC"
-    ).map_err(|e| e.to_string())?;
-
-    let channels = stmt
-        .query_map([sound_set_id], |row| {
-            Ok(AudioChannel {
-                id: row.get(0)?,
-                sound_set_id: row.get(1)?,
-                name: row.get(2)?,
-                icon: row.get(3)?,
-                volume: row.get(4)?,
-                order_index: row.get(5)?,
-                created_at: row.get(6)?,
-            })
-        })
-        .map_err(|e| e.to_string())?;
+async fn delete_channel_effect(
+    db: State<'_, Database>,
+    engine: State<'_, PlaybackEngine>,
+    id: i64,
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Remove effect",
+        &[Scope::new("channel_effects", "id = ?1", id)],
+        |conn| store::effects::delete_channel_effect(conn, id),
+    )?;
+    refresh_engine_mix(&conn, &engine)
+}
 
-    let result: Result<Vec<_>, _> = channels.collect();
-    result.map_err(|e| e.to_string())
+#[tauri::command]
+async fn list_snapshots(db: State<'_, Database>) -> AppResult<Vec<MixerSnapshot>> {
+    let conn = db.connection()?;
+    store::snapshots::list_snapshots(&conn)
 }
 
+/// Saves the mixer as `name`. Mute, solo and the active mood come from the
+/// frontend mixer; volumes and enabled sound sets are read from the library.
 #[tauri::command]
-async fn update_audio_channel(
-    app_handle: AppHandle,
-    id: i64,
+async fn save_snapshot(
+    db: State<'_, Database>,
     name: String,
-    icon: String,
-    volume: f64,
-) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    conn.execute(
-        "UPDATE audio_channels SET name = ?1, icon = ?2, volume = ?3 WHERE id = ?4",
-        rusqlite::params![name, icon, volume, id],
+    mood_id: Option<i64>,
+    channels: Vec<ChannelToggles>,
+) -> AppResult<MixerSnapshot> {
+    let conn = db.connection()?;
+    // Saving under an existing name rewrites that snapshot in place.
+    let first_id = match store::snapshots::find_snapshot_id(&conn, &name)? {
+        Some(id) => id,
+        None => history::next_id(&conn, "mixer_snapshots")?,
+    };
+    history::record(
+        &conn,
+        "Save snapshot",
+        &[
+            Scope::new("mixer_snapshots", "id >= ?1", first_id),
+            Scope::new("mixer_snapshot_channels", "snapshot_id >= ?1", first_id),
+            Scope::new("mixer_snapshot_sound_sets", "snapshot_id >= ?1", first_id),
+        ],
+        |conn| store::snapshots::save_snapshot(conn, name, mood_id, &channels),
     )
-    .map_err(|e| e.to_string())?;
-
-    Ok(())
 }
 
+/// Restores a snapshot's volumes and enabled sound sets.
+///
+/// `ramp_ms` only applies on the native engine path: the engine glides to the
+/// new volumes, while the webview mixer jumps to them when it reloads the
+/// channels. The engine has no mute or solo, so the snapshot's toggles never
+/// reach it; they come back in the returned snapshot, together with the mood,
+/// for the frontend mixer to apply.
 #[tauri::command]
-async fn delete_audio_channel(app_handle: AppHandle, id: i64) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    conn.execute("DELETE FROM audio_channels WHERE id = ?1", [id])
-        .map_err(|e| e.to_string())?;
+async fn recall_snapshot(
+    db: State<'_, Database>,
+    engine: State<'_, PlaybackEngine>,
+    id: i64,
+    ramp_ms: Option<i64>,
+) -> AppResult<MixerSnapshot> {
+    let ramp_ms = ramp_ms.unwrap_or(0);
+    store::audio_channels::check_range("Ramp time", ramp_ms as f64, 0.0..=60_000.0, " ms")?;
+    let (snapshot, mix) = {
+        let conn = db.connection()?;
+        let snapshot = history::record(
+            &conn,
+            "Recall snapshot",
+            &[
+                Scope::new(
+                    "sound_sets",
+                    "id IN (SELECT sound_set_id FROM mixer_snapshot_sound_sets WHERE snapshot_id = ?1)",
+                    id,
+                ),
+                Scope::new(
+                    "audio_channels",
+                    "id IN (SELECT channel_id FROM mixer_snapshot_channels WHERE snapshot_id = ?1)",
+                    id,
+                ),
+            ],
+            |conn| store::snapshots::recall_snapshot(conn, id),
+        )?;
+        (snapshot, audio::bus::load_mix_settings(&conn)?)
+    };
+    engine.with_mixer(|mixer| {
+        mixer.ramp_mix(mix, ramp_ms);
+        Ok(snapshot)
+    })
+}
 
-    Ok(())
+#[tauri::command]
+async fn delete_snapshot(db: State<'_, Database>, id: i64) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Delete snapshot",
+        &[
+            Scope::new("mixer_snapshots", "id = ?1", id),
+            Scope::new("mixer_snapshot_channels", "snapshot_id = ?1", id),
+            Scope::new("mixer_snapshot_sound_sets", "snapshot_id = ?1", id),
+        ],
+        |conn| store::snapshots::delete_snapshot(conn, id),
+    )
 }
 
 #[tauri::command]
 async fn reorder_audio_channels(
-    app_handle: AppHandle,
+    db: State<'_, Database>,
     id: i64,
     order_index: i64,
-) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    conn.execute(
-        "UPDATE audio_channels SET order_index = ?1 WHERE id = ?2",
-        rusqlite::params![order_index, id],
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Reorder channel",
+        &[Scope::new("audio_channels", "id = ?1", id)],
+        |conn| store::audio_channels::reorder_audio_channels(conn, id, order_index),
     )
-    .map_err(|e| e.to_string())?;
-
-    Ok(())
 }
 
 #[tauri::command]
 async fn seed_default_channels(
-    app_handle: AppHandle,
+    db: State<'_, Database>,
+    engine: State<'_, PlaybackEngine>,
     sound_set_id: i64,
-) -> Result<Vec<AudioChannel>, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let mut stmt = conn
-        .prepare("SELECT count(*) FROM audio_channels WHERE sound_set_id = ?1")
-        .map_err(|e| e.to_string())?;
-    let count: i64 = stmt
-        .query_row([&sound_set_id], |row| row.get(0))
-        .map_err(|e| e.to_string())?;
-
-    if count == 0 {
-        let defaults = [
-            ("Music", "music", 1.0, 0),
-            ("Ambient", "ambient", 1.0, 1),
-            ("Sound Effects", "sfx", 1.0, 2),
-        ];
-
-        for (name, icon, volume, order) in defaults.iter() {
-            conn.execute(
-                "INSERT INTO audio_channels (sound_set_id, name, icon, volume, order_index) VALUES (?1, ?2, ?3, ?4, ?5)",
-                (&sound_set_id, name, icon, volume, order),
-            ).map_err(|e| e.to_string())?;
-        }
-    }
-
-    let mut stmt = conn.prepare(
-        "SELECT id, sound_set_id, name, icon, volume, order_index, created_at FROM audio_channels WHERE sound_set_id = ?1 ORDER BY order_index ASC"
-    ).map_err(|e| e.to_string())?;
-
-    let channels = stmt
-        .query_map([sound_set_id], |row| {
-            Ok(AudioChannel {
-                id: row.get(0)?,
-                sound_set_id: row.get(1)?,
-                name: row.get(2)?,
-                icon: row.get(3)?,
-                volume: row.get(4)?,
-                order_index: row.get(5)?,
-                created_at: row.get(6)?,
-            })
-        })
-        .map_err(|e| e.to_string())?;
-
-    let result: Result<Vec<_>, _> = channels.collect();
-    result.map_err(|e| e.to_string())
+) -> AppResult<Vec<AudioChannel>> {
+    let conn = db.connection()?;
+    let channels = history::record(
+        &conn,
+        "Add default channels",
+        &[Scope::new(
+            "audio_channels",
+            "sound_set_id = ?1",
+            sound_set_id,
+        )],
+        |conn| store::audio_channels::seed_default_channels(conn, sound_set_id),
+    )?;
+    refresh_engine_mix(&conn, &engine)?;
+    Ok(channels)
 }
 
 #[tauri::command]
-async fn init_db_command(app_handle: AppHandle) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-    init_database(&conn).map_err(|e| e.to_string())?;
+async fn init_db_command(db: State<'_, Database>) -> AppResult<()> {
+    let conn = db.connection()?;
+    migrations::run_migrations(&conn)?;
     Ok(())
 }
 
 #[tauri::command]
 async fn create_global_oneshot(
     app_handle: AppHandle,
+    db: State<'_, Database>,
     file_path: String,
     file_name: String,
     channel_type: String,
-) -> Result<AudioElement, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let settings = get_app_settings(app_handle.clone()).await?;
-    let mut final_file_path = file_path.clone();
-
-    if settings.audio_file_strategy == "copy" {
-        let library_dir = if settings.library_path.trim().is_empty() {
-            get_default_library_path(&app_handle)
-        } else {
-            PathBuf::from(&settings.library_path)
-        };
-        if !library_dir.exists() {
-            fs::create_dir_all(&library_dir).map_err(|e| e.to_string())?;
-        }
-
-        let destination = library_dir.join(&file_name);
-        fs::copy(&file_path, &destination).map_err(|e| e.to_string())?;
-        final_file_path = destination.to_string_lossy().to_string();
-    }
+) -> AppResult<AudioElement> {
+    let info = audio::probe::probe_file(Path::new(&file_path))?;
+    let file_path = resolve_audio_file_path(&app_handle, file_path, &file_name)?;
+
+    let element = {
+        let conn = db.connection()?;
+        history::record(
+            &conn,
+            "Add one-shot",
+            &[Scope::new("audio_elements", "sound_set_id IS ?1", None)],
+            |conn| {
+                store::audio_elements::create_audio_element(
+                    conn,
+                    None,
+                    file_path,
+                    file_name,
+                    channel_type,
+                    None,
+                    Some(info),
+                )
+            },
+        )?
+    };
 
-    conn.execute(
-        "INSERT INTO audio_elements (sound_set_id, file_path, file_name, channel_type, channel_id) VALUES (NULL, ?1, ?2, ?3, NULL)",
-        (&final_file_path, &file_name, &channel_type),
-    ).map_err(|e| e.to_string())?;
-
-    let id = conn.last_insert_rowid();
-
-    Ok(AudioElement {
-        id,
-        sound_set_id: None,
-        channel_id: None,
-        file_path: final_file_path,
-        file_name,
-        channel_type,
-        volume_db: 0.0,
-        created_at: chrono::Local::now().to_rfc3339(),
-    })
+    warm_waveform(&app_handle, &db, element.id);
+    Ok(element)
 }
 
 #[tauri::command]
-async fn get_global_oneshots(app_handle: AppHandle) -> Result<Vec<AudioElement>, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let mut stmt = conn.prepare(
-        "SELECT id, sound_set_id, file_path, file_name, channel_type, volume_db, created_at, channel_id FROM audio_elements WHERE sound_set_id IS NULL ORDER BY created_at DESC"
-    ).map_err(|e| e.to_string())?;
-
-    let elements = stmt
-        .query_map([], |row| {
-            Ok(AudioElement {
-                id: row.get(0)?,
-                sound_set_id: row.get(1)?,
-                file_path: row.get(2)?,
-                file_name: row.get(3)?,
-                channel_type: row.get(4)?,
-                volume_db: row.get(5)?,
-                created_at: row.get(6)?,
-                channel_id: row.get(7)?,
-            })
-        })
-        .map_err(|e| e.to_string())?;
-
-    let result: Result<Vec<_>, _> = elements.collect();
-    result.map_err(|e| e.to_string())
+async fn get_global_oneshots(db: State<'_, Database>) -> AppResult<Vec<AudioElement>> {
+    let conn = db.connection()?;
+    store::audio_elements::get_global_oneshots(&conn)
 }
 
 #[tauri::command]
-async fn delete_global_oneshot(app_handle: AppHandle, id: i64) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    conn.execute(
-        "DELETE FROM audio_elements WHERE id = ?1 AND sound_set_id IS NULL",
-        [id],
+async fn delete_global_oneshot(db: State<'_, Database>, id: i64) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Delete one-shot",
+        &[
+            Scope::new("audio_elements", "id = ?1", id),
+            Scope::new("element_group_members", "audio_element_id = ?1", id),
+            Scope::new("timeline_elements", "audio_element_id = ?1", id),
+        ],
+        |conn| store::audio_elements::delete_global_oneshot(conn, id),
     )
-    .map_err(|e| e.to_string())?;
-
-    Ok(())
 }
 
 #[tauri::command]
 async fn create_element_group(
-    app_handle: AppHandle,
+    db: State<'_, Database>,
     name: String,
     sound_set_id: Option<i64>,
-) -> Result<ElementGroup, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    conn.execute(
-        "INSERT INTO element_groups (name, sound_set_id) VALUES (?1, ?2)",
-        (&name, &sound_set_id),
+) -> AppResult<ElementGroup> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Create group",
+        &[Scope::new(
+            "element_groups",
+            "sound_set_id IS ?1",
+            sound_set_id,
+        )],
+        |conn| store::element_groups::create_element_group(conn, name, sound_set_id),
     )
-    .map_err(|e| e.to_string())?;
-
-    let id = conn.last_insert_rowid();
-
-    Ok(ElementGroup {
-        id,
-        name,
-        sound_set_id,
-        created_at: chrono::Local::now().to_rfc3339(),
-    })
 }
 
 #[tauri::command]
-async fn rename_element_group(app_handle: AppHandle, id: i64, name: String) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    conn.execute(
-        "UPDATE element_groups SET name = ?1 WHERE id = ?2",
-        (&name, &id),
+async fn rename_element_group(db: State<'_, Database>, id: i64, name: String) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Rename group",
+        &[Scope::new("element_groups", "id = ?1", id)],
+        |conn| store::element_groups::rename_element_group(conn, id, &name),
     )
-    .map_err(|e| e.to_string())?;
-
-    Ok(())
 }
 
 #[tauri::command]
-async fn delete_element_group(app_handle: AppHandle, id: i64) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+async fn update_element_group_metadata(
+    db: State<'_, Database>,
+    id: i64,
+    notes: String,
+    rating: Option<i64>,
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Edit group notes",
+        &[Scope::new("element_groups", "id = ?1", id)],
+        |conn| store::element_groups::update_element_group_metadata(conn, id, &notes, rating),
+    )
+}
 
-    conn.execute("DELETE FROM element_groups WHERE id = ?1", [id])
-        .map_err(|e| e.to_string())?;
+#[tauri::command]
+async fn update_element_group_playback(
+    db: State<'_, Database>,
+    id: i64,
+    playback_mode: PlaybackMode,
+    pitch_variation_semitones: f64,
+    volume_variation_db: f64,
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Change group playback",
+        &[Scope::new("element_groups", "id = ?1", id)],
+        |conn| {
+            store::element_groups::update_element_group_playback(
+                conn,
+                id,
+                playback_mode,
+                pitch_variation_semitones,
+                volume_variation_db,
+            )
+        },
+    )
+}
 
-    Ok(())
+#[tauri::command]
+async fn delete_element_group(db: State<'_, Database>, id: i64) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Delete group",
+        &[
+            Scope::new("element_groups", "id = ?1", id),
+            Scope::new("element_group_members", "group_id = ?1", id),
+            Scope::new("timeline_elements", "element_group_id = ?1", id),
+        ],
+        |conn| store::element_groups::delete_element_group(conn, id),
+    )
 }
 
 #[tauri::command]
 async fn get_element_groups(
-    app_handle: AppHandle,
+    db: State<'_, Database>,
     sound_set_id: Option<i64>,
-) -> Result<Vec<ElementGroup>, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let mut stmt = if sound_set_id.is_some() {
-        conn.prepare("SELECT id, name, sound_set_id, created_at FROM element_groups WHERE sound_set_id = ?1 ORDER BY created_at DESC")
-    } else {
-        conn.prepare("SELECT id, name, sound_set_id, created_at FROM element_groups WHERE sound_set_id IS NULL ORDER BY created_at DESC")
-    }.map_err(|e| e.to_string())?;
-
-    let elements = if let Some(sid) = sound_set_id {
-        stmt.query_map([sid], |row| {
-            Ok(ElementGroup {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                sound_set_id: row.get(2)?,
-                created_at: row.get(3)?,
-            })
-        })
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-    } else {
-        stmt.query_map([], |row| {
-            Ok(ElementGroup {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                sound_set_id: row.get(2)?,
-                created_at: row.get(3)?,
-            })
-        })
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-    };
-
-    elements.map_err(|e| e.to_string())
+    tag: Option<String>,
+) -> AppResult<Vec<ElementGroup>> {
+    let conn = db.connection()?;
+    store::element_groups::get_element_groups(&conn, sound_set_id, tag.as_deref())
 }
 
 #[tauri::command]
-async fn get_all_available_element_groups(
-    app_handle: AppHandle,
-) -> Result<Vec<ElementGroup>, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let mut stmt = conn
-        .prepare(
-            "SELECT g.id, g.name, g.sound_set_id, g.created_at 
-         FROM element_groups g
-         LEFT JOIN sound_sets s ON g.sound_set_id = s.id
-         WHERE g.sound_set_id IS NULL OR s.is_enabled = 1
-         ORDER BY g.created_at DESC",
-        )
-        .map_err(|e| e.to_string())?;
-
-    let groups = stmt
-        .query_map([], |row| {
-            Ok(ElementGroup {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                sound_set_id: row.get(2)?,
-                created_at: row.get(3)?,
-            })
-        })
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| e.to_string())?;
-
-    Ok(groups)
+async fn get_all_available_element_groups(db: State<'_, Database>) -> AppResult<Vec<ElementGroup>> {
+    let conn = db.connection()?;
+    store::element_groups::get_all_available_element_groups(&conn)
 }
 
 #[tauri::command]
 async fn add_element_to_group(
-    app_handle: AppHandle,
+    db: State<'_, Database>,
     group_id: i64,
     audio_element_id: i64,
-) -> Result<ElementGroupMember, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let mut stmt = conn
-        .prepare("SELECT COALESCE(MAX(order_index), -1) + 1 FROM element_group_members WHERE group_id = ?1")
-        .map_err(|e| e.to_string())?;
+) -> AppResult<ElementGroupMember> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Add element to group",
+        &[Scope::new(
+            "element_group_members",
+            "group_id = ?1",
+            group_id,
+        )],
+        |conn| store::element_groups::add_element_to_group(conn, group_id, audio_element_id),
+    )
+}
 
-    let order_index: i64 = stmt
-        .query_row([&group_id], |row| row.get(0))
-        .map_err(|e| e.to_string())?;
+#[tauri::command]
+async fn remove_element_from_group(db: State<'_, Database>, id: i64) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Remove element from group",
+        &[Scope::new("element_group_members", "id = ?1", id)],
+        |conn| store::element_groups::remove_element_from_group(conn, id),
+    )
+}
 
-    conn.execute(
-        "INSERT INTO element_group_members (group_id, audio_element_id, order_index) VALUES (?1, ?2, ?3)",
-        (&group_id, &audio_element_id, &order_index),
+#[tauri::command]
+async fn update_group_member_weight(
+    db: State<'_, Database>,
+    id: i64,
+    weight: f64,
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Change member weight",
+        &[Scope::new("element_group_members", "id = ?1", id)],
+        |conn| store::element_groups::update_group_member_weight(conn, id, weight),
     )
-    .map_err(|e| e.to_string())?;
+}
 
-    let id = conn.last_insert_rowid();
+#[tauri::command]
+async fn pick_group_member(
+    db: State<'_, Database>,
+    group_id: i64,
+    seed: u64,
+    play_index: u64,
+    previous_member_id: Option<i64>,
+) -> AppResult<GroupPick> {
+    let conn = db.connection()?;
+    store::group_playback::pick_group_member(&conn, group_id, seed, play_index, previous_member_id)
+}
 
-    Ok(ElementGroupMember {
-        id,
-        group_id,
-        audio_element_id,
-        order_index,
-    })
+#[tauri::command]
+async fn get_group_members(
+    db: State<'_, Database>,
+    group_id: i64,
+) -> AppResult<Vec<ElementGroupMember>> {
+    let conn = db.connection()?;
+    store::element_groups::get_group_members(&conn, group_id)
 }
 
 #[tauri::command]
-async fn remove_element_from_group(app_handle: AppHandle, id: i64) -> Result<(), String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+async fn undo(
+    db: State<'_, Database>,
+    engine: State<'_, PlaybackEngine>,
+) -> AppResult<Option<history::HistoryEntry>> {
+    let conn = db.connection()?;
+    let entry = history::undo(&conn)?;
+    refresh_engine_mix(&conn, &engine)?;
+    Ok(entry)
+}
 
-    conn.execute("DELETE FROM element_group_members WHERE id = ?1", [id])
-        .map_err(|e| e.to_string())?;
+#[tauri::command]
+async fn redo(
+    db: State<'_, Database>,
+    engine: State<'_, PlaybackEngine>,
+) -> AppResult<Option<history::HistoryEntry>> {
+    let conn = db.connection()?;
+    let entry = history::redo(&conn)?;
+    refresh_engine_mix(&conn, &engine)?;
+    Ok(entry)
+}
 
-    Ok(())
+#[tauri::command]
+async fn get_history_state(db: State<'_, Database>) -> AppResult<history::HistoryState> {
+    let conn = db.connection()?;
+    history::get_history_state(&conn)
 }
 
 #[tauri::command]
-async fn get_group_members(
+async fn list_trash(db: State<'_, Database>) -> AppResult<Vec<TrashItem>> {
+    let conn = db.connection()?;
+    store::trash::list_trash(&conn)
+}
+
+/// Permanently deletes whatever has outlived the configured retention. The
+/// app does this at startup; a long-running session can call it again.
+#[tauri::command]
+async fn purge_expired_trash(
     app_handle: AppHandle,
-    group_id: i64,
-) -> Result<Vec<ElementGroupMember>, String> {
-    let db_path = get_db_path(&app_handle);
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let mut stmt = conn.prepare(
-        "SELECT id, group_id, audio_element_id, order_index FROM element_group_members WHERE group_id = ?1 ORDER BY order_index ASC"
-    ).map_err(|e| e.to_string())?;
-
-    let members = stmt
-        .query_map([group_id], |row| {
-            Ok(ElementGroupMember {
-                id: row.get(0)?,
-                group_id: row.get(1)?,
-                audio_element_id: row.get(2)?,
-                order_index: row.get(3)?,
-            })
-        })
-        .map_err(|e| e.to_string())?;
+    db: State<'_, Database>,
+    engine: State<'_, PlaybackEngine>,
+) -> AppResult<usize> {
+    let retention_days = read_app_settings(&app_handle).trash_retention_days;
+    let conn = db.connection()?;
+    let purged = store::trash::purge_expired_trash(&conn, retention_days)?;
+    refresh_engine_mix(&conn, &engine)?;
+    Ok(purged)
+}
+
+#[tauri::command]
+async fn restore_from_trash(db: State<'_, Database>, kind: TrashKind, id: i64) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Restore from trash",
+        &[Scope::new(kind.table(), "id = ?1", id)],
+        |conn| store::trash::restore_from_trash(conn, kind, id),
+    )
+}
+
+#[tauri::command]
+async fn empty_trash(
+    db: State<'_, Database>,
+    engine: State<'_, PlaybackEngine>,
+) -> AppResult<usize> {
+    let conn = db.connection()?;
+    let purged = history::record(
+        &conn,
+        "Empty trash",
+        &store::trash::purge_scopes(&conn)?,
+        store::trash::empty_trash,
+    )?;
+    refresh_engine_mix(&conn, &engine)?;
+    Ok(purged)
+}
+
+#[tauri::command]
+async fn search_library(
+    db: State<'_, Database>,
+    query: String,
+    include_disabled: Option<bool>,
+    limit: Option<i64>,
+) -> AppResult<Vec<SearchHit>> {
+    let conn = db.connection()?;
+    store::search::search_library(
+        &conn,
+        &query,
+        include_disabled.unwrap_or(false),
+        limit.unwrap_or(store::search::DEFAULT_SEARCH_LIMIT),
+    )
+}
+
+#[tauri::command]
+async fn list_tags(db: State<'_, Database>) -> AppResult<Vec<Tag>> {
+    let conn = db.connection()?;
+    store::tags::list_tags(&conn)
+}
+
+#[tauri::command]
+async fn tag_items(
+    db: State<'_, Database>,
+    target: TagTarget,
+    item_ids: Vec<i64>,
+    tags: Vec<String>,
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Tag items",
+        &store::tags::tag_scopes(target, &item_ids),
+        |conn| store::tags::tag_items(conn, target, &item_ids, &tags),
+    )
+}
 
-    let result: Result<Vec<_>, _> = members.collect();
-    result.map_err(|e| e.to_string())
+#[tauri::command]
+async fn untag_items(
+    db: State<'_, Database>,
+    target: TagTarget,
+    item_ids: Vec<i64>,
+    tags: Vec<String>,
+) -> AppResult<()> {
+    let conn = db.connection()?;
+    history::record(
+        &conn,
+        "Untag items",
+        &store::tags::tag_scopes(target, &item_ids),
+        |conn| store::tags::untag_items(conn, target, &item_ids, &tags),
+    )
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -1776,6 +2170,22 @@ pub fn run() {
             update_audio_channel,
             delete_audio_channel,
             reorder_audio_channels,
+            set_channel_compressor,
+            get_duck_rules,
+            create_duck_rule,
+            update_duck_rule,
+            delete_duck_rule,
+            get_master_limiter,
+            set_master_limiter,
+            get_channel_effects,
+            add_channel_effect,
+            update_channel_effect,
+            move_channel_effect,
+            delete_channel_effect,
+            list_snapshots,
+            save_snapshot,
+            recall_snapshot,
+            delete_snapshot,
             seed_default_channels,
             create_sound_set,
             get_sound_sets,
@@ -1784,7 +2194,33 @@ pub fn run() {
             create_mood,
             get_moods,
             delete_mood,
+            duplicate_mood,
+            copy_track_to_mood,
+            get_timeline_templates,
+            save_timeline_template,
+            delete_timeline_template,
+            instantiate_timeline_template,
+            get_mood_transitions,
+            set_mood_transition,
+            delete_mood_transition,
+            plan_mood_transition,
             create_audio_element,
+            probe_audio_element,
+            analyze_audio_element,
+            detect_loop_points,
+            render_timeline,
+            normalize_sound_set_loudness,
+            engine_start,
+            engine_stop,
+            engine_status,
+            engine_play_timeline,
+            engine_stop_timeline,
+            engine_seek,
+            engine_jump_to_marker,
+            engine_play_oneshot,
+            engine_stop_oneshots,
+            engine_set_master_volume,
+            get_waveform_peaks,
             get_audio_elements,
             get_all_available_audio_elements,
             delete_audio_element,
@@ -1793,18 +2229,39 @@ pub fn run() {
             delete_global_oneshot,
             update_audio_element_channel,
             update_audio_element_channel_id,
+            update_audio_element_metadata,
             create_timeline,
             get_timelines,
             delete_timeline,
             update_timeline_loop,
+            get_timeline_markers,
+            create_timeline_marker,
+            update_timeline_marker,
+            delete_timeline_marker,
+            set_timeline_loop_region,
+            jump_to_marker,
+            get_tempo_changes,
+            set_tempo_change,
+            delete_tempo_change,
+            ms_to_bars,
+            bars_to_ms,
             create_timeline_track,
             get_timeline_tracks,
             update_timeline_track_looping,
             delete_timeline_track,
             update_timeline_track_order,
+            get_track_gain_points,
+            get_track_gain_at,
+            add_track_gain_point,
+            move_track_gain_point,
+            delete_track_gain_point,
             add_element_to_track,
             get_track_elements,
             update_element_time_and_duration,
+            apply_element_edits,
+            move_element_to_track,
+            ripple_move,
+            update_element_fades,
             delete_timeline_element,
             export_sound_set,
             import_sound_set,
@@ -1818,18 +2275,37 @@ pub fn run() {
             get_group_members,
             create_element_group,
             rename_element_group,
+            update_element_group_metadata,
+            update_element_group_playback,
             delete_element_group,
             get_element_groups,
             get_all_available_element_groups,
             add_element_to_group,
             remove_element_from_group,
+            update_group_member_weight,
+            pick_group_member,
+            undo,
+            redo,
+            get_history_state,
+            list_trash,
+            purge_expired_trash,
+            restore_from_trash,
+            empty_trash,
+            search_library,
+            list_tags,
+            tag_items,
+            untag_items,
         ])
         .setup(|app| {
-            let app_handle = app.handle();
-            let db_path = get_db_path(&app_handle);
-            if let Ok(conn) = Connection::open(db_path) {
-                let _ = init_database(&conn);
+            let db_path = get_db_path(app.handle());
+            let database = Database::open(&db_path)?;
+            let retention_days = read_app_settings(app.handle()).trash_retention_days;
+            {
+                let conn = database.connection()?;
+                store::trash::purge_expired_trash(&conn, retention_days)?;
             }
+            app.manage(database);
+            app.manage(PlaybackEngine::default());
             Ok(())
         })
         .build(tauri::generate_context!())
@@ -1837,7 +2313,11 @@ pub fn run() {
 
     app.run(|app_handle, event| {
         if let tauri::RunEvent::ExitRequested { .. } = event {
-            tauri::async_runtime::block_on(discord::shutdown_discord_connection(app_handle.clone()));
+            // Finishes an engine recording before the process goes away.
+            let _ = app_handle.state::<PlaybackEngine>().stop();
+            tauri::async_runtime::block_on(discord::shutdown_discord_connection(
+                app_handle.clone(),
+            ));
         }
     });
 }
@@ -1882,7 +2362,7 @@ mod tests {
         .unwrap();
 
         // Run full database init which includes the migration
-        let _ = init_database(&conn).unwrap();
+        migrations::run_migrations(&conn).unwrap();
 
         // Verify deductions
         let mut stmt = conn
@@ -1906,142 +2386,4 @@ mod tests {
         );
         assert!(err.is_err());
     }
-
-    #[test]
-    fn test_element_overlap_rejection() {
-        let conn = Connection::open_in_memory().unwrap();
-        let _ = init_database(&conn).unwrap();
-
-        // Setup base tables
-        conn.execute(
-            "INSERT INTO sound_sets (id, name, description) VALUES (1, 'S', 'D')",
-            [],
-        )
-        .unwrap();
-        conn.execute("INSERT INTO audio_elements (id, sound_set_id, file_path, file_name, channel_type) VALUES (100, 1, 'path', 'f', 'music')", []).unwrap();
-        conn.execute("INSERT INTO moods (id, name) VALUES (1, 'M')", [])
-            .unwrap();
-        conn.execute(
-            "INSERT INTO timelines (id, mood_id, name) VALUES (1, 1, 'T')",
-            [],
-        )
-        .unwrap();
-        conn.execute(
-            "INSERT INTO timeline_tracks (id, timeline_id, name, is_looping) VALUES (5, 1, 'Trk', 0)",
-            [],
-        )
-        .unwrap();
-
-        // Add element 1: 1000ms to 3000ms (duration 2000ms)
-        conn.execute("INSERT INTO timeline_elements (id, track_id, audio_element_id, start_time_ms, duration_ms) VALUES (10, 5, 100, 1000, 2000)", []).unwrap();
-
-        // Test overlap checks
-        // 1. Completely before (0-500) - OK
-        assert!(!check_element_overlap(&conn, 5, 0, 500, None).unwrap());
-
-        // 2. Exactly before (0-1000) - OK
-        assert!(!check_element_overlap(&conn, 5, 0, 1000, None).unwrap());
-
-        // 3. Completely after (3000-4000) - OK
-        assert!(!check_element_overlap(&conn, 5, 3000, 1000, None).unwrap());
-
-        // 4. Overlap start (500-1500) - BAD
-        assert!(check_element_overlap(&conn, 5, 500, 1000, None).unwrap());
-
-        // 5. Overlap end (2500-3500) - BAD
-        assert!(check_element_overlap(&conn, 5, 2500, 1000, None).unwrap());
-
-        // 6. Enclosing (500-4000) - BAD
-        assert!(check_element_overlap(&conn, 5, 500, 3500, None).unwrap());
-
-        // 7. Contained (1500-2500) - BAD
-        assert!(check_element_overlap(&conn, 5, 1500, 1000, None).unwrap());
-
-        // 8. Overlapping itself when excluded - OK
-        assert!(!check_element_overlap(&conn, 5, 500, 1500, Some(10)).unwrap());
-
-        // 9. Different track overlap - OK
-        assert!(!check_element_overlap(&conn, 6, 1500, 1000, None).unwrap());
-    }
-
-    #[test]
-    fn test_sound_set_activation_logic() {
-        let conn = Connection::open_in_memory().unwrap();
-        let _ = init_database(&conn).unwrap();
-
-        // 1. Setup SoundSet and AudioElement
-        conn.execute(
-            "INSERT INTO sound_sets (id, name, is_enabled) VALUES (1, 'S1', 1)",
-            [],
-        )
-        .unwrap();
-        conn.execute(
-            "INSERT INTO sound_sets (id, name, is_enabled) VALUES (2, 'S2', 0)",
-            [],
-        )
-        .unwrap();
-
-        conn.execute("INSERT INTO audio_elements (id, sound_set_id, file_path, file_name, channel_type) VALUES (10, 1, 'p1', 'f1', 'music')", []).unwrap();
-        conn.execute("INSERT INTO audio_elements (id, sound_set_id, file_path, file_name, channel_type) VALUES (20, 2, 'p2', 'f2', 'music')", []).unwrap();
-
-        conn.execute(
-            "INSERT INTO element_groups (id, sound_set_id, name) VALUES (30, 1, 'g1')",
-            [],
-        )
-        .unwrap();
-        conn.execute(
-            "INSERT INTO element_groups (id, sound_set_id, name) VALUES (40, 2, 'g2')",
-            [],
-        )
-        .unwrap();
-
-        conn.execute("INSERT INTO moods (id, name) VALUES (1, 'M')", [])
-            .unwrap();
-        conn.execute(
-            "INSERT INTO timelines (id, mood_id, name) VALUES (1, 1, 'T')",
-            [],
-        )
-        .unwrap();
-        conn.execute(
-            "INSERT INTO timeline_tracks (id, timeline_id, name) VALUES (1, 1, 'Trk')",
-            [],
-        )
-        .unwrap();
-
-        // Add elements to timeline
-        conn.execute("INSERT INTO timeline_elements (id, track_id, audio_element_id, start_time_ms, duration_ms) VALUES (1, 1, 10, 0, 1000)", []).unwrap();
-        conn.execute("INSERT INTO timeline_elements (id, track_id, audio_element_id, start_time_ms, duration_ms) VALUES (2, 1, 20, 1000, 1000)", []).unwrap();
-        conn.execute("INSERT INTO timeline_elements (id, track_id, element_group_id, start_time_ms, duration_ms) VALUES (3, 1, 30, 2000, 1000)", []).unwrap();
-        conn.execute("INSERT INTO timeline_elements (id, track_id, element_group_id, start_time_ms, duration_ms) VALUES (4, 1, 40, 3000, 1000)", []).unwrap();
-
-        // Verify get_track_elements availability
-        let mut stmt = conn.prepare("
-            SELECT id, is_available FROM (
-                SELECT 
-                    te.id, 
-                    COALESCE(
-                        (SELECT ss.is_enabled FROM sound_sets ss JOIN audio_elements ae ON ae.sound_set_id = ss.id WHERE ae.id = te.audio_element_id),
-                        (SELECT ss.is_enabled FROM sound_sets ss JOIN element_groups eg ON eg.sound_set_id = ss.id WHERE eg.id = te.element_group_id),
-                        1
-                    ) as is_available
-                FROM timeline_elements te 
-                WHERE te.track_id = 1
-            )
-        ").unwrap();
-
-        let results: Vec<(i64, bool)> = stmt
-            .query_map([], |row| Ok((row.get(0)?, row.get::<_, i32>(1)? != 0)))
-            .unwrap()
-            .collect::<Result<_, _>>()
-            .unwrap();
-
-        // Element 1 (S1 enabled) -> true
-        assert!(results.iter().find(|(id, _)| *id == 1).unwrap().1);
-        // Element 2 (S2 disabled) -> false
-        assert!(!results.iter().find(|(id, _)| *id == 2).unwrap().1);
-        // Element 3 (Group S1 enabled) -> true
-        assert!(results.iter().find(|(id, _)| *id == 3).unwrap().1);
-        // Element 4 (Group S2 disabled) -> false
-        assert!(!results.iter().find(|(id, _)| *id == 4).unwrap().1);
-    }
 }
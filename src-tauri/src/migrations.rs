@@ -0,0 +1,1355 @@
+use rusqlite::{Connection, Result as SqliteResult};
+use std::fmt;
+
+/// A single, numbered schema change.
+///
+/// Migrations are applied in ascending `version` order and each one runs at most
+/// once per database: the highest applied version is stored in SQLite's
+/// `PRAGMA user_version`, and bumped inside the same transaction as the change.
+pub(crate) struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub up: fn(&Connection) -> SqliteResult<()>,
+}
+
+#[derive(Debug)]
+pub struct MigrationError {
+    pub version: i64,
+    pub name: &'static str,
+    pub source: rusqlite::Error,
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Migration {} ({}) failed: {}",
+            self.version, self.name, self.source
+        )
+    }
+}
+
+impl std::error::Error for MigrationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Ordered migration registry. Append new entries at the end; never renumber or
+/// edit a migration that has already shipped.
+///
+/// Versions 2 through 10 reproduce the upgrades that `init_database` used to
+/// detect with `PRAGMA table_info` on every startup. Databases created before
+/// the registry existed report `user_version = 0`, so those steps still inspect
+/// the schema to decide whether there is anything to do, but only once.
+pub(crate) const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_base_schema",
+        up: create_base_schema,
+    },
+    Migration {
+        version: 2,
+        name: "audio_elements_sound_set_id",
+        up: audio_elements_sound_set_id,
+    },
+    Migration {
+        version: 3,
+        name: "sound_sets_is_enabled",
+        up: sound_sets_is_enabled,
+    },
+    Migration {
+        version: 4,
+        name: "global_moods",
+        up: global_moods,
+    },
+    Migration {
+        version: 5,
+        name: "timelines_is_looping",
+        up: timelines_is_looping,
+    },
+    Migration {
+        version: 6,
+        name: "timeline_tracks_is_looping",
+        up: timeline_tracks_is_looping,
+    },
+    Migration {
+        version: 7,
+        name: "audio_elements_channel_id",
+        up: audio_elements_channel_id,
+    },
+    Migration {
+        version: 8,
+        name: "timeline_elements_tracks_and_groups",
+        up: timeline_elements_tracks_and_groups,
+    },
+    Migration {
+        version: 9,
+        name: "unique_timeline_per_mood",
+        up: unique_timeline_per_mood,
+    },
+    Migration {
+        version: 10,
+        name: "global_oneshots",
+        up: global_oneshots,
+    },
+    Migration {
+        version: 11,
+        name: "edit_history",
+        up: edit_history,
+    },
+    Migration {
+        version: 12,
+        name: "soft_delete",
+        up: soft_delete,
+    },
+    Migration {
+        version: 13,
+        name: "library_search",
+        up: library_search,
+    },
+    Migration {
+        version: 14,
+        name: "tags_and_metadata",
+        up: tags_and_metadata,
+    },
+    Migration {
+        version: 15,
+        name: "timeline_element_fades",
+        up: timeline_element_fades,
+    },
+    Migration {
+        version: 16,
+        name: "track_gain_automation",
+        up: track_gain_automation,
+    },
+    Migration {
+        version: 17,
+        name: "group_playback_modes",
+        up: group_playback_modes,
+    },
+    Migration {
+        version: 18,
+        name: "mood_transitions",
+        up: mood_transitions,
+    },
+    Migration {
+        version: 19,
+        name: "timeline_markers",
+        up: timeline_markers,
+    },
+    Migration {
+        version: 20,
+        name: "timeline_tempo_map",
+        up: timeline_tempo_map,
+    },
+    Migration {
+        version: 21,
+        name: "timeline_templates",
+        up: timeline_templates,
+    },
+    Migration {
+        version: 22,
+        name: "audio_element_probe",
+        up: audio_element_probe,
+    },
+    Migration {
+        version: 23,
+        name: "audio_element_content_hash",
+        up: audio_element_content_hash,
+    },
+    Migration {
+        version: 24,
+        name: "audio_element_loudness",
+        up: audio_element_loudness,
+    },
+    Migration {
+        version: 25,
+        name: "audio_element_loop_points",
+        up: audio_element_loop_points,
+    },
+    Migration {
+        version: 26,
+        name: "mixer_dynamics",
+        up: mixer_dynamics,
+    },
+    Migration {
+        version: 27,
+        name: "channel_effects",
+        up: channel_effects,
+    },
+    Migration {
+        version: 28,
+        name: "mixer_snapshots",
+        up: mixer_snapshots,
+    },
+    Migration {
+        version: 29,
+        name: "audio_element_content_stamp",
+        up: audio_element_content_stamp,
+    },
+];
+
+/// The schema version a fully migrated database reports.
+pub(crate) fn latest_version() -> i64 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+pub(crate) fn run_migrations(conn: &Connection) -> Result<(), MigrationError> {
+    apply_migrations(conn, MIGRATIONS)
+}
+
+pub(crate) fn apply_migrations(
+    conn: &Connection,
+    migrations: &[Migration],
+) -> Result<(), MigrationError> {
+    let current = schema_version(conn).map_err(|source| MigrationError {
+        version: 0,
+        name: "read_user_version",
+        source,
+    })?;
+
+    let pending: Vec<&Migration> = migrations.iter().filter(|m| m.version > current).collect();
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    // Table rebuilds drop and rename tables that other tables reference, so
+    // foreign key enforcement has to be off while they run. The pragma is a
+    // no-op inside a transaction, which is why it wraps the whole batch.
+    let foreign_keys_were_on: bool = conn
+        .query_row("PRAGMA foreign_keys", [], |row| row.get(0))
+        .unwrap_or(false);
+    if foreign_keys_were_on {
+        let _ = conn.execute_batch("PRAGMA foreign_keys=off;");
+    }
+
+    let result = pending
+        .into_iter()
+        .try_for_each(|migration| apply_migration(conn, migration));
+
+    if foreign_keys_were_on {
+        let _ = conn.execute_batch("PRAGMA foreign_keys=on;");
+    }
+
+    result
+}
+
+fn apply_migration(conn: &Connection, migration: &Migration) -> Result<(), MigrationError> {
+    let to_error = |source| MigrationError {
+        version: migration.version,
+        name: migration.name,
+        source,
+    };
+
+    // Dropping the transaction without committing rolls it back, so a failing
+    // step leaves neither a bumped user_version nor half-built `*_new` tables.
+    let tx = conn.unchecked_transaction().map_err(to_error)?;
+    (migration.up)(&tx).map_err(to_error)?;
+    check_foreign_keys(&tx).map_err(to_error)?;
+    tx.pragma_update(None, "user_version", migration.version)
+        .map_err(to_error)?;
+    tx.commit().map_err(to_error)
+}
+
+/// Fails on the first row whose foreign key points at nothing. Enforcement is
+/// off while migrations run, so a rebuild that drops referenced rows would
+/// otherwise commit without complaint.
+fn check_foreign_keys(conn: &Connection) -> SqliteResult<()> {
+    let mut stmt = conn.prepare("PRAGMA foreign_key_check")?;
+    let mut rows = stmt.query([])?;
+    match rows.next()? {
+        Some(row) => {
+            let table: String = row.get(0)?;
+            let rowid: Option<i64> = row.get(1)?;
+            let parent: String = row.get(2)?;
+            Err(rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CONSTRAINT_FOREIGNKEY),
+                Some(format!(
+                    "{} row {} references a missing {} row",
+                    table,
+                    rowid.map_or_else(|| "?".to_string(), |rowid| rowid.to_string()),
+                    parent
+                )),
+            ))
+        }
+        None => Ok(()),
+    }
+}
+
+pub(crate) fn schema_version(conn: &Connection) -> SqliteResult<i64> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> SqliteResult<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let names = stmt.query_map([], |row| row.get::<_, String>(1))?;
+    for name in names {
+        if name? == column {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn is_column_not_null(conn: &Connection, table: &str, column: &str) -> SqliteResult<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let columns = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(1)?, row.get::<_, bool>(3)?))
+    })?;
+    for column_info in columns {
+        let (name, not_null) = column_info?;
+        if name == column {
+            return Ok(not_null);
+        }
+    }
+    Ok(false)
+}
+
+fn create_base_schema(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS sound_sets (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            description TEXT,
+            is_enabled INTEGER DEFAULT 1,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE TABLE IF NOT EXISTS moods (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            description TEXT,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE TABLE IF NOT EXISTS audio_channels (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            sound_set_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            icon TEXT NOT NULL DEFAULT 'generic',
+            volume REAL NOT NULL DEFAULT 1.0,
+            order_index INTEGER NOT NULL DEFAULT 0,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (sound_set_id) REFERENCES sound_sets(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS audio_elements (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            sound_set_id INTEGER,
+            channel_id INTEGER,
+            file_path TEXT NOT NULL,
+            file_name TEXT NOT NULL,
+            channel_type TEXT DEFAULT 'ambient',
+            volume_db REAL DEFAULT 0.0,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (sound_set_id) REFERENCES sound_sets(id) ON DELETE CASCADE,
+            FOREIGN KEY (channel_id) REFERENCES audio_channels(id) ON DELETE SET NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS timelines (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            mood_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            order_index INTEGER DEFAULT 0,
+            is_looping INTEGER DEFAULT 0,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (mood_id) REFERENCES moods(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS timeline_tracks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timeline_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            order_index INTEGER DEFAULT 0,
+            is_looping INTEGER DEFAULT 0,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (timeline_id) REFERENCES timelines(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS element_groups (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            sound_set_id INTEGER,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (sound_set_id) REFERENCES sound_sets(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS element_group_members (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            group_id INTEGER NOT NULL,
+            audio_element_id INTEGER NOT NULL,
+            order_index INTEGER DEFAULT 0,
+            FOREIGN KEY (group_id) REFERENCES element_groups(id) ON DELETE CASCADE,
+            FOREIGN KEY (audio_element_id) REFERENCES audio_elements(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS timeline_elements (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timeline_id INTEGER,
+            track_id INTEGER,
+            audio_element_id INTEGER,
+            element_group_id INTEGER,
+            start_time_ms INTEGER DEFAULT 0,
+            duration_ms INTEGER DEFAULT 0,
+            FOREIGN KEY (timeline_id) REFERENCES timelines(id) ON DELETE CASCADE,
+            FOREIGN KEY (track_id) REFERENCES timeline_tracks(id) ON DELETE CASCADE,
+            FOREIGN KEY (audio_element_id) REFERENCES audio_elements(id) ON DELETE CASCADE,
+            FOREIGN KEY (element_group_id) REFERENCES element_groups(id) ON DELETE CASCADE
+        );",
+    )
+}
+
+/// Audio elements used to belong to a mood; moods used to belong to a sound set.
+fn audio_elements_sound_set_id(conn: &Connection) -> SqliteResult<()> {
+    if !has_column(conn, "audio_elements", "mood_id")?
+        || has_column(conn, "audio_elements", "sound_set_id")?
+    {
+        return Ok(());
+    }
+
+    conn.execute(
+        "ALTER TABLE audio_elements ADD COLUMN sound_set_id INTEGER REFERENCES sound_sets(id)",
+        [],
+    )?;
+    conn.execute(
+        "UPDATE audio_elements SET sound_set_id = (SELECT sound_set_id FROM moods WHERE moods.id = audio_elements.mood_id)",
+        [],
+    )?;
+    Ok(())
+}
+
+fn sound_sets_is_enabled(conn: &Connection) -> SqliteResult<()> {
+    if has_column(conn, "sound_sets", "is_enabled")? {
+        return Ok(());
+    }
+
+    conn.execute(
+        "ALTER TABLE sound_sets ADD COLUMN is_enabled INTEGER DEFAULT 1",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Moods became global, so the legacy `moods.sound_set_id` column is dropped.
+fn global_moods(conn: &Connection) -> SqliteResult<()> {
+    if !has_column(conn, "moods", "sound_set_id")? {
+        return Ok(());
+    }
+
+    conn.execute_batch(
+        "CREATE TABLE moods_new (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            description TEXT,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+        );
+
+        INSERT INTO moods_new (id, name, description, created_at)
+        SELECT id, name, description, created_at FROM moods;
+
+        DROP TABLE moods;
+        ALTER TABLE moods_new RENAME TO moods;",
+    )
+}
+
+fn timelines_is_looping(conn: &Connection) -> SqliteResult<()> {
+    if has_column(conn, "timelines", "is_looping")? {
+        return Ok(());
+    }
+
+    conn.execute(
+        "ALTER TABLE timelines ADD COLUMN is_looping INTEGER DEFAULT 0",
+        [],
+    )?;
+    Ok(())
+}
+
+fn timeline_tracks_is_looping(conn: &Connection) -> SqliteResult<()> {
+    if has_column(conn, "timeline_tracks", "is_looping")? {
+        return Ok(());
+    }
+
+    conn.execute(
+        "ALTER TABLE timeline_tracks ADD COLUMN is_looping INTEGER DEFAULT 0",
+        [],
+    )?;
+    Ok(())
+}
+
+fn audio_elements_channel_id(conn: &Connection) -> SqliteResult<()> {
+    if has_column(conn, "audio_elements", "channel_id")? {
+        return Ok(());
+    }
+
+    conn.execute(
+        "ALTER TABLE audio_elements ADD COLUMN channel_id INTEGER REFERENCES audio_channels(id)",
+        [],
+    )?;
+
+    // Ensure default Music channel exists for all soundsets that have elements before we update
+    conn.execute(
+        "INSERT INTO audio_channels (sound_set_id, name, icon, volume, order_index)
+         SELECT DISTINCT sound_set_id, 'Music', 'music', 1.0, 0
+         FROM audio_elements WHERE sound_set_id NOT IN (SELECT sound_set_id FROM audio_channels WHERE name = 'Music')",
+        [],
+    )?;
+
+    // Assign existing elements to the Music channel of their sound set
+    conn.execute(
+        "UPDATE audio_elements SET channel_id = (
+            SELECT id FROM audio_channels
+            WHERE audio_channels.sound_set_id = audio_elements.sound_set_id
+            AND audio_channels.name = 'Music' LIMIT 1
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Timeline elements moved from timelines onto tracks and gained element group references.
+fn timeline_elements_tracks_and_groups(conn: &Connection) -> SqliteResult<()> {
+    let has_track_id = has_column(conn, "timeline_elements", "track_id")?;
+    let has_element_group_id = has_column(conn, "timeline_elements", "element_group_id")?;
+    if has_track_id && has_element_group_id {
+        return Ok(());
+    }
+
+    conn.execute(
+        "CREATE TABLE timeline_elements_new (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timeline_id INTEGER,
+            track_id INTEGER,
+            audio_element_id INTEGER,
+            element_group_id INTEGER,
+            start_time_ms INTEGER DEFAULT 0,
+            duration_ms INTEGER DEFAULT 0,
+            FOREIGN KEY (timeline_id) REFERENCES timelines(id) ON DELETE CASCADE,
+            FOREIGN KEY (track_id) REFERENCES timeline_tracks(id) ON DELETE CASCADE,
+            FOREIGN KEY (audio_element_id) REFERENCES audio_elements(id) ON DELETE CASCADE,
+            FOREIGN KEY (element_group_id) REFERENCES element_groups(id) ON DELETE CASCADE
+        )",
+        [],
+    )?;
+
+    if !has_track_id {
+        // Every legacy timeline gets a master track that receives its elements
+        conn.execute(
+            "INSERT INTO timeline_tracks (timeline_id, name, order_index) SELECT id, 'Master Track', 0 FROM timelines",
+            [],
+        )?;
+        conn.execute(
+            "INSERT INTO timeline_elements_new (id, timeline_id, track_id, audio_element_id, start_time_ms, duration_ms)
+             SELECT e.id, e.timeline_id, t.id, e.audio_element_id, COALESCE(e.start_time_ms, 0), 0
+             FROM timeline_elements e
+             LEFT JOIN timeline_tracks t ON t.timeline_id = e.timeline_id",
+            [],
+        )?;
+    } else {
+        conn.execute(
+            "INSERT INTO timeline_elements_new (id, timeline_id, track_id, audio_element_id, start_time_ms, duration_ms)
+             SELECT id, timeline_id, track_id, audio_element_id, start_time_ms, duration_ms FROM timeline_elements",
+            [],
+        )?;
+    }
+
+    conn.execute_batch(
+        "DROP TABLE timeline_elements;
+         ALTER TABLE timeline_elements_new RENAME TO timeline_elements;",
+    )
+}
+
+/// Each mood owns a single timeline: keep the oldest one and enforce it with an index.
+fn unique_timeline_per_mood(conn: &Connection) -> SqliteResult<()> {
+    conn.execute(
+        "DELETE FROM timelines WHERE id NOT IN (
+            SELECT MIN(id) FROM timelines GROUP BY mood_id
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS ux_timelines_mood_id ON timelines(mood_id)",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Global one-shots are audio elements without a sound set, so `sound_set_id`
+/// must be nullable and the legacy `mood_id` column goes away.
+fn global_oneshots(conn: &Connection) -> SqliteResult<()> {
+    let has_mood_id = has_column(conn, "audio_elements", "mood_id")?;
+    if !has_mood_id && !is_column_not_null(conn, "audio_elements", "sound_set_id")? {
+        return Ok(());
+    }
+
+    conn.execute(
+        "CREATE TABLE audio_elements_new (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            sound_set_id INTEGER,
+            channel_id INTEGER,
+            file_path TEXT NOT NULL,
+            file_name TEXT NOT NULL,
+            channel_type TEXT DEFAULT 'ambient',
+            volume_db REAL DEFAULT 0.0,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (sound_set_id) REFERENCES sound_sets(id) ON DELETE CASCADE,
+            FOREIGN KEY (channel_id) REFERENCES audio_channels(id) ON DELETE SET NULL
+        )",
+        [],
+    )?;
+
+    // Moods no longer carry a sound_set_id by now, so rows that still lack one
+    // can only be recovered from what migration 2 backfilled.
+    conn.execute(
+        "INSERT INTO audio_elements_new (id, sound_set_id, channel_id, file_path, file_name, channel_type, volume_db, created_at)
+         SELECT id, sound_set_id, channel_id, file_path, file_name, channel_type, volume_db, created_at
+         FROM audio_elements",
+        [],
+    )?;
+
+    conn.execute_batch(
+        "DROP TABLE audio_elements;
+         ALTER TABLE audio_elements_new RENAME TO audio_elements;",
+    )
+}
+
+/// Journal behind undo/redo; `changes` holds the row snapshots as JSON.
+fn edit_history(conn: &Connection) -> SqliteResult<()> {
+    conn.execute(
+        "CREATE TABLE edit_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            label TEXT NOT NULL,
+            changes TEXT NOT NULL,
+            is_undone INTEGER NOT NULL DEFAULT 0,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Deleting sound sets, moods and audio elements moves them to the trash.
+fn soft_delete(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch(
+        "ALTER TABLE sound_sets ADD COLUMN deleted_at DATETIME;
+         ALTER TABLE moods ADD COLUMN deleted_at DATETIME;
+         ALTER TABLE audio_elements ADD COLUMN deleted_at DATETIME;",
+    )
+}
+
+/// FTS5 index behind `search_library`, kept in sync by triggers.
+///
+/// The rowid encodes what a hit points at as `id * 4 + kind`, with kinds
+/// 0 = sound set, 1 = mood, 2 = audio element and 3 = element group, so the
+/// triggers can address a document without scanning the index.
+fn library_search(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch(
+        "CREATE VIRTUAL TABLE library_search USING fts5(
+            name,
+            details,
+            tags,
+            tokenize = 'unicode61 remove_diacritics 2'
+        );
+
+        CREATE TRIGGER sound_sets_search_insert AFTER INSERT ON sound_sets BEGIN
+            INSERT INTO library_search (rowid, name, details, tags)
+            VALUES (new.id * 4, new.name, COALESCE(new.description, ''), '');
+        END;
+        CREATE TRIGGER sound_sets_search_update AFTER UPDATE OF name, description ON sound_sets BEGIN
+            UPDATE library_search SET name = new.name, details = COALESCE(new.description, '')
+            WHERE rowid = new.id * 4;
+        END;
+        CREATE TRIGGER sound_sets_search_delete AFTER DELETE ON sound_sets BEGIN
+            DELETE FROM library_search WHERE rowid = old.id * 4;
+        END;
+
+        CREATE TRIGGER moods_search_insert AFTER INSERT ON moods BEGIN
+            INSERT INTO library_search (rowid, name, details, tags)
+            VALUES (new.id * 4 + 1, new.name, COALESCE(new.description, ''), '');
+        END;
+        CREATE TRIGGER moods_search_update AFTER UPDATE OF name, description ON moods BEGIN
+            UPDATE library_search SET name = new.name, details = COALESCE(new.description, '')
+            WHERE rowid = new.id * 4 + 1;
+        END;
+        CREATE TRIGGER moods_search_delete AFTER DELETE ON moods BEGIN
+            DELETE FROM library_search WHERE rowid = old.id * 4 + 1;
+        END;
+
+        CREATE TRIGGER audio_elements_search_insert AFTER INSERT ON audio_elements BEGIN
+            INSERT INTO library_search (rowid, name, details, tags)
+            VALUES (
+                new.id * 4 + 2,
+                new.file_name,
+                COALESCE((SELECT name FROM audio_channels WHERE id = new.channel_id), '')
+                    || ' ' || COALESCE(new.channel_type, ''),
+                ''
+            );
+        END;
+        CREATE TRIGGER audio_elements_search_update
+        AFTER UPDATE OF file_name, channel_id, channel_type ON audio_elements BEGIN
+            UPDATE library_search
+            SET name = new.file_name,
+                details = COALESCE((SELECT name FROM audio_channels WHERE id = new.channel_id), '')
+                    || ' ' || COALESCE(new.channel_type, '')
+            WHERE rowid = new.id * 4 + 2;
+        END;
+        CREATE TRIGGER audio_elements_search_delete AFTER DELETE ON audio_elements BEGIN
+            DELETE FROM library_search WHERE rowid = old.id * 4 + 2;
+        END;
+
+        CREATE TRIGGER audio_channels_search_update AFTER UPDATE OF name ON audio_channels BEGIN
+            UPDATE library_search
+            SET details = new.name || ' ' || COALESCE(
+                (SELECT channel_type FROM audio_elements WHERE id = library_search.rowid / 4), ''
+            )
+            WHERE rowid IN (SELECT id * 4 + 2 FROM audio_elements WHERE channel_id = new.id);
+        END;
+
+        CREATE TRIGGER element_groups_search_insert AFTER INSERT ON element_groups BEGIN
+            INSERT INTO library_search (rowid, name, details, tags)
+            VALUES (new.id * 4 + 3, new.name, '', '');
+        END;
+        CREATE TRIGGER element_groups_search_update AFTER UPDATE OF name ON element_groups BEGIN
+            UPDATE library_search SET name = new.name WHERE rowid = new.id * 4 + 3;
+        END;
+        CREATE TRIGGER element_groups_search_delete AFTER DELETE ON element_groups BEGIN
+            DELETE FROM library_search WHERE rowid = old.id * 4 + 3;
+        END;
+
+        INSERT INTO library_search (rowid, name, details, tags)
+            SELECT id * 4, name, COALESCE(description, ''), '' FROM sound_sets;
+        INSERT INTO library_search (rowid, name, details, tags)
+            SELECT id * 4 + 1, name, COALESCE(description, ''), '' FROM moods;
+        INSERT INTO library_search (rowid, name, details, tags)
+            SELECT e.id * 4 + 2, e.file_name, COALESCE(c.name, '') || ' ' || COALESCE(e.channel_type, ''), ''
+            FROM audio_elements e LEFT JOIN audio_channels c ON c.id = e.channel_id;
+        INSERT INTO library_search (rowid, name, details, tags)
+            SELECT id * 4 + 3, name, '', '' FROM element_groups;",
+    )
+}
+
+/// Tags shared by audio elements and element groups, plus free-form notes and
+/// a 1-5 star rating on both. Tag names are unique regardless of case.
+fn tags_and_metadata(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE tags (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE
+        );
+
+        CREATE TABLE audio_element_tags (
+            audio_element_id INTEGER NOT NULL,
+            tag_id INTEGER NOT NULL,
+            PRIMARY KEY (audio_element_id, tag_id),
+            FOREIGN KEY (audio_element_id) REFERENCES audio_elements(id) ON DELETE CASCADE,
+            FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE element_group_tags (
+            element_group_id INTEGER NOT NULL,
+            tag_id INTEGER NOT NULL,
+            PRIMARY KEY (element_group_id, tag_id),
+            FOREIGN KEY (element_group_id) REFERENCES element_groups(id) ON DELETE CASCADE,
+            FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+        );
+
+        ALTER TABLE audio_elements ADD COLUMN notes TEXT NOT NULL DEFAULT '';
+        ALTER TABLE audio_elements ADD COLUMN rating INTEGER;
+        ALTER TABLE element_groups ADD COLUMN notes TEXT NOT NULL DEFAULT '';
+        ALTER TABLE element_groups ADD COLUMN rating INTEGER;
+
+        CREATE TRIGGER audio_element_tags_search_insert AFTER INSERT ON audio_element_tags BEGIN
+            UPDATE library_search SET tags = (
+                SELECT COALESCE(group_concat(t.name, ' '), '') FROM audio_element_tags link
+                JOIN tags t ON t.id = link.tag_id
+                WHERE link.audio_element_id = new.audio_element_id
+            )
+            WHERE rowid = new.audio_element_id * 4 + 2;
+        END;
+        CREATE TRIGGER audio_element_tags_search_delete AFTER DELETE ON audio_element_tags BEGIN
+            UPDATE library_search SET tags = (
+                SELECT COALESCE(group_concat(t.name, ' '), '') FROM audio_element_tags link
+                JOIN tags t ON t.id = link.tag_id
+                WHERE link.audio_element_id = old.audio_element_id
+            )
+            WHERE rowid = old.audio_element_id * 4 + 2;
+        END;
+        CREATE TRIGGER element_group_tags_search_insert AFTER INSERT ON element_group_tags BEGIN
+            UPDATE library_search SET tags = (
+                SELECT COALESCE(group_concat(t.name, ' '), '') FROM element_group_tags link
+                JOIN tags t ON t.id = link.tag_id
+                WHERE link.element_group_id = new.element_group_id
+            )
+            WHERE rowid = new.element_group_id * 4 + 3;
+        END;
+        CREATE TRIGGER element_group_tags_search_delete AFTER DELETE ON element_group_tags BEGIN
+            UPDATE library_search SET tags = (
+                SELECT COALESCE(group_concat(t.name, ' '), '') FROM element_group_tags link
+                JOIN tags t ON t.id = link.tag_id
+                WHERE link.element_group_id = old.element_group_id
+            )
+            WHERE rowid = old.element_group_id * 4 + 3;
+        END;",
+    )
+}
+
+/// Trim offset into the source file and fade in/out envelopes on timeline
+/// elements. Existing placements keep starting at the top of the file and
+/// cutting hard.
+fn timeline_element_fades(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch(
+        "ALTER TABLE timeline_elements ADD COLUMN source_offset_ms INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE timeline_elements ADD COLUMN fade_in_ms INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE timeline_elements ADD COLUMN fade_out_ms INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE timeline_elements ADD COLUMN fade_curve TEXT NOT NULL DEFAULT 'linear';",
+    )
+}
+
+/// Gain automation breakpoints per timeline track. A point's curve shapes the
+/// segment that runs from it to the next point.
+fn track_gain_automation(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE track_gain_points (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            track_id INTEGER NOT NULL,
+            time_ms INTEGER NOT NULL,
+            gain_db REAL NOT NULL,
+            curve TEXT NOT NULL DEFAULT 'linear',
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (track_id) REFERENCES timeline_tracks(id) ON DELETE CASCADE
+        );
+
+        CREATE UNIQUE INDEX idx_track_gain_points_time ON track_gain_points(track_id, time_ms);",
+    )
+}
+
+/// How an element group picks its next member, with per-member weights and
+/// random pitch and volume variation per play. Existing groups stay sequential.
+fn group_playback_modes(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch(
+        "ALTER TABLE element_groups ADD COLUMN playback_mode TEXT NOT NULL DEFAULT 'sequential';
+        ALTER TABLE element_groups ADD COLUMN pitch_variation_semitones REAL NOT NULL DEFAULT 0;
+        ALTER TABLE element_groups ADD COLUMN volume_variation_db REAL NOT NULL DEFAULT 0;
+        ALTER TABLE element_group_members ADD COLUMN weight REAL NOT NULL DEFAULT 1;",
+    )
+}
+
+/// How switching from one mood to another sounds. A NULL mood on either side
+/// makes the row a fallback for every mood on that side.
+fn mood_transitions(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE mood_transitions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            from_mood_id INTEGER,
+            to_mood_id INTEGER,
+            crossfade_ms INTEGER NOT NULL DEFAULT 2000,
+            curve TEXT NOT NULL DEFAULT 'equal_power',
+            sync TEXT NOT NULL DEFAULT 'immediate',
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (from_mood_id) REFERENCES moods(id) ON DELETE CASCADE,
+            FOREIGN KEY (to_mood_id) REFERENCES moods(id) ON DELETE CASCADE
+        );
+
+        CREATE UNIQUE INDEX idx_mood_transitions_pair
+            ON mood_transitions(IFNULL(from_mood_id, 0), IFNULL(to_mood_id, 0));",
+    )
+}
+
+/// Named cue points on a timeline. A marker with an end is a region, which the
+/// timeline can loop instead of its whole length.
+fn timeline_markers(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE timeline_markers (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timeline_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            position_ms INTEGER NOT NULL,
+            end_ms INTEGER,
+            color TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (timeline_id) REFERENCES timelines(id) ON DELETE CASCADE
+        );
+
+        CREATE INDEX idx_timeline_markers_timeline ON timeline_markers(timeline_id, position_ms);
+
+        ALTER TABLE timelines ADD COLUMN loop_marker_id INTEGER
+            REFERENCES timeline_markers(id) ON DELETE SET NULL;",
+    )
+}
+
+/// Tempo and time signature changes of a timeline, each starting on a bar.
+/// A timeline without rows has no musical grid.
+fn timeline_tempo_map(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE timeline_tempo_changes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timeline_id INTEGER NOT NULL,
+            bar INTEGER NOT NULL,
+            bpm REAL NOT NULL,
+            beats_per_bar INTEGER NOT NULL DEFAULT 4,
+            beat_unit INTEGER NOT NULL DEFAULT 4,
+            FOREIGN KEY (timeline_id) REFERENCES timelines(id) ON DELETE CASCADE,
+            UNIQUE (timeline_id, bar)
+        );",
+    )
+}
+
+/// Saved timeline layouts, stored as JSON so they outlive the timeline they
+/// were taken from.
+fn timeline_templates(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE timeline_templates (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            blueprint TEXT NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );",
+    )
+}
+
+/// What decoding the file revealed about each element. Rows added before
+/// probing existed keep NULLs until they are probed again.
+fn audio_element_probe(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch(
+        "ALTER TABLE audio_elements ADD COLUMN duration_ms INTEGER;
+        ALTER TABLE audio_elements ADD COLUMN sample_rate INTEGER;
+        ALTER TABLE audio_elements ADD COLUMN channel_count INTEGER;
+        ALTER TABLE audio_elements ADD COLUMN codec TEXT;
+        ALTER TABLE audio_elements ADD COLUMN file_size_bytes INTEGER;",
+    )
+}
+
+/// SHA-256 of each element's file, which keys its cached waveform peaks.
+fn audio_element_content_hash(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch("ALTER TABLE audio_elements ADD COLUMN content_hash TEXT;")
+}
+
+/// EBU R128 measurements of each element's file, used to normalise volumes.
+fn audio_element_loudness(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch(
+        "ALTER TABLE audio_elements ADD COLUMN loudness_lufs REAL;
+        ALTER TABLE audio_elements ADD COLUMN loudness_range_lu REAL;
+        ALTER TABLE audio_elements ADD COLUMN true_peak_dbtp REAL;",
+    )
+}
+
+/// Detected silence and loop points of each element's file. New timeline
+/// elements start playing their source at `loop_start_ms`.
+fn audio_element_loop_points(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch(
+        "ALTER TABLE audio_elements ADD COLUMN leading_silence_ms INTEGER;
+        ALTER TABLE audio_elements ADD COLUMN trailing_silence_ms INTEGER;
+        ALTER TABLE audio_elements ADD COLUMN loop_start_ms INTEGER;
+        ALTER TABLE audio_elements ADD COLUMN loop_end_ms INTEGER;",
+    )
+}
+
+/// Per-channel compressor settings, sidechain ducking rules between channels
+/// and the master bus limiter, which starts out enabled.
+fn mixer_dynamics(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch(
+        "ALTER TABLE audio_channels ADD COLUMN compressor_enabled INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE audio_channels ADD COLUMN compressor_threshold_db REAL NOT NULL DEFAULT -18.0;
+        ALTER TABLE audio_channels ADD COLUMN compressor_ratio REAL NOT NULL DEFAULT 4.0;
+        ALTER TABLE audio_channels ADD COLUMN compressor_attack_ms REAL NOT NULL DEFAULT 10.0;
+        ALTER TABLE audio_channels ADD COLUMN compressor_release_ms REAL NOT NULL DEFAULT 200.0;
+        ALTER TABLE audio_channels ADD COLUMN compressor_makeup_db REAL NOT NULL DEFAULT 0.0;
+
+        CREATE TABLE channel_duck_rules (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            target_channel_id INTEGER NOT NULL,
+            trigger_channel_id INTEGER NOT NULL,
+            depth_db REAL NOT NULL,
+            threshold_db REAL NOT NULL,
+            attack_ms REAL NOT NULL,
+            release_ms REAL NOT NULL,
+            FOREIGN KEY (target_channel_id) REFERENCES audio_channels(id) ON DELETE CASCADE,
+            FOREIGN KEY (trigger_channel_id) REFERENCES audio_channels(id) ON DELETE CASCADE,
+            UNIQUE (target_channel_id, trigger_channel_id)
+        );
+
+        CREATE TABLE master_bus (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            limiter_enabled INTEGER NOT NULL DEFAULT 1,
+            limiter_ceiling_dbtp REAL NOT NULL DEFAULT -1.0,
+            limiter_release_ms REAL NOT NULL DEFAULT 100.0
+        );
+        INSERT INTO master_bus (id) VALUES (1);",
+    )
+}
+
+/// Ordered insert effects on each channel. Parameters are stored as JSON
+/// tagged by effect kind, so new kinds need no schema change.
+fn channel_effects(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE channel_effects (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            channel_id INTEGER NOT NULL,
+            order_index INTEGER NOT NULL,
+            enabled INTEGER NOT NULL DEFAULT 1,
+            effect TEXT NOT NULL,
+            FOREIGN KEY (channel_id) REFERENCES audio_channels(id) ON DELETE CASCADE
+        );
+        CREATE INDEX idx_channel_effects_channel ON channel_effects(channel_id, order_index);",
+    )
+}
+
+/// Named mixer snapshots: every channel's volume, mute and solo, which sound
+/// sets are enabled and the mood that was active.
+fn mixer_snapshots(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE mixer_snapshots (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE,
+            mood_id INTEGER,
+            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (mood_id) REFERENCES moods(id) ON DELETE SET NULL
+        );
+
+        CREATE TABLE mixer_snapshot_channels (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            snapshot_id INTEGER NOT NULL,
+            channel_id INTEGER NOT NULL,
+            volume REAL NOT NULL,
+            muted INTEGER NOT NULL DEFAULT 0,
+            solo INTEGER NOT NULL DEFAULT 0,
+            FOREIGN KEY (snapshot_id) REFERENCES mixer_snapshots(id) ON DELETE CASCADE,
+            FOREIGN KEY (channel_id) REFERENCES audio_channels(id) ON DELETE CASCADE,
+            UNIQUE (snapshot_id, channel_id)
+        );
+
+        CREATE TABLE mixer_snapshot_sound_sets (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            snapshot_id INTEGER NOT NULL,
+            sound_set_id INTEGER NOT NULL,
+            is_enabled INTEGER NOT NULL,
+            FOREIGN KEY (snapshot_id) REFERENCES mixer_snapshots(id) ON DELETE CASCADE,
+            FOREIGN KEY (sound_set_id) REFERENCES sound_sets(id) ON DELETE CASCADE,
+            UNIQUE (snapshot_id, sound_set_id)
+        );",
+    )
+}
+
+/// Size and modification time of an element's file when it was hashed, so a
+/// file replaced at the same path is hashed again.
+fn audio_element_content_stamp(conn: &Connection) -> SqliteResult<()> {
+    conn.execute_batch(
+        "ALTER TABLE audio_elements ADD COLUMN content_size INTEGER;
+        ALTER TABLE audio_elements ADD COLUMN content_modified_ms INTEGER;",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn latest_version() -> i64 {
+        MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+    }
+
+    fn column_names(conn: &Connection, table: &str) -> Vec<String> {
+        let mut stmt = conn
+            .prepare(&format!("PRAGMA table_info({})", table))
+            .unwrap();
+        stmt.query_map([], |row| row.get::<_, String>(1))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn table_exists(conn: &Connection, table: &str) -> bool {
+        conn.query_row(
+            "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [table],
+            |row| row.get::<_, i64>(0),
+        )
+        .unwrap()
+            > 0
+    }
+
+    #[test]
+    fn fresh_database_reaches_latest_version() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+
+        assert_eq!(schema_version(&conn).unwrap(), latest_version());
+        for table in [
+            "sound_sets",
+            "moods",
+            "audio_channels",
+            "audio_elements",
+            "timelines",
+            "timeline_tracks",
+            "element_groups",
+            "element_group_members",
+            "timeline_elements",
+        ] {
+            assert!(table_exists(&conn, table), "missing table {}", table);
+        }
+    }
+
+    #[test]
+    fn running_twice_is_a_no_op() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO sound_sets (id, name, description) VALUES (1, 'S', 'D')",
+            [],
+        )
+        .unwrap();
+
+        run_migrations(&conn).unwrap();
+
+        let count: i64 = conn
+            .query_row("SELECT count(*) FROM sound_sets", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(schema_version(&conn).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn upgrades_legacy_mood_scoped_audio_elements() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE sound_sets (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                description TEXT,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );
+            CREATE TABLE moods (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                sound_set_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                description TEXT,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );
+            CREATE TABLE audio_elements (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                mood_id INTEGER NOT NULL,
+                file_path TEXT NOT NULL,
+                file_name TEXT NOT NULL,
+                channel_type TEXT DEFAULT 'ambient',
+                volume_db REAL DEFAULT 0.0,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );
+            INSERT INTO sound_sets (id, name, description) VALUES (7, 'Forest', '');
+            INSERT INTO moods (id, sound_set_id, name) VALUES (3, 7, 'Night');
+            INSERT INTO audio_elements (id, mood_id, file_path, file_name, channel_type)
+                VALUES (11, 3, '/tmp/owl.wav', 'owl.wav', 'ambient');",
+        )
+        .unwrap();
+
+        run_migrations(&conn).unwrap();
+
+        let audio_columns = column_names(&conn, "audio_elements");
+        assert!(!audio_columns.contains(&"mood_id".to_string()));
+        assert!(audio_columns.contains(&"channel_id".to_string()));
+        assert!(!column_names(&conn, "moods").contains(&"sound_set_id".to_string()));
+        assert!(column_names(&conn, "sound_sets").contains(&"is_enabled".to_string()));
+
+        let (sound_set_id, channel_name): (i64, String) = conn
+            .query_row(
+                "SELECT e.sound_set_id, c.name FROM audio_elements e
+                 JOIN audio_channels c ON c.id = e.channel_id
+                 WHERE e.id = 11",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(sound_set_id, 7);
+        assert_eq!(channel_name, "Music");
+
+        let mood_name: String = conn
+            .query_row("SELECT name FROM moods WHERE id = 3", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(mood_name, "Night");
+
+        // Global one-shots need a nullable sound_set_id
+        conn.execute(
+            "INSERT INTO audio_elements (sound_set_id, file_path, file_name) VALUES (NULL, 'p', 'f')",
+            [],
+        )
+        .unwrap();
+        assert!(!table_exists(&conn, "audio_elements_new"));
+        assert!(!table_exists(&conn, "moods_new"));
+    }
+
+    #[test]
+    fn upgrades_legacy_timeline_elements_without_tracks() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE sound_sets (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                description TEXT,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );
+            CREATE TABLE moods (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                sound_set_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                description TEXT,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );
+            CREATE TABLE audio_elements (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                mood_id INTEGER NOT NULL,
+                file_path TEXT NOT NULL,
+                file_name TEXT NOT NULL,
+                channel_type TEXT DEFAULT 'ambient',
+                volume_db REAL DEFAULT 0.0,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );
+            CREATE TABLE timelines (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                mood_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                order_index INTEGER DEFAULT 0,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            );
+            CREATE TABLE timeline_elements (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timeline_id INTEGER NOT NULL,
+                audio_element_id INTEGER,
+                start_time_ms INTEGER
+            );
+            INSERT INTO sound_sets (id, name, description) VALUES (1, 'Forest', '');
+            INSERT INTO moods (id, sound_set_id, name) VALUES (1, 1, 'Night');
+            INSERT INTO audio_elements (id, mood_id, file_path, file_name)
+                VALUES (100, 1, '/tmp/owl.wav', 'owl.wav');
+            INSERT INTO timelines (id, mood_id, name) VALUES (1, 1, 'Main');
+            INSERT INTO timeline_elements (id, timeline_id, audio_element_id, start_time_ms)
+                VALUES (5, 1, 100, NULL);",
+        )
+        .unwrap();
+
+        run_migrations(&conn).unwrap();
+
+        assert!(column_names(&conn, "timelines").contains(&"is_looping".to_string()));
+        assert!(column_names(&conn, "timeline_tracks").contains(&"is_looping".to_string()));
+
+        let (track_name, start_time_ms, duration_ms): (String, i64, i64) = conn
+            .query_row(
+                "SELECT t.name, e.start_time_ms, e.duration_ms FROM timeline_elements e
+                 JOIN timeline_tracks t ON t.id = e.track_id
+                 WHERE e.id = 5",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(track_name, "Master Track");
+        assert_eq!(start_time_ms, 0);
+        assert_eq!(duration_ms, 0);
+        assert!(column_names(&conn, "timeline_elements").contains(&"element_group_id".to_string()));
+        assert!(!table_exists(&conn, "timeline_elements_new"));
+    }
+
+    #[test]
+    fn database_from_before_the_registry_keeps_its_data() {
+        // Schema as written by the last release that probed with PRAGMA table_info
+        let conn = Connection::open_in_memory().unwrap();
+        create_base_schema(&conn).unwrap();
+        conn.execute_batch(
+            "CREATE UNIQUE INDEX ux_timelines_mood_id ON timelines(mood_id);
+             INSERT INTO moods (id, name) VALUES (1, 'M');
+             INSERT INTO timelines (id, mood_id, name) VALUES (1, 1, 'T');
+             INSERT INTO timeline_tracks (id, timeline_id, name) VALUES (2, 1, 'Trk');",
+        )
+        .unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+
+        run_migrations(&conn).unwrap();
+
+        let tracks: i64 = conn
+            .query_row("SELECT count(*) FROM timeline_tracks", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(tracks, 1);
+        assert_eq!(schema_version(&conn).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn failed_migration_rolls_back_and_names_the_step() {
+        fn create_probe(conn: &Connection) -> SqliteResult<()> {
+            conn.execute("CREATE TABLE probe (id INTEGER)", [])?;
+            Ok(())
+        }
+        fn half_rebuild(conn: &Connection) -> SqliteResult<()> {
+            conn.execute("CREATE TABLE probe_new (id INTEGER)", [])?;
+            conn.execute("INSERT INTO missing_table VALUES (1)", [])?;
+            Ok(())
+        }
+
+        let migrations = [
+            Migration {
+                version: 1,
+                name: "create_probe",
+                up: create_probe,
+            },
+            Migration {
+                version: 2,
+                name: "half_rebuild",
+                up: half_rebuild,
+            },
+        ];
+
+        let conn = Connection::open_in_memory().unwrap();
+        let error = apply_migrations(&conn, &migrations).unwrap_err();
+
+        assert_eq!(error.version, 2);
+        assert_eq!(error.name, "half_rebuild");
+        assert!(error
+            .to_string()
+            .contains("Migration 2 (half_rebuild) failed"));
+        assert_eq!(schema_version(&conn).unwrap(), 1);
+        assert!(table_exists(&conn, "probe"));
+        assert!(!table_exists(&conn, "probe_new"));
+    }
+
+    #[test]
+    fn migrations_leaving_dangling_references_roll_back() {
+        fn create_parent(conn: &Connection) -> SqliteResult<()> {
+            conn.execute_batch(
+                "CREATE TABLE parent (id INTEGER PRIMARY KEY);
+                 CREATE TABLE child (id INTEGER PRIMARY KEY, parent_id INTEGER REFERENCES parent(id));
+                 INSERT INTO parent (id) VALUES (1);
+                 INSERT INTO child (id, parent_id) VALUES (7, 1);",
+            )
+        }
+        fn lossy_rebuild(conn: &Connection) -> SqliteResult<()> {
+            conn.execute_batch(
+                "CREATE TABLE parent_new (id INTEGER PRIMARY KEY);
+                 DROP TABLE parent;
+                 ALTER TABLE parent_new RENAME TO parent;",
+            )
+        }
+
+        let migrations = [
+            Migration {
+                version: 1,
+                name: "create_parent",
+                up: create_parent,
+            },
+            Migration {
+                version: 2,
+                name: "lossy_rebuild",
+                up: lossy_rebuild,
+            },
+        ];
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("PRAGMA foreign_keys=on;").unwrap();
+        let error = apply_migrations(&conn, &migrations).unwrap_err();
+
+        assert_eq!(error.name, "lossy_rebuild");
+        assert!(error
+            .to_string()
+            .contains("child row 7 references a missing parent row"));
+        assert_eq!(schema_version(&conn).unwrap(), 1);
+        assert!(!table_exists(&conn, "parent_new"));
+        let parents: i64 = conn
+            .query_row("SELECT COUNT(*) FROM parent", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(parents, 1);
+    }
+}
@@ -0,0 +1,323 @@
+use rusqlite::{Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use super::collect_rows;
+use crate::{AppError, AppResult};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AudioChannel {
+    pub id: i64,
+    pub sound_set_id: i64,
+    pub name: String,
+    pub icon: String,
+    pub volume: f64,
+    pub order_index: i64,
+    pub created_at: String,
+    pub compressor: ChannelCompressor,
+}
+
+/// Downward compressor on a channel's bus, ahead of its volume.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChannelCompressor {
+    pub enabled: bool,
+    pub threshold_db: f64,
+    /// Input dB above the threshold per output dB, at least 1.
+    pub ratio: f64,
+    pub attack_ms: f64,
+    pub release_ms: f64,
+    /// Gain added after compression.
+    pub makeup_db: f64,
+}
+
+impl Default for ChannelCompressor {
+    fn default() -> Self {
+        ChannelCompressor {
+            enabled: false,
+            threshold_db: -18.0,
+            ratio: 4.0,
+            attack_ms: 10.0,
+            release_ms: 200.0,
+            makeup_db: 0.0,
+        }
+    }
+}
+
+const CHANNEL_COLUMNS: &str = "id, sound_set_id, name, icon, volume, order_index, created_at, compressor_enabled, compressor_threshold_db, compressor_ratio, compressor_attack_ms, compressor_release_ms, compressor_makeup_db";
+
+const DEFAULT_CHANNELS: [(&str, &str, f64, i64); 3] = [
+    ("Music", "music", 1.0, 0),
+    ("Ambient", "ambient", 1.0, 1),
+    ("Sound Effects", "sfx", 1.0, 2),
+];
+
+fn map_audio_channel(row: &Row<'_>) -> rusqlite::Result<AudioChannel> {
+    Ok(AudioChannel {
+        id: row.get(0)?,
+        sound_set_id: row.get(1)?,
+        name: row.get(2)?,
+        icon: row.get(3)?,
+        volume: row.get(4)?,
+        order_index: row.get(5)?,
+        created_at: row.get(6)?,
+        compressor: ChannelCompressor {
+            enabled: row.get(7)?,
+            threshold_db: row.get(8)?,
+            ratio: row.get(9)?,
+            attack_ms: row.get(10)?,
+            release_ms: row.get(11)?,
+            makeup_db: row.get(12)?,
+        },
+    })
+}
+
+pub fn create_audio_channel(
+    conn: &Connection,
+    sound_set_id: i64,
+    name: String,
+    icon: String,
+    volume: f64,
+) -> AppResult<AudioChannel> {
+    let order_index: i64 = conn.query_row(
+        "SELECT COALESCE(MAX(order_index), -1) + 1 FROM audio_channels WHERE sound_set_id = ?1",
+        [&sound_set_id],
+        |row| row.get(0),
+    )?;
+
+    conn.execute(
+        "INSERT INTO audio_channels (sound_set_id, name, icon, volume, order_index) VALUES (?1, ?2, ?3, ?4, ?5)",
+        (&sound_set_id, &name, &icon, &volume, &order_index),
+    )?;
+
+    let id = conn.last_insert_rowid();
+
+    Ok(AudioChannel {
+        id,
+        sound_set_id,
+        name,
+        icon,
+        volume,
+        order_index,
+        created_at: chrono::Local::now().to_rfc3339(),
+        compressor: ChannelCompressor::default(),
+    })
+}
+
+pub fn get_audio_channel(conn: &Connection, id: i64) -> AppResult<AudioChannel> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM audio_channels WHERE id = ?1",
+            CHANNEL_COLUMNS
+        ),
+        [id],
+        map_audio_channel,
+    )
+    .optional()?
+    .ok_or_else(|| AppError::not_found("Audio channel", id))
+}
+
+pub fn get_audio_channels(conn: &Connection, sound_set_id: i64) -> AppResult<Vec<AudioChannel>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM audio_channels WHERE sound_set_id = ?1 ORDER BY order_index ASC",
+        CHANNEL_COLUMNS
+    ))?;
+
+    collect_rows(&mut stmt, [sound_set_id], map_audio_channel)
+}
+
+/// Every channel of every sound set, for mixing outside a single sound set.
+pub fn get_all_audio_channels(conn: &Connection) -> AppResult<Vec<AudioChannel>> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM audio_channels", CHANNEL_COLUMNS))?;
+
+    collect_rows(&mut stmt, [], map_audio_channel)
+}
+
+pub fn update_audio_channel(
+    conn: &Connection,
+    id: i64,
+    name: &str,
+    icon: &str,
+    volume: f64,
+) -> AppResult<()> {
+    conn.execute(
+        "UPDATE audio_channels SET name = ?1, icon = ?2, volume = ?3 WHERE id = ?4",
+        rusqlite::params![name, icon, volume, id],
+    )?;
+
+    Ok(())
+}
+
+/// Fails with `ValidationFailed` unless `value` lies in `range`.
+pub(crate) fn check_range(
+    name: &str,
+    value: f64,
+    range: std::ops::RangeInclusive<f64>,
+    unit: &str,
+) -> AppResult<()> {
+    if !range.contains(&value) {
+        return Err(AppError::ValidationFailed(format!(
+            "{} must be between {}{unit} and {}{unit}, got {}{unit}",
+            name,
+            range.start(),
+            range.end(),
+            value
+        )));
+    }
+    Ok(())
+}
+
+pub fn set_channel_compressor(
+    conn: &Connection,
+    id: i64,
+    compressor: &ChannelCompressor,
+) -> AppResult<AudioChannel> {
+    check_range("Threshold", compressor.threshold_db, -60.0..=0.0, " dB")?;
+    check_range("Ratio", compressor.ratio, 1.0..=20.0, ":1")?;
+    check_range("Attack", compressor.attack_ms, 0.1..=500.0, " ms")?;
+    check_range("Release", compressor.release_ms, 1.0..=5000.0, " ms")?;
+    check_range("Makeup gain", compressor.makeup_db, 0.0..=24.0, " dB")?;
+
+    let updated = conn.execute(
+        "UPDATE audio_channels SET compressor_enabled = ?1, compressor_threshold_db = ?2, compressor_ratio = ?3,
+             compressor_attack_ms = ?4, compressor_release_ms = ?5, compressor_makeup_db = ?6
+         WHERE id = ?7",
+        rusqlite::params![
+            compressor.enabled,
+            compressor.threshold_db,
+            compressor.ratio,
+            compressor.attack_ms,
+            compressor.release_ms,
+            compressor.makeup_db,
+            id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(AppError::not_found("Audio channel", id));
+    }
+
+    get_audio_channel(conn, id)
+}
+
+pub fn delete_audio_channel(conn: &Connection, id: i64) -> AppResult<()> {
+    conn.execute("DELETE FROM audio_channels WHERE id = ?1", [id])?;
+
+    Ok(())
+}
+
+pub fn reorder_audio_channels(conn: &Connection, id: i64, order_index: i64) -> AppResult<()> {
+    conn.execute(
+        "UPDATE audio_channels SET order_index = ?1 WHERE id = ?2",
+        rusqlite::params![order_index, id],
+    )?;
+
+    Ok(())
+}
+
+/// Creates the Music/Ambient/Sound Effects channels when the sound set has none.
+pub fn seed_default_channels(conn: &Connection, sound_set_id: i64) -> AppResult<Vec<AudioChannel>> {
+    let count: i64 = conn.query_row(
+        "SELECT count(*) FROM audio_channels WHERE sound_set_id = ?1",
+        [&sound_set_id],
+        |row| row.get(0),
+    )?;
+
+    if count == 0 {
+        for (name, icon, volume, order) in DEFAULT_CHANNELS.iter() {
+            conn.execute(
+                "INSERT INTO audio_channels (sound_set_id, name, icon, volume, order_index) VALUES (?1, ?2, ?3, ?4, ?5)",
+                (&sound_set_id, name, icon, volume, order),
+            )?;
+        }
+    }
+
+    get_audio_channels(conn, sound_set_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::sound_sets::create_sound_set;
+    use crate::store::test_connection;
+
+    #[test]
+    fn new_channels_are_appended_after_existing_ones() {
+        let conn = test_connection();
+        let sound_set = create_sound_set(&conn, "S".into(), String::new()).unwrap();
+
+        let first =
+            create_audio_channel(&conn, sound_set.id, "Music".into(), "music".into(), 1.0).unwrap();
+        let second =
+            create_audio_channel(&conn, sound_set.id, "Rain".into(), "ambient".into(), 0.5)
+                .unwrap();
+
+        assert_eq!(first.order_index, 0);
+        assert_eq!(second.order_index, 1);
+    }
+
+    #[test]
+    fn reordering_changes_listing_order() {
+        let conn = test_connection();
+        let sound_set = create_sound_set(&conn, "S".into(), String::new()).unwrap();
+        let first =
+            create_audio_channel(&conn, sound_set.id, "A".into(), "music".into(), 1.0).unwrap();
+        let second =
+            create_audio_channel(&conn, sound_set.id, "B".into(), "music".into(), 1.0).unwrap();
+
+        reorder_audio_channels(&conn, first.id, 1).unwrap();
+        reorder_audio_channels(&conn, second.id, 0).unwrap();
+
+        let names: Vec<String> = get_audio_channels(&conn, sound_set.id)
+            .unwrap()
+            .into_iter()
+            .map(|channel| channel.name)
+            .collect();
+        assert_eq!(names, ["B", "A"]);
+    }
+
+    #[test]
+    fn seeding_only_happens_once() {
+        let conn = test_connection();
+        let sound_set = create_sound_set(&conn, "S".into(), String::new()).unwrap();
+
+        let seeded = seed_default_channels(&conn, sound_set.id).unwrap();
+        assert_eq!(seeded.len(), 3);
+        assert_eq!(seeded[0].name, "Music");
+
+        update_audio_channel(&conn, seeded[0].id, "Score", "music", 0.8).unwrap();
+        let reseeded = seed_default_channels(&conn, sound_set.id).unwrap();
+
+        assert_eq!(reseeded.len(), 3);
+        assert_eq!(reseeded[0].name, "Score");
+        assert_eq!(reseeded[0].volume, 0.8);
+    }
+
+    #[test]
+    fn compressor_settings_are_validated_and_stored() {
+        let conn = test_connection();
+        let sound_set = create_sound_set(&conn, "S".into(), String::new()).unwrap();
+        let channel =
+            create_audio_channel(&conn, sound_set.id, "Music".into(), "music".into(), 1.0).unwrap();
+        assert_eq!(channel.compressor, ChannelCompressor::default());
+
+        let compressor = ChannelCompressor {
+            enabled: true,
+            threshold_db: -24.0,
+            ratio: 3.0,
+            ..ChannelCompressor::default()
+        };
+        let updated = set_channel_compressor(&conn, channel.id, &compressor).unwrap();
+        assert_eq!(updated.compressor, compressor);
+        assert_eq!(
+            get_audio_channels(&conn, sound_set.id).unwrap()[0].compressor,
+            compressor
+        );
+
+        let gentle = ChannelCompressor {
+            ratio: 0.5,
+            ..compressor.clone()
+        };
+        let error = set_channel_compressor(&conn, channel.id, &gentle).unwrap_err();
+        assert_eq!(error.code(), "ValidationFailed");
+        let missing = set_channel_compressor(&conn, 999, &compressor).unwrap_err();
+        assert_eq!(missing.code(), "NotFound");
+    }
+}
@@ -0,0 +1,563 @@
+use std::collections::HashMap;
+
+use rusqlite::{Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use super::collect_rows;
+use super::tags::{
+    has_tag_condition, split_tag_names, tag_names_column, validate_rating, TagTarget,
+};
+use super::trash::{move_to_trash, TrashKind, TrashOwner};
+use crate::audio::loops::LoopPoints;
+use crate::audio::loudness::LoudnessReport;
+use crate::audio::probe::AudioInfo;
+use crate::audio::waveform::FileStamp;
+use crate::{AppError, AppResult};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AudioElement {
+    pub id: i64,
+    pub sound_set_id: Option<i64>,
+    pub channel_id: Option<i64>,
+    pub file_path: String,
+    pub file_name: String,
+    pub channel_type: String,
+    pub volume_db: f64,
+    pub created_at: String,
+    pub tags: Vec<String>,
+    pub notes: String,
+    /// One to five stars; `None` when unrated.
+    pub rating: Option<i64>,
+    /// Probed from the file; `None` for elements added before probing existed.
+    pub duration_ms: Option<i64>,
+    pub sample_rate: Option<i64>,
+    pub channel_count: Option<i64>,
+    pub codec: Option<String>,
+    pub file_size_bytes: Option<i64>,
+    /// EBU R128 measurements; `None` until the file has been analysed, or
+    /// when it is too quiet to measure.
+    pub loudness_lufs: Option<f64>,
+    pub loudness_range_lu: Option<f64>,
+    pub true_peak_dbtp: Option<f64>,
+    /// Detected silence and seamless loop points; `None` until detected.
+    pub leading_silence_ms: Option<i64>,
+    pub trailing_silence_ms: Option<i64>,
+    pub loop_start_ms: Option<i64>,
+    pub loop_end_ms: Option<i64>,
+}
+
+/// Columns read by [`map_audio_element`], for a query aliasing the table `e`.
+fn audio_element_columns() -> String {
+    format!(
+        "e.id, e.sound_set_id, e.file_path, e.file_name, e.channel_type, e.volume_db, e.created_at, e.channel_id, e.notes, e.rating, {}, e.duration_ms, e.sample_rate, e.channel_count, e.codec, e.file_size_bytes, e.loudness_lufs, e.loudness_range_lu, e.true_peak_dbtp, e.leading_silence_ms, e.trailing_silence_ms, e.loop_start_ms, e.loop_end_ms",
+        tag_names_column(TagTarget::AudioElement, "e.id")
+    )
+}
+
+fn map_audio_element(row: &Row<'_>) -> rusqlite::Result<AudioElement> {
+    Ok(AudioElement {
+        id: row.get(0)?,
+        sound_set_id: row.get(1)?,
+        file_path: row.get(2)?,
+        file_name: row.get(3)?,
+        channel_type: row.get(4)?,
+        volume_db: row.get(5)?,
+        created_at: row.get(6)?,
+        channel_id: row.get(7)?,
+        notes: row.get(8)?,
+        rating: row.get(9)?,
+        tags: split_tag_names(row.get(10)?),
+        duration_ms: row.get(11)?,
+        sample_rate: row.get(12)?,
+        channel_count: row.get(13)?,
+        codec: row.get(14)?,
+        file_size_bytes: row.get(15)?,
+        loudness_lufs: row.get(16)?,
+        loudness_range_lu: row.get(17)?,
+        true_peak_dbtp: row.get(18)?,
+        leading_silence_ms: row.get(19)?,
+        trailing_silence_ms: row.get(20)?,
+        loop_start_ms: row.get(21)?,
+        loop_end_ms: row.get(22)?,
+    })
+}
+
+/// Inserts an element. A `None` sound set makes it a global one-shot.
+pub fn create_audio_element(
+    conn: &Connection,
+    sound_set_id: Option<i64>,
+    file_path: String,
+    file_name: String,
+    channel_type: String,
+    channel_id: Option<i64>,
+    info: Option<AudioInfo>,
+) -> AppResult<AudioElement> {
+    conn.execute(
+        "INSERT INTO audio_elements (sound_set_id, file_path, file_name, channel_type, channel_id) VALUES (?1, ?2, ?3, ?4, ?5)",
+        (&sound_set_id, &file_path, &file_name, &channel_type, &channel_id),
+    )?;
+
+    let id = conn.last_insert_rowid();
+    if let Some(info) = &info {
+        update_audio_element_info(conn, id, info)?;
+    }
+
+    Ok(AudioElement {
+        id,
+        sound_set_id,
+        channel_id,
+        file_path,
+        file_name,
+        channel_type,
+        volume_db: 0.0,
+        created_at: chrono::Local::now().to_rfc3339(),
+        tags: Vec::new(),
+        notes: String::new(),
+        rating: None,
+        duration_ms: info.as_ref().map(|info| info.duration_ms),
+        sample_rate: info.as_ref().map(|info| info.sample_rate),
+        channel_count: info.as_ref().map(|info| info.channel_count),
+        file_size_bytes: info.as_ref().map(|info| info.file_size_bytes),
+        codec: info.map(|info| info.codec),
+        loudness_lufs: None,
+        loudness_range_lu: None,
+        true_peak_dbtp: None,
+        leading_silence_ms: None,
+        trailing_silence_ms: None,
+        loop_start_ms: None,
+        loop_end_ms: None,
+    })
+}
+
+pub fn get_audio_element(conn: &Connection, id: i64) -> AppResult<AudioElement> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM audio_elements e WHERE e.id = ?1",
+            audio_element_columns()
+        ),
+        [id],
+        map_audio_element,
+    )
+    .optional()?
+    .ok_or_else(|| AppError::not_found("Audio element", id))
+}
+
+/// Elements of a sound set, optionally only those carrying `tag`.
+pub fn get_audio_elements(
+    conn: &Connection,
+    sound_set_id: i64,
+    tag: Option<&str>,
+) -> AppResult<Vec<AudioElement>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM audio_elements e WHERE e.sound_set_id = ?1 AND e.deleted_at IS NULL AND {} ORDER BY e.created_at DESC, e.id DESC",
+        audio_element_columns(),
+        has_tag_condition(TagTarget::AudioElement, "e.id", "?2")
+    ))?;
+
+    collect_rows(
+        &mut stmt,
+        rusqlite::params![sound_set_id, tag],
+        map_audio_element,
+    )
+}
+
+/// Elements from enabled sound sets plus the global one-shots.
+pub fn get_all_available_audio_elements(conn: &Connection) -> AppResult<Vec<AudioElement>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}
+         FROM audio_elements e
+         LEFT JOIN sound_sets s ON e.sound_set_id = s.id
+         WHERE e.deleted_at IS NULL AND (e.sound_set_id IS NULL OR (s.is_enabled = 1 AND s.deleted_at IS NULL))
+         ORDER BY e.created_at DESC",
+        audio_element_columns()
+    ))?;
+
+    collect_rows(&mut stmt, [], map_audio_element)
+}
+
+pub fn get_global_oneshots(conn: &Connection) -> AppResult<Vec<AudioElement>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM audio_elements e WHERE e.sound_set_id IS NULL AND e.deleted_at IS NULL ORDER BY e.created_at DESC",
+        audio_element_columns()
+    ))?;
+
+    collect_rows(&mut stmt, [], map_audio_element)
+}
+
+/// Moves the element to the trash; its timeline placements and group
+/// memberships are hidden until it is restored.
+pub fn delete_audio_element(conn: &Connection, id: i64) -> AppResult<()> {
+    move_to_trash(conn, TrashKind::AudioElement, TrashOwner::Any, id)
+}
+
+/// Trashes the element only if it is a global one-shot.
+pub fn delete_global_oneshot(conn: &Connection, id: i64) -> AppResult<()> {
+    move_to_trash(conn, TrashKind::AudioElement, TrashOwner::Global, id)
+}
+
+pub fn update_audio_element_channel(
+    conn: &Connection,
+    id: i64,
+    channel_type: &str,
+) -> AppResult<()> {
+    conn.execute(
+        "UPDATE audio_elements SET channel_type = ?1 WHERE id = ?2",
+        (channel_type, &id),
+    )?;
+
+    Ok(())
+}
+
+pub fn update_audio_element_channel_id(
+    conn: &Connection,
+    id: i64,
+    channel_id: Option<i64>,
+) -> AppResult<()> {
+    conn.execute(
+        "UPDATE audio_elements SET channel_id = ?1 WHERE id = ?2",
+        (&channel_id, &id),
+    )?;
+
+    Ok(())
+}
+
+pub fn update_audio_element_metadata(
+    conn: &Connection,
+    id: i64,
+    notes: &str,
+    rating: Option<i64>,
+) -> AppResult<()> {
+    validate_rating(rating)?;
+
+    conn.execute(
+        "UPDATE audio_elements SET notes = ?1, rating = ?2 WHERE id = ?3",
+        (notes, &rating, &id),
+    )?;
+
+    Ok(())
+}
+
+/// Stores what probing the element's file found.
+pub fn update_audio_element_info(conn: &Connection, id: i64, info: &AudioInfo) -> AppResult<()> {
+    let updated = conn.execute(
+        "UPDATE audio_elements SET duration_ms = ?1, sample_rate = ?2, channel_count = ?3, codec = ?4, file_size_bytes = ?5 WHERE id = ?6",
+        rusqlite::params![
+            info.duration_ms,
+            info.sample_rate,
+            info.channel_count,
+            info.codec,
+            info.file_size_bytes,
+            id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(AppError::not_found("Audio element", id));
+    }
+
+    Ok(())
+}
+
+/// Stores what loudness analysis of the element's file found.
+pub fn update_audio_element_loudness(
+    conn: &Connection,
+    id: i64,
+    report: &LoudnessReport,
+) -> AppResult<()> {
+    let updated = conn.execute(
+        "UPDATE audio_elements SET loudness_lufs = ?1, loudness_range_lu = ?2, true_peak_dbtp = ?3 WHERE id = ?4",
+        rusqlite::params![
+            report.integrated_lufs,
+            report.loudness_range_lu,
+            report.true_peak_dbtp,
+            id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(AppError::not_found("Audio element", id));
+    }
+
+    Ok(())
+}
+
+/// Stores the silence and loop points detected in the element's file.
+pub fn update_audio_element_loop_points(
+    conn: &Connection,
+    id: i64,
+    points: &LoopPoints,
+) -> AppResult<()> {
+    let updated = conn.execute(
+        "UPDATE audio_elements SET leading_silence_ms = ?1, trailing_silence_ms = ?2, loop_start_ms = ?3, loop_end_ms = ?4 WHERE id = ?5",
+        rusqlite::params![
+            points.leading_silence_ms,
+            points.trailing_silence_ms,
+            points.loop_start_ms,
+            points.loop_end_ms,
+            id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(AppError::not_found("Audio element", id));
+    }
+
+    Ok(())
+}
+
+/// Loudness a channel type is normalised to when no target is given for it.
+/// Music and ambience sit under the action, effects and voices cut through.
+pub fn default_loudness_target(channel_type: &str) -> f64 {
+    match channel_type {
+        "music" => -20.0,
+        "ambient" => -26.0,
+        "effects" | "creatures" => -18.0,
+        "voice" => -16.0,
+        _ => -20.0,
+    }
+}
+
+/// Highest true peak normalisation may push an element to.
+pub const NORMALIZATION_PEAK_CEILING_DBTP: f64 = -1.0;
+
+/// Sets `volume_db` on every measured element of the sound set so it plays at
+/// its channel type's target loudness, from `targets` or else
+/// [`default_loudness_target`]. Gain is capped so the true peak stays at or
+/// below [`NORMALIZATION_PEAK_CEILING_DBTP`]. Unmeasured elements keep their
+/// volume. Returns the elements whose volume changed.
+pub fn normalize_sound_set_loudness(
+    conn: &Connection,
+    sound_set_id: i64,
+    targets: &HashMap<String, f64>,
+) -> AppResult<Vec<AudioElement>> {
+    for (channel_type, target) in targets {
+        if !(-60.0..=0.0).contains(target) {
+            return Err(AppError::ValidationFailed(format!(
+                "Loudness target for '{}' must be between -60 and 0 LUFS, got {}",
+                channel_type, target
+            )));
+        }
+    }
+
+    let mut changed = Vec::new();
+    for mut element in get_audio_elements(conn, sound_set_id, None)? {
+        let Some(loudness) = element.loudness_lufs else {
+            continue;
+        };
+        let target = targets
+            .get(&element.channel_type)
+            .copied()
+            .unwrap_or_else(|| default_loudness_target(&element.channel_type));
+        let mut volume_db = target - loudness;
+        if let Some(true_peak) = element.true_peak_dbtp {
+            volume_db = volume_db.min(NORMALIZATION_PEAK_CEILING_DBTP - true_peak);
+        }
+        let volume_db = (volume_db * 10.0).round() / 10.0;
+        if volume_db == element.volume_db {
+            continue;
+        }
+
+        conn.execute(
+            "UPDATE audio_elements SET volume_db = ?1 WHERE id = ?2",
+            (volume_db, element.id),
+        )?;
+        element.volume_db = volume_db;
+        changed.push(element);
+    }
+
+    Ok(changed)
+}
+
+/// Path of an element's file, with its content hash and the file stamp the
+/// hash was taken at. The hash is `None` until the file has been hashed.
+pub fn get_audio_element_source(
+    conn: &Connection,
+    id: i64,
+) -> AppResult<(String, Option<(String, FileStamp)>)> {
+    conn.query_row(
+        "SELECT file_path, content_hash, content_size, content_modified_ms FROM audio_elements WHERE id = ?1",
+        [id],
+        |row| {
+            let hashed = match (row.get(1)?, row.get(2)?, row.get(3)?) {
+                (Some(content_hash), Some(size), Some(modified_ms)) => {
+                    Some((content_hash, FileStamp { size, modified_ms }))
+                }
+                // Hashes stored before stamps were recorded are taken again.
+                _ => None,
+            };
+            Ok((row.get(0)?, hashed))
+        },
+    )
+    .optional()?
+    .ok_or_else(|| AppError::not_found("Audio element", id))
+}
+
+pub fn set_audio_element_content_hash(
+    conn: &Connection,
+    id: i64,
+    content_hash: &str,
+    stamp: FileStamp,
+) -> AppResult<()> {
+    conn.execute(
+        "UPDATE audio_elements SET content_hash = ?1, content_size = ?2, content_modified_ms = ?3 WHERE id = ?4",
+        rusqlite::params![content_hash, stamp.size, stamp.modified_ms, id],
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::sound_sets::{create_sound_set, update_sound_set_enabled};
+    use crate::store::test_connection;
+
+    fn add(conn: &Connection, sound_set_id: Option<i64>, name: &str) -> AudioElement {
+        create_audio_element(
+            conn,
+            sound_set_id,
+            format!("/audio/{}", name),
+            name.into(),
+            "music".into(),
+            None,
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn available_elements_skip_disabled_sound_sets() {
+        let conn = test_connection();
+        let enabled = create_sound_set(&conn, "On".into(), String::new()).unwrap();
+        let disabled = create_sound_set(&conn, "Off".into(), String::new()).unwrap();
+        update_sound_set_enabled(&conn, disabled.id, false).unwrap();
+
+        let kept = add(&conn, Some(enabled.id), "a.ogg");
+        add(&conn, Some(disabled.id), "b.ogg");
+        let global = add(&conn, None, "c.ogg");
+
+        let mut ids: Vec<i64> = get_all_available_audio_elements(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|element| element.id)
+            .collect();
+        ids.sort();
+        assert_eq!(ids, [kept.id, global.id]);
+    }
+
+    #[test]
+    fn deleting_a_global_oneshot_leaves_sound_set_elements_alone() {
+        let conn = test_connection();
+        let sound_set = create_sound_set(&conn, "S".into(), String::new()).unwrap();
+        let owned = add(&conn, Some(sound_set.id), "a.ogg");
+        let global = add(&conn, None, "b.ogg");
+
+        delete_global_oneshot(&conn, owned.id).unwrap();
+        delete_global_oneshot(&conn, global.id).unwrap();
+
+        assert_eq!(
+            get_audio_elements(&conn, sound_set.id, None).unwrap().len(),
+            1
+        );
+        assert!(get_global_oneshots(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn probed_info_is_stored_with_the_element() {
+        let conn = test_connection();
+        let info = AudioInfo {
+            duration_ms: 4200,
+            sample_rate: 48000,
+            channel_count: 2,
+            codec: "vorbis".into(),
+            file_size_bytes: 65536,
+        };
+        let created = create_audio_element(
+            &conn,
+            None,
+            "/audio/rain.ogg".into(),
+            "rain.ogg".into(),
+            "ambient".into(),
+            None,
+            Some(info.clone()),
+        )
+        .unwrap();
+        let unprobed = add(&conn, None, "old.ogg");
+
+        let stored = get_audio_element(&conn, created.id).unwrap();
+        assert_eq!(stored.duration_ms, Some(4200));
+        assert_eq!(stored.codec.as_deref(), Some("vorbis"));
+        assert_eq!(
+            get_audio_element(&conn, unprobed.id).unwrap().sample_rate,
+            None
+        );
+
+        update_audio_element_info(&conn, unprobed.id, &info).unwrap();
+        assert_eq!(
+            get_audio_element(&conn, unprobed.id).unwrap().channel_count,
+            Some(2)
+        );
+        let missing = update_audio_element_info(&conn, 999, &info).unwrap_err();
+        assert_eq!(missing.code(), "NotFound");
+    }
+
+    #[test]
+    fn content_hashes_are_kept_with_the_stamp_they_were_taken_at() {
+        let conn = test_connection();
+        let element = add(&conn, None, "rain.ogg");
+        let (file_path, hashed) = get_audio_element_source(&conn, element.id).unwrap();
+        assert_eq!(file_path, "/audio/rain.ogg");
+        assert_eq!(hashed, None);
+
+        let stamp = FileStamp {
+            size: 65536,
+            modified_ms: 1_700_000_000_000,
+        };
+        set_audio_element_content_hash(&conn, element.id, "abc", stamp).unwrap();
+        let (_, hashed) = get_audio_element_source(&conn, element.id).unwrap();
+        assert_eq!(hashed, Some(("abc".to_string(), stamp)));
+
+        // A hash without a stamp, as stored before stamps existed, is not trusted.
+        conn.execute(
+            "UPDATE audio_elements SET content_size = NULL WHERE id = ?1",
+            [element.id],
+        )
+        .unwrap();
+        assert_eq!(get_audio_element_source(&conn, element.id).unwrap().1, None);
+    }
+
+    #[test]
+    fn normalizing_meets_each_channel_target_under_the_peak_ceiling() {
+        let conn = test_connection();
+        let sound_set = create_sound_set(&conn, "S".into(), String::new()).unwrap();
+        let measure = |element: &AudioElement, lufs: f64, peak: f64| {
+            let report = LoudnessReport {
+                integrated_lufs: Some(lufs),
+                loudness_range_lu: Some(4.0),
+                true_peak_dbtp: Some(peak),
+            };
+            update_audio_element_loudness(&conn, element.id, &report).unwrap();
+        };
+
+        let music = add(&conn, Some(sound_set.id), "theme.ogg");
+        measure(&music, -14.0, -1.5);
+        // Quiet but peaky: reaching -20 LUFS would clip, so the peak limits it.
+        let peaky = add(&conn, Some(sound_set.id), "drums.ogg");
+        measure(&peaky, -30.0, -4.0);
+        let unmeasured = add(&conn, Some(sound_set.id), "new.ogg");
+
+        let changed = normalize_sound_set_loudness(&conn, sound_set.id, &HashMap::new()).unwrap();
+        assert_eq!(changed.len(), 2);
+        assert_eq!(get_audio_element(&conn, music.id).unwrap().volume_db, -6.0);
+        assert_eq!(get_audio_element(&conn, peaky.id).unwrap().volume_db, 3.0);
+        assert_eq!(
+            get_audio_element(&conn, unmeasured.id).unwrap().volume_db,
+            0.0
+        );
+
+        let targets = HashMap::from([("music".to_string(), -16.0)]);
+        normalize_sound_set_loudness(&conn, sound_set.id, &targets).unwrap();
+        assert_eq!(get_audio_element(&conn, music.id).unwrap().volume_db, -2.0);
+
+        let loud = HashMap::from([("music".to_string(), 6.0)]);
+        let error = normalize_sound_set_loudness(&conn, sound_set.id, &loud).unwrap_err();
+        assert_eq!(error.code(), "ValidationFailed");
+    }
+}
@@ -0,0 +1,305 @@
+//! Gain automation lanes on timeline tracks.
+//!
+//! A lane is a list of breakpoints sorted by time. [`gain_db_at`] is the single
+//! definition of how a lane sounds, so live playback and offline renders read
+//! the same value at any instant.
+
+use rusqlite::{Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use super::collect_rows;
+use crate::{AppError, AppResult};
+
+/// Gains at or below this are treated as silence.
+pub const MIN_GAIN_DB: f64 = -96.0;
+pub const MAX_GAIN_DB: f64 = 12.0;
+
+/// How the gain travels from a breakpoint to the next one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutomationCurve {
+    /// Straight line in decibels.
+    #[default]
+    Linear,
+    /// Holds the point's gain until the next point, then jumps.
+    Step,
+    /// Eases in and out (cosine) between the two gains.
+    Smooth,
+}
+
+impl AutomationCurve {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            AutomationCurve::Linear => "linear",
+            AutomationCurve::Step => "step",
+            AutomationCurve::Smooth => "smooth",
+        }
+    }
+
+    fn from_name(name: &str) -> Self {
+        match name {
+            "step" => AutomationCurve::Step,
+            "smooth" => AutomationCurve::Smooth,
+            _ => AutomationCurve::Linear,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GainPoint {
+    pub id: i64,
+    pub track_id: i64,
+    pub time_ms: i64,
+    pub gain_db: f64,
+    pub curve: AutomationCurve,
+}
+
+fn map_gain_point(row: &Row<'_>) -> rusqlite::Result<GainPoint> {
+    Ok(GainPoint {
+        id: row.get(0)?,
+        track_id: row.get(1)?,
+        time_ms: row.get(2)?,
+        gain_db: row.get(3)?,
+        curve: AutomationCurve::from_name(&row.get::<_, String>(4)?),
+    })
+}
+
+/// Gain of a lane at `time_ms`, in dB. `points` must be sorted by time, as
+/// returned by [`get_track_gain_points`].
+///
+/// An empty lane is unity gain; before the first point and after the last one
+/// the lane holds that point's gain.
+pub fn gain_db_at(points: &[GainPoint], time_ms: f64) -> f64 {
+    let (first, last) = match (points.first(), points.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return 0.0,
+    };
+    if time_ms <= first.time_ms as f64 {
+        return first.gain_db;
+    }
+    if time_ms >= last.time_ms as f64 {
+        return last.gain_db;
+    }
+
+    let next_index = points.partition_point(|point| point.time_ms as f64 <= time_ms);
+    let (from, to) = (&points[next_index - 1], &points[next_index]);
+    let progress = (time_ms - from.time_ms as f64) / (to.time_ms - from.time_ms) as f64;
+    let eased = match from.curve {
+        AutomationCurve::Linear => progress,
+        AutomationCurve::Step => 0.0,
+        AutomationCurve::Smooth => (1.0 - (progress * std::f64::consts::PI).cos()) / 2.0,
+    };
+
+    from.gain_db + (to.gain_db - from.gain_db) * eased
+}
+
+/// Converts decibels to a linear amplitude factor, with [`MIN_GAIN_DB`] and
+/// below mapping to silence.
+pub fn db_to_amplitude(gain_db: f64) -> f64 {
+    if gain_db <= MIN_GAIN_DB {
+        0.0
+    } else {
+        10f64.powf(gain_db / 20.0)
+    }
+}
+
+fn validate_point(time_ms: i64, gain_db: f64) -> AppResult<()> {
+    if time_ms < 0 {
+        return Err(AppError::ValidationFailed(
+            "Automation point time must not be negative".into(),
+        ));
+    }
+    if !(MIN_GAIN_DB..=MAX_GAIN_DB).contains(&gain_db) {
+        return Err(AppError::ValidationFailed(format!(
+            "Automation gain must be between {} and {} dB, got {}",
+            MIN_GAIN_DB, MAX_GAIN_DB, gain_db
+        )));
+    }
+    Ok(())
+}
+
+/// Fails with `ValidationFailed` when another point of the lane already sits at `time_ms`.
+fn ensure_time_is_free(
+    conn: &Connection,
+    track_id: i64,
+    time_ms: i64,
+    exclude_id: Option<i64>,
+) -> AppResult<()> {
+    let taken = conn
+        .query_row(
+            "SELECT id FROM track_gain_points WHERE track_id = ?1 AND time_ms = ?2 AND id != ?3",
+            rusqlite::params![track_id, time_ms, exclude_id.unwrap_or(-1)],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()?;
+
+    match taken {
+        Some(_) => Err(AppError::ValidationFailed(format!(
+            "Track {} already has an automation point at {} ms",
+            track_id, time_ms
+        ))),
+        None => Ok(()),
+    }
+}
+
+pub fn get_track_gain_points(conn: &Connection, track_id: i64) -> AppResult<Vec<GainPoint>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, track_id, time_ms, gain_db, curve FROM track_gain_points WHERE track_id = ?1 ORDER BY time_ms ASC",
+    )?;
+
+    collect_rows(&mut stmt, [track_id], map_gain_point)
+}
+
+/// Gain of the track's automation lane at `time_ms`, in dB.
+pub fn get_track_gain_at(conn: &Connection, track_id: i64, time_ms: f64) -> AppResult<f64> {
+    Ok(gain_db_at(&get_track_gain_points(conn, track_id)?, time_ms))
+}
+
+pub fn add_track_gain_point(
+    conn: &Connection,
+    track_id: i64,
+    time_ms: i64,
+    gain_db: f64,
+    curve: AutomationCurve,
+) -> AppResult<GainPoint> {
+    validate_point(time_ms, gain_db)?;
+
+    conn.query_row(
+        "SELECT id FROM timeline_tracks WHERE id = ?1",
+        [track_id],
+        |row| row.get::<_, i64>(0),
+    )
+    .optional()?
+    .ok_or_else(|| AppError::not_found("Timeline track", track_id))?;
+    ensure_time_is_free(conn, track_id, time_ms, None)?;
+
+    conn.execute(
+        "INSERT INTO track_gain_points (track_id, time_ms, gain_db, curve) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![track_id, time_ms, gain_db, curve.as_str()],
+    )?;
+
+    Ok(GainPoint {
+        id: conn.last_insert_rowid(),
+        track_id,
+        time_ms,
+        gain_db,
+        curve,
+    })
+}
+
+/// Moves a point in time and gain and changes the curve leaving it.
+pub fn move_track_gain_point(
+    conn: &Connection,
+    id: i64,
+    time_ms: i64,
+    gain_db: f64,
+    curve: AutomationCurve,
+) -> AppResult<()> {
+    validate_point(time_ms, gain_db)?;
+
+    let track_id: i64 = conn
+        .query_row(
+            "SELECT track_id FROM track_gain_points WHERE id = ?1",
+            [id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::not_found("Automation point", id))?;
+    ensure_time_is_free(conn, track_id, time_ms, Some(id))?;
+
+    conn.execute(
+        "UPDATE track_gain_points SET time_ms = ?1, gain_db = ?2, curve = ?3 WHERE id = ?4",
+        rusqlite::params![time_ms, gain_db, curve.as_str(), id],
+    )?;
+
+    Ok(())
+}
+
+pub fn delete_track_gain_point(conn: &Connection, id: i64) -> AppResult<()> {
+    conn.execute("DELETE FROM track_gain_points WHERE id = ?1", [id])?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::test_connection;
+
+    fn point(time_ms: i64, gain_db: f64, curve: AutomationCurve) -> GainPoint {
+        GainPoint {
+            id: time_ms,
+            track_id: 5,
+            time_ms,
+            gain_db,
+            curve,
+        }
+    }
+
+    fn seeded_track() -> Connection {
+        let conn = test_connection();
+        conn.execute_batch(
+            "INSERT INTO moods (id, name) VALUES (1, 'M');
+             INSERT INTO timelines (id, mood_id, name) VALUES (1, 1, 'T');
+             INSERT INTO timeline_tracks (id, timeline_id, name) VALUES (5, 1, 'Trk');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn evaluation_follows_each_segment_curve_and_holds_at_the_ends() {
+        let lane = [
+            point(1000, -20.0, AutomationCurve::Linear),
+            point(2000, 0.0, AutomationCurve::Step),
+            point(3000, -12.0, AutomationCurve::Smooth),
+            point(5000, 0.0, AutomationCurve::Linear),
+        ];
+
+        assert_eq!(gain_db_at(&[], 1234.0), 0.0);
+        assert_eq!(gain_db_at(&lane, 0.0), -20.0);
+        assert_eq!(gain_db_at(&lane, 1500.0), -10.0);
+        assert_eq!(gain_db_at(&lane, 2000.0), 0.0);
+        assert_eq!(gain_db_at(&lane, 2999.0), 0.0);
+        assert_eq!(gain_db_at(&lane, 3000.0), -12.0);
+        let eased_quarter = (1.0 - std::f64::consts::FRAC_1_SQRT_2) / 2.0;
+        assert!((gain_db_at(&lane, 3500.0) - (-12.0 + 12.0 * eased_quarter)).abs() < 1e-9);
+        assert!((gain_db_at(&lane, 4000.0) - -6.0).abs() < 1e-9);
+        assert_eq!(gain_db_at(&lane, 60_000.0), 0.0);
+    }
+
+    #[test]
+    fn decibels_convert_to_amplitude_with_a_silence_floor() {
+        assert_eq!(db_to_amplitude(0.0), 1.0);
+        assert!((db_to_amplitude(-6.0) - 0.501).abs() < 1e-3);
+        assert_eq!(db_to_amplitude(MIN_GAIN_DB), 0.0);
+    }
+
+    #[test]
+    fn points_are_validated_and_kept_in_time_order() {
+        let conn = seeded_track();
+
+        let late = add_track_gain_point(&conn, 5, 4000, -6.0, AutomationCurve::Linear).unwrap();
+        add_track_gain_point(&conn, 5, 0, 0.0, AutomationCurve::Smooth).unwrap();
+
+        let clash = add_track_gain_point(&conn, 5, 4000, 0.0, AutomationCurve::Linear);
+        assert_eq!(clash.unwrap_err().code(), "ValidationFailed");
+        let too_loud = add_track_gain_point(&conn, 5, 100, 24.0, AutomationCurve::Linear);
+        assert_eq!(too_loud.unwrap_err().code(), "ValidationFailed");
+        let no_track = add_track_gain_point(&conn, 99, 100, 0.0, AutomationCurve::Linear);
+        assert_eq!(no_track.unwrap_err().code(), "NotFound");
+
+        move_track_gain_point(&conn, late.id, 2000, -3.0, AutomationCurve::Step).unwrap();
+        let lane = get_track_gain_points(&conn, 5).unwrap();
+        let times: Vec<i64> = lane.iter().map(|point| point.time_ms).collect();
+        assert_eq!(times, [0, 2000]);
+        assert_eq!(lane[1].curve, AutomationCurve::Step);
+        assert!((get_track_gain_at(&conn, 5, 1000.0).unwrap() - -1.5).abs() < 1e-9);
+        assert_eq!(get_track_gain_at(&conn, 5, 9000.0).unwrap(), -3.0);
+
+        delete_track_gain_point(&conn, late.id).unwrap();
+        conn.execute("DELETE FROM timeline_tracks WHERE id = 5", [])
+            .unwrap();
+        assert!(get_track_gain_points(&conn, 5).unwrap().is_empty());
+    }
+}
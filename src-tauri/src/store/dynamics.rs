@@ -0,0 +1,297 @@
+//! Sidechain ducking rules between channels, and the master bus limiter.
+//!
+//! Channel compressors live on the channel itself; see
+//! [`ChannelCompressor`](super::audio_channels::ChannelCompressor).
+
+use rusqlite::{Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use super::audio_channels::{check_range, get_audio_channel};
+use super::collect_rows;
+use crate::{AppError, AppResult};
+
+/// Lowers `target_channel_id` by `depth_db` while `trigger_channel_id` is
+/// louder than `threshold_db`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DuckRule {
+    pub id: i64,
+    pub target_channel_id: i64,
+    pub trigger_channel_id: i64,
+    pub depth_db: f64,
+    pub threshold_db: f64,
+    /// Time to duck once the trigger is active.
+    pub attack_ms: f64,
+    /// Time to recover once the trigger falls silent.
+    pub release_ms: f64,
+}
+
+pub const DEFAULT_DUCK_DEPTH_DB: f64 = 6.0;
+pub const DEFAULT_DUCK_THRESHOLD_DB: f64 = -40.0;
+pub const DEFAULT_DUCK_ATTACK_MS: f64 = 50.0;
+pub const DEFAULT_DUCK_RELEASE_MS: f64 = 500.0;
+
+/// Brick-wall limiter at the end of the master bus.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MasterLimiter {
+    pub enabled: bool,
+    /// Highest true peak let through.
+    pub ceiling_dbtp: f64,
+    pub release_ms: f64,
+}
+
+const DUCK_RULE_COLUMNS: &str =
+    "r.id, r.target_channel_id, r.trigger_channel_id, r.depth_db, r.threshold_db, r.attack_ms, r.release_ms";
+
+fn map_duck_rule(row: &Row<'_>) -> rusqlite::Result<DuckRule> {
+    Ok(DuckRule {
+        id: row.get(0)?,
+        target_channel_id: row.get(1)?,
+        trigger_channel_id: row.get(2)?,
+        depth_db: row.get(3)?,
+        threshold_db: row.get(4)?,
+        attack_ms: row.get(5)?,
+        release_ms: row.get(6)?,
+    })
+}
+
+fn validate_duck_settings(
+    depth_db: f64,
+    threshold_db: f64,
+    attack_ms: f64,
+    release_ms: f64,
+) -> AppResult<()> {
+    check_range("Duck depth", depth_db, 0.0..=60.0, " dB")?;
+    check_range("Duck threshold", threshold_db, -80.0..=0.0, " dB")?;
+    check_range("Duck attack", attack_ms, 1.0..=5000.0, " ms")?;
+    check_range("Duck release", release_ms, 1.0..=10000.0, " ms")
+}
+
+pub fn get_duck_rule(conn: &Connection, id: i64) -> AppResult<DuckRule> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM channel_duck_rules r WHERE r.id = ?1",
+            DUCK_RULE_COLUMNS
+        ),
+        [id],
+        map_duck_rule,
+    )
+    .optional()?
+    .ok_or_else(|| AppError::not_found("Duck rule", id))
+}
+
+/// Rules between the channels of `sound_set_id`.
+pub fn get_duck_rules(conn: &Connection, sound_set_id: i64) -> AppResult<Vec<DuckRule>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM channel_duck_rules r
+         JOIN audio_channels c ON c.id = r.target_channel_id
+         WHERE c.sound_set_id = ?1
+         ORDER BY r.id",
+        DUCK_RULE_COLUMNS
+    ))?;
+
+    collect_rows(&mut stmt, [sound_set_id], map_duck_rule)
+}
+
+pub fn get_all_duck_rules(conn: &Connection) -> AppResult<Vec<DuckRule>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM channel_duck_rules r ORDER BY r.id",
+        DUCK_RULE_COLUMNS
+    ))?;
+
+    collect_rows(&mut stmt, [], map_duck_rule)
+}
+
+/// Adds a rule ducking `target_channel_id` under `trigger_channel_id`. Both
+/// channels must belong to the same sound set, and a pair has one rule at most.
+pub fn create_duck_rule(
+    conn: &Connection,
+    target_channel_id: i64,
+    trigger_channel_id: i64,
+    depth_db: f64,
+    threshold_db: f64,
+    attack_ms: f64,
+    release_ms: f64,
+) -> AppResult<DuckRule> {
+    if target_channel_id == trigger_channel_id {
+        return Err(AppError::ValidationFailed(
+            "A channel cannot duck under itself".into(),
+        ));
+    }
+    validate_duck_settings(depth_db, threshold_db, attack_ms, release_ms)?;
+    let target = get_audio_channel(conn, target_channel_id)?;
+    let trigger = get_audio_channel(conn, trigger_channel_id)?;
+    if target.sound_set_id != trigger.sound_set_id {
+        return Err(AppError::ValidationFailed(format!(
+            "Channels '{}' and '{}' belong to different sound sets",
+            target.name, trigger.name
+        )));
+    }
+
+    let exists: Option<i64> = conn
+        .query_row(
+            "SELECT id FROM channel_duck_rules WHERE target_channel_id = ?1 AND trigger_channel_id = ?2",
+            [target_channel_id, trigger_channel_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if exists.is_some() {
+        return Err(AppError::ValidationFailed(format!(
+            "'{}' already ducks under '{}'",
+            target.name, trigger.name
+        )));
+    }
+
+    conn.execute(
+        "INSERT INTO channel_duck_rules (target_channel_id, trigger_channel_id, depth_db, threshold_db, attack_ms, release_ms)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        rusqlite::params![
+            target_channel_id,
+            trigger_channel_id,
+            depth_db,
+            threshold_db,
+            attack_ms,
+            release_ms
+        ],
+    )?;
+
+    Ok(DuckRule {
+        id: conn.last_insert_rowid(),
+        target_channel_id,
+        trigger_channel_id,
+        depth_db,
+        threshold_db,
+        attack_ms,
+        release_ms,
+    })
+}
+
+pub fn update_duck_rule(
+    conn: &Connection,
+    id: i64,
+    depth_db: f64,
+    threshold_db: f64,
+    attack_ms: f64,
+    release_ms: f64,
+) -> AppResult<DuckRule> {
+    validate_duck_settings(depth_db, threshold_db, attack_ms, release_ms)?;
+    let updated = conn.execute(
+        "UPDATE channel_duck_rules SET depth_db = ?1, threshold_db = ?2, attack_ms = ?3, release_ms = ?4 WHERE id = ?5",
+        rusqlite::params![depth_db, threshold_db, attack_ms, release_ms, id],
+    )?;
+    if updated == 0 {
+        return Err(AppError::not_found("Duck rule", id));
+    }
+
+    get_duck_rule(conn, id)
+}
+
+pub fn delete_duck_rule(conn: &Connection, id: i64) -> AppResult<()> {
+    let deleted = conn.execute("DELETE FROM channel_duck_rules WHERE id = ?1", [id])?;
+    if deleted == 0 {
+        return Err(AppError::not_found("Duck rule", id));
+    }
+
+    Ok(())
+}
+
+pub fn get_master_limiter(conn: &Connection) -> AppResult<MasterLimiter> {
+    Ok(conn.query_row(
+        "SELECT limiter_enabled, limiter_ceiling_dbtp, limiter_release_ms FROM master_bus WHERE id = 1",
+        [],
+        |row| {
+            Ok(MasterLimiter {
+                enabled: row.get(0)?,
+                ceiling_dbtp: row.get(1)?,
+                release_ms: row.get(2)?,
+            })
+        },
+    )?)
+}
+
+pub fn set_master_limiter(conn: &Connection, limiter: &MasterLimiter) -> AppResult<MasterLimiter> {
+    check_range(
+        "Limiter ceiling",
+        limiter.ceiling_dbtp,
+        -20.0..=0.0,
+        " dBTP",
+    )?;
+    check_range("Limiter release", limiter.release_ms, 1.0..=2000.0, " ms")?;
+
+    conn.execute(
+        "UPDATE master_bus SET limiter_enabled = ?1, limiter_ceiling_dbtp = ?2, limiter_release_ms = ?3 WHERE id = 1",
+        rusqlite::params![limiter.enabled, limiter.ceiling_dbtp, limiter.release_ms],
+    )?;
+
+    get_master_limiter(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::audio_channels::{create_audio_channel, delete_audio_channel};
+    use crate::store::sound_sets::create_sound_set;
+    use crate::store::test_connection;
+
+    fn channel(conn: &Connection, sound_set_id: i64, name: &str) -> i64 {
+        create_audio_channel(conn, sound_set_id, name.into(), "music".into(), 1.0)
+            .unwrap()
+            .id
+    }
+
+    #[test]
+    fn duck_rules_pair_channels_of_one_sound_set() {
+        let conn = test_connection();
+        let sound_set = create_sound_set(&conn, "S".into(), String::new()).unwrap();
+        let other = create_sound_set(&conn, "O".into(), String::new()).unwrap();
+        let music = channel(&conn, sound_set.id, "Music");
+        let effects = channel(&conn, sound_set.id, "Sound Effects");
+        let elsewhere = channel(&conn, other.id, "Elsewhere");
+
+        let rule = create_duck_rule(&conn, music, effects, 6.0, -40.0, 50.0, 500.0).unwrap();
+        assert_eq!(
+            get_duck_rules(&conn, sound_set.id).unwrap(),
+            vec![rule.clone()]
+        );
+        assert!(get_duck_rules(&conn, other.id).unwrap().is_empty());
+
+        let again = create_duck_rule(&conn, music, effects, 3.0, -40.0, 50.0, 500.0);
+        assert_eq!(again.unwrap_err().code(), "ValidationFailed");
+        let itself = create_duck_rule(&conn, music, music, 6.0, -40.0, 50.0, 500.0);
+        assert_eq!(itself.unwrap_err().code(), "ValidationFailed");
+        let across = create_duck_rule(&conn, music, elsewhere, 6.0, -40.0, 50.0, 500.0);
+        assert_eq!(across.unwrap_err().code(), "ValidationFailed");
+
+        let deeper = update_duck_rule(&conn, rule.id, 12.0, -30.0, 20.0, 800.0).unwrap();
+        assert_eq!(deeper.depth_db, 12.0);
+        let too_deep = update_duck_rule(&conn, rule.id, 90.0, -30.0, 20.0, 800.0);
+        assert_eq!(too_deep.unwrap_err().code(), "ValidationFailed");
+
+        delete_audio_channel(&conn, effects).unwrap();
+        assert!(get_all_duck_rules(&conn).unwrap().is_empty());
+        assert_eq!(
+            delete_duck_rule(&conn, rule.id).unwrap_err().code(),
+            "NotFound"
+        );
+    }
+
+    #[test]
+    fn the_master_limiter_starts_enabled() {
+        let conn = test_connection();
+        let limiter = get_master_limiter(&conn).unwrap();
+        assert!(limiter.enabled);
+        assert_eq!(limiter.ceiling_dbtp, -1.0);
+
+        let relaxed = MasterLimiter {
+            enabled: false,
+            ceiling_dbtp: -0.3,
+            release_ms: 250.0,
+        };
+        assert_eq!(set_master_limiter(&conn, &relaxed).unwrap(), relaxed);
+        let above_full_scale = MasterLimiter {
+            ceiling_dbtp: 1.0,
+            ..relaxed
+        };
+        let error = set_master_limiter(&conn, &above_full_scale).unwrap_err();
+        assert_eq!(error.code(), "ValidationFailed");
+    }
+}
@@ -0,0 +1,323 @@
+//! Ordered insert effects on each channel.
+
+use rusqlite::{Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::audio_channels::{check_range, get_audio_channel};
+use super::collect_rows;
+use crate::{AppError, AppResult};
+
+/// One effect and its parameters. Stored as JSON, tagged by `kind`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Effect {
+    /// Peaking band of a parametric EQ.
+    Eq {
+        frequency_hz: f64,
+        gain_db: f64,
+        q: f64,
+    },
+    LowPass {
+        frequency_hz: f64,
+        q: f64,
+    },
+    HighPass {
+        frequency_hz: f64,
+        q: f64,
+    },
+    Reverb {
+        /// 0 for a small room to 1 for a hall.
+        room_size: f64,
+        /// How quickly high frequencies die away in the tail, 0 to 1.
+        damping: f64,
+        /// Wet share of the output, 0 to 1.
+        mix: f64,
+    },
+    Delay {
+        time_ms: f64,
+        /// Share of each echo fed back into the next, below 1.
+        feedback: f64,
+        mix: f64,
+    },
+}
+
+impl Effect {
+    pub fn validate(&self) -> AppResult<()> {
+        let frequency = |value| check_range("Frequency", value, 20.0..=20_000.0, " Hz");
+        let q = |value| check_range("Q", value, 0.1..=18.0, "");
+        let share = |name, value| check_range(name, value, 0.0..=1.0, "");
+        match *self {
+            Effect::Eq {
+                frequency_hz,
+                gain_db,
+                q: width,
+            } => {
+                frequency(frequency_hz)?;
+                check_range("EQ gain", gain_db, -24.0..=24.0, " dB")?;
+                q(width)
+            }
+            Effect::LowPass {
+                frequency_hz,
+                q: width,
+            }
+            | Effect::HighPass {
+                frequency_hz,
+                q: width,
+            } => {
+                frequency(frequency_hz)?;
+                q(width)
+            }
+            Effect::Reverb {
+                room_size,
+                damping,
+                mix,
+            } => {
+                share("Room size", room_size)?;
+                share("Damping", damping)?;
+                share("Mix", mix)
+            }
+            Effect::Delay {
+                time_ms,
+                feedback,
+                mix,
+            } => {
+                check_range("Delay time", time_ms, 1.0..=2000.0, " ms")?;
+                check_range("Feedback", feedback, 0.0..=0.95, "")?;
+                share("Mix", mix)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChannelEffect {
+    pub id: i64,
+    pub channel_id: i64,
+    /// Position in the channel's chain; lower runs first.
+    pub order_index: i64,
+    /// A disabled effect stays in the chain but passes audio through.
+    pub enabled: bool,
+    pub effect: Effect,
+}
+
+const EFFECT_COLUMNS: &str = "id, channel_id, order_index, enabled, effect";
+
+fn map_channel_effect(row: &Row<'_>) -> rusqlite::Result<ChannelEffect> {
+    let effect: String = row.get(4)?;
+    Ok(ChannelEffect {
+        id: row.get(0)?,
+        channel_id: row.get(1)?,
+        order_index: row.get(2)?,
+        enabled: row.get(3)?,
+        effect: serde_json::from_str(&effect).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(4, rusqlite::types::Type::Text, Box::new(e))
+        })?,
+    })
+}
+
+fn effect_json(effect: &Effect) -> AppResult<String> {
+    serde_json::to_string(effect)
+        .map_err(|e| AppError::Internal(format!("Failed to encode effect: {}", e)))
+}
+
+pub fn get_channel_effect(conn: &Connection, id: i64) -> AppResult<ChannelEffect> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM channel_effects WHERE id = ?1",
+            EFFECT_COLUMNS
+        ),
+        [id],
+        map_channel_effect,
+    )
+    .optional()?
+    .ok_or_else(|| AppError::not_found("Channel effect", id))
+}
+
+/// The chain of `channel_id`, in processing order.
+pub fn get_channel_effects(conn: &Connection, channel_id: i64) -> AppResult<Vec<ChannelEffect>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM channel_effects WHERE channel_id = ?1 ORDER BY order_index, id",
+        EFFECT_COLUMNS
+    ))?;
+
+    collect_rows(&mut stmt, [channel_id], map_channel_effect)
+}
+
+/// Every channel's chain, keyed by channel.
+pub fn get_all_channel_effects(conn: &Connection) -> AppResult<HashMap<i64, Vec<ChannelEffect>>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM channel_effects ORDER BY channel_id, order_index, id",
+        EFFECT_COLUMNS
+    ))?;
+
+    let mut chains: HashMap<i64, Vec<ChannelEffect>> = HashMap::new();
+    for effect in collect_rows(&mut stmt, [], map_channel_effect)? {
+        chains.entry(effect.channel_id).or_default().push(effect);
+    }
+    Ok(chains)
+}
+
+/// Appends `effect` to the end of the channel's chain.
+pub fn add_channel_effect(
+    conn: &Connection,
+    channel_id: i64,
+    effect: &Effect,
+) -> AppResult<ChannelEffect> {
+    get_audio_channel(conn, channel_id)?;
+    append_channel_effect(conn, channel_id, true, effect)
+}
+
+/// Appends without checking the channel, for imports that just created it.
+pub(crate) fn append_channel_effect(
+    conn: &Connection,
+    channel_id: i64,
+    enabled: bool,
+    effect: &Effect,
+) -> AppResult<ChannelEffect> {
+    effect.validate()?;
+
+    let order_index: i64 = conn.query_row(
+        "SELECT COALESCE(MAX(order_index), -1) + 1 FROM channel_effects WHERE channel_id = ?1",
+        [channel_id],
+        |row| row.get(0),
+    )?;
+    conn.execute(
+        "INSERT INTO channel_effects (channel_id, order_index, enabled, effect) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![channel_id, order_index, enabled, effect_json(effect)?],
+    )?;
+
+    Ok(ChannelEffect {
+        id: conn.last_insert_rowid(),
+        channel_id,
+        order_index,
+        enabled,
+        effect: effect.clone(),
+    })
+}
+
+/// Replaces an effect's parameters. Its kind may change as well.
+pub fn update_channel_effect(
+    conn: &Connection,
+    id: i64,
+    enabled: bool,
+    effect: &Effect,
+) -> AppResult<ChannelEffect> {
+    effect.validate()?;
+    let updated = conn.execute(
+        "UPDATE channel_effects SET enabled = ?1, effect = ?2 WHERE id = ?3",
+        rusqlite::params![enabled, effect_json(effect)?, id],
+    )?;
+    if updated == 0 {
+        return Err(AppError::not_found("Channel effect", id));
+    }
+
+    get_channel_effect(conn, id)
+}
+
+/// Moves an effect to `order_index` within its chain, renumbering the rest.
+pub fn move_channel_effect(
+    conn: &Connection,
+    id: i64,
+    order_index: i64,
+) -> AppResult<Vec<ChannelEffect>> {
+    let moved = get_channel_effect(conn, id)?;
+    let mut chain: Vec<i64> = get_channel_effects(conn, moved.channel_id)?
+        .into_iter()
+        .map(|effect| effect.id)
+        .filter(|effect_id| *effect_id != id)
+        .collect();
+    let position = order_index.clamp(0, chain.len() as i64) as usize;
+    chain.insert(position, id);
+
+    for (index, effect_id) in chain.iter().enumerate() {
+        conn.execute(
+            "UPDATE channel_effects SET order_index = ?1 WHERE id = ?2",
+            [index as i64, *effect_id],
+        )?;
+    }
+
+    get_channel_effects(conn, moved.channel_id)
+}
+
+pub fn delete_channel_effect(conn: &Connection, id: i64) -> AppResult<()> {
+    let deleted = conn.execute("DELETE FROM channel_effects WHERE id = ?1", [id])?;
+    if deleted == 0 {
+        return Err(AppError::not_found("Channel effect", id));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::audio_channels::{create_audio_channel, delete_audio_channel};
+    use crate::store::sound_sets::create_sound_set;
+    use crate::store::test_connection;
+
+    fn low_pass(frequency_hz: f64) -> Effect {
+        Effect::LowPass {
+            frequency_hz,
+            q: 0.707,
+        }
+    }
+
+    #[test]
+    fn chains_keep_their_order_and_parameters() {
+        let conn = test_connection();
+        let sound_set = create_sound_set(&conn, "S".into(), String::new()).unwrap();
+        let channel =
+            create_audio_channel(&conn, sound_set.id, "Rain".into(), "ambient".into(), 1.0)
+                .unwrap();
+
+        let muffle = add_channel_effect(&conn, channel.id, &low_pass(800.0)).unwrap();
+        let room = Effect::Reverb {
+            room_size: 0.4,
+            damping: 0.6,
+            mix: 0.25,
+        };
+        let reverb = add_channel_effect(&conn, channel.id, &room).unwrap();
+        assert_eq!(reverb.order_index, 1);
+        assert_eq!(
+            get_channel_effects(&conn, channel.id).unwrap(),
+            vec![muffle.clone(), reverb.clone()]
+        );
+
+        let chain = move_channel_effect(&conn, reverb.id, 0).unwrap();
+        let ids: Vec<i64> = chain.iter().map(|effect| effect.id).collect();
+        assert_eq!(ids, [reverb.id, muffle.id]);
+
+        let bypassed = update_channel_effect(&conn, muffle.id, false, &low_pass(1200.0)).unwrap();
+        assert!(!bypassed.enabled);
+        assert_eq!(bypassed.effect, low_pass(1200.0));
+        assert_eq!(
+            get_all_channel_effects(&conn).unwrap()[&channel.id].len(),
+            2
+        );
+
+        delete_audio_channel(&conn, channel.id).unwrap();
+        assert!(get_all_channel_effects(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn parameters_out_of_range_are_rejected() {
+        let conn = test_connection();
+        let sound_set = create_sound_set(&conn, "S".into(), String::new()).unwrap();
+        let channel =
+            create_audio_channel(&conn, sound_set.id, "Rain".into(), "ambient".into(), 1.0)
+                .unwrap();
+
+        let inaudible = add_channel_effect(&conn, channel.id, &low_pass(5.0)).unwrap_err();
+        assert_eq!(inaudible.code(), "ValidationFailed");
+        let runaway = Effect::Delay {
+            time_ms: 300.0,
+            feedback: 1.2,
+            mix: 0.3,
+        };
+        let error = add_channel_effect(&conn, channel.id, &runaway).unwrap_err();
+        assert_eq!(error.code(), "ValidationFailed");
+        let missing = add_channel_effect(&conn, 999, &low_pass(800.0)).unwrap_err();
+        assert_eq!(missing.code(), "NotFound");
+    }
+}
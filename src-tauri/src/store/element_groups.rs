@@ -0,0 +1,386 @@
+use rusqlite::{Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use super::collect_rows;
+use super::group_playback::PlaybackMode;
+use super::tags::{
+    has_tag_condition, split_tag_names, tag_names_column, validate_rating, TagTarget,
+};
+use super::trash::{ensure_live_audio_element, ensure_live_element_group};
+use crate::{AppError, AppResult};
+
+/// Largest random detune either way, in semitones.
+pub const MAX_PITCH_VARIATION_SEMITONES: f64 = 12.0;
+/// Largest random gain change either way, in dB.
+pub const MAX_VOLUME_VARIATION_DB: f64 = 24.0;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ElementGroup {
+    pub id: i64,
+    pub name: String,
+    pub sound_set_id: Option<i64>,
+    pub created_at: String,
+    pub tags: Vec<String>,
+    pub notes: String,
+    /// One to five stars; `None` when unrated.
+    pub rating: Option<i64>,
+    pub playback_mode: PlaybackMode,
+    /// Each play is detuned by a random amount within plus or minus this.
+    pub pitch_variation_semitones: f64,
+    /// Each play's gain moves by a random amount within plus or minus this.
+    pub volume_variation_db: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ElementGroupMember {
+    pub id: i64,
+    pub group_id: i64,
+    pub audio_element_id: i64,
+    pub order_index: i64,
+    /// Relative likelihood under `PlaybackMode::Weighted`.
+    pub weight: f64,
+}
+
+/// Columns read by [`map_element_group`], for a query aliasing the table `g`.
+fn element_group_columns() -> String {
+    format!(
+        "g.id, g.name, g.sound_set_id, g.created_at, g.notes, g.rating, {}, g.playback_mode, g.pitch_variation_semitones, g.volume_variation_db",
+        tag_names_column(TagTarget::ElementGroup, "g.id")
+    )
+}
+
+fn map_element_group(row: &Row<'_>) -> rusqlite::Result<ElementGroup> {
+    Ok(ElementGroup {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        sound_set_id: row.get(2)?,
+        created_at: row.get(3)?,
+        notes: row.get(4)?,
+        rating: row.get(5)?,
+        tags: split_tag_names(row.get(6)?),
+        playback_mode: PlaybackMode::from_name(&row.get::<_, String>(7)?),
+        pitch_variation_semitones: row.get(8)?,
+        volume_variation_db: row.get(9)?,
+    })
+}
+
+pub fn create_element_group(
+    conn: &Connection,
+    name: String,
+    sound_set_id: Option<i64>,
+) -> AppResult<ElementGroup> {
+    conn.execute(
+        "INSERT INTO element_groups (name, sound_set_id) VALUES (?1, ?2)",
+        (&name, &sound_set_id),
+    )?;
+
+    let id = conn.last_insert_rowid();
+
+    Ok(ElementGroup {
+        id,
+        name,
+        sound_set_id,
+        created_at: chrono::Local::now().to_rfc3339(),
+        tags: Vec::new(),
+        notes: String::new(),
+        rating: None,
+        playback_mode: PlaybackMode::Sequential,
+        pitch_variation_semitones: 0.0,
+        volume_variation_db: 0.0,
+    })
+}
+
+pub fn rename_element_group(conn: &Connection, id: i64, name: &str) -> AppResult<()> {
+    conn.execute(
+        "UPDATE element_groups SET name = ?1 WHERE id = ?2",
+        (name, &id),
+    )?;
+
+    Ok(())
+}
+
+pub fn update_element_group_metadata(
+    conn: &Connection,
+    id: i64,
+    notes: &str,
+    rating: Option<i64>,
+) -> AppResult<()> {
+    validate_rating(rating)?;
+
+    conn.execute(
+        "UPDATE element_groups SET notes = ?1, rating = ?2 WHERE id = ?3",
+        (notes, &rating, &id),
+    )?;
+
+    Ok(())
+}
+
+pub(crate) fn validate_variation(
+    pitch_variation_semitones: f64,
+    volume_variation_db: f64,
+) -> AppResult<()> {
+    if !(0.0..=MAX_PITCH_VARIATION_SEMITONES).contains(&pitch_variation_semitones) {
+        return Err(AppError::ValidationFailed(format!(
+            "Pitch variation must be between 0 and {} semitones, got {}",
+            MAX_PITCH_VARIATION_SEMITONES, pitch_variation_semitones
+        )));
+    }
+    if !(0.0..=MAX_VOLUME_VARIATION_DB).contains(&volume_variation_db) {
+        return Err(AppError::ValidationFailed(format!(
+            "Volume variation must be between 0 and {} dB, got {}",
+            MAX_VOLUME_VARIATION_DB, volume_variation_db
+        )));
+    }
+    Ok(())
+}
+
+/// Sets how the group picks its next member and how much each play varies.
+pub fn update_element_group_playback(
+    conn: &Connection,
+    id: i64,
+    playback_mode: PlaybackMode,
+    pitch_variation_semitones: f64,
+    volume_variation_db: f64,
+) -> AppResult<()> {
+    validate_variation(pitch_variation_semitones, volume_variation_db)?;
+
+    conn.execute(
+        "UPDATE element_groups SET playback_mode = ?1, pitch_variation_semitones = ?2, volume_variation_db = ?3 WHERE id = ?4",
+        rusqlite::params![
+            playback_mode.as_str(),
+            pitch_variation_semitones,
+            volume_variation_db,
+            id
+        ],
+    )?;
+
+    Ok(())
+}
+
+pub fn delete_element_group(conn: &Connection, id: i64) -> AppResult<()> {
+    conn.execute("DELETE FROM element_groups WHERE id = ?1", [id])?;
+
+    Ok(())
+}
+
+pub fn get_element_group(conn: &Connection, id: i64) -> AppResult<ElementGroup> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM element_groups g WHERE g.id = ?1",
+            element_group_columns()
+        ),
+        [id],
+        map_element_group,
+    )
+    .optional()?
+    .ok_or_else(|| AppError::not_found("Element group", id))
+}
+
+/// Groups of one sound set, or the global groups when `sound_set_id` is `None`,
+/// optionally only those carrying `tag`.
+pub fn get_element_groups(
+    conn: &Connection,
+    sound_set_id: Option<i64>,
+    tag: Option<&str>,
+) -> AppResult<Vec<ElementGroup>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM element_groups g WHERE g.sound_set_id IS ?1 AND {} ORDER BY g.created_at DESC, g.id DESC",
+        element_group_columns(),
+        has_tag_condition(TagTarget::ElementGroup, "g.id", "?2")
+    ))?;
+
+    collect_rows(
+        &mut stmt,
+        rusqlite::params![sound_set_id, tag],
+        map_element_group,
+    )
+}
+
+/// Groups from enabled sound sets plus the global groups.
+pub fn get_all_available_element_groups(conn: &Connection) -> AppResult<Vec<ElementGroup>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}
+         FROM element_groups g
+         LEFT JOIN sound_sets s ON g.sound_set_id = s.id
+         WHERE g.sound_set_id IS NULL OR (s.is_enabled = 1 AND s.deleted_at IS NULL)
+         ORDER BY g.created_at DESC",
+        element_group_columns()
+    ))?;
+
+    collect_rows(&mut stmt, [], map_element_group)
+}
+
+pub fn add_element_to_group(
+    conn: &Connection,
+    group_id: i64,
+    audio_element_id: i64,
+) -> AppResult<ElementGroupMember> {
+    ensure_live_element_group(conn, group_id)?;
+    ensure_live_audio_element(conn, audio_element_id)?;
+
+    let order_index: i64 = conn.query_row(
+        "SELECT COALESCE(MAX(order_index), -1) + 1 FROM element_group_members WHERE group_id = ?1",
+        [&group_id],
+        |row| row.get(0),
+    )?;
+
+    conn.execute(
+        "INSERT INTO element_group_members (group_id, audio_element_id, order_index) VALUES (?1, ?2, ?3)",
+        (&group_id, &audio_element_id, &order_index),
+    )?;
+
+    let id = conn.last_insert_rowid();
+
+    Ok(ElementGroupMember {
+        id,
+        group_id,
+        audio_element_id,
+        order_index,
+        weight: 1.0,
+    })
+}
+
+pub fn update_group_member_weight(conn: &Connection, id: i64, weight: f64) -> AppResult<()> {
+    if !weight.is_finite() || weight < 0.0 {
+        return Err(AppError::ValidationFailed(format!(
+            "Member weight must be zero or positive, got {}",
+            weight
+        )));
+    }
+
+    conn.execute(
+        "UPDATE element_group_members SET weight = ?1 WHERE id = ?2",
+        (weight, &id),
+    )?;
+
+    Ok(())
+}
+
+pub fn remove_element_from_group(conn: &Connection, id: i64) -> AppResult<()> {
+    conn.execute("DELETE FROM element_group_members WHERE id = ?1", [id])?;
+
+    Ok(())
+}
+
+pub fn get_group_members(conn: &Connection, group_id: i64) -> AppResult<Vec<ElementGroupMember>> {
+    let mut stmt = conn.prepare(
+        "SELECT m.id, m.group_id, m.audio_element_id, m.order_index, m.weight
+         FROM element_group_members m
+         JOIN audio_elements e ON e.id = m.audio_element_id
+         WHERE m.group_id = ?1 AND e.deleted_at IS NULL
+         ORDER BY m.order_index ASC",
+    )?;
+
+    collect_rows(&mut stmt, [group_id], |row| {
+        Ok(ElementGroupMember {
+            id: row.get(0)?,
+            group_id: row.get(1)?,
+            audio_element_id: row.get(2)?,
+            order_index: row.get(3)?,
+            weight: row.get(4)?,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::audio_elements::create_audio_element;
+    use crate::store::sound_sets::{create_sound_set, update_sound_set_enabled};
+    use crate::store::test_connection;
+
+    #[test]
+    fn members_keep_insertion_order() {
+        let conn = test_connection();
+        let group = create_element_group(&conn, "Thunder".into(), None).unwrap();
+        let mut element_ids = Vec::new();
+        for name in ["a.ogg", "b.ogg"] {
+            let element = create_audio_element(
+                &conn,
+                None,
+                name.into(),
+                name.into(),
+                "sfx".into(),
+                None,
+                None,
+            )
+            .unwrap();
+            element_ids.push(element.id);
+        }
+
+        for element_id in &element_ids {
+            add_element_to_group(&conn, group.id, *element_id).unwrap();
+        }
+
+        let members = get_group_members(&conn, group.id).unwrap();
+        let order: Vec<(i64, i64)> = members
+            .iter()
+            .map(|member| (member.audio_element_id, member.order_index))
+            .collect();
+        assert_eq!(order, [(element_ids[0], 0), (element_ids[1], 1)]);
+
+        remove_element_from_group(&conn, members[0].id).unwrap();
+        assert_eq!(get_group_members(&conn, group.id).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn groups_are_scoped_to_their_sound_set() {
+        let conn = test_connection();
+        let enabled = create_sound_set(&conn, "On".into(), String::new()).unwrap();
+        let disabled = create_sound_set(&conn, "Off".into(), String::new()).unwrap();
+        update_sound_set_enabled(&conn, disabled.id, false).unwrap();
+
+        let owned = create_element_group(&conn, "Owned".into(), Some(enabled.id)).unwrap();
+        create_element_group(&conn, "Hidden".into(), Some(disabled.id)).unwrap();
+        let global = create_element_group(&conn, "Global".into(), None).unwrap();
+        rename_element_group(&conn, global.id, "Shared").unwrap();
+
+        let scoped = get_element_groups(&conn, Some(enabled.id), None).unwrap();
+        assert_eq!(scoped.len(), 1);
+        assert_eq!(scoped[0].id, owned.id);
+
+        let globals = get_element_groups(&conn, None, None).unwrap();
+        assert_eq!(globals.len(), 1);
+        assert_eq!(globals[0].name, "Shared");
+
+        let mut available: Vec<i64> = get_all_available_element_groups(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|group| group.id)
+            .collect();
+        available.sort();
+        assert_eq!(available, [owned.id, global.id]);
+    }
+
+    #[test]
+    fn playback_settings_are_validated_and_persisted() {
+        let conn = test_connection();
+        let group = create_element_group(&conn, "Crows".into(), None).unwrap();
+        let element = create_audio_element(
+            &conn,
+            None,
+            "c".into(),
+            "c.ogg".into(),
+            "sfx".into(),
+            None,
+            None,
+        )
+        .unwrap();
+        let member = add_element_to_group(&conn, group.id, element.id).unwrap();
+
+        update_element_group_playback(&conn, group.id, PlaybackMode::Shuffle, 2.0, 3.0).unwrap();
+        update_group_member_weight(&conn, member.id, 4.0).unwrap();
+
+        let too_wide =
+            update_element_group_playback(&conn, group.id, PlaybackMode::Weighted, 13.0, 0.0);
+        assert_eq!(too_wide.unwrap_err().code(), "ValidationFailed");
+        let negative = update_group_member_weight(&conn, member.id, -1.0);
+        assert_eq!(negative.unwrap_err().code(), "ValidationFailed");
+
+        let stored = get_element_group(&conn, group.id).unwrap();
+        assert_eq!(stored.playback_mode, PlaybackMode::Shuffle);
+        assert_eq!(stored.pitch_variation_semitones, 2.0);
+        assert_eq!(stored.volume_variation_db, 3.0);
+        assert_eq!(get_group_members(&conn, group.id).unwrap()[0].weight, 4.0);
+    }
+}
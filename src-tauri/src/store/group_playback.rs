@@ -0,0 +1,328 @@
+//! Picks which member of an element group plays next.
+//!
+//! Every pick is a pure function of the group, its members, a caller-chosen
+//! seed and the number of picks made so far, so a sequence can be replayed
+//! exactly, in tests or after reloading a session.
+
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use super::element_groups::{
+    get_element_group, get_group_members, ElementGroup, ElementGroupMember,
+};
+use crate::{AppError, AppResult};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaybackMode {
+    /// Members in their stored order, wrapping around.
+    #[default]
+    Sequential,
+    /// Every member once per round, in a new order each round.
+    Shuffle,
+    /// Any member except the one that just played.
+    RandomNoRepeat,
+    /// Any member, more often the heavier its weight.
+    Weighted,
+}
+
+impl PlaybackMode {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            PlaybackMode::Sequential => "sequential",
+            PlaybackMode::Shuffle => "shuffle",
+            PlaybackMode::RandomNoRepeat => "random_no_repeat",
+            PlaybackMode::Weighted => "weighted",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Self {
+        match name {
+            "shuffle" => PlaybackMode::Shuffle,
+            "random_no_repeat" => PlaybackMode::RandomNoRepeat,
+            "weighted" => PlaybackMode::Weighted,
+            _ => PlaybackMode::Sequential,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupPick {
+    pub member_id: i64,
+    pub audio_element_id: i64,
+    /// Detune to apply to this play.
+    pub pitch_semitones: f64,
+    /// Gain offset to apply to this play.
+    pub volume_db: f64,
+}
+
+/// Picks the member for play number `play_index` (counting from 0) of a
+/// sequence seeded with `seed`. `previous_member_id` is the member that played
+/// last, which `RandomNoRepeat` avoids. Returns `None` for an empty group.
+pub fn pick_member(
+    group: &ElementGroup,
+    members: &[ElementGroupMember],
+    seed: u64,
+    play_index: u64,
+    previous_member_id: Option<i64>,
+) -> Option<GroupPick> {
+    if members.is_empty() {
+        return None;
+    }
+
+    let count = members.len() as u64;
+    let mut rng = rng_for(seed, Draw::Pick, play_index);
+    let index = match group.playback_mode {
+        PlaybackMode::Sequential => (play_index % count) as usize,
+        PlaybackMode::Shuffle => {
+            shuffled_round(members.len(), seed, play_index / count)[(play_index % count) as usize]
+        }
+        PlaybackMode::RandomNoRepeat => {
+            let candidates: Vec<usize> = (0..members.len())
+                .filter(|&index| {
+                    members.len() == 1 || Some(members[index].id) != previous_member_id
+                })
+                .collect();
+            candidates[rng.gen_range(0..candidates.len())]
+        }
+        PlaybackMode::Weighted => {
+            match WeightedIndex::new(members.iter().map(|member| member.weight)) {
+                Ok(weights) => weights.sample(&mut rng),
+                // All weights zero: every member is equally likely.
+                Err(_) => rng.gen_range(0..members.len()),
+            }
+        }
+    };
+
+    let member = &members[index];
+    Some(GroupPick {
+        member_id: member.id,
+        audio_element_id: member.audio_element_id,
+        pitch_semitones: vary(&mut rng, group.pitch_variation_semitones),
+        volume_db: vary(&mut rng, group.volume_variation_db),
+    })
+}
+
+/// Loads the group and its playable members, then delegates to [`pick_member`].
+pub fn pick_group_member(
+    conn: &Connection,
+    group_id: i64,
+    seed: u64,
+    play_index: u64,
+    previous_member_id: Option<i64>,
+) -> AppResult<GroupPick> {
+    let group = get_element_group(conn, group_id)?;
+    let members = get_group_members(conn, group_id)?;
+
+    pick_member(&group, &members, seed, play_index, previous_member_id).ok_or_else(|| {
+        AppError::ValidationFailed(format!("Element group {} has no members to play", group_id))
+    })
+}
+
+/// What a random stream is drawn for. Each gets its own half of the word
+/// space, so a pick and a shuffle never share numbers.
+#[derive(Clone, Copy)]
+enum Draw {
+    Pick,
+    Shuffle,
+}
+
+/// Independent random stream number `index` of the sequence seeded with `seed`.
+fn rng_for(seed: u64, draw: Draw, index: u64) -> ChaCha8Rng {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    rng.set_stream(index);
+    if let Draw::Shuffle = draw {
+        rng.set_word_pos(1 << 64);
+    }
+    rng
+}
+
+/// Member order for one shuffle round. A round never opens with the member
+/// that closed the round before it.
+fn shuffled_round(count: usize, seed: u64, round: u64) -> Vec<usize> {
+    let shuffle = |round: u64| {
+        let mut order: Vec<usize> = (0..count).collect();
+        order.shuffle(&mut rng_for(seed, Draw::Shuffle, round));
+        order
+    };
+
+    // With two members, the only rounds without a seam repeat the first one.
+    if count == 2 {
+        return shuffle(0);
+    }
+
+    // Swapping the first two keeps the last member, which the next round checks.
+    let mut order = shuffle(round);
+    if round > 0 && count > 2 && shuffle(round - 1)[count - 1] == order[0] {
+        order.swap(0, 1);
+    }
+    order
+}
+
+fn vary(rng: &mut ChaCha8Rng, range: f64) -> f64 {
+    if range > 0.0 {
+        rng.gen_range(-range..=range)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(playback_mode: PlaybackMode) -> ElementGroup {
+        ElementGroup {
+            id: 1,
+            name: "Thunder".into(),
+            sound_set_id: None,
+            created_at: String::new(),
+            tags: Vec::new(),
+            notes: String::new(),
+            rating: None,
+            playback_mode,
+            pitch_variation_semitones: 0.0,
+            volume_variation_db: 0.0,
+        }
+    }
+
+    fn members(weights: &[f64]) -> Vec<ElementGroupMember> {
+        weights
+            .iter()
+            .enumerate()
+            .map(|(index, weight)| ElementGroupMember {
+                id: index as i64 + 1,
+                group_id: 1,
+                audio_element_id: index as i64 + 100,
+                order_index: index as i64,
+                weight: *weight,
+            })
+            .collect()
+    }
+
+    /// Member ids of the first `plays` picks, feeding each pick back as the previous one.
+    fn sequence(
+        group: &ElementGroup,
+        members: &[ElementGroupMember],
+        seed: u64,
+        plays: u64,
+    ) -> Vec<i64> {
+        let mut previous = None;
+        (0..plays)
+            .map(|play_index| {
+                let pick = pick_member(group, members, seed, play_index, previous).unwrap();
+                previous = Some(pick.member_id);
+                pick.member_id
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sequential_wraps_around_in_order() {
+        let picks = sequence(&group(PlaybackMode::Sequential), &members(&[1.0; 3]), 7, 5);
+        assert_eq!(picks, [1, 2, 3, 1, 2]);
+    }
+
+    #[test]
+    fn same_seed_replays_the_same_sequence() {
+        let members = members(&[1.0; 5]);
+        for mode in [
+            PlaybackMode::Shuffle,
+            PlaybackMode::RandomNoRepeat,
+            PlaybackMode::Weighted,
+        ] {
+            let group = group(mode);
+            assert_eq!(
+                sequence(&group, &members, 42, 40),
+                sequence(&group, &members, 42, 40)
+            );
+            assert_ne!(
+                sequence(&group, &members, 42, 40),
+                sequence(&group, &members, 43, 40)
+            );
+        }
+    }
+
+    #[test]
+    fn shuffle_plays_everything_once_per_round_without_seams() {
+        let picks = sequence(&group(PlaybackMode::Shuffle), &members(&[1.0; 4]), 3, 40);
+
+        for round in picks.chunks(4) {
+            let mut sorted = round.to_vec();
+            sorted.sort();
+            assert_eq!(sorted, [1, 2, 3, 4]);
+        }
+        assert!(picks.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn two_member_shuffles_alternate() {
+        let picks = sequence(&group(PlaybackMode::Shuffle), &members(&[1.0; 2]), 11, 20);
+        assert!(picks.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn random_no_repeat_never_plays_the_same_member_twice_in_a_row() {
+        let picks = sequence(
+            &group(PlaybackMode::RandomNoRepeat),
+            &members(&[1.0; 2]),
+            9,
+            50,
+        );
+        assert!(picks.windows(2).all(|pair| pair[0] != pair[1]));
+
+        let single = sequence(&group(PlaybackMode::RandomNoRepeat), &members(&[1.0]), 9, 3);
+        assert_eq!(single, [1, 1, 1]);
+    }
+
+    #[test]
+    fn weighted_follows_the_weights() {
+        let picks = sequence(
+            &group(PlaybackMode::Weighted),
+            &members(&[0.0, 1.0, 3.0]),
+            5,
+            400,
+        );
+
+        let count = |id| picks.iter().filter(|pick| **pick == id).count();
+        assert_eq!(count(1), 0);
+        assert!(count(3) > count(2) * 2);
+
+        let silent = sequence(&group(PlaybackMode::Weighted), &members(&[0.0, 0.0]), 5, 20);
+        assert!(silent.contains(&1) && silent.contains(&2));
+    }
+
+    #[test]
+    fn late_plays_still_pick_a_member() {
+        let members = members(&[1.0; 3]);
+        for mode in [
+            PlaybackMode::Sequential,
+            PlaybackMode::Shuffle,
+            PlaybackMode::RandomNoRepeat,
+            PlaybackMode::Weighted,
+        ] {
+            assert!(pick_member(&group(mode), &members, 1, u64::MAX, Some(1)).is_some());
+        }
+    }
+
+    #[test]
+    fn variation_stays_within_its_ranges() {
+        let mut group = group(PlaybackMode::Sequential);
+        group.pitch_variation_semitones = 2.0;
+        group.volume_variation_db = 3.0;
+        let members = members(&[1.0; 2]);
+
+        let picks: Vec<GroupPick> = (0..50)
+            .map(|play_index| pick_member(&group, &members, 1, play_index, None).unwrap())
+            .collect();
+
+        assert!(picks.iter().all(|pick| pick.pitch_semitones.abs() <= 2.0));
+        assert!(picks.iter().all(|pick| pick.volume_db.abs() <= 3.0));
+        assert!(picks.iter().any(|pick| pick.pitch_semitones != 0.0));
+        assert!(pick_member(&group, &[], 1, 0, None).is_none());
+    }
+}
@@ -0,0 +1,981 @@
+//! Undo/redo journal for library and timeline edits.
+//!
+//! A journaled edit names the rows it may touch as [`Scope`]s. Those rows are
+//! snapshotted before and after the edit and the differences are stored in
+//! `edit_history`, so the edit can be reverted or re-applied later, including
+//! after a restart.
+
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use super::collect_rows;
+use crate::{AppError, AppResult};
+
+/// Number of edits kept in the journal; older ones are dropped.
+pub const HISTORY_LIMIT: i64 = 100;
+
+/// Tables edits may be journaled in whose rows are keyed by their `id` column.
+const JOURNALED_TABLES: &[&str] = &[
+    "sound_sets",
+    "tags",
+    "moods",
+    "audio_channels",
+    "audio_elements",
+    "channel_duck_rules",
+    "channel_effects",
+    "element_groups",
+    "element_group_members",
+    "master_bus",
+    "mood_transitions",
+    "timelines",
+    "timeline_markers",
+    "timeline_tempo_changes",
+    "timeline_tracks",
+    "timeline_elements",
+    "track_gain_points",
+    "mixer_snapshots",
+    "mixer_snapshot_channels",
+    "mixer_snapshot_sound_sets",
+    "timeline_templates",
+];
+
+/// Link tables edits may be journaled in, with the columns of their composite
+/// primary key.
+const LINK_TABLES: &[(&str, &[&str])] = &[
+    ("audio_element_tags", &["audio_element_id", "tag_id"]),
+    ("element_group_tags", &["element_group_id", "tag_id"]),
+];
+
+/// Columns that cache facts about a row's file and are rewritten outside the
+/// journal whenever the file is read, so they never make a row count as
+/// changed.
+const CACHE_COLUMNS: &[&str] = &["content_hash", "content_size", "content_modified_ms"];
+
+type RowValues = Map<String, Value>;
+
+/// Identifies a journaled row: its `id`, or its key columns in order for a
+/// link table. Stored entries written before link tables were journaled only
+/// hold plain ids, which still read back as [`RowKey::Id`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+enum RowKey {
+    Id(i64),
+    Composite(Vec<i64>),
+}
+
+impl RowKey {
+    fn values(&self) -> Vec<i64> {
+        match self {
+            RowKey::Id(id) => vec![*id],
+            RowKey::Composite(values) => values.clone(),
+        }
+    }
+}
+
+/// Rows of `table` matching `filter`, where `filter` may refer to `param` as `?1`.
+///
+/// `table` is one of the id-keyed journaled tables or one of the tag link
+/// tables, whose rows are keyed by the item and tag they link.
+pub struct Scope {
+    table: &'static str,
+    filter: Cow<'static, str>,
+    param: Option<Option<i64>>,
+}
+
+impl Scope {
+    pub fn new(table: &'static str, filter: &'static str, param: impl Into<Option<i64>>) -> Self {
+        Scope {
+            table,
+            filter: filter.into(),
+            param: Some(param.into()),
+        }
+    }
+
+    /// Rows of `table` matching `filter`, which takes no parameter.
+    pub fn matching(table: &'static str, filter: impl Into<Cow<'static, str>>) -> Self {
+        Scope {
+            table,
+            filter: filter.into(),
+            param: None,
+        }
+    }
+}
+
+/// Scopes covering the rows matching `roots`, given as `(table, condition)`,
+/// and every row deleting them reaches through the schema's foreign keys:
+/// rows the delete cascades to, and rows whose reference it clears.
+///
+/// Scopes are listed in journal order, parents before their children.
+pub fn cascade_scopes(conn: &Connection, roots: &[(&str, &str)]) -> AppResult<Vec<Scope>> {
+    let mut references = Vec::new();
+    for table in all_tables() {
+        let mut stmt = conn.prepare(
+            "SELECT \"from\", \"table\", \"to\", on_delete FROM pragma_foreign_key_list(?1)",
+        )?;
+        for (column, parent, parent_column, on_delete) in collect_rows(&mut stmt, [table], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })? {
+            references.push(CascadeReference {
+                table,
+                column,
+                parent,
+                parent_column: parent_column.unwrap_or_else(|| "id".to_string()),
+                cascades: on_delete.eq_ignore_ascii_case("CASCADE"),
+            });
+        }
+    }
+
+    let mut scopes = Vec::new();
+    for table in all_tables() {
+        let mut conditions: Vec<String> =
+            deleted_condition(table, roots, &references, &mut Vec::new())
+                .into_iter()
+                .collect();
+        // Rows whose reference is set to NULL or a default change without
+        // being deleted, and take nothing else with them.
+        for reference in references
+            .iter()
+            .filter(|reference| reference.table == table && !reference.cascades)
+        {
+            if let Some(parent) =
+                deleted_condition(&reference.parent, roots, &references, &mut Vec::new())
+            {
+                conditions.push(reference.matching(&parent));
+            }
+        }
+
+        if !conditions.is_empty() {
+            scopes.push(Scope::matching(table, conditions.join(" OR ")));
+        }
+    }
+
+    Ok(scopes)
+}
+
+struct CascadeReference {
+    table: &'static str,
+    column: String,
+    parent: String,
+    parent_column: String,
+    cascades: bool,
+}
+
+impl CascadeReference {
+    /// Condition on the child table holding for rows that point at a parent
+    /// matching `parent_condition`.
+    fn matching(&self, parent_condition: &str) -> String {
+        format!(
+            "\"{}\" IN (SELECT \"{}\" FROM {} WHERE {})",
+            self.column, self.parent_column, self.parent, parent_condition
+        )
+    }
+}
+
+/// Condition holding for the rows of `table` that deleting `roots` removes,
+/// or `None` if it removes none. `visiting` guards against cascade cycles.
+fn deleted_condition<'a>(
+    table: &'a str,
+    roots: &[(&str, &str)],
+    references: &'a [CascadeReference],
+    visiting: &mut Vec<&'a str>,
+) -> Option<String> {
+    if visiting.contains(&table) {
+        return None;
+    }
+    visiting.push(table);
+
+    let mut conditions: Vec<String> = roots
+        .iter()
+        .filter(|(root, _)| *root == table)
+        .map(|(_, condition)| format!("({})", condition))
+        .collect();
+    for reference in references
+        .iter()
+        .filter(|reference| reference.table == table && reference.cascades)
+    {
+        if let Some(parent) = deleted_condition(&reference.parent, roots, references, visiting) {
+            conditions.push(reference.matching(&parent));
+        }
+    }
+
+    visiting.pop();
+    (!conditions.is_empty()).then(|| conditions.join(" OR "))
+}
+
+/// The lowest id a row inserted into `table` can get, so a scope of
+/// `id >= ?1` covers the rows an edit creates.
+pub fn next_id(conn: &Connection, table: &'static str) -> AppResult<i64> {
+    let next = conn.query_row(
+        &format!(
+            "SELECT COALESCE(MAX(id), 0) + 1 FROM {}",
+            journaled_table(table)?
+        ),
+        [],
+        |row| row.get(0),
+    )?;
+
+    Ok(next)
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct RowChange {
+    table: String,
+    id: RowKey,
+    before: Option<RowValues>,
+    after: Option<RowValues>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub label: String,
+    pub created_at: String,
+}
+
+/// The edits `undo` and `redo` would apply next.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryState {
+    pub undo: Option<HistoryEntry>,
+    pub redo: Option<HistoryEntry>,
+}
+
+/// Runs `edit` in a transaction and journals the rows it changed within `scopes`.
+///
+/// Recording a new edit discards everything that was undone but not redone.
+pub fn record<T>(
+    conn: &Connection,
+    label: &str,
+    scopes: &[Scope],
+    edit: impl FnOnce(&Connection) -> AppResult<T>,
+) -> AppResult<T> {
+    let tx = conn.unchecked_transaction()?;
+
+    let before = scopes
+        .iter()
+        .map(|scope| snapshot(&tx, scope, &[]))
+        .collect::<AppResult<Vec<_>>>()?;
+    let result = edit(&tx)?;
+
+    let mut changes = Vec::new();
+    let mut seen = HashSet::new();
+    for (scope, before_rows) in scopes.iter().zip(before) {
+        let known_keys: Vec<RowKey> = before_rows.iter().map(|(key, _)| key.clone()).collect();
+        let after_rows = snapshot(&tx, scope, &known_keys)?;
+
+        for change in diff(scope.table, before_rows, after_rows) {
+            if seen.insert((change.table.clone(), change.id.clone())) {
+                changes.push(change);
+            }
+        }
+    }
+
+    if !changes.is_empty() {
+        let changes =
+            serde_json::to_string(&changes).map_err(|e| AppError::Internal(e.to_string()))?;
+
+        tx.execute("DELETE FROM edit_history WHERE is_undone = 1", [])?;
+        tx.execute(
+            "INSERT INTO edit_history (label, changes) VALUES (?1, ?2)",
+            (label, &changes),
+        )?;
+        tx.execute(
+            "DELETE FROM edit_history WHERE id NOT IN (
+                SELECT id FROM edit_history ORDER BY id DESC LIMIT ?1
+            )",
+            [HISTORY_LIMIT],
+        )?;
+    }
+
+    tx.commit()?;
+    Ok(result)
+}
+
+/// Reverts the most recent edit that has not been undone yet.
+pub fn undo(conn: &Connection) -> AppResult<Option<HistoryEntry>> {
+    step(
+        conn,
+        "WHERE is_undone = 0 ORDER BY id DESC LIMIT 1",
+        Direction::Undo,
+    )
+}
+
+/// Re-applies the oldest undone edit.
+pub fn redo(conn: &Connection) -> AppResult<Option<HistoryEntry>> {
+    step(
+        conn,
+        "WHERE is_undone = 1 ORDER BY id ASC LIMIT 1",
+        Direction::Redo,
+    )
+}
+
+pub fn get_history_state(conn: &Connection) -> AppResult<HistoryState> {
+    Ok(HistoryState {
+        undo: load_entry(conn, "WHERE is_undone = 0 ORDER BY id DESC LIMIT 1")?
+            .map(|(entry, _)| entry),
+        redo: load_entry(conn, "WHERE is_undone = 1 ORDER BY id ASC LIMIT 1")?
+            .map(|(entry, _)| entry),
+    })
+}
+
+/// Runs `remove`, which deletes rows without journaling them, then drops the
+/// entries that refer to a removed row, directly or through a foreign key.
+/// Entries undo or redo would have to step past to reach a dropped one go
+/// with it; the rest of the history stays.
+pub(crate) fn forget_removed_rows<T>(
+    conn: &Connection,
+    remove: impl FnOnce(&Connection) -> AppResult<T>,
+) -> AppResult<T> {
+    let before = journaled_row_ids(conn)?;
+    let result = remove(conn)?;
+    let after = journaled_row_ids(conn)?;
+
+    let removed: HashSet<(&str, RowKey)> = before.difference(&after).cloned().collect();
+    if removed.is_empty() {
+        return Ok(result);
+    }
+    let references = journaled_foreign_keys(conn)?;
+
+    let mut stmt = conn.prepare("SELECT id, is_undone, changes FROM edit_history")?;
+    let entries: Vec<(i64, bool, String)> = collect_rows(&mut stmt, [], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    })?;
+
+    let mut last_undo = None;
+    let mut first_redo = None;
+    for (id, is_undone, changes) in entries {
+        let changes: Vec<RowChange> = serde_json::from_str(&changes).map_err(|e| {
+            AppError::Internal(format!("Edit history entry {} is corrupt: {}", id, e))
+        })?;
+        if !changes
+            .iter()
+            .any(|change| refers_to(change, &removed, &references))
+        {
+            continue;
+        }
+        if is_undone {
+            first_redo = Some(first_redo.map_or(id, |first: i64| first.min(id)));
+        } else {
+            last_undo = Some(last_undo.map_or(id, |last: i64| last.max(id)));
+        }
+    }
+
+    if let Some(id) = last_undo {
+        conn.execute(
+            "DELETE FROM edit_history WHERE is_undone = 0 AND id <= ?1",
+            [id],
+        )?;
+    }
+    if let Some(id) = first_redo {
+        conn.execute(
+            "DELETE FROM edit_history WHERE is_undone = 1 AND id >= ?1",
+            [id],
+        )?;
+    }
+
+    Ok(result)
+}
+
+fn journaled_row_ids(conn: &Connection) -> AppResult<HashSet<(&'static str, RowKey)>> {
+    let mut ids = HashSet::new();
+    for table in all_tables() {
+        let columns = key_columns(table).join(", ");
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM {}", columns, table))?;
+        for values in collect_rows(&mut stmt, [], |row| {
+            (0..key_columns(table).len())
+                .map(|index| row.get::<_, i64>(index))
+                .collect::<rusqlite::Result<Vec<_>>>()
+        })? {
+            ids.insert((table, row_key(table, values)));
+        }
+    }
+
+    Ok(ids)
+}
+
+/// `(table, column, parent table)` of every foreign key on a journaled table.
+fn journaled_foreign_keys(conn: &Connection) -> AppResult<Vec<(&'static str, String, String)>> {
+    let mut references = Vec::new();
+    for table in all_tables() {
+        let mut stmt =
+            conn.prepare("SELECT \"from\", \"table\" FROM pragma_foreign_key_list(?1)")?;
+        for (column, parent) in collect_rows(&mut stmt, [table], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })? {
+            references.push((table, column, parent));
+        }
+    }
+
+    Ok(references)
+}
+
+/// Whether the change is about a removed row, or either version of its row
+/// points at one.
+fn refers_to(
+    change: &RowChange,
+    removed: &HashSet<(&str, RowKey)>,
+    references: &[(&'static str, String, String)],
+) -> bool {
+    if removed.contains(&(change.table.as_str(), change.id.clone())) {
+        return true;
+    }
+
+    [&change.before, &change.after]
+        .into_iter()
+        .flatten()
+        .any(|values| {
+            references
+                .iter()
+                .filter(|(table, _, _)| *table == change.table)
+                .any(|(_, column, parent)| {
+                    values
+                        .get(column)
+                        .and_then(Value::as_i64)
+                        .is_some_and(|id| removed.contains(&(parent.as_str(), RowKey::Id(id))))
+                })
+        })
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Direction {
+    Undo,
+    Redo,
+}
+
+impl Direction {
+    /// The state a row should end up in when stepping in this direction.
+    fn target(self, change: &RowChange) -> Option<&RowValues> {
+        match self {
+            Direction::Undo => change.before.as_ref(),
+            Direction::Redo => change.after.as_ref(),
+        }
+    }
+
+    /// The state a row must still be in for this step to apply.
+    fn source(self, change: &RowChange) -> Option<&RowValues> {
+        match self {
+            Direction::Undo => change.after.as_ref(),
+            Direction::Redo => change.before.as_ref(),
+        }
+    }
+
+    fn verb(self) -> &'static str {
+        match self {
+            Direction::Undo => "undo",
+            Direction::Redo => "redo",
+        }
+    }
+}
+
+/// Fails with `ValidationFailed` when a row the entry touched was changed
+/// outside the journal since, so stepping would silently overwrite that.
+/// Only the columns the entry itself changed have to match.
+fn ensure_unchanged(
+    conn: &Connection,
+    entry: &HistoryEntry,
+    changes: &[RowChange],
+    direction: Direction,
+) -> AppResult<()> {
+    for change in changes {
+        let current = select_row(conn, journaled_table(&change.table)?, &change.id)?;
+
+        let unchanged = match (direction.source(change), current) {
+            (None, None) => true,
+            (Some(expected), Some(current)) => match direction.target(change) {
+                Some(target) => expected.iter().all(|(column, value)| {
+                    target.get(column) == Some(value) || current.get(column) == Some(value)
+                }),
+                // The row is about to be removed, so every column counts.
+                None => expected.iter().all(|(column, value)| {
+                    CACHE_COLUMNS.contains(&column.as_str()) || current.get(column) == Some(value)
+                }),
+            },
+            _ => false,
+        };
+        if !unchanged {
+            return Err(AppError::ValidationFailed(format!(
+                "Cannot {} '{}': it has been changed since",
+                direction.verb(),
+                entry.label
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn step(
+    conn: &Connection,
+    selector: &str,
+    direction: Direction,
+) -> AppResult<Option<HistoryEntry>> {
+    let tx = conn.unchecked_transaction()?;
+    // Rows may point at each other both ways (a timeline at its loop marker,
+    // the marker at its timeline), so only check references at commit.
+    tx.execute_batch("PRAGMA defer_foreign_keys = ON")?;
+
+    let (entry, changes) = match load_entry(&tx, selector)? {
+        Some(loaded) => loaded,
+        None => return Ok(None),
+    };
+    ensure_unchanged(&tx, &entry, &changes, direction)?;
+
+    // Parents are journaled before their children, so rows are restored in
+    // order and removed in reverse.
+    for change in &changes {
+        if let Some(values) = direction.target(change) {
+            restore_row(&tx, &change.table, &change.id, values)?;
+        }
+    }
+    for change in changes.iter().rev() {
+        if direction.target(change).is_none() {
+            let table = journaled_table(&change.table)?;
+            tx.execute(
+                &format!("DELETE FROM {} WHERE {}", table, key_condition(table)),
+                rusqlite::params_from_iter(change.id.values()),
+            )?;
+        }
+    }
+
+    tx.execute(
+        "UPDATE edit_history SET is_undone = ?1 WHERE id = ?2",
+        (direction == Direction::Undo, entry.id),
+    )?;
+    tx.commit()?;
+
+    Ok(Some(entry))
+}
+
+fn load_entry(
+    conn: &Connection,
+    selector: &str,
+) -> AppResult<Option<(HistoryEntry, Vec<RowChange>)>> {
+    let row = conn
+        .query_row(
+            &format!(
+                "SELECT id, label, created_at, changes FROM edit_history {}",
+                selector
+            ),
+            [],
+            |row| {
+                Ok((
+                    HistoryEntry {
+                        id: row.get(0)?,
+                        label: row.get(1)?,
+                        created_at: row.get(2)?,
+                    },
+                    row.get::<_, String>(3)?,
+                ))
+            },
+        )
+        .optional()?;
+
+    match row {
+        Some((entry, changes)) => {
+            let changes = serde_json::from_str(&changes).map_err(|e| {
+                AppError::Internal(format!("Edit history entry {} is corrupt: {}", entry.id, e))
+            })?;
+            Ok(Some((entry, changes)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Reads the rows of a scope plus any of `known_ids` that no longer match it,
+/// so rows an edit moves out of the scope are still compared.
+fn snapshot(
+    conn: &Connection,
+    scope: &Scope,
+    known_keys: &[RowKey],
+) -> AppResult<Vec<(RowKey, RowValues)>> {
+    let table = journaled_table(scope.table)?;
+    let sql = format!(
+        "SELECT * FROM {} WHERE {} ORDER BY {} ASC",
+        table,
+        scope.filter,
+        key_columns(table).join(", ")
+    );
+    let mut rows = match scope.param {
+        Some(param) => select_rows(conn, table, &sql, [param])?,
+        None => select_rows(conn, table, &sql, [])?,
+    };
+
+    for key in known_keys {
+        if rows.iter().any(|(row_key, _)| row_key == key) {
+            continue;
+        }
+        if let Some(values) = select_row(conn, table, key)? {
+            rows.push((key.clone(), values));
+        }
+    }
+
+    Ok(rows)
+}
+
+fn select_row(conn: &Connection, table: &str, key: &RowKey) -> AppResult<Option<RowValues>> {
+    let sql = format!("SELECT * FROM {} WHERE {}", table, key_condition(table));
+    let row = select_rows(conn, table, &sql, rusqlite::params_from_iter(key.values()))?
+        .pop()
+        .map(|(_, values)| values);
+
+    Ok(row)
+}
+
+fn select_rows(
+    conn: &Connection,
+    table: &str,
+    sql: &str,
+    params: impl rusqlite::Params,
+) -> AppResult<Vec<(RowKey, RowValues)>> {
+    let mut stmt = conn.prepare(sql)?;
+    let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+
+    let rows = stmt.query_map(params, |row| {
+        let mut values = RowValues::new();
+        for (index, column) in columns.iter().enumerate() {
+            values.insert(column.clone(), to_json(row.get(index)?));
+        }
+        let key = key_columns(table)
+            .iter()
+            .map(|column| row.get::<_, i64>(*column))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok((row_key(table, key), values))
+    })?;
+
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+fn diff(
+    table: &str,
+    before: Vec<(RowKey, RowValues)>,
+    after: Vec<(RowKey, RowValues)>,
+) -> Vec<RowChange> {
+    let mut changes = Vec::new();
+
+    for (id, before_values) in &before {
+        let after_values = after
+            .iter()
+            .find(|(after_id, _)| after_id == id)
+            .map(|(_, values)| values);
+
+        if after_values != Some(before_values) {
+            changes.push(RowChange {
+                table: table.to_string(),
+                id: id.clone(),
+                before: Some(before_values.clone()),
+                after: after_values.cloned(),
+            });
+        }
+    }
+
+    for (id, after_values) in after {
+        if !before.iter().any(|(before_id, _)| *before_id == id) {
+            changes.push(RowChange {
+                table: table.to_string(),
+                id,
+                before: None,
+                after: Some(after_values),
+            });
+        }
+    }
+
+    changes
+}
+
+fn restore_row(conn: &Connection, table: &str, key: &RowKey, values: &RowValues) -> AppResult<()> {
+    let table = journaled_table(table)?;
+    let columns: Vec<&String> = values.keys().collect();
+    let params: Vec<SqlValue> = values.values().map(to_sql).collect();
+
+    let exists = select_row(conn, table, key)?.is_some();
+
+    let sql = if exists {
+        let assignments: Vec<String> = columns
+            .iter()
+            .enumerate()
+            .map(|(index, column)| format!("\"{}\" = ?{}", column, index + 1))
+            .collect();
+        let key_matches: Vec<String> = key_columns(table)
+            .iter()
+            .zip(key.values())
+            .map(|(column, value)| format!("\"{}\" = {}", column, value))
+            .collect();
+        format!(
+            "UPDATE {} SET {} WHERE {}",
+            table,
+            assignments.join(", "),
+            key_matches.join(" AND ")
+        )
+    } else {
+        let names: Vec<String> = columns
+            .iter()
+            .map(|column| format!("\"{}\"", column))
+            .collect();
+        let placeholders: Vec<String> = (1..=columns.len())
+            .map(|index| format!("?{}", index))
+            .collect();
+        format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            table,
+            names.join(", "),
+            placeholders.join(", ")
+        )
+    };
+
+    conn.execute(&sql, rusqlite::params_from_iter(params))?;
+    Ok(())
+}
+
+/// Only tables the journal writes to may be named by a stored entry.
+fn journaled_table(table: &str) -> AppResult<&'static str> {
+    all_tables().find(|known| *known == table).ok_or_else(|| {
+        AppError::Internal(format!("Edit history refers to unknown table '{}'", table))
+    })
+}
+
+fn all_tables() -> impl Iterator<Item = &'static str> {
+    JOURNALED_TABLES
+        .iter()
+        .copied()
+        .chain(LINK_TABLES.iter().map(|(table, _)| *table))
+}
+
+fn key_columns(table: &str) -> &'static [&'static str] {
+    LINK_TABLES
+        .iter()
+        .find(|(link_table, _)| *link_table == table)
+        .map_or(&["id"], |(_, columns)| columns)
+}
+
+fn row_key(table: &str, values: Vec<i64>) -> RowKey {
+    match (key_columns(table), values.as_slice()) {
+        (["id"], [id]) => RowKey::Id(*id),
+        _ => RowKey::Composite(values),
+    }
+}
+
+/// `WHERE` condition matching a row of `table` by its key, bound as `?1`, `?2`, ...
+fn key_condition(table: &str) -> String {
+    key_columns(table)
+        .iter()
+        .enumerate()
+        .map(|(index, column)| format!("\"{}\" = ?{}", column, index + 1))
+        .collect::<Vec<_>>()
+        .join(" AND ")
+}
+
+fn to_json(value: SqlValue) -> Value {
+    match value {
+        SqlValue::Null => Value::Null,
+        SqlValue::Integer(number) => number.into(),
+        SqlValue::Real(number) => number.into(),
+        SqlValue::Text(text) => text.into(),
+        SqlValue::Blob(bytes) => bytes.into(),
+    }
+}
+
+fn to_sql(value: &Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(flag) => SqlValue::Integer(*flag as i64),
+        Value::Number(number) => match number.as_i64() {
+            Some(integer) => SqlValue::Integer(integer),
+            None => SqlValue::Real(number.as_f64().unwrap_or_default()),
+        },
+        Value::String(text) => SqlValue::Text(text.clone()),
+        Value::Array(bytes) => SqlValue::Blob(
+            bytes
+                .iter()
+                .filter_map(|byte| byte.as_u64().map(|byte| byte as u8))
+                .collect(),
+        ),
+        Value::Object(_) => SqlValue::Text(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::test_connection;
+    use crate::store::timelines::{
+        add_element_to_track, delete_timeline_track, get_timeline_tracks, get_track_elements,
+        update_element_time_and_duration,
+    };
+
+    /// Mood 1 with a timeline, track 5 and an audio element 100 to place on it.
+    fn seeded() -> Connection {
+        let conn = test_connection();
+        conn.execute_batch(
+            "INSERT INTO sound_sets (id, name, description) VALUES (1, 'S', 'D');
+             INSERT INTO audio_elements (id, sound_set_id, file_path, file_name) VALUES (100, 1, 'p', 'f');
+             INSERT INTO moods (id, name) VALUES (1, 'M');
+             INSERT INTO timelines (id, mood_id, name) VALUES (1, 1, 'T');
+             INSERT INTO timeline_tracks (id, timeline_id, name) VALUES (5, 1, 'Trk');",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn move_element(conn: &Connection, id: i64, start_time_ms: i64) {
+        record(
+            conn,
+            "Move element",
+            &[Scope::new("timeline_elements", "id = ?1", id)],
+            |conn| update_element_time_and_duration(conn, id, start_time_ms, 1000, None),
+        )
+        .unwrap();
+    }
+
+    fn start_of(conn: &Connection, track_id: i64) -> i64 {
+        get_track_elements(conn, track_id).unwrap()[0].start_time_ms
+    }
+
+    #[test]
+    fn undo_and_redo_a_move() {
+        let conn = seeded();
+        let element = add_element_to_track(&conn, 5, Some(100), None, 0, 1000, None).unwrap();
+
+        move_element(&conn, element.id, 2000);
+        assert_eq!(start_of(&conn, 5), 2000);
+
+        let undone = undo(&conn).unwrap().unwrap();
+        assert_eq!(undone.label, "Move element");
+        assert_eq!(start_of(&conn, 5), 0);
+        assert!(undo(&conn).unwrap().is_none());
+
+        redo(&conn).unwrap().unwrap();
+        assert_eq!(start_of(&conn, 5), 2000);
+        assert!(redo(&conn).unwrap().is_none());
+    }
+
+    #[test]
+    fn undoing_a_track_deletion_restores_its_elements() {
+        let conn = seeded();
+        let element = add_element_to_track(&conn, 5, Some(100), None, 500, 1000, None).unwrap();
+
+        record(
+            &conn,
+            "Delete track",
+            &[
+                Scope::new("timeline_tracks", "id = ?1", 5),
+                Scope::new("timeline_elements", "track_id = ?1", 5),
+            ],
+            |conn| delete_timeline_track(conn, 5),
+        )
+        .unwrap();
+        assert!(get_timeline_tracks(&conn, 1).unwrap().is_empty());
+
+        undo(&conn).unwrap();
+
+        assert_eq!(get_timeline_tracks(&conn, 1).unwrap()[0].name, "Trk");
+        let elements = get_track_elements(&conn, 5).unwrap();
+        assert_eq!(elements.len(), 1);
+        assert_eq!(elements[0].id, element.id);
+        assert_eq!(elements[0].start_time_ms, 500);
+
+        redo(&conn).unwrap();
+        assert!(get_timeline_tracks(&conn, 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn undoing_an_insert_removes_the_row() {
+        let conn = seeded();
+
+        record(
+            &conn,
+            "Add element",
+            &[Scope::new("timeline_elements", "track_id = ?1", 5)],
+            |conn| add_element_to_track(conn, 5, Some(100), None, 0, 1000, None),
+        )
+        .unwrap();
+        undo(&conn).unwrap();
+
+        assert!(get_track_elements(&conn, 5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn a_new_edit_clears_the_redo_stack() {
+        let conn = seeded();
+        let element = add_element_to_track(&conn, 5, Some(100), None, 0, 1000, None).unwrap();
+
+        move_element(&conn, element.id, 2000);
+        undo(&conn).unwrap();
+        move_element(&conn, element.id, 4000);
+
+        assert!(get_history_state(&conn).unwrap().redo.is_none());
+        assert!(redo(&conn).unwrap().is_none());
+    }
+
+    #[test]
+    fn failed_edits_are_not_journaled() {
+        let conn = seeded();
+        add_element_to_track(&conn, 5, Some(100), None, 0, 1000, None).unwrap();
+
+        let result = record(
+            &conn,
+            "Add element",
+            &[Scope::new("timeline_elements", "track_id = ?1", 5)],
+            |conn| add_element_to_track(conn, 5, Some(100), None, 500, 1000, None),
+        );
+
+        assert!(result.is_err());
+        assert!(get_history_state(&conn).unwrap().undo.is_none());
+    }
+
+    #[test]
+    fn undo_refuses_to_overwrite_changes_made_outside_the_journal() {
+        let conn = seeded();
+        let element = add_element_to_track(&conn, 5, Some(100), None, 0, 1000, None).unwrap();
+        move_element(&conn, element.id, 2000);
+
+        update_element_time_and_duration(&conn, element.id, 3000, 1000, None).unwrap();
+        let error = undo(&conn).unwrap_err();
+        assert_eq!(error.code(), "ValidationFailed");
+        assert_eq!(start_of(&conn, 5), 3000);
+
+        update_element_time_and_duration(&conn, element.id, 2000, 1000, None).unwrap();
+        undo(&conn).unwrap().unwrap();
+        assert_eq!(start_of(&conn, 5), 0);
+    }
+
+    #[test]
+    fn undoing_an_insert_refuses_to_remove_a_row_changed_since() {
+        let conn = seeded();
+        record(
+            &conn,
+            "Add element",
+            &[Scope::new("timeline_elements", "track_id = ?1", 5)],
+            |conn| add_element_to_track(conn, 5, Some(100), None, 0, 1000, None),
+        )
+        .unwrap();
+        let element_id = get_track_elements(&conn, 5).unwrap()[0].id;
+
+        update_element_time_and_duration(&conn, element_id, 3000, 1000, None).unwrap();
+        let error = undo(&conn).unwrap_err();
+        assert_eq!(error.code(), "ValidationFailed");
+        assert_eq!(start_of(&conn, 5), 3000);
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let conn = seeded();
+        let element = add_element_to_track(&conn, 5, Some(100), None, 0, 1000, None).unwrap();
+
+        for step in 1..=HISTORY_LIMIT + 5 {
+            move_element(&conn, element.id, step * 1000);
+        }
+
+        let count: i64 = conn
+            .query_row("SELECT count(*) FROM edit_history", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, HISTORY_LIMIT);
+    }
+}
@@ -0,0 +1,365 @@
+//! Named cue points on timelines, and the regions the transport can loop.
+
+use rusqlite::{Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use super::collect_rows;
+use crate::{AppError, AppResult};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineMarker {
+    pub id: i64,
+    pub timeline_id: i64,
+    pub name: String,
+    pub position_ms: i64,
+    /// End of the region starting at `position_ms`; `None` for a plain cue point.
+    pub end_ms: Option<i64>,
+    /// `#rrggbb`, or `None` for the default color.
+    pub color: Option<String>,
+}
+
+/// Where the transport lands after jumping to a marker, and the range it loops.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransportJump {
+    pub timeline_id: i64,
+    pub marker_id: i64,
+    pub position_ms: i64,
+    /// Loop bounds of the timeline's region, when it loops one.
+    pub loop_start_ms: Option<i64>,
+    pub loop_end_ms: Option<i64>,
+}
+
+fn map_marker(row: &Row<'_>) -> rusqlite::Result<TimelineMarker> {
+    Ok(TimelineMarker {
+        id: row.get(0)?,
+        timeline_id: row.get(1)?,
+        name: row.get(2)?,
+        position_ms: row.get(3)?,
+        end_ms: row.get(4)?,
+        color: row.get(5)?,
+    })
+}
+
+fn validate_marker(
+    name: &str,
+    position_ms: i64,
+    end_ms: Option<i64>,
+    color: Option<&str>,
+) -> AppResult<()> {
+    if name.trim().is_empty() {
+        return Err(AppError::ValidationFailed(
+            "Marker name must not be empty".into(),
+        ));
+    }
+    if position_ms < 0 {
+        return Err(AppError::ValidationFailed(
+            "Marker position must not be negative".into(),
+        ));
+    }
+    if let Some(end_ms) = end_ms {
+        if end_ms <= position_ms {
+            return Err(AppError::ValidationFailed(format!(
+                "Region end ({} ms) must come after its start ({} ms)",
+                end_ms, position_ms
+            )));
+        }
+    }
+    if let Some(color) = color {
+        let is_hex = color.len() == 7
+            && color.starts_with('#')
+            && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+        if !is_hex {
+            return Err(AppError::ValidationFailed(format!(
+                "Marker color must look like #rrggbb, got '{}'",
+                color
+            )));
+        }
+    }
+    Ok(())
+}
+
+pub fn get_timeline_markers(conn: &Connection, timeline_id: i64) -> AppResult<Vec<TimelineMarker>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, timeline_id, name, position_ms, end_ms, color FROM timeline_markers
+         WHERE timeline_id = ?1 ORDER BY position_ms ASC, id ASC",
+    )?;
+
+    collect_rows(&mut stmt, [timeline_id], map_marker)
+}
+
+pub fn get_timeline_marker(conn: &Connection, id: i64) -> AppResult<TimelineMarker> {
+    conn.query_row(
+        "SELECT id, timeline_id, name, position_ms, end_ms, color FROM timeline_markers WHERE id = ?1",
+        [id],
+        map_marker,
+    )
+    .optional()?
+    .ok_or_else(|| AppError::not_found("Timeline marker", id))
+}
+
+pub fn create_timeline_marker(
+    conn: &Connection,
+    timeline_id: i64,
+    name: String,
+    position_ms: i64,
+    end_ms: Option<i64>,
+    color: Option<String>,
+) -> AppResult<TimelineMarker> {
+    validate_marker(&name, position_ms, end_ms, color.as_deref())?;
+
+    conn.query_row(
+        "SELECT id FROM timelines WHERE id = ?1",
+        [timeline_id],
+        |row| row.get::<_, i64>(0),
+    )
+    .optional()?
+    .ok_or_else(|| AppError::not_found("Timeline", timeline_id))?;
+
+    conn.execute(
+        "INSERT INTO timeline_markers (timeline_id, name, position_ms, end_ms, color) VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![timeline_id, name, position_ms, end_ms, color],
+    )?;
+
+    Ok(TimelineMarker {
+        id: conn.last_insert_rowid(),
+        timeline_id,
+        name,
+        position_ms,
+        end_ms,
+        color,
+    })
+}
+
+/// Renames, moves or recolors a marker. Turning a looped region back into a
+/// plain cue point makes its timeline loop as a whole again.
+pub fn update_timeline_marker(
+    conn: &Connection,
+    id: i64,
+    name: &str,
+    position_ms: i64,
+    end_ms: Option<i64>,
+    color: Option<&str>,
+) -> AppResult<()> {
+    validate_marker(name, position_ms, end_ms, color)?;
+
+    let updated = conn.execute(
+        "UPDATE timeline_markers SET name = ?1, position_ms = ?2, end_ms = ?3, color = ?4 WHERE id = ?5",
+        rusqlite::params![name, position_ms, end_ms, color, id],
+    )?;
+    if updated == 0 {
+        return Err(AppError::not_found("Timeline marker", id));
+    }
+
+    if end_ms.is_none() {
+        conn.execute(
+            "UPDATE timelines SET loop_marker_id = NULL WHERE loop_marker_id = ?1",
+            [id],
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn delete_timeline_marker(conn: &Connection, id: i64) -> AppResult<()> {
+    conn.execute("DELETE FROM timeline_markers WHERE id = ?1", [id])?;
+
+    Ok(())
+}
+
+/// Makes looping on `timeline_id` cover only the region `marker_id`, or the
+/// whole timeline again when `marker_id` is `None`.
+pub fn set_timeline_loop_region(
+    conn: &Connection,
+    timeline_id: i64,
+    marker_id: Option<i64>,
+) -> AppResult<()> {
+    if let Some(marker_id) = marker_id {
+        let marker = get_timeline_marker(conn, marker_id)?;
+        if marker.timeline_id != timeline_id {
+            return Err(AppError::ValidationFailed(format!(
+                "Marker {} belongs to another timeline",
+                marker_id
+            )));
+        }
+        if marker.end_ms.is_none() {
+            return Err(AppError::ValidationFailed(format!(
+                "Marker '{}' is a cue point, not a region",
+                marker.name
+            )));
+        }
+    }
+
+    let updated = conn.execute(
+        "UPDATE timelines SET loop_marker_id = ?1 WHERE id = ?2",
+        rusqlite::params![marker_id, timeline_id],
+    )?;
+    if updated == 0 {
+        return Err(AppError::not_found("Timeline", timeline_id));
+    }
+
+    Ok(())
+}
+
+/// Resolves a jump to `marker_id`, reporting the loop range that applies there.
+pub fn jump_to_marker(conn: &Connection, marker_id: i64) -> AppResult<TransportJump> {
+    let marker = get_timeline_marker(conn, marker_id)?;
+
+    let loop_region: Option<(i64, Option<i64>)> = conn
+        .query_row(
+            "SELECT m.position_ms, m.end_ms FROM timelines t
+         LEFT JOIN timeline_markers m ON m.id = t.loop_marker_id
+         WHERE t.id = ?1 AND t.is_looping = 1 AND m.id IS NOT NULL",
+            [marker.timeline_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+
+    Ok(TransportJump {
+        timeline_id: marker.timeline_id,
+        marker_id,
+        position_ms: marker.position_ms,
+        loop_start_ms: loop_region.map(|(start_ms, _)| start_ms),
+        loop_end_ms: loop_region.and_then(|(_, end_ms)| end_ms),
+    })
+}
+
+/// Position of the first marker of `timeline_id` at or after `position_ms`.
+pub fn next_marker_position(
+    conn: &Connection,
+    timeline_id: i64,
+    position_ms: i64,
+) -> AppResult<Option<i64>> {
+    let position = conn
+        .query_row(
+            "SELECT MIN(position_ms) FROM timeline_markers WHERE timeline_id = ?1 AND position_ms >= ?2",
+            rusqlite::params![timeline_id, position_ms],
+            |row| row.get(0),
+        )?;
+
+    Ok(position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::history;
+    use crate::store::history::Scope;
+    use crate::store::test_connection;
+    use crate::store::timelines::{get_timelines, update_timeline_loop};
+
+    fn seeded() -> Connection {
+        let conn = test_connection();
+        conn.execute_batch(
+            "INSERT INTO moods (id, name) VALUES (1, 'M'), (2, 'N');
+             INSERT INTO timelines (id, mood_id, name) VALUES (1, 1, 'T'), (2, 2, 'Other');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn markers_are_validated_and_listed_in_time_order() {
+        let conn = seeded();
+        create_timeline_marker(&conn, 1, "Boss".into(), 9000, None, Some("#ff0044".into()))
+            .unwrap();
+        let intro = create_timeline_marker(&conn, 1, "Intro".into(), 0, Some(4000), None).unwrap();
+
+        let invalid = [
+            create_timeline_marker(&conn, 1, " ".into(), 0, None, None),
+            create_timeline_marker(&conn, 1, "A".into(), 500, Some(500), None),
+            create_timeline_marker(&conn, 1, "A".into(), 0, None, Some("red".into())),
+        ];
+        assert!(invalid
+            .into_iter()
+            .all(|result| result.unwrap_err().code() == "ValidationFailed"));
+        let missing = create_timeline_marker(&conn, 99, "A".into(), 0, None, None);
+        assert_eq!(missing.unwrap_err().code(), "NotFound");
+
+        update_timeline_marker(&conn, intro.id, "Opening", 100, Some(4000), None).unwrap();
+        let names: Vec<String> = get_timeline_markers(&conn, 1)
+            .unwrap()
+            .into_iter()
+            .map(|marker| marker.name)
+            .collect();
+        assert_eq!(names, ["Opening", "Boss"]);
+        assert_eq!(next_marker_position(&conn, 1, 101).unwrap(), Some(9000));
+        assert_eq!(next_marker_position(&conn, 1, 9001).unwrap(), None);
+    }
+
+    #[test]
+    fn only_regions_of_the_same_timeline_can_be_looped() {
+        let conn = seeded();
+        let cue = create_timeline_marker(&conn, 1, "Cue".into(), 0, None, None).unwrap();
+        let region =
+            create_timeline_marker(&conn, 1, "Loop".into(), 2000, Some(6000), None).unwrap();
+        let elsewhere = create_timeline_marker(&conn, 2, "Loop".into(), 0, Some(10), None).unwrap();
+
+        let errors = [
+            set_timeline_loop_region(&conn, 1, Some(cue.id)),
+            set_timeline_loop_region(&conn, 1, Some(elsewhere.id)),
+        ];
+        assert!(errors
+            .into_iter()
+            .all(|result| result.unwrap_err().code() == "ValidationFailed"));
+
+        set_timeline_loop_region(&conn, 1, Some(region.id)).unwrap();
+        assert_eq!(
+            get_timelines(&conn, 1).unwrap()[0].loop_marker_id,
+            Some(region.id)
+        );
+
+        update_timeline_marker(&conn, region.id, "Loop", 2000, None, None).unwrap();
+        assert_eq!(get_timelines(&conn, 1).unwrap()[0].loop_marker_id, None);
+    }
+
+    #[test]
+    fn jumps_report_the_active_loop_region() {
+        let conn = seeded();
+        let cue = create_timeline_marker(&conn, 1, "Cue".into(), 3000, None, None).unwrap();
+        let region =
+            create_timeline_marker(&conn, 1, "Loop".into(), 2000, Some(6000), None).unwrap();
+        set_timeline_loop_region(&conn, 1, Some(region.id)).unwrap();
+
+        let not_looping = jump_to_marker(&conn, cue.id).unwrap();
+        assert_eq!(not_looping.position_ms, 3000);
+        assert_eq!(not_looping.loop_start_ms, None);
+
+        update_timeline_loop(&conn, 1, true).unwrap();
+        assert_eq!(
+            jump_to_marker(&conn, cue.id).unwrap(),
+            TransportJump {
+                timeline_id: 1,
+                marker_id: cue.id,
+                position_ms: 3000,
+                loop_start_ms: Some(2000),
+                loop_end_ms: Some(6000),
+            }
+        );
+    }
+
+    #[test]
+    fn deleting_a_looped_region_can_be_undone() {
+        let conn = seeded();
+        let region = create_timeline_marker(&conn, 1, "Loop".into(), 0, Some(1000), None).unwrap();
+        set_timeline_loop_region(&conn, 1, Some(region.id)).unwrap();
+
+        history::record(
+            &conn,
+            "Delete marker",
+            &[
+                Scope::new("timeline_markers", "id = ?1", region.id),
+                Scope::new("timelines", "loop_marker_id = ?1", region.id),
+            ],
+            |conn| delete_timeline_marker(conn, region.id),
+        )
+        .unwrap();
+        assert_eq!(get_timelines(&conn, 1).unwrap()[0].loop_marker_id, None);
+
+        history::undo(&conn).unwrap();
+        assert_eq!(get_timeline_markers(&conn, 1).unwrap().len(), 1);
+        assert_eq!(
+            get_timelines(&conn, 1).unwrap()[0].loop_marker_id,
+            Some(region.id)
+        );
+    }
+}
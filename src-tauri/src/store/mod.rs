@@ -0,0 +1,67 @@
+//! Plain-Rust data access for the library and timeline tables.
+//!
+//! Every function takes a borrowed `&Connection` and returns `AppResult`, so the
+//! Tauri commands in `lib.rs` stay thin wrappers around the shared `Database`
+//! and the behaviour can be exercised against an in-memory connection.
+
+pub mod audio_channels;
+pub mod audio_elements;
+pub mod automation;
+pub mod dynamics;
+pub mod effects;
+pub mod element_groups;
+pub mod group_playback;
+pub mod history;
+pub mod markers;
+pub mod moods;
+pub mod search;
+pub mod snapshots;
+pub mod sound_sets;
+pub mod tags;
+pub mod tempo;
+pub mod timeline_edits;
+pub mod timeline_templates;
+pub mod timelines;
+pub mod transitions;
+pub mod trash;
+
+use rusqlite::{Connection, Params, Row, Statement};
+
+use crate::{AppError, AppResult};
+
+/// Runs a prepared query and collects every mapped row.
+fn collect_rows<T, P: Params>(
+    stmt: &mut Statement<'_>,
+    params: P,
+    map: impl FnMut(&Row<'_>) -> rusqlite::Result<T>,
+) -> AppResult<Vec<T>> {
+    let rows = stmt.query_map(params, map)?;
+    rows.collect::<Result<Vec<_>, _>>().map_err(AppError::from)
+}
+
+/// Runs `edit` in a savepoint, so a failed edit leaves nothing behind even
+/// inside a transaction the caller already opened.
+fn in_savepoint<T>(
+    conn: &Connection,
+    edit: impl FnOnce(&Connection) -> AppResult<T>,
+) -> AppResult<T> {
+    conn.execute_batch("SAVEPOINT store_edit")?;
+    match edit(conn) {
+        Ok(value) => {
+            conn.execute_batch("RELEASE store_edit")?;
+            Ok(value)
+        }
+        Err(error) => {
+            conn.execute_batch("ROLLBACK TO store_edit; RELEASE store_edit")?;
+            Err(error)
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn test_connection() -> rusqlite::Connection {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    conn.execute_batch("PRAGMA foreign_keys = ON;").unwrap();
+    crate::migrations::run_migrations(&conn).unwrap();
+    conn
+}
@@ -0,0 +1,69 @@
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use super::collect_rows;
+use super::trash::{move_to_trash, TrashKind, TrashOwner};
+use crate::AppResult;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Mood {
+    pub id: i64,
+    pub name: String,
+    pub description: String,
+    pub created_at: String,
+}
+
+pub fn create_mood(conn: &Connection, name: String, description: String) -> AppResult<Mood> {
+    conn.execute(
+        "INSERT INTO moods (name, description) VALUES (?1, ?2)",
+        [&name, &description],
+    )?;
+
+    let id = conn.last_insert_rowid();
+
+    Ok(Mood {
+        id,
+        name,
+        description,
+        created_at: chrono::Local::now().to_rfc3339(),
+    })
+}
+
+pub fn get_moods(conn: &Connection) -> AppResult<Vec<Mood>> {
+    let mut stmt = conn
+        .prepare("SELECT id, name, description, created_at FROM moods WHERE deleted_at IS NULL ORDER BY created_at DESC")?;
+
+    collect_rows(&mut stmt, [], |row| {
+        Ok(Mood {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            description: row.get(2)?,
+            created_at: row.get(3)?,
+        })
+    })
+}
+
+/// Moves the mood, timeline included, to the trash.
+pub fn delete_mood(conn: &Connection, id: i64) -> AppResult<()> {
+    move_to_trash(conn, TrashKind::Mood, TrashOwner::Any, id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::test_connection;
+
+    #[test]
+    fn moods_round_trip() {
+        let conn = test_connection();
+
+        let mood = create_mood(&conn, "Tense".into(), "Before the ambush".into()).unwrap();
+        let moods = get_moods(&conn).unwrap();
+        assert_eq!(moods.len(), 1);
+        assert_eq!(moods[0].id, mood.id);
+        assert_eq!(moods[0].description, "Before the ambush");
+
+        delete_mood(&conn, mood.id).unwrap();
+        assert!(get_moods(&conn).unwrap().is_empty());
+    }
+}
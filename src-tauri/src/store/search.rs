@@ -0,0 +1,214 @@
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use super::collect_rows;
+use crate::AppResult;
+
+/// Hits returned when the caller does not ask for a specific number.
+pub const DEFAULT_SEARCH_LIMIT: i64 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchHitKind {
+    SoundSet,
+    Mood,
+    AudioElement,
+    ElementGroup,
+}
+
+impl SearchHitKind {
+    /// Inverse of the `id * 4 + kind` rowid encoding used by `library_search`.
+    fn from_code(code: i64) -> Self {
+        match code {
+            0 => SearchHitKind::SoundSet,
+            1 => SearchHitKind::Mood,
+            2 => SearchHitKind::AudioElement,
+            _ => SearchHitKind::ElementGroup,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub kind: SearchHitKind,
+    pub id: i64,
+    pub name: String,
+    /// Owning sound set of an element or group; `None` for global ones.
+    pub sound_set_id: Option<i64>,
+    /// Higher is a better match.
+    pub score: f64,
+}
+
+/// Searches names, descriptions, channel names and tags across the library.
+///
+/// Every word of `query` must match, as a prefix, somewhere in the item. Items
+/// in the trash are never returned, and neither are disabled sound sets or
+/// their contents unless `include_disabled` is set.
+pub fn search_library(
+    conn: &Connection,
+    query: &str,
+    include_disabled: bool,
+    limit: i64,
+) -> AppResult<Vec<SearchHit>> {
+    let match_expression = to_match_expression(query);
+    if match_expression.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Matches on the name weigh more than tags, which weigh more than details.
+    let mut stmt = conn.prepare(
+        "SELECT
+            f.rowid % 4,
+            f.rowid / 4,
+            f.name,
+            CASE f.rowid % 4
+                WHEN 2 THEN (SELECT sound_set_id FROM audio_elements WHERE id = f.rowid / 4)
+                WHEN 3 THEN (SELECT sound_set_id FROM element_groups WHERE id = f.rowid / 4)
+            END,
+            -bm25(library_search, 10.0, 1.0, 5.0) AS score
+        FROM library_search f
+        WHERE library_search MATCH ?1
+          AND CASE f.rowid % 4
+            WHEN 0 THEN EXISTS (
+                SELECT 1 FROM sound_sets s
+                WHERE s.id = f.rowid / 4 AND s.deleted_at IS NULL AND (?2 OR s.is_enabled = 1)
+            )
+            WHEN 1 THEN EXISTS (
+                SELECT 1 FROM moods m WHERE m.id = f.rowid / 4 AND m.deleted_at IS NULL
+            )
+            WHEN 2 THEN EXISTS (
+                SELECT 1 FROM audio_elements e
+                LEFT JOIN sound_sets s ON s.id = e.sound_set_id
+                WHERE e.id = f.rowid / 4 AND e.deleted_at IS NULL
+                  AND (e.sound_set_id IS NULL OR (s.deleted_at IS NULL AND (?2 OR s.is_enabled = 1)))
+            )
+            ELSE EXISTS (
+                SELECT 1 FROM element_groups g
+                LEFT JOIN sound_sets s ON s.id = g.sound_set_id
+                WHERE g.id = f.rowid / 4
+                  AND (g.sound_set_id IS NULL OR (s.deleted_at IS NULL AND (?2 OR s.is_enabled = 1)))
+            )
+          END
+        ORDER BY score DESC
+        LIMIT ?3",
+    )?;
+
+    collect_rows(
+        &mut stmt,
+        rusqlite::params![match_expression, include_disabled, limit],
+        |row| {
+            Ok(SearchHit {
+                kind: SearchHitKind::from_code(row.get(0)?),
+                id: row.get(1)?,
+                name: row.get(2)?,
+                sound_set_id: row.get(3)?,
+                score: row.get(4)?,
+            })
+        },
+    )
+}
+
+/// Turns free text into an FTS5 expression where each word is a quoted prefix
+/// term, so punctuation typed by the user is never parsed as query syntax.
+fn to_match_expression(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::test_connection;
+
+    fn seeded() -> Connection {
+        let conn = test_connection();
+        conn.execute_batch(
+            "INSERT INTO sound_sets (id, name, description, is_enabled) VALUES (1, 'Storm', 'Heavy weather', 1);
+             INSERT INTO sound_sets (id, name, description, is_enabled) VALUES (2, 'Tavern', 'Thunderous laughter', 0);
+             INSERT INTO audio_channels (id, sound_set_id, name) VALUES (1, 1, 'Weather FX');
+             INSERT INTO audio_elements (id, sound_set_id, channel_id, file_path, file_name, channel_type)
+                 VALUES (10, 1, 1, 'p', 'thunder_close.ogg', 'sfx');
+             INSERT INTO audio_elements (id, sound_set_id, file_path, file_name) VALUES (20, 2, 'p', 'thunder_mug.ogg');
+             INSERT INTO element_groups (id, sound_set_id, name) VALUES (30, 1, 'Thunder rolls');
+             INSERT INTO moods (id, name, description) VALUES (1, 'Ça chauffe', 'Combat');",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn hits(conn: &Connection, query: &str, include_disabled: bool) -> Vec<(SearchHitKind, i64)> {
+        search_library(conn, query, include_disabled, DEFAULT_SEARCH_LIMIT)
+            .unwrap()
+            .into_iter()
+            .map(|hit| (hit.kind, hit.id))
+            .collect()
+    }
+
+    #[test]
+    fn finds_prefixes_across_kinds_and_skips_disabled_sound_sets() {
+        let conn = seeded();
+
+        let mut found = hits(&conn, "thund", false);
+        found.sort_by_key(|(_, id)| *id);
+
+        assert_eq!(
+            found,
+            [
+                (SearchHitKind::AudioElement, 10),
+                (SearchHitKind::ElementGroup, 30)
+            ]
+        );
+        assert_eq!(hits(&conn, "thund", true).len(), 4);
+    }
+
+    #[test]
+    fn name_matches_rank_above_detail_matches() {
+        let conn = seeded();
+        conn.execute(
+            "INSERT INTO moods (id, name, description) VALUES (2, 'Calm', 'Storm has passed')",
+            [],
+        )
+        .unwrap();
+
+        let found = hits(&conn, "storm", false);
+
+        assert_eq!(found[0], (SearchHitKind::SoundSet, 1));
+        assert_eq!(found[1], (SearchHitKind::Mood, 2));
+    }
+
+    #[test]
+    fn channel_renames_and_trash_are_reflected() {
+        let conn = seeded();
+        assert_eq!(
+            hits(&conn, "weather fx", false),
+            [(SearchHitKind::AudioElement, 10)]
+        );
+
+        conn.execute("UPDATE audio_channels SET name = 'Sky' WHERE id = 1", [])
+            .unwrap();
+        assert!(hits(&conn, "weather fx", false).is_empty());
+        assert_eq!(
+            hits(&conn, "sky", false),
+            [(SearchHitKind::AudioElement, 10)]
+        );
+
+        conn.execute(
+            "UPDATE audio_elements SET deleted_at = CURRENT_TIMESTAMP WHERE id = 10",
+            [],
+        )
+        .unwrap();
+        assert!(hits(&conn, "sky", false).is_empty());
+    }
+
+    #[test]
+    fn ignores_diacritics_and_query_syntax() {
+        let conn = seeded();
+
+        assert_eq!(hits(&conn, "chauffe ca", false), [(SearchHitKind::Mood, 1)]);
+        assert!(hits(&conn, "\"NEAR( OR", false).is_empty());
+        assert!(hits(&conn, "   ", false).is_empty());
+    }
+}
@@ -0,0 +1,311 @@
+//! Named snapshots of the whole mixer, recalled in one step.
+//!
+//! Volumes and enabled sound sets are read from the library when saving and
+//! written back on recall. Mute, solo and the active mood live in the
+//! frontend mixer, so the caller passes them in and gets them back.
+
+use std::collections::HashMap;
+
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use super::audio_channels::{get_audio_channel, update_audio_channel};
+use super::sound_sets::update_sound_set_enabled;
+use super::{collect_rows, in_savepoint};
+use crate::{AppError, AppResult};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MixerSnapshot {
+    pub id: i64,
+    pub name: String,
+    /// `None` when no mood was active, or the mood has since been deleted.
+    pub mood_id: Option<i64>,
+    pub created_at: String,
+    pub channels: Vec<SnapshotChannel>,
+    pub sound_sets: Vec<SnapshotSoundSet>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotChannel {
+    pub channel_id: i64,
+    pub volume: f64,
+    pub muted: bool,
+    pub solo: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotSoundSet {
+    pub sound_set_id: i64,
+    pub is_enabled: bool,
+}
+
+/// Mute and solo of one channel, as the frontend mixer has them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChannelToggles {
+    pub channel_id: i64,
+    pub muted: bool,
+    pub solo: bool,
+}
+
+fn get_snapshot_row(conn: &Connection, id: i64) -> AppResult<(String, Option<i64>, String)> {
+    conn.query_row(
+        "SELECT s.name, m.id, s.created_at FROM mixer_snapshots s
+         LEFT JOIN moods m ON m.id = s.mood_id AND m.deleted_at IS NULL
+         WHERE s.id = ?1",
+        [id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )
+    .optional()?
+    .ok_or_else(|| AppError::not_found("Mixer snapshot", id))
+}
+
+/// Id of the snapshot saved as `name`, if there is one.
+pub fn find_snapshot_id(conn: &Connection, name: &str) -> AppResult<Option<i64>> {
+    let id = conn
+        .query_row(
+            "SELECT id FROM mixer_snapshots WHERE name = ?1",
+            [name],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(id)
+}
+
+pub fn get_snapshot(conn: &Connection, id: i64) -> AppResult<MixerSnapshot> {
+    let (name, mood_id, created_at) = get_snapshot_row(conn, id)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT channel_id, volume, muted, solo FROM mixer_snapshot_channels
+         WHERE snapshot_id = ?1 ORDER BY channel_id",
+    )?;
+    let channels = collect_rows(&mut stmt, [id], |row| {
+        Ok(SnapshotChannel {
+            channel_id: row.get(0)?,
+            volume: row.get(1)?,
+            muted: row.get(2)?,
+            solo: row.get(3)?,
+        })
+    })?;
+
+    let mut stmt = conn.prepare(
+        "SELECT sound_set_id, is_enabled FROM mixer_snapshot_sound_sets
+         WHERE snapshot_id = ?1 ORDER BY sound_set_id",
+    )?;
+    let sound_sets = collect_rows(&mut stmt, [id], |row| {
+        Ok(SnapshotSoundSet {
+            sound_set_id: row.get(0)?,
+            is_enabled: row.get(1)?,
+        })
+    })?;
+
+    Ok(MixerSnapshot {
+        id,
+        name,
+        mood_id,
+        created_at,
+        channels,
+        sound_sets,
+    })
+}
+
+pub fn list_snapshots(conn: &Connection) -> AppResult<Vec<MixerSnapshot>> {
+    let mut stmt = conn.prepare("SELECT id FROM mixer_snapshots ORDER BY name ASC, id ASC")?;
+    let ids: Vec<i64> = collect_rows(&mut stmt, [], |row| row.get(0))?;
+
+    ids.into_iter().map(|id| get_snapshot(conn, id)).collect()
+}
+
+/// Captures the current mixer as `name`, replacing any snapshot of that
+/// name. Channels missing from `toggles` are saved unmuted and not soloed.
+pub fn save_snapshot(
+    conn: &Connection,
+    name: String,
+    mood_id: Option<i64>,
+    toggles: &[ChannelToggles],
+) -> AppResult<MixerSnapshot> {
+    if name.trim().is_empty() {
+        return Err(AppError::ValidationFailed(
+            "Snapshot name must not be empty".into(),
+        ));
+    }
+    if let Some(mood_id) = mood_id {
+        conn.query_row(
+            "SELECT id FROM moods WHERE id = ?1 AND deleted_at IS NULL",
+            [mood_id],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::not_found("Mood", mood_id))?;
+    }
+    let mut toggled = HashMap::new();
+    for toggle in toggles {
+        get_audio_channel(conn, toggle.channel_id)?;
+        toggled.insert(toggle.channel_id, (toggle.muted, toggle.solo));
+    }
+
+    let id = in_savepoint(conn, |conn| {
+        let id: i64 = conn.query_row(
+            "INSERT INTO mixer_snapshots (name, mood_id) VALUES (?1, ?2)
+             ON CONFLICT(name) DO UPDATE SET mood_id = excluded.mood_id, created_at = CURRENT_TIMESTAMP
+             RETURNING id",
+            rusqlite::params![name, mood_id],
+            |row| row.get(0),
+        )?;
+        conn.execute(
+            "DELETE FROM mixer_snapshot_channels WHERE snapshot_id = ?1",
+            [id],
+        )?;
+        conn.execute(
+            "DELETE FROM mixer_snapshot_sound_sets WHERE snapshot_id = ?1",
+            [id],
+        )?;
+
+        let volumes: Vec<(i64, f64)> = {
+            let mut stmt = conn.prepare(
+                "SELECT c.id, c.volume FROM audio_channels c
+                 JOIN sound_sets s ON s.id = c.sound_set_id
+                 WHERE s.deleted_at IS NULL",
+            )?;
+            collect_rows(&mut stmt, [], |row| Ok((row.get(0)?, row.get(1)?)))?
+        };
+        for (channel_id, volume) in volumes {
+            let (muted, solo) = toggled.get(&channel_id).copied().unwrap_or_default();
+            conn.execute(
+                "INSERT INTO mixer_snapshot_channels (snapshot_id, channel_id, volume, muted, solo)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                rusqlite::params![id, channel_id, volume, muted, solo],
+            )?;
+        }
+
+        conn.execute(
+            "INSERT INTO mixer_snapshot_sound_sets (snapshot_id, sound_set_id, is_enabled)
+             SELECT ?1, id, is_enabled FROM sound_sets WHERE deleted_at IS NULL",
+            [id],
+        )?;
+
+        Ok(id)
+    })?;
+
+    get_snapshot(conn, id)
+}
+
+/// Writes the snapshot's channel volumes and enabled sound sets back to the
+/// library and returns it, so the caller can restore mute, solo and the mood.
+/// Channels and sound sets created after the snapshot are left alone.
+pub fn recall_snapshot(conn: &Connection, id: i64) -> AppResult<MixerSnapshot> {
+    let snapshot = get_snapshot(conn, id)?;
+
+    for saved in &snapshot.channels {
+        let channel = get_audio_channel(conn, saved.channel_id)?;
+        update_audio_channel(conn, channel.id, &channel.name, &channel.icon, saved.volume)?;
+    }
+    for saved in &snapshot.sound_sets {
+        update_sound_set_enabled(conn, saved.sound_set_id, saved.is_enabled)?;
+    }
+
+    Ok(snapshot)
+}
+
+pub fn delete_snapshot(conn: &Connection, id: i64) -> AppResult<()> {
+    let deleted = conn.execute("DELETE FROM mixer_snapshots WHERE id = ?1", [id])?;
+    if deleted == 0 {
+        return Err(AppError::not_found("Mixer snapshot", id));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::audio_channels::create_audio_channel;
+    use crate::store::moods::{create_mood, delete_mood};
+    use crate::store::sound_sets::{create_sound_set, get_sound_sets};
+    use crate::store::test_connection;
+
+    fn volume(conn: &Connection, channel_id: i64) -> f64 {
+        get_audio_channel(conn, channel_id).unwrap().volume
+    }
+
+    #[test]
+    fn recalling_a_snapshot_restores_volumes_and_sound_sets() {
+        let conn = test_connection();
+        let forest = create_sound_set(&conn, "Forest".into(), String::new()).unwrap();
+        let tavern = create_sound_set(&conn, "Tavern".into(), String::new()).unwrap();
+        let music = create_audio_channel(&conn, forest.id, "Music".into(), "music".into(), 0.8)
+            .unwrap()
+            .id;
+        let crowd = create_audio_channel(&conn, tavern.id, "Crowd".into(), "ambient".into(), 0.5)
+            .unwrap()
+            .id;
+        let mood = create_mood(&conn, "Calm".into(), String::new()).unwrap();
+        update_sound_set_enabled(&conn, tavern.id, false).unwrap();
+
+        let toggles = [ChannelToggles {
+            channel_id: crowd,
+            muted: true,
+            solo: false,
+        }];
+        let calm = save_snapshot(&conn, "Calm forest".into(), Some(mood.id), &toggles).unwrap();
+        assert_eq!(calm.mood_id, Some(mood.id));
+        assert_eq!(calm.channels.len(), 2);
+        assert!(calm
+            .channels
+            .iter()
+            .any(|saved| saved.channel_id == crowd && saved.muted));
+
+        update_audio_channel(&conn, music, "Music", "music", 0.2).unwrap();
+        update_sound_set_enabled(&conn, tavern.id, true).unwrap();
+        let recalled = recall_snapshot(&conn, calm.id).unwrap();
+        assert_eq!(recalled, calm);
+        assert_eq!(volume(&conn, music), 0.8);
+        assert_eq!(volume(&conn, crowd), 0.5);
+        // The native engine takes the volumes only; the crowd's mute stays with the frontend.
+        let engine_mix = crate::audio::bus::load_mix_settings(&conn).unwrap();
+        assert_eq!(engine_mix.channels[&music].volume, 0.8);
+        assert_eq!(engine_mix.channels[&crowd].volume, 0.5);
+        let enabled: Vec<bool> = get_sound_sets(&conn)
+            .unwrap()
+            .iter()
+            .map(|sound_set| sound_set.is_enabled)
+            .collect();
+        assert_eq!(enabled.iter().filter(|enabled| **enabled).count(), 1);
+
+        delete_mood(&conn, mood.id).unwrap();
+        assert_eq!(get_snapshot(&conn, calm.id).unwrap().mood_id, None);
+    }
+
+    #[test]
+    fn saving_under_an_existing_name_replaces_the_snapshot() {
+        let conn = test_connection();
+        let forest = create_sound_set(&conn, "Forest".into(), String::new()).unwrap();
+        let music = create_audio_channel(&conn, forest.id, "Music".into(), "music".into(), 0.8)
+            .unwrap()
+            .id;
+
+        let first = save_snapshot(&conn, "Fight".into(), None, &[]).unwrap();
+        update_audio_channel(&conn, music, "Music", "music", 1.0).unwrap();
+        let second = save_snapshot(&conn, "Fight".into(), None, &[]).unwrap();
+        assert_eq!(second.id, first.id);
+        assert_eq!(second.channels[0].volume, 1.0);
+        assert_eq!(list_snapshots(&conn).unwrap(), vec![second.clone()]);
+
+        let unnamed = save_snapshot(&conn, " ".into(), None, &[]).unwrap_err();
+        assert_eq!(unnamed.code(), "ValidationFailed");
+        let missing = ChannelToggles {
+            channel_id: 999,
+            muted: false,
+            solo: true,
+        };
+        let error = save_snapshot(&conn, "Solo".into(), None, &[missing]).unwrap_err();
+        assert_eq!(error.code(), "NotFound");
+
+        delete_snapshot(&conn, second.id).unwrap();
+        assert!(list_snapshots(&conn).unwrap().is_empty());
+        assert_eq!(
+            recall_snapshot(&conn, second.id).unwrap_err().code(),
+            "NotFound"
+        );
+    }
+}
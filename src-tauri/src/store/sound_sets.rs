@@ -0,0 +1,99 @@
+use rusqlite::{Connection, Row};
+use serde::{Deserialize, Serialize};
+
+use super::collect_rows;
+use super::trash::{move_to_trash, TrashKind, TrashOwner};
+use crate::AppResult;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SoundSet {
+    pub id: i64,
+    pub name: String,
+    pub description: String,
+    pub is_enabled: bool,
+    pub created_at: String,
+}
+
+fn map_sound_set(row: &Row<'_>) -> rusqlite::Result<SoundSet> {
+    Ok(SoundSet {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        description: row.get(2)?,
+        is_enabled: row.get(3)?,
+        created_at: row.get(4)?,
+    })
+}
+
+pub fn create_sound_set(
+    conn: &Connection,
+    name: String,
+    description: String,
+) -> AppResult<SoundSet> {
+    conn.execute(
+        "INSERT INTO sound_sets (name, description) VALUES (?1, ?2)",
+        [&name, &description],
+    )?;
+
+    let id = conn.last_insert_rowid();
+
+    Ok(SoundSet {
+        id,
+        name,
+        description,
+        is_enabled: true,
+        created_at: chrono::Local::now().to_rfc3339(),
+    })
+}
+
+pub fn get_sound_sets(conn: &Connection) -> AppResult<Vec<SoundSet>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, description, is_enabled, created_at FROM sound_sets WHERE deleted_at IS NULL ORDER BY created_at DESC",
+    )?;
+
+    collect_rows(&mut stmt, [], map_sound_set)
+}
+
+/// Moves the sound set, and with it its whole library, to the trash.
+pub fn delete_sound_set(conn: &Connection, id: i64) -> AppResult<()> {
+    move_to_trash(conn, TrashKind::SoundSet, TrashOwner::Any, id)
+}
+
+pub fn update_sound_set_enabled(conn: &Connection, id: i64, is_enabled: bool) -> AppResult<()> {
+    conn.execute(
+        "UPDATE sound_sets SET is_enabled = ?1 WHERE id = ?2",
+        (&is_enabled, &id),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::test_connection;
+
+    #[test]
+    fn created_sound_sets_start_enabled_and_can_be_disabled() {
+        let conn = test_connection();
+
+        let sound_set = create_sound_set(&conn, "Forest".into(), "Night ambience".into()).unwrap();
+        assert!(sound_set.is_enabled);
+
+        update_sound_set_enabled(&conn, sound_set.id, false).unwrap();
+
+        let stored = get_sound_sets(&conn).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].name, "Forest");
+        assert!(!stored[0].is_enabled);
+    }
+
+    #[test]
+    fn deleting_a_sound_set_removes_it() {
+        let conn = test_connection();
+        let sound_set = create_sound_set(&conn, "Forest".into(), String::new()).unwrap();
+
+        delete_sound_set(&conn, sound_set.id).unwrap();
+
+        assert!(get_sound_sets(&conn).unwrap().is_empty());
+    }
+}
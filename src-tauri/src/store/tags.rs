@@ -0,0 +1,381 @@
+//! Tags, notes and star ratings on audio elements and element groups.
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use super::history::Scope;
+use super::{collect_rows, in_savepoint};
+use crate::{AppError, AppResult};
+
+/// Separator used when a query folds an item's tag names into one column.
+const TAG_SEPARATOR: char = '\u{1f}';
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagTarget {
+    AudioElement,
+    ElementGroup,
+}
+
+impl TagTarget {
+    fn link_table(self) -> &'static str {
+        match self {
+            TagTarget::AudioElement => "audio_element_tags",
+            TagTarget::ElementGroup => "element_group_tags",
+        }
+    }
+
+    fn item_column(self) -> &'static str {
+        match self {
+            TagTarget::AudioElement => "audio_element_id",
+            TagTarget::ElementGroup => "element_group_id",
+        }
+    }
+
+    fn item_filter(self) -> &'static str {
+        match self {
+            TagTarget::AudioElement => "audio_element_id = ?1",
+            TagTarget::ElementGroup => "element_group_id = ?1",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Tag {
+    pub id: i64,
+    pub name: String,
+    pub usage_count: i64,
+}
+
+/// SQL expression listing the tags of the row whose id is `item_id`, for use
+/// in a SELECT list and decoding with [`split_tag_names`].
+pub(crate) fn tag_names_column(target: TagTarget, item_id: &str) -> String {
+    format!(
+        "(SELECT group_concat(t.name, char(31)) FROM {} link JOIN tags t ON t.id = link.tag_id WHERE link.{} = {})",
+        target.link_table(),
+        target.item_column(),
+        item_id
+    )
+}
+
+/// SQL condition that holds when the row whose id is `item_id` carries the
+/// tag bound to `param`, or when that parameter is NULL.
+pub(crate) fn has_tag_condition(target: TagTarget, item_id: &str, param: &str) -> String {
+    format!(
+        "({param} IS NULL OR EXISTS (SELECT 1 FROM {} link JOIN tags t ON t.id = link.tag_id WHERE link.{} = {} AND t.name = {param}))",
+        target.link_table(),
+        target.item_column(),
+        item_id,
+        param = param
+    )
+}
+
+pub(crate) fn split_tag_names(value: Option<String>) -> Vec<String> {
+    let mut names: Vec<String> = value
+        .unwrap_or_default()
+        .split(TAG_SEPARATOR)
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect();
+    names.sort_by_key(|name| name.to_lowercase());
+    names
+}
+
+/// Ratings are 1 to 5 stars; `None` means unrated.
+pub(crate) fn validate_rating(rating: Option<i64>) -> AppResult<()> {
+    match rating {
+        Some(stars) if !(1..=5).contains(&stars) => Err(AppError::ValidationFailed(format!(
+            "Rating must be between 1 and 5, got {}",
+            stars
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Every tag in use, alphabetically.
+pub fn list_tags(conn: &Connection) -> AppResult<Vec<Tag>> {
+    let mut stmt = conn.prepare(
+        "SELECT t.id, t.name,
+            (SELECT count(*) FROM audio_element_tags WHERE tag_id = t.id)
+            + (SELECT count(*) FROM element_group_tags WHERE tag_id = t.id)
+         FROM tags t
+         ORDER BY t.name COLLATE NOCASE ASC",
+    )?;
+
+    collect_rows(&mut stmt, [], |row| {
+        Ok(Tag {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            usage_count: row.get(2)?,
+        })
+    })
+}
+
+/// The rows tagging or untagging `item_ids` may touch, for journaling: every
+/// tag, since either can create or drop one, and the items' tag links.
+pub fn tag_scopes(target: TagTarget, item_ids: &[i64]) -> Vec<Scope> {
+    let mut scopes = vec![Scope::matching("tags", "TRUE")];
+    scopes.extend(
+        item_ids
+            .iter()
+            .map(|item_id| Scope::new(target.link_table(), target.item_filter(), *item_id)),
+    );
+    scopes
+}
+
+/// Adds every tag in `tags` to every item in `item_ids`, creating missing tags.
+pub fn tag_items(
+    conn: &Connection,
+    target: TagTarget,
+    item_ids: &[i64],
+    tags: &[String],
+) -> AppResult<()> {
+    in_savepoint(conn, |conn| {
+        for item_id in item_ids {
+            add_tags(conn, target, *item_id, tags)?;
+        }
+        Ok(())
+    })
+}
+
+/// Removes the given tags from every item in `item_ids`. Tags left without any
+/// item are deleted.
+pub fn untag_items(
+    conn: &Connection,
+    target: TagTarget,
+    item_ids: &[i64],
+    tags: &[String],
+) -> AppResult<()> {
+    let sql = format!(
+        "DELETE FROM {} WHERE {} = ?1 AND tag_id IN (SELECT id FROM tags WHERE name = ?2)",
+        target.link_table(),
+        target.item_column()
+    );
+
+    in_savepoint(conn, |conn| {
+        for item_id in item_ids {
+            for name in normalize_tags(tags) {
+                conn.execute(&sql, rusqlite::params![item_id, name])?;
+            }
+        }
+
+        conn.execute(
+            "DELETE FROM tags
+             WHERE id NOT IN (SELECT tag_id FROM audio_element_tags)
+               AND id NOT IN (SELECT tag_id FROM element_group_tags)",
+            [],
+        )?;
+        Ok(())
+    })
+}
+
+/// Links tags to one item without opening a transaction, so importers can
+/// call it inside their own.
+pub(crate) fn add_tags(
+    conn: &Connection,
+    target: TagTarget,
+    item_id: i64,
+    tags: &[String],
+) -> AppResult<()> {
+    let sql = format!(
+        "INSERT OR IGNORE INTO {} ({}, tag_id) SELECT ?1, id FROM tags WHERE name = ?2",
+        target.link_table(),
+        target.item_column()
+    );
+
+    for name in normalize_tags(tags) {
+        conn.execute("INSERT OR IGNORE INTO tags (name) VALUES (?1)", [&name])?;
+        conn.execute(&sql, rusqlite::params![item_id, name])?;
+    }
+
+    Ok(())
+}
+
+/// Trims names and drops blanks and case-insensitive duplicates.
+fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for name in tags.iter().map(|name| name.trim()) {
+        if !name.is_empty()
+            && !normalized
+                .iter()
+                .any(|existing| existing.to_lowercase() == name.to_lowercase())
+        {
+            normalized.push(name.to_string());
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::audio_elements::{get_audio_elements, update_audio_element_metadata};
+    use crate::store::element_groups::get_element_groups;
+    use crate::store::history;
+    use crate::store::search::{search_library, DEFAULT_SEARCH_LIMIT};
+    use crate::store::test_connection;
+
+    fn seeded() -> Connection {
+        let conn = test_connection();
+        conn.execute_batch(
+            "INSERT INTO sound_sets (id, name, description) VALUES (1, 'S', 'D');
+             INSERT INTO audio_elements (id, sound_set_id, file_path, file_name) VALUES (10, 1, 'p', 'a.ogg');
+             INSERT INTO audio_elements (id, sound_set_id, file_path, file_name) VALUES (11, 1, 'p', 'b.ogg');
+             INSERT INTO element_groups (id, sound_set_id, name) VALUES (20, 1, 'G');",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn tags(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn bulk_tagging_is_case_insensitive_and_filters_listings() {
+        let conn = seeded();
+
+        tag_items(
+            &conn,
+            TagTarget::AudioElement,
+            &[10, 11],
+            &tags(&["Forest", " night ", "forest", ""]),
+        )
+        .unwrap();
+        tag_items(
+            &conn,
+            TagTarget::AudioElement,
+            &[10],
+            &tags(&["FOREST", "combat"]),
+        )
+        .unwrap();
+        tag_items(&conn, TagTarget::ElementGroup, &[20], &tags(&["night"])).unwrap();
+
+        let used: Vec<(String, i64)> = list_tags(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|tag| (tag.name, tag.usage_count))
+            .collect();
+        assert_eq!(
+            used,
+            [
+                ("combat".to_string(), 1),
+                ("Forest".to_string(), 2),
+                ("night".to_string(), 3)
+            ]
+        );
+
+        let combat = get_audio_elements(&conn, 1, Some("Combat")).unwrap();
+        assert_eq!(combat.len(), 1);
+        assert_eq!(combat[0].id, 10);
+        assert_eq!(combat[0].tags, ["combat", "Forest", "night"]);
+
+        assert_eq!(get_audio_elements(&conn, 1, None).unwrap().len(), 2);
+        assert_eq!(
+            get_element_groups(&conn, Some(1), Some("night"))
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(get_element_groups(&conn, Some(1), Some("forest"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn untagging_removes_orphaned_tags() {
+        let conn = seeded();
+        tag_items(
+            &conn,
+            TagTarget::AudioElement,
+            &[10, 11],
+            &tags(&["rain", "loop"]),
+        )
+        .unwrap();
+
+        untag_items(&conn, TagTarget::AudioElement, &[10, 11], &tags(&["RAIN"])).unwrap();
+        untag_items(&conn, TagTarget::AudioElement, &[10], &tags(&["loop"])).unwrap();
+
+        let remaining: Vec<String> = list_tags(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect();
+        assert_eq!(remaining, ["loop"]);
+        assert_eq!(
+            get_audio_elements(&conn, 1, Some("loop")).unwrap()[0].id,
+            11
+        );
+    }
+
+    #[test]
+    fn tags_are_searchable() {
+        let conn = seeded();
+        tag_items(&conn, TagTarget::ElementGroup, &[20], &tags(&["ambush"])).unwrap();
+
+        let hits = search_library(&conn, "ambush", false, DEFAULT_SEARCH_LIMIT).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].id, 20);
+
+        untag_items(&conn, TagTarget::ElementGroup, &[20], &tags(&["ambush"])).unwrap();
+        assert!(search_library(&conn, "ambush", false, DEFAULT_SEARCH_LIMIT)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn tagging_and_untagging_can_be_undone() {
+        let conn = seeded();
+        let record_tagging = |label: &str, tag: bool, names: &[&str]| {
+            history::record(
+                &conn,
+                label,
+                &tag_scopes(TagTarget::AudioElement, &[10, 11]),
+                |conn| {
+                    if tag {
+                        tag_items(conn, TagTarget::AudioElement, &[10, 11], &tags(names))
+                    } else {
+                        untag_items(conn, TagTarget::AudioElement, &[10, 11], &tags(names))
+                    }
+                },
+            )
+            .unwrap();
+        };
+
+        record_tagging("Tag items", true, &["rain"]);
+        record_tagging("Untag items", false, &["rain"]);
+        assert!(list_tags(&conn).unwrap().is_empty());
+
+        history::undo(&conn).unwrap().unwrap();
+        assert_eq!(list_tags(&conn).unwrap()[0].usage_count, 2);
+        assert_eq!(get_audio_elements(&conn, 1, Some("rain")).unwrap().len(), 2);
+        let hits = search_library(&conn, "rain", false, DEFAULT_SEARCH_LIMIT).unwrap();
+        assert_eq!(hits.len(), 2);
+
+        history::undo(&conn).unwrap().unwrap();
+        assert!(list_tags(&conn).unwrap().is_empty());
+        assert!(get_audio_elements(&conn, 1, Some("rain"))
+            .unwrap()
+            .is_empty());
+
+        history::redo(&conn).unwrap().unwrap();
+        assert_eq!(get_audio_elements(&conn, 1, Some("rain")).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn ratings_must_be_one_to_five_stars() {
+        let conn = seeded();
+
+        update_audio_element_metadata(&conn, 10, "Use sparingly", Some(4)).unwrap();
+        let error = update_audio_element_metadata(&conn, 10, "", Some(6)).unwrap_err();
+        assert_eq!(error.code(), "ValidationFailed");
+
+        let element = get_audio_elements(&conn, 1, None)
+            .unwrap()
+            .into_iter()
+            .find(|element| element.id == 10)
+            .unwrap();
+        assert_eq!(element.notes, "Use sparingly");
+        assert_eq!(element.rating, Some(4));
+    }
+}
@@ -0,0 +1,392 @@
+//! Tempo maps: the musical grid laid over a timeline's milliseconds.
+//!
+//! Tempo changes start on a bar, so bar lines never fall between two tempos.
+//! The first change also covers the bars before it, which keeps every position
+//! on the timeline inside the grid.
+
+use rusqlite::{Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use super::collect_rows;
+use crate::{AppError, AppResult};
+
+pub const MIN_BPM: f64 = 20.0;
+pub const MAX_BPM: f64 = 400.0;
+pub const MAX_BEATS_PER_BAR: i64 = 32;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TempoChange {
+    pub id: i64,
+    pub timeline_id: i64,
+    /// 1-based bar the change takes effect on.
+    pub bar: i64,
+    /// Beats of `beat_unit` per minute.
+    pub bpm: f64,
+    pub beats_per_bar: i64,
+    /// Note value of one beat: 4 for quarter notes, 8 for eighths.
+    pub beat_unit: i64,
+}
+
+/// A position on the grid. Both parts are 1-based; `beat` keeps the fraction
+/// of the beat the position lies in.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MusicalPosition {
+    pub bar: i64,
+    pub beat: f64,
+}
+
+/// Grid lines that element edges snap to.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapMode {
+    Bar,
+    Beat,
+    HalfBeat,
+    QuarterBeat,
+}
+
+impl SnapMode {
+    fn beats_per_step(self, beats_per_bar: i64) -> f64 {
+        match self {
+            SnapMode::Bar => beats_per_bar as f64,
+            SnapMode::Beat => 1.0,
+            SnapMode::HalfBeat => 0.5,
+            SnapMode::QuarterBeat => 0.25,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct TempoSegment {
+    start_ms: f64,
+    start_bar: i64,
+    beat_ms: f64,
+    beats_per_bar: i64,
+}
+
+impl TempoSegment {
+    fn bar_ms(&self) -> f64 {
+        self.beat_ms * self.beats_per_bar as f64
+    }
+}
+
+/// A timeline's tempo changes resolved into millisecond segments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoMap {
+    segments: Vec<TempoSegment>,
+}
+
+impl TempoMap {
+    /// Builds the map from changes sorted by bar, or `None` without any.
+    pub fn new(changes: &[TempoChange]) -> Option<Self> {
+        let mut segments: Vec<TempoSegment> = Vec::with_capacity(changes.len());
+        for change in changes {
+            let start_ms = match segments.last() {
+                Some(previous) => {
+                    previous.start_ms + (change.bar - previous.start_bar) as f64 * previous.bar_ms()
+                }
+                None => 0.0,
+            };
+            segments.push(TempoSegment {
+                start_ms,
+                start_bar: if segments.is_empty() { 1 } else { change.bar },
+                beat_ms: 60_000.0 / change.bpm,
+                beats_per_bar: change.beats_per_bar,
+            });
+        }
+
+        if segments.is_empty() {
+            None
+        } else {
+            Some(TempoMap { segments })
+        }
+    }
+
+    fn segment_at_ms(&self, position_ms: f64) -> &TempoSegment {
+        self.segments
+            .iter()
+            .rev()
+            .find(|segment| segment.start_ms <= position_ms)
+            .unwrap_or(&self.segments[0])
+    }
+
+    fn segment_end_ms(&self, segment: &TempoSegment) -> Option<f64> {
+        self.segments
+            .iter()
+            .find(|next| next.start_bar > segment.start_bar)
+            .map(|next| next.start_ms)
+    }
+
+    pub fn to_musical(&self, position_ms: f64) -> MusicalPosition {
+        let segment = self.segment_at_ms(position_ms);
+        let beats = (position_ms - segment.start_ms) / segment.beat_ms;
+        let whole_bars = (beats / segment.beats_per_bar as f64).floor();
+
+        MusicalPosition {
+            bar: segment.start_bar + whole_bars as i64,
+            beat: beats - whole_bars * segment.beats_per_bar as f64 + 1.0,
+        }
+    }
+
+    pub fn to_ms(&self, position: MusicalPosition) -> AppResult<f64> {
+        let segment = self
+            .segments
+            .iter()
+            .rev()
+            .find(|segment| segment.start_bar <= position.bar)
+            .ok_or_else(|| AppError::ValidationFailed("Bars are numbered from 1".into()))?;
+        if position.beat < 1.0 || position.beat >= (segment.beats_per_bar + 1) as f64 {
+            return Err(AppError::ValidationFailed(format!(
+                "Bar {} has beats 1 to {}, got {}",
+                position.bar, segment.beats_per_bar, position.beat
+            )));
+        }
+
+        let beats = (position.bar - segment.start_bar) as f64 * segment.beats_per_bar as f64
+            + position.beat
+            - 1.0;
+        Ok(segment.start_ms + beats * segment.beat_ms)
+    }
+
+    /// Nearest grid line to `position_ms`.
+    pub fn snap(&self, position_ms: f64, mode: SnapMode) -> f64 {
+        let segment = self.segment_at_ms(position_ms);
+        let step_ms = segment.beat_ms * mode.beats_per_step(segment.beats_per_bar);
+        let snapped =
+            segment.start_ms + ((position_ms - segment.start_ms) / step_ms).round() * step_ms;
+
+        // The next segment starts on a bar, which is a line of every grid.
+        match self.segment_end_ms(segment) {
+            Some(end_ms) if snapped > end_ms => end_ms,
+            _ => snapped.max(0.0),
+        }
+    }
+
+    /// First bar line at or after `position_ms`.
+    pub fn next_bar_ms(&self, position_ms: f64) -> f64 {
+        let segment = self.segment_at_ms(position_ms);
+        let bar_ms = segment.bar_ms();
+        let next = segment.start_ms + ((position_ms - segment.start_ms) / bar_ms).ceil() * bar_ms;
+
+        match self.segment_end_ms(segment) {
+            Some(end_ms) if next > end_ms => end_ms,
+            _ => next,
+        }
+    }
+
+    /// Snaps both edges of a span, keeping it at least one grid step long.
+    pub fn snap_span(&self, start_ms: i64, duration_ms: i64, mode: SnapMode) -> (i64, i64) {
+        let start = self.snap(start_ms as f64, mode);
+        let mut end = self.snap((start_ms + duration_ms) as f64, mode);
+        if end <= start {
+            let segment = self.segment_at_ms(start);
+            end = start + segment.beat_ms * mode.beats_per_step(segment.beats_per_bar);
+        }
+
+        let start = start.round() as i64;
+        (start, end.round() as i64 - start)
+    }
+}
+
+fn map_tempo_change(row: &Row<'_>) -> rusqlite::Result<TempoChange> {
+    Ok(TempoChange {
+        id: row.get(0)?,
+        timeline_id: row.get(1)?,
+        bar: row.get(2)?,
+        bpm: row.get(3)?,
+        beats_per_bar: row.get(4)?,
+        beat_unit: row.get(5)?,
+    })
+}
+
+fn validate_tempo_change(bar: i64, bpm: f64, beats_per_bar: i64, beat_unit: i64) -> AppResult<()> {
+    if bar < 1 {
+        return Err(AppError::ValidationFailed(
+            "Bars are numbered from 1".into(),
+        ));
+    }
+    if !(MIN_BPM..=MAX_BPM).contains(&bpm) {
+        return Err(AppError::ValidationFailed(format!(
+            "Tempo must be between {} and {} BPM, got {}",
+            MIN_BPM, MAX_BPM, bpm
+        )));
+    }
+    if !(1..=MAX_BEATS_PER_BAR).contains(&beats_per_bar) {
+        return Err(AppError::ValidationFailed(format!(
+            "A bar must have between 1 and {} beats, got {}",
+            MAX_BEATS_PER_BAR, beats_per_bar
+        )));
+    }
+    if ![1, 2, 4, 8, 16, 32].contains(&beat_unit) {
+        return Err(AppError::ValidationFailed(format!(
+            "Beat unit must be a power of two up to 32, got {}",
+            beat_unit
+        )));
+    }
+    Ok(())
+}
+
+pub fn get_tempo_changes(conn: &Connection, timeline_id: i64) -> AppResult<Vec<TempoChange>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, timeline_id, bar, bpm, beats_per_bar, beat_unit FROM timeline_tempo_changes
+         WHERE timeline_id = ?1 ORDER BY bar ASC",
+    )?;
+
+    collect_rows(&mut stmt, [timeline_id], map_tempo_change)
+}
+
+/// The timeline's grid, or `None` when it has no tempo.
+pub fn load_tempo_map(conn: &Connection, timeline_id: i64) -> AppResult<Option<TempoMap>> {
+    Ok(TempoMap::new(&get_tempo_changes(conn, timeline_id)?))
+}
+
+/// Like [`load_tempo_map`], but a missing tempo is an error.
+pub fn require_tempo_map(conn: &Connection, timeline_id: i64) -> AppResult<TempoMap> {
+    load_tempo_map(conn, timeline_id)?
+        .ok_or_else(|| AppError::ValidationFailed(format!("Timeline {} has no tempo", timeline_id)))
+}
+
+/// Sets the tempo and time signature from `bar` on, replacing any change
+/// already starting there.
+pub fn set_tempo_change(
+    conn: &Connection,
+    timeline_id: i64,
+    bar: i64,
+    bpm: f64,
+    beats_per_bar: i64,
+    beat_unit: i64,
+) -> AppResult<TempoChange> {
+    validate_tempo_change(bar, bpm, beats_per_bar, beat_unit)?;
+
+    conn.query_row(
+        "SELECT id FROM timelines WHERE id = ?1",
+        [timeline_id],
+        |row| row.get::<_, i64>(0),
+    )
+    .optional()?
+    .ok_or_else(|| AppError::not_found("Timeline", timeline_id))?;
+
+    let id = conn.query_row(
+        "INSERT INTO timeline_tempo_changes (timeline_id, bar, bpm, beats_per_bar, beat_unit)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT (timeline_id, bar) DO UPDATE SET
+             bpm = excluded.bpm,
+             beats_per_bar = excluded.beats_per_bar,
+             beat_unit = excluded.beat_unit
+         RETURNING id",
+        rusqlite::params![timeline_id, bar, bpm, beats_per_bar, beat_unit],
+        |row| row.get(0),
+    )?;
+
+    Ok(TempoChange {
+        id,
+        timeline_id,
+        bar,
+        bpm,
+        beats_per_bar,
+        beat_unit,
+    })
+}
+
+pub fn delete_tempo_change(conn: &Connection, id: i64) -> AppResult<()> {
+    conn.execute("DELETE FROM timeline_tempo_changes WHERE id = ?1", [id])?;
+
+    Ok(())
+}
+
+pub fn ms_to_musical(
+    conn: &Connection,
+    timeline_id: i64,
+    position_ms: i64,
+) -> AppResult<MusicalPosition> {
+    if position_ms < 0 {
+        return Err(AppError::ValidationFailed(
+            "Position must not be negative".into(),
+        ));
+    }
+
+    Ok(require_tempo_map(conn, timeline_id)?.to_musical(position_ms as f64))
+}
+
+pub fn musical_to_ms(
+    conn: &Connection,
+    timeline_id: i64,
+    position: MusicalPosition,
+) -> AppResult<i64> {
+    Ok(require_tempo_map(conn, timeline_id)?
+        .to_ms(position)?
+        .round() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::test_connection;
+
+    fn seeded() -> Connection {
+        let conn = test_connection();
+        conn.execute_batch(
+            "INSERT INTO moods (id, name) VALUES (1, 'Battle');
+             INSERT INTO timelines (id, mood_id, name) VALUES (1, 1, 'T1');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn conversions_follow_tempo_changes() {
+        let conn = seeded();
+        // 120 BPM in 4/4 gives 2000 ms bars; from bar 3, 3/4 at 90 BPM gives 2000 ms bars of 3 beats.
+        set_tempo_change(&conn, 1, 1, 120.0, 4, 4).unwrap();
+        set_tempo_change(&conn, 1, 3, 90.0, 3, 4).unwrap();
+
+        let start_of_bar_3 = ms_to_musical(&conn, 1, 4000).unwrap();
+        assert_eq!(start_of_bar_3, MusicalPosition { bar: 3, beat: 1.0 });
+        let mid_bar_2 = ms_to_musical(&conn, 1, 3250).unwrap();
+        assert_eq!(mid_bar_2, MusicalPosition { bar: 2, beat: 3.5 });
+
+        assert_eq!(
+            musical_to_ms(&conn, 1, MusicalPosition { bar: 4, beat: 3.0 }).unwrap(),
+            7333
+        );
+        let past_the_bar = musical_to_ms(&conn, 1, MusicalPosition { bar: 4, beat: 4.0 });
+        assert_eq!(past_the_bar.unwrap_err().code(), "ValidationFailed");
+
+        let replaced = set_tempo_change(&conn, 1, 3, 60.0, 3, 4).unwrap();
+        assert_eq!(get_tempo_changes(&conn, 1).unwrap().len(), 2);
+        assert_eq!(get_tempo_changes(&conn, 1).unwrap()[1].id, replaced.id);
+    }
+
+    #[test]
+    fn timelines_without_tempo_have_no_grid() {
+        let conn = seeded();
+
+        assert!(load_tempo_map(&conn, 1).unwrap().is_none());
+        let error = ms_to_musical(&conn, 1, 0).unwrap_err();
+        assert_eq!(error.code(), "ValidationFailed");
+
+        let bad_tempo = set_tempo_change(&conn, 1, 1, 5.0, 4, 4).unwrap_err();
+        assert_eq!(bad_tempo.code(), "ValidationFailed");
+        let bad_unit = set_tempo_change(&conn, 1, 1, 120.0, 4, 3).unwrap_err();
+        assert_eq!(bad_unit.code(), "ValidationFailed");
+        let missing = set_tempo_change(&conn, 9, 1, 120.0, 4, 4).unwrap_err();
+        assert_eq!(missing.code(), "NotFound");
+    }
+
+    #[test]
+    fn snapping_lands_on_the_nearest_grid_line() {
+        let conn = seeded();
+        set_tempo_change(&conn, 1, 1, 120.0, 4, 4).unwrap();
+        set_tempo_change(&conn, 1, 2, 60.0, 4, 4).unwrap();
+        let map = load_tempo_map(&conn, 1).unwrap().unwrap();
+
+        assert_eq!(map.snap(740.0, SnapMode::Beat), 500.0);
+        assert_eq!(map.snap(1900.0, SnapMode::Bar), 2000.0);
+        // Bar 2 starts at 2000 ms with 1000 ms beats.
+        assert_eq!(map.snap(2600.0, SnapMode::HalfBeat), 2500.0);
+        assert_eq!(map.next_bar_ms(2001.0), 6000.0);
+
+        assert_eq!(map.snap_span(130, 800, SnapMode::Beat), (0, 1000));
+        assert_eq!(map.snap_span(100, 50, SnapMode::Beat), (0, 500));
+    }
+}
@@ -0,0 +1,431 @@
+//! Edits that touch many timeline elements at once.
+//!
+//! Every batch runs inside a savepoint and checks overlaps only once all of its
+//! edits are applied, so clips moved together may pass through each other on
+//! the way to their final positions.
+
+use std::collections::{BTreeSet, HashSet};
+
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use super::tempo::SnapMode;
+use super::timelines::{ensure_within_source, snap_to_grid, validate_fades};
+use super::trash::TIMELINE_ELEMENT_IS_VISIBLE;
+use super::{collect_rows, in_savepoint};
+use crate::{AppError, AppResult};
+
+/// One change to an element within a batch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ElementEdit {
+    /// Moves and resizes an element on its own track.
+    Move {
+        id: i64,
+        start_time_ms: i64,
+        duration_ms: i64,
+    },
+    /// Moves an element onto another track of the same timeline, keeping its length.
+    MoveToTrack {
+        id: i64,
+        track_id: i64,
+        start_time_ms: i64,
+    },
+    Delete {
+        id: i64,
+    },
+}
+
+struct ElementSpan {
+    track_id: i64,
+    timeline_id: i64,
+    audio_element_id: Option<i64>,
+    duration_ms: i64,
+    source_offset_ms: i64,
+    fade_in_ms: i64,
+    fade_out_ms: i64,
+}
+
+fn element_span(conn: &Connection, id: i64) -> AppResult<ElementSpan> {
+    conn.query_row(
+        "SELECT te.track_id, tt.timeline_id, te.audio_element_id, te.duration_ms, te.source_offset_ms,
+                te.fade_in_ms, te.fade_out_ms
+         FROM timeline_elements te JOIN timeline_tracks tt ON tt.id = te.track_id
+         WHERE te.id = ?1",
+        [id],
+        |row| {
+            Ok(ElementSpan {
+                track_id: row.get(0)?,
+                timeline_id: row.get(1)?,
+                audio_element_id: row.get(2)?,
+                duration_ms: row.get(3)?,
+                source_offset_ms: row.get(4)?,
+                fade_in_ms: row.get(5)?,
+                fade_out_ms: row.get(6)?,
+            })
+        },
+    )
+    .optional()?
+    .ok_or_else(|| AppError::not_found("Timeline element", id))
+}
+
+fn track_timeline_id(conn: &Connection, track_id: i64) -> AppResult<i64> {
+    conn.query_row(
+        "SELECT timeline_id FROM timeline_tracks WHERE id = ?1",
+        [track_id],
+        |row| row.get(0),
+    )
+    .optional()?
+    .ok_or_else(|| AppError::not_found("Timeline track", track_id))
+}
+
+fn ensure_in_timeline(what: &str, id: i64, actual: i64, timeline_id: i64) -> AppResult<()> {
+    if actual != timeline_id {
+        return Err(AppError::ValidationFailed(format!(
+            "{} {} belongs to another timeline",
+            what, id
+        )));
+    }
+    Ok(())
+}
+
+fn validate_span(start_time_ms: i64, duration_ms: i64) -> AppResult<()> {
+    if start_time_ms < 0 || duration_ms <= 0 {
+        return Err(AppError::ValidationFailed(format!(
+            "An element needs a start of at least 0 ms and a positive duration, got {} ms for {} ms",
+            start_time_ms, duration_ms
+        )));
+    }
+    Ok(())
+}
+
+/// Fails with `AppError::Overlap` on the first two visible elements of the
+/// track that collide, naming the one the batch left in place when it can.
+fn ensure_track_has_no_overlaps(
+    conn: &Connection,
+    track_id: i64,
+    edited_ids: &HashSet<i64>,
+) -> AppResult<()> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT te.id, te.start_time_ms, te.start_time_ms + te.duration_ms
+         FROM timeline_elements te
+         WHERE te.track_id = ?1 AND {}
+         ORDER BY te.start_time_ms ASC, te.id ASC",
+        TIMELINE_ELEMENT_IS_VISIBLE
+    ))?;
+    let spans: Vec<(i64, i64, i64)> = collect_rows(&mut stmt, [track_id], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    })?;
+
+    // The element reaching furthest so far is the one a later start can collide with.
+    let mut furthest: Option<(i64, i64)> = None;
+    for (id, start_ms, end_ms) in spans {
+        if let Some((furthest_id, furthest_end_ms)) = furthest {
+            if start_ms < furthest_end_ms {
+                let conflicting_element_id = if edited_ids.contains(&furthest_id) {
+                    id
+                } else {
+                    furthest_id
+                };
+                return Err(AppError::Overlap {
+                    track_id,
+                    conflicting_element_id,
+                });
+            }
+        }
+        if furthest.is_none_or(|(_, furthest_end_ms)| end_ms > furthest_end_ms) {
+            furthest = Some((id, end_ms));
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies `edits` to elements of `timeline_id` in order, all or nothing.
+/// With a `snap` mode moved elements land on the timeline's grid.
+pub fn apply_element_edits(
+    conn: &Connection,
+    timeline_id: i64,
+    edits: &[ElementEdit],
+    snap: Option<SnapMode>,
+) -> AppResult<()> {
+    in_savepoint(conn, |conn| {
+        let mut touched_tracks = BTreeSet::new();
+        let mut edited_ids = HashSet::new();
+
+        for edit in edits {
+            match *edit {
+                ElementEdit::Move {
+                    id,
+                    start_time_ms,
+                    duration_ms,
+                } => {
+                    let element = element_span(conn, id)?;
+                    ensure_in_timeline("Element", id, element.timeline_id, timeline_id)?;
+                    let (start_time_ms, duration_ms) =
+                        snap_to_grid(conn, timeline_id, start_time_ms, duration_ms, snap)?;
+                    validate_span(start_time_ms, duration_ms)?;
+                    validate_fades(
+                        duration_ms,
+                        element.source_offset_ms,
+                        element.fade_in_ms,
+                        element.fade_out_ms,
+                    )?;
+                    ensure_within_source(
+                        conn,
+                        element.audio_element_id,
+                        element.source_offset_ms,
+                        duration_ms,
+                    )?;
+
+                    conn.execute(
+                        "UPDATE timeline_elements SET start_time_ms = ?1, duration_ms = ?2 WHERE id = ?3",
+                        (&start_time_ms, &duration_ms, &id),
+                    )?;
+                    touched_tracks.insert(element.track_id);
+                    edited_ids.insert(id);
+                }
+                ElementEdit::MoveToTrack {
+                    id,
+                    track_id,
+                    start_time_ms,
+                } => {
+                    let element = element_span(conn, id)?;
+                    ensure_in_timeline("Element", id, element.timeline_id, timeline_id)?;
+                    ensure_in_timeline(
+                        "Track",
+                        track_id,
+                        track_timeline_id(conn, track_id)?,
+                        timeline_id,
+                    )?;
+                    // Only the start snaps; the element keeps its length.
+                    let (start_time_ms, _) =
+                        snap_to_grid(conn, timeline_id, start_time_ms, element.duration_ms, snap)?;
+                    validate_span(start_time_ms, element.duration_ms)?;
+
+                    conn.execute(
+                        "UPDATE timeline_elements
+                         SET track_id = ?1, timeline_id = ?2, start_time_ms = ?3
+                         WHERE id = ?4",
+                        rusqlite::params![track_id, timeline_id, start_time_ms, id],
+                    )?;
+                    touched_tracks.insert(track_id);
+                    edited_ids.insert(id);
+                }
+                ElementEdit::Delete { id } => {
+                    let element = element_span(conn, id)?;
+                    ensure_in_timeline("Element", id, element.timeline_id, timeline_id)?;
+                    conn.execute("DELETE FROM timeline_elements WHERE id = ?1", [id])?;
+                }
+            }
+        }
+
+        for track_id in touched_tracks {
+            ensure_track_has_no_overlaps(conn, track_id, &edited_ids)?;
+        }
+
+        Ok(())
+    })
+}
+
+/// Moves a single element onto another track of its timeline.
+pub fn move_element_to_track(
+    conn: &Connection,
+    id: i64,
+    track_id: i64,
+    start_time_ms: i64,
+    snap: Option<SnapMode>,
+) -> AppResult<()> {
+    let timeline_id = element_span(conn, id)?.timeline_id;
+
+    apply_element_edits(
+        conn,
+        timeline_id,
+        &[ElementEdit::MoveToTrack {
+            id,
+            track_id,
+            start_time_ms,
+        }],
+        snap,
+    )
+}
+
+/// Shifts every element starting at or after `from_ms` by `delta_ms`, on one
+/// track or, without `track_id`, across the whole timeline. Returns how many
+/// elements moved.
+pub fn ripple_move(
+    conn: &Connection,
+    timeline_id: i64,
+    track_id: Option<i64>,
+    from_ms: i64,
+    delta_ms: i64,
+) -> AppResult<usize> {
+    if let Some(track_id) = track_id {
+        ensure_in_timeline(
+            "Track",
+            track_id,
+            track_timeline_id(conn, track_id)?,
+            timeline_id,
+        )?;
+    }
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT te.id, te.start_time_ms, te.duration_ms
+         FROM timeline_elements te JOIN timeline_tracks tt ON tt.id = te.track_id
+         WHERE tt.timeline_id = ?1 AND (?2 IS NULL OR te.track_id = ?2) AND te.start_time_ms >= ?3
+           AND {}
+         ORDER BY te.start_time_ms ASC",
+        TIMELINE_ELEMENT_IS_VISIBLE
+    ))?;
+    let edits = collect_rows(
+        &mut stmt,
+        rusqlite::params![timeline_id, track_id, from_ms],
+        |row| {
+            Ok(ElementEdit::Move {
+                id: row.get(0)?,
+                start_time_ms: row.get::<_, i64>(1)? + delta_ms,
+                duration_ms: row.get(2)?,
+            })
+        },
+    )?;
+
+    apply_element_edits(conn, timeline_id, &edits, None)?;
+
+    Ok(edits.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::test_connection;
+    use crate::store::timelines::get_track_elements;
+
+    /// Timeline 1 has track 5 (elements 10 at 0-1000, 11 at 1000-2000, 12 at
+    /// 4000-5000) and track 6 (element 20 at 0-3000). Track 7 is on timeline 2.
+    fn seeded() -> Connection {
+        let conn = test_connection();
+        conn.execute_batch(
+            "INSERT INTO audio_elements (id, file_path, file_name) VALUES (100, 'p', 'a.ogg');
+             INSERT INTO moods (id, name) VALUES (1, 'A'), (2, 'B');
+             INSERT INTO timelines (id, mood_id, name) VALUES (1, 1, 'T1'), (2, 2, 'T2');
+             INSERT INTO timeline_tracks (id, timeline_id, name) VALUES (5, 1, 'A'), (6, 1, 'B'), (7, 2, 'C');
+             INSERT INTO timeline_elements (id, timeline_id, track_id, audio_element_id, start_time_ms, duration_ms) VALUES
+                 (10, 1, 5, 100, 0, 1000), (11, 1, 5, 100, 1000, 1000), (12, 1, 5, 100, 4000, 1000),
+                 (20, 1, 6, 100, 0, 3000);",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn starts(conn: &Connection, track_id: i64) -> Vec<(i64, i64)> {
+        get_track_elements(conn, track_id)
+            .unwrap()
+            .iter()
+            .map(|element| (element.id, element.start_time_ms))
+            .collect()
+    }
+
+    #[test]
+    fn moving_a_selection_checks_only_the_final_state() {
+        let conn = seeded();
+        // Element 10 passes through 11's old span on its way.
+        let edits = [
+            ElementEdit::Move {
+                id: 10,
+                start_time_ms: 1000,
+                duration_ms: 1000,
+            },
+            ElementEdit::Move {
+                id: 11,
+                start_time_ms: 2000,
+                duration_ms: 1000,
+            },
+        ];
+        apply_element_edits(&conn, 1, &edits, None).unwrap();
+
+        assert_eq!(starts(&conn, 5), vec![(10, 1000), (11, 2000), (12, 4000)]);
+    }
+
+    #[test]
+    fn a_failing_batch_changes_nothing() {
+        let conn = seeded();
+        let edits = [
+            ElementEdit::Delete { id: 12 },
+            ElementEdit::MoveToTrack {
+                id: 11,
+                track_id: 6,
+                start_time_ms: 2500,
+            },
+        ];
+        let error = apply_element_edits(&conn, 1, &edits, None).unwrap_err();
+        assert_eq!(error.code(), "Overlap");
+        assert_eq!(error.details()["conflicting_element_id"], 20);
+
+        assert_eq!(starts(&conn, 5), vec![(10, 0), (11, 1000), (12, 4000)]);
+        assert_eq!(starts(&conn, 6), vec![(20, 0)]);
+    }
+
+    #[test]
+    fn elements_move_only_within_their_timeline() {
+        let conn = seeded();
+
+        let other_timeline = move_element_to_track(&conn, 10, 7, 0, None).unwrap_err();
+        assert_eq!(other_timeline.code(), "ValidationFailed");
+
+        move_element_to_track(&conn, 12, 6, 3000, None).unwrap();
+        assert_eq!(starts(&conn, 6), vec![(20, 0), (12, 3000)]);
+        assert_eq!(starts(&conn, 5), vec![(10, 0), (11, 1000)]);
+    }
+
+    #[test]
+    fn snapped_moves_to_another_track_keep_the_length() {
+        let conn = seeded();
+        conn.execute(
+            "UPDATE timeline_elements SET duration_ms = 730 WHERE id = 12",
+            [],
+        )
+        .unwrap();
+        // 120 BPM in 4/4: 500 ms beats.
+        crate::store::tempo::set_tempo_change(&conn, 1, 1, 120.0, 4, 4).unwrap();
+
+        move_element_to_track(&conn, 12, 6, 3120, Some(SnapMode::Beat)).unwrap();
+
+        let moved = &get_track_elements(&conn, 6).unwrap()[1];
+        assert_eq!(
+            (moved.id, moved.start_time_ms, moved.duration_ms),
+            (12, 3000, 730)
+        );
+    }
+
+    #[test]
+    fn ripple_shifts_everything_after_the_point() {
+        let conn = seeded();
+
+        assert_eq!(ripple_move(&conn, 1, Some(5), 1000, 500).unwrap(), 2);
+        assert_eq!(starts(&conn, 5), vec![(10, 0), (11, 1500), (12, 4500)]);
+
+        let into_earlier = ripple_move(&conn, 1, Some(5), 1500, -1000).unwrap_err();
+        assert_eq!(into_earlier.code(), "Overlap");
+        let before_zero = ripple_move(&conn, 1, None, 0, -100).unwrap_err();
+        assert_eq!(before_zero.code(), "ValidationFailed");
+
+        // Elements whose audio is in the trash stay where they are.
+        conn.execute_batch(
+            "INSERT INTO audio_elements (id, file_path, file_name, deleted_at)
+                 VALUES (101, 'p', 'b.ogg', CURRENT_TIMESTAMP);
+             INSERT INTO timeline_elements (id, timeline_id, track_id, audio_element_id, start_time_ms, duration_ms)
+                 VALUES (21, 1, 6, 101, 8000, 1000);",
+        )
+        .unwrap();
+        assert_eq!(ripple_move(&conn, 1, None, 0, 1000).unwrap(), 4);
+        assert_eq!(starts(&conn, 6), vec![(20, 1000)]);
+        let hidden_start: i64 = conn
+            .query_row(
+                "SELECT start_time_ms FROM timeline_elements WHERE id = 21",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(hidden_start, 8000);
+    }
+}
@@ -0,0 +1,566 @@
+//! Reusing timelines: duplicating moods, copying tracks between moods and
+//! saving timelines as templates.
+//!
+//! All three go through a [`TimelineBlueprint`], the content of a timeline
+//! without any of its row ids. Elements whose source has since been deleted for
+//! good are dropped when a blueprint is built into a timeline.
+
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use super::automation::{get_track_gain_points, AutomationCurve};
+use super::markers::get_timeline_markers;
+use super::moods::Mood;
+use super::tempo::get_tempo_changes;
+use super::timelines::{get_timeline_tracks, FadeCurve, Timeline, TimelineTrack};
+use super::{collect_rows, in_savepoint};
+use crate::{AppError, AppResult};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ElementBlueprint {
+    pub audio_element_id: Option<i64>,
+    pub element_group_id: Option<i64>,
+    pub start_time_ms: i64,
+    pub duration_ms: i64,
+    pub source_offset_ms: i64,
+    pub fade_in_ms: i64,
+    pub fade_out_ms: i64,
+    pub fade_curve: FadeCurve,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GainPointBlueprint {
+    pub time_ms: i64,
+    pub gain_db: f64,
+    pub curve: AutomationCurve,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackBlueprint {
+    pub name: String,
+    pub is_looping: bool,
+    pub elements: Vec<ElementBlueprint>,
+    #[serde(default)]
+    pub gain_points: Vec<GainPointBlueprint>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MarkerBlueprint {
+    pub name: String,
+    pub position_ms: i64,
+    pub end_ms: Option<i64>,
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TempoBlueprint {
+    pub bar: i64,
+    pub bpm: f64,
+    pub beats_per_bar: i64,
+    pub beat_unit: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimelineBlueprint {
+    pub is_looping: bool,
+    pub tracks: Vec<TrackBlueprint>,
+    #[serde(default)]
+    pub markers: Vec<MarkerBlueprint>,
+    /// Index into `markers` of the region the timeline loops.
+    #[serde(default)]
+    pub loop_marker_index: Option<usize>,
+    #[serde(default)]
+    pub tempo_changes: Vec<TempoBlueprint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineTemplate {
+    pub id: i64,
+    pub name: String,
+    pub track_count: usize,
+    pub created_at: String,
+}
+
+/// Reads every element of the track, including those whose source is in the
+/// trash, so the copy gets them back too when the source is restored.
+fn read_track(conn: &Connection, track: &TimelineTrack) -> AppResult<TrackBlueprint> {
+    let mut stmt = conn.prepare(
+        "SELECT audio_element_id, element_group_id, start_time_ms, duration_ms,
+                source_offset_ms, fade_in_ms, fade_out_ms, fade_curve
+         FROM timeline_elements WHERE track_id = ?1
+         ORDER BY start_time_ms ASC, id ASC",
+    )?;
+    let elements = collect_rows(&mut stmt, [track.id], |row| {
+        Ok(ElementBlueprint {
+            audio_element_id: row.get(0)?,
+            element_group_id: row.get(1)?,
+            start_time_ms: row.get(2)?,
+            duration_ms: row.get(3)?,
+            source_offset_ms: row.get(4)?,
+            fade_in_ms: row.get(5)?,
+            fade_out_ms: row.get(6)?,
+            fade_curve: FadeCurve::from_name(&row.get::<_, String>(7)?),
+        })
+    })?;
+    let gain_points = get_track_gain_points(conn, track.id)?
+        .into_iter()
+        .map(|point| GainPointBlueprint {
+            time_ms: point.time_ms,
+            gain_db: point.gain_db,
+            curve: point.curve,
+        })
+        .collect();
+
+    Ok(TrackBlueprint {
+        name: track.name.clone(),
+        is_looping: track.is_looping,
+        elements,
+        gain_points,
+    })
+}
+
+/// Captures a timeline's tracks, elements, automation, markers and tempo.
+pub fn read_blueprint(conn: &Connection, timeline_id: i64) -> AppResult<TimelineBlueprint> {
+    let (is_looping, loop_marker_id): (bool, Option<i64>) = conn
+        .query_row(
+            "SELECT is_looping, loop_marker_id FROM timelines WHERE id = ?1",
+            [timeline_id],
+            |row| Ok((row.get::<_, i64>(0)? != 0, row.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::not_found("Timeline", timeline_id))?;
+
+    let tracks = get_timeline_tracks(conn, timeline_id)?
+        .iter()
+        .map(|track| read_track(conn, track))
+        .collect::<AppResult<Vec<_>>>()?;
+    let markers = get_timeline_markers(conn, timeline_id)?;
+    let loop_marker_index =
+        loop_marker_id.and_then(|id| markers.iter().position(|marker| marker.id == id));
+    let tempo_changes = get_tempo_changes(conn, timeline_id)?
+        .into_iter()
+        .map(|change| TempoBlueprint {
+            bar: change.bar,
+            bpm: change.bpm,
+            beats_per_bar: change.beats_per_bar,
+            beat_unit: change.beat_unit,
+        })
+        .collect();
+
+    Ok(TimelineBlueprint {
+        is_looping,
+        tracks,
+        markers: markers
+            .into_iter()
+            .map(|marker| MarkerBlueprint {
+                name: marker.name,
+                position_ms: marker.position_ms,
+                end_ms: marker.end_ms,
+                color: marker.color,
+            })
+            .collect(),
+        loop_marker_index,
+        tempo_changes,
+    })
+}
+
+fn source_exists(conn: &Connection, element: &ElementBlueprint) -> AppResult<bool> {
+    let exists = conn.query_row(
+        "SELECT (?1 IS NULL OR EXISTS (SELECT 1 FROM audio_elements WHERE id = ?1))
+            AND (?2 IS NULL OR EXISTS (SELECT 1 FROM element_groups WHERE id = ?2))",
+        rusqlite::params![element.audio_element_id, element.element_group_id],
+        |row| row.get(0),
+    )?;
+
+    Ok(exists)
+}
+
+fn insert_track(
+    conn: &Connection,
+    timeline_id: i64,
+    order_index: i64,
+    track: &TrackBlueprint,
+) -> AppResult<TimelineTrack> {
+    conn.execute(
+        "INSERT INTO timeline_tracks (timeline_id, name, order_index, is_looping) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![timeline_id, track.name, order_index, track.is_looping],
+    )?;
+    let track_id = conn.last_insert_rowid();
+
+    for element in &track.elements {
+        if !source_exists(conn, element)? {
+            continue;
+        }
+        conn.execute(
+            "INSERT INTO timeline_elements (timeline_id, track_id, audio_element_id, element_group_id, start_time_ms, duration_ms, source_offset_ms, fade_in_ms, fade_out_ms, fade_curve)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            rusqlite::params![
+                timeline_id,
+                track_id,
+                element.audio_element_id,
+                element.element_group_id,
+                element.start_time_ms,
+                element.duration_ms,
+                element.source_offset_ms,
+                element.fade_in_ms,
+                element.fade_out_ms,
+                element.fade_curve.as_str()
+            ],
+        )?;
+    }
+    for point in &track.gain_points {
+        conn.execute(
+            "INSERT INTO track_gain_points (track_id, time_ms, gain_db, curve) VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![track_id, point.time_ms, point.gain_db, point.curve.as_str()],
+        )?;
+    }
+
+    Ok(TimelineTrack {
+        id: track_id,
+        timeline_id,
+        name: track.name.clone(),
+        order_index,
+        is_looping: track.is_looping,
+        created_at: chrono::Local::now().to_rfc3339(),
+    })
+}
+
+/// Fails with `NotFound` unless the mood exists and is not in the trash.
+fn ensure_live_mood(conn: &Connection, mood_id: i64) -> AppResult<()> {
+    conn.query_row(
+        "SELECT id FROM moods WHERE id = ?1 AND deleted_at IS NULL",
+        [mood_id],
+        |row| row.get::<_, i64>(0),
+    )
+    .optional()?
+    .ok_or_else(|| AppError::not_found("Mood", mood_id))?;
+
+    Ok(())
+}
+
+fn mood_timeline_id(conn: &Connection, mood_id: i64) -> AppResult<Option<i64>> {
+    let timeline_id = conn
+        .query_row(
+            "SELECT id FROM timelines WHERE mood_id = ?1",
+            [mood_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(timeline_id)
+}
+
+/// Builds `blueprint` into a new timeline for `mood_id`, which must not have
+/// one yet.
+pub fn build_timeline(
+    conn: &Connection,
+    mood_id: i64,
+    name: String,
+    blueprint: &TimelineBlueprint,
+) -> AppResult<Timeline> {
+    if mood_timeline_id(conn, mood_id)?.is_some() {
+        return Err(AppError::ValidationFailed(format!(
+            "Mood {} already has a timeline",
+            mood_id
+        )));
+    }
+
+    conn.execute(
+        "INSERT INTO timelines (mood_id, name, order_index, is_looping) VALUES (?1, ?2, 0, ?3)",
+        rusqlite::params![mood_id, name, blueprint.is_looping],
+    )?;
+    let timeline_id = conn.last_insert_rowid();
+
+    for (order_index, track) in blueprint.tracks.iter().enumerate() {
+        insert_track(conn, timeline_id, order_index as i64, track)?;
+    }
+
+    let mut loop_marker_id = None;
+    for (index, marker) in blueprint.markers.iter().enumerate() {
+        conn.execute(
+            "INSERT INTO timeline_markers (timeline_id, name, position_ms, end_ms, color) VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![timeline_id, marker.name, marker.position_ms, marker.end_ms, marker.color],
+        )?;
+        if blueprint.loop_marker_index == Some(index) {
+            loop_marker_id = Some(conn.last_insert_rowid());
+        }
+    }
+    conn.execute(
+        "UPDATE timelines SET loop_marker_id = ?1 WHERE id = ?2",
+        rusqlite::params![loop_marker_id, timeline_id],
+    )?;
+
+    for change in &blueprint.tempo_changes {
+        conn.execute(
+            "INSERT INTO timeline_tempo_changes (timeline_id, bar, bpm, beats_per_bar, beat_unit) VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![timeline_id, change.bar, change.bpm, change.beats_per_bar, change.beat_unit],
+        )?;
+    }
+
+    Ok(Timeline {
+        id: timeline_id,
+        mood_id,
+        name,
+        order_index: 0,
+        is_looping: blueprint.is_looping,
+        created_at: chrono::Local::now().to_rfc3339(),
+        loop_marker_id,
+    })
+}
+
+/// Creates a copy of the mood named `name`, with a copy of its timeline.
+pub fn duplicate_mood(conn: &Connection, mood_id: i64, name: String) -> AppResult<Mood> {
+    let description: Option<String> = conn
+        .query_row(
+            "SELECT description FROM moods WHERE id = ?1 AND deleted_at IS NULL",
+            [mood_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::not_found("Mood", mood_id))?;
+
+    in_savepoint(conn, |conn| {
+        let mood = super::moods::create_mood(conn, name, description.unwrap_or_default())?;
+        let timeline = conn
+            .query_row(
+                "SELECT id, name FROM timelines WHERE mood_id = ?1",
+                [mood_id],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)),
+            )
+            .optional()?;
+        if let Some((timeline_id, timeline_name)) = timeline {
+            let blueprint = read_blueprint(conn, timeline_id)?;
+            build_timeline(conn, mood.id, timeline_name, &blueprint)?;
+        }
+
+        Ok(mood)
+    })
+}
+
+/// Appends a copy of the track, elements and automation included, to the
+/// timeline of `mood_id`, creating that timeline when the mood has none.
+pub fn copy_track_to_mood(
+    conn: &Connection,
+    track_id: i64,
+    mood_id: i64,
+) -> AppResult<TimelineTrack> {
+    let track = conn
+        .query_row(
+            "SELECT id, timeline_id, name, order_index, is_looping, created_at FROM timeline_tracks WHERE id = ?1",
+            [track_id],
+            |row| {
+                Ok(TimelineTrack {
+                    id: row.get(0)?,
+                    timeline_id: row.get(1)?,
+                    name: row.get(2)?,
+                    order_index: row.get(3)?,
+                    is_looping: row.get::<_, i64>(4)? != 0,
+                    created_at: row.get(5)?,
+                })
+            },
+        )
+        .optional()?
+        .ok_or_else(|| AppError::not_found("Timeline track", track_id))?;
+    ensure_live_mood(conn, mood_id)?;
+
+    let blueprint = read_track(conn, &track)?;
+    let timeline_id = match mood_timeline_id(conn, mood_id)? {
+        Some(timeline_id) => timeline_id,
+        None => {
+            let empty = TimelineBlueprint {
+                is_looping: false,
+                tracks: Vec::new(),
+                markers: Vec::new(),
+                loop_marker_index: None,
+                tempo_changes: Vec::new(),
+            };
+            build_timeline(conn, mood_id, "Main Timeline".into(), &empty)?.id
+        }
+    };
+    let order_index: i64 = conn.query_row(
+        "SELECT COALESCE(MAX(order_index) + 1, 0) FROM timeline_tracks WHERE timeline_id = ?1",
+        [timeline_id],
+        |row| row.get(0),
+    )?;
+
+    insert_track(conn, timeline_id, order_index, &blueprint)
+}
+
+pub fn get_timeline_templates(conn: &Connection) -> AppResult<Vec<TimelineTemplate>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, blueprint, created_at FROM timeline_templates ORDER BY name ASC, id ASC",
+    )?;
+    let rows: Vec<(i64, String, String, String)> = collect_rows(&mut stmt, [], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+    })?;
+
+    rows.into_iter()
+        .map(|(id, name, blueprint, created_at)| {
+            Ok(TimelineTemplate {
+                id,
+                name,
+                track_count: parse_blueprint(&blueprint)?.tracks.len(),
+                created_at,
+            })
+        })
+        .collect()
+}
+
+fn parse_blueprint(json: &str) -> AppResult<TimelineBlueprint> {
+    serde_json::from_str(json)
+        .map_err(|e| AppError::Internal(format!("Unreadable timeline template: {}", e)))
+}
+
+/// Saves the timeline's current content as a template named `name`.
+pub fn save_timeline_template(
+    conn: &Connection,
+    timeline_id: i64,
+    name: String,
+) -> AppResult<TimelineTemplate> {
+    if name.trim().is_empty() {
+        return Err(AppError::ValidationFailed(
+            "Template name must not be empty".into(),
+        ));
+    }
+
+    let blueprint = read_blueprint(conn, timeline_id)?;
+    let json = serde_json::to_string(&blueprint).map_err(|e| AppError::Internal(e.to_string()))?;
+    conn.execute(
+        "INSERT INTO timeline_templates (name, blueprint) VALUES (?1, ?2)",
+        (&name, &json),
+    )?;
+
+    Ok(TimelineTemplate {
+        id: conn.last_insert_rowid(),
+        name,
+        track_count: blueprint.tracks.len(),
+        created_at: chrono::Local::now().to_rfc3339(),
+    })
+}
+
+pub fn delete_timeline_template(conn: &Connection, id: i64) -> AppResult<()> {
+    conn.execute("DELETE FROM timeline_templates WHERE id = ?1", [id])?;
+
+    Ok(())
+}
+
+/// Gives `mood_id`, which must not have a timeline yet, one built from the template.
+pub fn instantiate_timeline_template(
+    conn: &Connection,
+    template_id: i64,
+    mood_id: i64,
+) -> AppResult<Timeline> {
+    let (name, json): (String, String) = conn
+        .query_row(
+            "SELECT name, blueprint FROM timeline_templates WHERE id = ?1",
+            [template_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::not_found("Timeline template", template_id))?;
+    ensure_live_mood(conn, mood_id)?;
+
+    build_timeline(conn, mood_id, name, &parse_blueprint(&json)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::audio_elements::delete_audio_element;
+    use crate::store::moods::delete_mood;
+    use crate::store::test_connection;
+
+    /// Mood 1's timeline 1 has track 10 with two elements and a gain point,
+    /// a looped region and a tempo. Mood 2 has no timeline.
+    fn seeded() -> Connection {
+        let conn = test_connection();
+        conn.execute_batch(
+            "INSERT INTO audio_elements (id, file_path, file_name) VALUES (100, 'p', 'a.ogg'), (101, 'q', 'b.ogg');
+             INSERT INTO moods (id, name, description) VALUES (1, 'Tavern', 'Warm'), (2, 'Road', '');
+             INSERT INTO timelines (id, mood_id, name, is_looping) VALUES (1, 1, 'Main', 1);
+             INSERT INTO timeline_tracks (id, timeline_id, name, order_index) VALUES (10, 1, 'Music', 0);
+             INSERT INTO timeline_elements (timeline_id, track_id, audio_element_id, start_time_ms, duration_ms, fade_in_ms, fade_curve)
+                 VALUES (1, 10, 100, 0, 4000, 500, 'equal_power'), (1, 10, 101, 4000, 2000, 0, 'linear');
+             INSERT INTO track_gain_points (track_id, time_ms, gain_db) VALUES (10, 1000, -6.0);
+             INSERT INTO timeline_markers (id, timeline_id, name, position_ms, end_ms) VALUES (7, 1, 'Verse', 0, 4000);
+             UPDATE timelines SET loop_marker_id = 7 WHERE id = 1;
+             INSERT INTO timeline_tempo_changes (timeline_id, bar, bpm) VALUES (1, 1, 120.0);",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn timeline_of(conn: &Connection, mood_id: i64) -> i64 {
+        mood_timeline_id(conn, mood_id).unwrap().unwrap()
+    }
+
+    #[test]
+    fn duplicating_a_mood_copies_its_whole_timeline() {
+        let conn = seeded();
+
+        let copy = duplicate_mood(&conn, 1, "Tavern (copy)".into()).unwrap();
+        assert_eq!(copy.description, "Warm");
+
+        let copied = timeline_of(&conn, copy.id);
+        assert_ne!(copied, 1);
+        assert_eq!(
+            read_blueprint(&conn, copied).unwrap(),
+            read_blueprint(&conn, 1).unwrap()
+        );
+
+        // Elements whose source is in the trash are copied and return with it.
+        delete_audio_element(&conn, 101).unwrap();
+        let with_trashed = duplicate_mood(&conn, 1, "Tavern (trashed)".into()).unwrap();
+        let copied = read_blueprint(&conn, timeline_of(&conn, with_trashed.id)).unwrap();
+        assert_eq!(copied.tracks[0].elements.len(), 2);
+
+        let missing = duplicate_mood(&conn, 9, "Nope".into()).unwrap_err();
+        assert_eq!(missing.code(), "NotFound");
+    }
+
+    #[test]
+    fn copying_a_track_creates_the_target_timeline() {
+        let conn = seeded();
+
+        let track = copy_track_to_mood(&conn, 10, 2).unwrap();
+        assert_eq!(track.timeline_id, timeline_of(&conn, 2));
+        assert_eq!(track.order_index, 0);
+        let copied = read_blueprint(&conn, track.timeline_id).unwrap();
+        assert_eq!(copied.tracks.len(), 1);
+        assert_eq!(copied.tracks[0].elements.len(), 2);
+        assert_eq!(copied.tracks[0].gain_points.len(), 1);
+        assert!(copied.markers.is_empty());
+
+        let again = copy_track_to_mood(&conn, 10, 2).unwrap();
+        assert_eq!(again.order_index, 1);
+
+        delete_mood(&conn, 2).unwrap();
+        let trashed = copy_track_to_mood(&conn, 10, 2).unwrap_err();
+        assert_eq!(trashed.code(), "NotFound");
+    }
+
+    #[test]
+    fn templates_outlive_their_sources() {
+        let conn = seeded();
+        let template = save_timeline_template(&conn, 1, "Tavern layout".into()).unwrap();
+        assert_eq!(template.track_count, 1);
+
+        conn.execute("DELETE FROM audio_elements WHERE id = 101", [])
+            .unwrap();
+        let timeline = instantiate_timeline_template(&conn, template.id, 2).unwrap();
+        assert!(timeline.is_looping);
+        assert!(timeline.loop_marker_id.is_some());
+        let built = read_blueprint(&conn, timeline.id).unwrap();
+        assert_eq!(built.tracks[0].elements.len(), 1);
+        assert_eq!(built.tempo_changes.len(), 1);
+
+        let taken = instantiate_timeline_template(&conn, template.id, 1).unwrap_err();
+        assert_eq!(taken.code(), "ValidationFailed");
+        conn.execute("INSERT INTO moods (id, name) VALUES (3, 'Cave')", [])
+            .unwrap();
+        delete_mood(&conn, 3).unwrap();
+        let trashed = instantiate_timeline_template(&conn, template.id, 3).unwrap_err();
+        assert_eq!(trashed.code(), "NotFound");
+        assert_eq!(get_timeline_templates(&conn).unwrap().len(), 1);
+    }
+}
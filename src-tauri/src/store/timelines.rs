@@ -0,0 +1,959 @@
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use super::collect_rows;
+use super::tempo::{require_tempo_map, SnapMode};
+use super::trash::{
+    ensure_live_audio_element, ensure_live_element_group, TIMELINE_ELEMENT_IS_VISIBLE,
+};
+use crate::{AppError, AppResult};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Timeline {
+    pub id: i64,
+    pub mood_id: i64,
+    pub name: String,
+    pub order_index: i64,
+    pub is_looping: bool,
+    pub created_at: String,
+    /// Region marker that bounds looping; `None` loops the whole timeline.
+    pub loop_marker_id: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimelineTrack {
+    pub id: i64,
+    pub timeline_id: i64,
+    pub name: String,
+    pub order_index: i64,
+    pub is_looping: bool,
+    pub created_at: String,
+}
+
+/// Shape of a timeline element's fade in and fade out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FadeCurve {
+    #[default]
+    Linear,
+    /// Constant perceived loudness when crossfading two overlapping beds.
+    EqualPower,
+    /// Linear in decibels, from -60 dB up to unity.
+    Exponential,
+}
+
+impl FadeCurve {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            FadeCurve::Linear => "linear",
+            FadeCurve::EqualPower => "equal_power",
+            FadeCurve::Exponential => "exponential",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Self {
+        match name {
+            "equal_power" => FadeCurve::EqualPower,
+            "exponential" => FadeCurve::Exponential,
+            _ => FadeCurve::Linear,
+        }
+    }
+
+    /// Amplitude at `progress` through a fade in, from 0.0 (silent) to 1.0 (unity).
+    /// A fade out uses the same curve with the progress reversed.
+    pub fn gain(self, progress: f64) -> f64 {
+        let progress = progress.clamp(0.0, 1.0);
+        match self {
+            FadeCurve::Linear => progress,
+            FadeCurve::EqualPower => (progress * std::f64::consts::FRAC_PI_2).sin(),
+            FadeCurve::Exponential => {
+                const FLOOR: f64 = 0.001; // -60 dB
+                (FLOOR.powf(1.0 - progress) - FLOOR) / (1.0 - FLOOR)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimelineElement {
+    pub id: i64,
+    pub track_id: i64,
+    pub audio_element_id: Option<i64>,
+    pub element_group_id: Option<i64>,
+    pub start_time_ms: i64,
+    pub duration_ms: i64,
+    pub is_available: bool,
+    /// Where playback starts within the source file.
+    pub source_offset_ms: i64,
+    pub fade_in_ms: i64,
+    pub fade_out_ms: i64,
+    pub fade_curve: FadeCurve,
+}
+
+impl TimelineElement {
+    /// Fade envelope at `position_ms` from the element's start; 1.0 outside the fades.
+    pub fn fade_gain(&self, position_ms: i64) -> f64 {
+        let mut gain = 1.0;
+        if self.fade_in_ms > 0 && position_ms < self.fade_in_ms {
+            gain *= self
+                .fade_curve
+                .gain(position_ms as f64 / self.fade_in_ms as f64);
+        }
+        let remaining_ms = self.duration_ms - position_ms;
+        if self.fade_out_ms > 0 && remaining_ms < self.fade_out_ms {
+            gain *= self
+                .fade_curve
+                .gain(remaining_ms as f64 / self.fade_out_ms as f64);
+        }
+        gain
+    }
+}
+
+/// Fails with `ValidationFailed` unless the trim offset and fades are
+/// non-negative and both fades fit inside `duration_ms` together.
+pub fn validate_fades(
+    duration_ms: i64,
+    source_offset_ms: i64,
+    fade_in_ms: i64,
+    fade_out_ms: i64,
+) -> AppResult<()> {
+    if source_offset_ms < 0 || fade_in_ms < 0 || fade_out_ms < 0 {
+        return Err(AppError::ValidationFailed(
+            "Source offset and fade lengths must not be negative".into(),
+        ));
+    }
+
+    if fade_in_ms + fade_out_ms > duration_ms {
+        return Err(AppError::ValidationFailed(format!(
+            "Fade in ({} ms) and fade out ({} ms) together exceed the element duration of {} ms",
+            fade_in_ms, fade_out_ms, duration_ms
+        )));
+    }
+
+    Ok(())
+}
+
+/// Fails with `ValidationFailed` when reading `duration_ms` of the source from
+/// `source_offset_ms` on would run past the end of the probed file, since a
+/// source plays once per placement. Groups and unprobed sources pass.
+pub fn ensure_within_source(
+    conn: &Connection,
+    audio_element_id: Option<i64>,
+    source_offset_ms: i64,
+    duration_ms: i64,
+) -> AppResult<()> {
+    let source_ms: Option<i64> = match audio_element_id {
+        Some(audio_element_id) => conn
+            .query_row(
+                "SELECT duration_ms FROM audio_elements WHERE id = ?1",
+                [audio_element_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten(),
+        None => None,
+    };
+
+    match source_ms {
+        Some(source_ms) if source_offset_ms + duration_ms > source_ms => {
+            Err(AppError::ValidationFailed(format!(
+                "Playing {} ms from {} ms runs past the end of the {} ms source",
+                duration_ms, source_offset_ms, source_ms
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Returns the mood's timeline, creating it with a default track if it has none.
+pub fn create_timeline(conn: &Connection, mood_id: i64, name: String) -> AppResult<Timeline> {
+    let existing = conn
+        .query_row(
+            "SELECT id, name, order_index, is_looping, created_at, loop_marker_id FROM timelines WHERE mood_id = ?1",
+            [&mood_id],
+            |row| {
+                Ok(Timeline {
+                    id: row.get(0)?,
+                    mood_id,
+                    name: row.get(1)?,
+                    order_index: row.get(2)?,
+                    is_looping: row.get::<_, i64>(3)? != 0,
+                    created_at: row.get(4)?,
+                    loop_marker_id: row.get(5)?,
+                })
+            },
+        )
+        .optional()?;
+
+    if let Some(timeline) = existing {
+        return Ok(timeline);
+    }
+
+    conn.execute(
+        "INSERT INTO timelines (mood_id, name, order_index, is_looping) VALUES (?1, ?2, 0, 0)",
+        (&mood_id, &name),
+    )?;
+
+    let id = conn.last_insert_rowid();
+
+    // Automatically create a default track for new timelines
+    conn.execute(
+        "INSERT INTO timeline_tracks (timeline_id, name, order_index, is_looping) VALUES (?1, 'Track 1', 0, 0)",
+        [&id],
+    )?;
+
+    Ok(Timeline {
+        id,
+        mood_id,
+        name,
+        order_index: 0,
+        is_looping: false,
+        created_at: chrono::Local::now().to_rfc3339(),
+        loop_marker_id: None,
+    })
+}
+
+pub fn get_timelines(conn: &Connection, mood_id: i64) -> AppResult<Vec<Timeline>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, mood_id, name, order_index, is_looping, created_at, loop_marker_id FROM timelines WHERE mood_id = ?1 ORDER BY order_index ASC"
+    )?;
+
+    collect_rows(&mut stmt, [mood_id], |row| {
+        Ok(Timeline {
+            id: row.get(0)?,
+            mood_id: row.get(1)?,
+            name: row.get(2)?,
+            order_index: row.get(3)?,
+            is_looping: row.get::<_, i64>(4)? != 0,
+            created_at: row.get(5)?,
+            loop_marker_id: row.get(6)?,
+        })
+    })
+}
+
+pub fn delete_timeline(conn: &Connection, id: i64) -> AppResult<()> {
+    conn.execute("DELETE FROM timelines WHERE id = ?1", [id])?;
+
+    Ok(())
+}
+
+/// Sets looping on the timeline and on every one of its tracks.
+pub fn update_timeline_loop(conn: &Connection, id: i64, is_looping: bool) -> AppResult<()> {
+    conn.execute(
+        "UPDATE timelines SET is_looping = ?1 WHERE id = ?2",
+        (&is_looping, &id),
+    )?;
+
+    conn.execute(
+        "UPDATE timeline_tracks SET is_looping = ?1 WHERE timeline_id = ?2",
+        (&is_looping, &id),
+    )?;
+
+    Ok(())
+}
+
+pub fn create_timeline_track(
+    conn: &Connection,
+    timeline_id: i64,
+    name: String,
+) -> AppResult<TimelineTrack> {
+    let order_index: i64 = conn.query_row(
+        "SELECT COALESCE(MAX(order_index), -1) + 1 FROM timeline_tracks WHERE timeline_id = ?1",
+        [&timeline_id],
+        |row| row.get(0),
+    )?;
+
+    conn.execute(
+        "INSERT INTO timeline_tracks (timeline_id, name, order_index, is_looping) VALUES (?1, ?2, ?3, 0)",
+        (&timeline_id, &name, &order_index),
+    )?;
+
+    let id = conn.last_insert_rowid();
+
+    Ok(TimelineTrack {
+        id,
+        timeline_id,
+        name,
+        order_index,
+        is_looping: false,
+        created_at: chrono::Local::now().to_rfc3339(),
+    })
+}
+
+pub fn get_timeline_tracks(conn: &Connection, timeline_id: i64) -> AppResult<Vec<TimelineTrack>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, timeline_id, name, order_index, is_looping, created_at FROM timeline_tracks WHERE timeline_id = ?1 ORDER BY order_index ASC"
+    )?;
+
+    collect_rows(&mut stmt, [timeline_id], |row| {
+        Ok(TimelineTrack {
+            id: row.get(0)?,
+            timeline_id: row.get(1)?,
+            name: row.get(2)?,
+            order_index: row.get(3)?,
+            is_looping: row.get::<_, i64>(4)? != 0,
+            created_at: row.get(5)?,
+        })
+    })
+}
+
+pub fn update_timeline_track_looping(
+    conn: &Connection,
+    id: i64,
+    is_looping: bool,
+) -> AppResult<()> {
+    conn.execute(
+        "UPDATE timeline_tracks SET is_looping = ?1 WHERE id = ?2",
+        (&is_looping, &id),
+    )?;
+
+    Ok(())
+}
+
+pub fn delete_timeline_track(conn: &Connection, id: i64) -> AppResult<()> {
+    conn.execute("DELETE FROM timeline_tracks WHERE id = ?1", [id])?;
+
+    Ok(())
+}
+
+pub fn update_timeline_track_order(conn: &Connection, id: i64, order_index: i64) -> AppResult<()> {
+    conn.execute(
+        "UPDATE timeline_tracks SET order_index = ?1 WHERE id = ?2",
+        (&order_index, &id),
+    )?;
+
+    Ok(())
+}
+
+/// Returns the id of an element on `track_id` whose span intersects the given one.
+/// Elements hidden by the trash do not count.
+pub fn find_overlapping_element(
+    conn: &Connection,
+    track_id: i64,
+    start_time_ms: i64,
+    duration_ms: i64,
+    exclude_id: Option<i64>,
+) -> AppResult<Option<i64>> {
+    let end_time_ms = start_time_ms + duration_ms;
+
+    let conflicting_id = conn
+        .query_row(
+            &format!(
+                "SELECT te.id FROM timeline_elements te
+                 WHERE te.track_id = ?1 AND te.id != ?2
+                   AND te.start_time_ms < ?3 AND (te.start_time_ms + te.duration_ms) > ?4
+                   AND {}
+                 ORDER BY te.start_time_ms ASC LIMIT 1",
+                TIMELINE_ELEMENT_IS_VISIBLE
+            ),
+            rusqlite::params![
+                track_id,
+                exclude_id.unwrap_or(-1),
+                end_time_ms,
+                start_time_ms
+            ],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(conflicting_id)
+}
+
+/// Fails with `AppError::Overlap` when the span would collide with another element.
+pub fn ensure_no_overlap(
+    conn: &Connection,
+    track_id: i64,
+    start_time_ms: i64,
+    duration_ms: i64,
+    exclude_id: Option<i64>,
+) -> AppResult<()> {
+    match find_overlapping_element(conn, track_id, start_time_ms, duration_ms, exclude_id)? {
+        Some(conflicting_element_id) => Err(AppError::Overlap {
+            track_id,
+            conflicting_element_id,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Returns the span unchanged without a snap mode; snapping needs the timeline
+/// to have a tempo.
+pub(crate) fn snap_to_grid(
+    conn: &Connection,
+    timeline_id: i64,
+    start_time_ms: i64,
+    duration_ms: i64,
+    snap: Option<SnapMode>,
+) -> AppResult<(i64, i64)> {
+    match snap {
+        Some(mode) => {
+            Ok(require_tempo_map(conn, timeline_id)?.snap_span(start_time_ms, duration_ms, mode))
+        }
+        None => Ok((start_time_ms, duration_ms)),
+    }
+}
+
+/// Places an audio element or an element group on a track. With a `snap` mode
+/// both edges move to the nearest line of the timeline's musical grid first.
+/// A placement longer than what is left of a probed source is cut short.
+pub fn add_element_to_track(
+    conn: &Connection,
+    track_id: i64,
+    audio_element_id: Option<i64>,
+    element_group_id: Option<i64>,
+    start_time_ms: i64,
+    duration_ms: i64,
+    snap: Option<SnapMode>,
+) -> AppResult<TimelineElement> {
+    if audio_element_id.is_none() && element_group_id.is_none() {
+        return Err(AppError::ValidationFailed(
+            "Must provide either audio_element_id or element_group_id".into(),
+        ));
+    }
+    if let Some(audio_element_id) = audio_element_id {
+        ensure_live_audio_element(conn, audio_element_id)?;
+    }
+    if let Some(element_group_id) = element_group_id {
+        ensure_live_element_group(conn, element_group_id)?;
+    }
+
+    let timeline_id: i64 = conn
+        .query_row(
+            "SELECT timeline_id FROM timeline_tracks WHERE id = ?1",
+            [track_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::not_found("Timeline track", track_id))?;
+
+    let (start_time_ms, mut duration_ms) =
+        snap_to_grid(conn, timeline_id, start_time_ms, duration_ms, snap)?;
+
+    // Skip the source's leading silence when its loop points are known, and
+    // end the placement with the source when asked for more than is left.
+    let (loop_start_ms, source_ms) = match audio_element_id {
+        Some(audio_element_id) => conn.query_row(
+            "SELECT loop_start_ms, duration_ms FROM audio_elements WHERE id = ?1",
+            [audio_element_id],
+            |row| Ok((row.get::<_, Option<i64>>(0)?, row.get::<_, Option<i64>>(1)?)),
+        )?,
+        None => (None, None),
+    };
+    let mut source_offset_ms = loop_start_ms.unwrap_or(0);
+    if let Some(source_ms) = source_ms {
+        if source_offset_ms >= source_ms {
+            source_offset_ms = 0;
+        }
+        duration_ms = duration_ms.min(source_ms - source_offset_ms);
+    }
+    ensure_no_overlap(conn, track_id, start_time_ms, duration_ms, None)?;
+
+    conn.execute(
+        "INSERT INTO timeline_elements (timeline_id, track_id, audio_element_id, element_group_id, start_time_ms, duration_ms, source_offset_ms) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        (
+            &timeline_id,
+            &track_id,
+            &audio_element_id,
+            &element_group_id,
+            &start_time_ms,
+            &duration_ms,
+            &source_offset_ms,
+        ),
+    )?;
+
+    let id = conn.last_insert_rowid();
+
+    // The created element is by definition from the selected library, so we assume it is available
+    Ok(TimelineElement {
+        id,
+        track_id,
+        audio_element_id,
+        element_group_id,
+        start_time_ms,
+        duration_ms,
+        is_available: true,
+        source_offset_ms,
+        fade_in_ms: 0,
+        fade_out_ms: 0,
+        fade_curve: FadeCurve::Linear,
+    })
+}
+
+/// Lists a track's elements, flagging those whose sound set is disabled as unavailable
+/// and leaving out those whose source is in the trash.
+pub fn get_track_elements(conn: &Connection, track_id: i64) -> AppResult<Vec<TimelineElement>> {
+    let mut stmt = conn.prepare(&format!(
+        "
+        SELECT
+            te.id,
+            te.track_id,
+            te.audio_element_id,
+            te.element_group_id,
+            te.start_time_ms,
+            te.duration_ms,
+            te.source_offset_ms,
+            te.fade_in_ms,
+            te.fade_out_ms,
+            te.fade_curve,
+            COALESCE(
+                (SELECT ss.is_enabled FROM sound_sets ss JOIN audio_elements ae ON ae.sound_set_id = ss.id WHERE ae.id = te.audio_element_id),
+                (SELECT ss.is_enabled FROM sound_sets ss JOIN element_groups eg ON eg.sound_set_id = ss.id WHERE eg.id = te.element_group_id),
+                1
+            ) as is_available
+        FROM timeline_elements te
+        WHERE te.track_id = ?1 AND {}
+        ORDER BY te.start_time_ms ASC
+        ",
+        TIMELINE_ELEMENT_IS_VISIBLE
+    ))?;
+
+    collect_rows(&mut stmt, [track_id], |row| {
+        Ok(TimelineElement {
+            id: row.get(0)?,
+            track_id: row.get(1)?,
+            audio_element_id: row.get(2)?,
+            element_group_id: row.get(3)?,
+            start_time_ms: row.get(4)?,
+            duration_ms: row.get(5)?,
+            source_offset_ms: row.get(6)?,
+            fade_in_ms: row.get(7)?,
+            fade_out_ms: row.get(8)?,
+            fade_curve: FadeCurve::from_name(&row.get::<_, String>(9)?),
+            is_available: row.get::<_, Option<bool>>(10)?.unwrap_or(true),
+        })
+    })
+}
+
+/// Moves or resizes an element, snapping both edges like [`add_element_to_track`].
+pub fn update_element_time_and_duration(
+    conn: &Connection,
+    id: i64,
+    start_time_ms: i64,
+    duration_ms: i64,
+    snap: Option<SnapMode>,
+) -> AppResult<()> {
+    let (timeline_id, track_id, audio_element_id, source_offset_ms, fade_in_ms, fade_out_ms): (
+        i64,
+        i64,
+        Option<i64>,
+        i64,
+        i64,
+        i64,
+    ) = conn
+        .query_row(
+            "SELECT timeline_id, track_id, audio_element_id, source_offset_ms, fade_in_ms, fade_out_ms FROM timeline_elements WHERE id = ?1",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::not_found("Timeline element", id))?;
+
+    let (start_time_ms, duration_ms) =
+        snap_to_grid(conn, timeline_id, start_time_ms, duration_ms, snap)?;
+    validate_fades(duration_ms, source_offset_ms, fade_in_ms, fade_out_ms)?;
+    ensure_within_source(conn, audio_element_id, source_offset_ms, duration_ms)?;
+    ensure_no_overlap(conn, track_id, start_time_ms, duration_ms, Some(id))?;
+
+    conn.execute(
+        "UPDATE timeline_elements SET start_time_ms = ?1, duration_ms = ?2 WHERE id = ?3",
+        (&start_time_ms, &duration_ms, &id),
+    )?;
+
+    Ok(())
+}
+
+/// Sets where the element starts reading its source and how it fades in and out.
+pub fn update_element_fades(
+    conn: &Connection,
+    id: i64,
+    source_offset_ms: i64,
+    fade_in_ms: i64,
+    fade_out_ms: i64,
+    fade_curve: FadeCurve,
+) -> AppResult<()> {
+    let (audio_element_id, duration_ms): (Option<i64>, i64) = conn
+        .query_row(
+            "SELECT audio_element_id, duration_ms FROM timeline_elements WHERE id = ?1",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::not_found("Timeline element", id))?;
+
+    validate_fades(duration_ms, source_offset_ms, fade_in_ms, fade_out_ms)?;
+    ensure_within_source(conn, audio_element_id, source_offset_ms, duration_ms)?;
+
+    conn.execute(
+        "UPDATE timeline_elements SET source_offset_ms = ?1, fade_in_ms = ?2, fade_out_ms = ?3, fade_curve = ?4 WHERE id = ?5",
+        rusqlite::params![source_offset_ms, fade_in_ms, fade_out_ms, fade_curve.as_str(), id],
+    )?;
+
+    Ok(())
+}
+
+pub fn delete_timeline_element(conn: &Connection, id: i64) -> AppResult<()> {
+    conn.execute("DELETE FROM timeline_elements WHERE id = ?1", [id])?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::test_connection;
+
+    /// One sound set with audio element 100, and mood 1 whose timeline has track 5.
+    fn seeded_track() -> Connection {
+        let conn = test_connection();
+        conn.execute_batch(
+            "INSERT INTO sound_sets (id, name, description) VALUES (1, 'S', 'D');
+             INSERT INTO audio_elements (id, sound_set_id, file_path, file_name, channel_type) VALUES (100, 1, 'path', 'f', 'music');
+             INSERT INTO moods (id, name) VALUES (1, 'M');
+             INSERT INTO timelines (id, mood_id, name) VALUES (1, 1, 'T');
+             INSERT INTO timeline_tracks (id, timeline_id, name, is_looping) VALUES (5, 1, 'Trk', 0);",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_element_overlap_rejection() {
+        let conn = seeded_track();
+
+        // Add element 1: 1000ms to 3000ms (duration 2000ms)
+        conn.execute("INSERT INTO timeline_elements (id, track_id, audio_element_id, start_time_ms, duration_ms) VALUES (10, 5, 100, 1000, 2000)", []).unwrap();
+
+        // Test overlap checks
+        // 1. Completely before (0-500) - OK
+        assert!(find_overlapping_element(&conn, 5, 0, 500, None)
+            .unwrap()
+            .is_none());
+
+        // 2. Exactly before (0-1000) - OK
+        assert!(find_overlapping_element(&conn, 5, 0, 1000, None)
+            .unwrap()
+            .is_none());
+
+        // 3. Completely after (3000-4000) - OK
+        assert!(find_overlapping_element(&conn, 5, 3000, 1000, None)
+            .unwrap()
+            .is_none());
+
+        // 4. Overlap start (500-1500) - BAD, reports the conflicting element
+        assert_eq!(
+            find_overlapping_element(&conn, 5, 500, 1000, None).unwrap(),
+            Some(10)
+        );
+
+        // 5. Overlap end (2500-3500) - BAD
+        assert!(find_overlapping_element(&conn, 5, 2500, 1000, None)
+            .unwrap()
+            .is_some());
+
+        // 6. Enclosing (500-4000) - BAD
+        assert!(find_overlapping_element(&conn, 5, 500, 3500, None)
+            .unwrap()
+            .is_some());
+
+        // 7. Contained (1500-2500) - BAD
+        assert!(find_overlapping_element(&conn, 5, 1500, 1000, None)
+            .unwrap()
+            .is_some());
+
+        // 8. Overlapping itself when excluded - OK
+        assert!(find_overlapping_element(&conn, 5, 500, 1500, Some(10))
+            .unwrap()
+            .is_none());
+
+        // 9. Different track overlap - OK
+        assert!(find_overlapping_element(&conn, 6, 1500, 1000, None)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn add_element_to_track_rejects_overlaps_with_the_conflicting_id() {
+        let conn = seeded_track();
+        let placed = add_element_to_track(&conn, 5, Some(100), None, 1000, 2000, None).unwrap();
+
+        let error = add_element_to_track(&conn, 5, Some(100), None, 2500, 1000, None).unwrap_err();
+
+        assert!(matches!(
+            error,
+            AppError::Overlap { track_id: 5, conflicting_element_id } if conflicting_element_id == placed.id
+        ));
+        assert_eq!(get_track_elements(&conn, 5).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn add_element_to_track_validates_its_input() {
+        let conn = seeded_track();
+
+        let missing_source = add_element_to_track(&conn, 5, None, None, 0, 1000, None).unwrap_err();
+        assert_eq!(missing_source.code(), "ValidationFailed");
+
+        let missing_track =
+            add_element_to_track(&conn, 99, Some(100), None, 0, 1000, None).unwrap_err();
+        assert_eq!(missing_track.code(), "NotFound");
+    }
+
+    #[test]
+    fn snapping_places_elements_on_the_grid() {
+        let conn = seeded_track();
+        let untimed =
+            add_element_to_track(&conn, 5, Some(100), None, 130, 900, Some(SnapMode::Beat));
+        assert_eq!(untimed.unwrap_err().code(), "ValidationFailed");
+
+        // 120 BPM in 4/4: 500 ms beats, 2000 ms bars.
+        crate::store::tempo::set_tempo_change(&conn, 1, 1, 120.0, 4, 4).unwrap();
+        let element =
+            add_element_to_track(&conn, 5, Some(100), None, 130, 900, Some(SnapMode::Beat))
+                .unwrap();
+        assert_eq!((element.start_time_ms, element.duration_ms), (0, 1000));
+
+        update_element_time_and_duration(&conn, element.id, 2100, 1500, Some(SnapMode::Bar))
+            .unwrap();
+        let elements = get_track_elements(&conn, 5).unwrap();
+        assert_eq!(
+            (elements[0].start_time_ms, elements[0].duration_ms),
+            (2000, 2000)
+        );
+    }
+
+    #[test]
+    fn moving_an_element_ignores_its_own_span() {
+        let conn = seeded_track();
+        let first = add_element_to_track(&conn, 5, Some(100), None, 0, 1000, None).unwrap();
+        add_element_to_track(&conn, 5, Some(100), None, 2000, 1000, None).unwrap();
+
+        update_element_time_and_duration(&conn, first.id, 500, 1500, None).unwrap();
+        let error =
+            update_element_time_and_duration(&conn, first.id, 1500, 1000, None).unwrap_err();
+        assert_eq!(error.code(), "Overlap");
+
+        let elements = get_track_elements(&conn, 5).unwrap();
+        assert_eq!(elements[0].start_time_ms, 500);
+        assert_eq!(elements[0].duration_ms, 1500);
+    }
+
+    #[test]
+    fn fades_must_fit_inside_the_element() {
+        let conn = seeded_track();
+        let element = add_element_to_track(&conn, 5, Some(100), None, 0, 4000, None).unwrap();
+
+        update_element_fades(&conn, element.id, 250, 1500, 2500, FadeCurve::EqualPower).unwrap();
+
+        let too_long = update_element_fades(&conn, element.id, 0, 2000, 2001, FadeCurve::Linear);
+        assert_eq!(too_long.unwrap_err().code(), "ValidationFailed");
+        let negative = update_element_fades(&conn, element.id, -1, 0, 0, FadeCurve::Linear);
+        assert_eq!(negative.unwrap_err().code(), "ValidationFailed");
+        let shrunk = update_element_time_and_duration(&conn, element.id, 0, 3000, None);
+        assert_eq!(shrunk.unwrap_err().code(), "ValidationFailed");
+
+        let stored = &get_track_elements(&conn, 5).unwrap()[0];
+        assert_eq!(stored.source_offset_ms, 250);
+        assert_eq!((stored.fade_in_ms, stored.fade_out_ms), (1500, 2500));
+        assert_eq!(stored.fade_curve, FadeCurve::EqualPower);
+        assert_eq!(stored.duration_ms, 4000);
+    }
+
+    #[test]
+    fn new_elements_start_at_the_detected_loop_start() {
+        let conn = seeded_track();
+        let untrimmed = add_element_to_track(&conn, 5, Some(100), None, 0, 1000, None).unwrap();
+        assert_eq!(untrimmed.source_offset_ms, 0);
+
+        conn.execute(
+            "UPDATE audio_elements SET loop_start_ms = 320 WHERE id = 100",
+            [],
+        )
+        .unwrap();
+        let trimmed = add_element_to_track(&conn, 5, Some(100), None, 2000, 1000, None).unwrap();
+        assert_eq!(trimmed.source_offset_ms, 320);
+        assert_eq!(
+            get_track_elements(&conn, 5).unwrap()[1].source_offset_ms,
+            320
+        );
+    }
+
+    #[test]
+    fn elements_cannot_run_past_the_end_of_their_source() {
+        let conn = seeded_track();
+        conn.execute(
+            "UPDATE audio_elements SET duration_ms = 1500 WHERE id = 100",
+            [],
+        )
+        .unwrap();
+
+        let element = add_element_to_track(&conn, 5, Some(100), None, 0, 2000, None).unwrap();
+        assert_eq!(element.duration_ms, 1500);
+
+        let stretched = update_element_time_and_duration(&conn, element.id, 0, 1600, None);
+        assert_eq!(stretched.unwrap_err().code(), "ValidationFailed");
+        let trimmed = update_element_fades(&conn, element.id, 100, 0, 0, FadeCurve::Linear);
+        assert_eq!(trimmed.unwrap_err().code(), "ValidationFailed");
+        update_element_time_and_duration(&conn, element.id, 0, 1000, None).unwrap();
+        update_element_fades(&conn, element.id, 500, 0, 0, FadeCurve::Linear).unwrap();
+    }
+
+    #[test]
+    fn full_length_drops_fit_what_follows_the_loop_start() {
+        let conn = seeded_track();
+        conn.execute(
+            "UPDATE audio_elements SET duration_ms = 4000, loop_start_ms = 320 WHERE id = 100",
+            [],
+        )
+        .unwrap();
+
+        // The whole buffer, as the frontend sends once it has loaded.
+        let full = add_element_to_track(&conn, 5, Some(100), None, 0, 4000, None).unwrap();
+        assert_eq!((full.source_offset_ms, full.duration_ms), (320, 3680));
+
+        // The default length it sends before then runs past the source, and
+        // unclamped would overlap the element placed at 9000 ms.
+        add_element_to_track(&conn, 5, Some(100), None, 9000, 500, None).unwrap();
+        let early = add_element_to_track(&conn, 5, Some(100), None, 5000, 10_000, None).unwrap();
+        assert_eq!((early.source_offset_ms, early.duration_ms), (320, 3680));
+        assert_eq!(get_track_elements(&conn, 5).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn fade_curves_run_from_silence_to_unity() {
+        for curve in [
+            FadeCurve::Linear,
+            FadeCurve::EqualPower,
+            FadeCurve::Exponential,
+        ] {
+            assert_eq!(curve.gain(0.0), 0.0);
+            assert!((curve.gain(1.0) - 1.0).abs() < 1e-12);
+            assert!(curve.gain(0.25) < curve.gain(0.75));
+        }
+
+        let half = FadeCurve::EqualPower.gain(0.5);
+        assert!((half * half * 2.0 - 1.0).abs() < 1e-12);
+        assert!(FadeCurve::Exponential.gain(0.5) < FadeCurve::Linear.gain(0.5));
+    }
+
+    #[test]
+    fn fade_gain_shapes_both_ends_of_an_element() {
+        let element = TimelineElement {
+            id: 1,
+            track_id: 5,
+            audio_element_id: Some(100),
+            element_group_id: None,
+            start_time_ms: 0,
+            duration_ms: 10_000,
+            is_available: true,
+            source_offset_ms: 0,
+            fade_in_ms: 1000,
+            fade_out_ms: 2000,
+            fade_curve: FadeCurve::Linear,
+        };
+
+        assert_eq!(element.fade_gain(0), 0.0);
+        assert_eq!(element.fade_gain(500), 0.5);
+        assert_eq!(element.fade_gain(5000), 1.0);
+        assert_eq!(element.fade_gain(9000), 0.5);
+        assert_eq!(element.fade_gain(10_000), 0.0);
+    }
+
+    #[test]
+    fn create_timeline_returns_the_existing_timeline_for_a_mood() {
+        let conn = test_connection();
+        conn.execute("INSERT INTO moods (id, name) VALUES (1, 'M')", [])
+            .unwrap();
+
+        let created = create_timeline(&conn, 1, "Main".into()).unwrap();
+        let again = create_timeline(&conn, 1, "Other".into()).unwrap();
+
+        assert_eq!(again.id, created.id);
+        assert_eq!(again.name, "Main");
+        let tracks = get_timeline_tracks(&conn, created.id).unwrap();
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].name, "Track 1");
+    }
+
+    #[test]
+    fn timeline_looping_propagates_to_tracks() {
+        let conn = test_connection();
+        conn.execute("INSERT INTO moods (id, name) VALUES (1, 'M')", [])
+            .unwrap();
+        let timeline = create_timeline(&conn, 1, "Main".into()).unwrap();
+        let track = create_timeline_track(&conn, timeline.id, "Track 2".into()).unwrap();
+        assert_eq!(track.order_index, 1);
+
+        update_timeline_loop(&conn, timeline.id, true).unwrap();
+
+        assert!(get_timelines(&conn, 1).unwrap()[0].is_looping);
+        assert!(get_timeline_tracks(&conn, timeline.id)
+            .unwrap()
+            .iter()
+            .all(|track| track.is_looping));
+    }
+
+    #[test]
+    fn test_sound_set_activation_logic() {
+        let conn = test_connection();
+
+        // 1. Setup SoundSet and AudioElement
+        conn.execute(
+            "INSERT INTO sound_sets (id, name, is_enabled) VALUES (1, 'S1', 1)",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO sound_sets (id, name, is_enabled) VALUES (2, 'S2', 0)",
+            [],
+        )
+        .unwrap();
+
+        conn.execute("INSERT INTO audio_elements (id, sound_set_id, file_path, file_name, channel_type) VALUES (10, 1, 'p1', 'f1', 'music')", []).unwrap();
+        conn.execute("INSERT INTO audio_elements (id, sound_set_id, file_path, file_name, channel_type) VALUES (20, 2, 'p2', 'f2', 'music')", []).unwrap();
+
+        conn.execute(
+            "INSERT INTO element_groups (id, sound_set_id, name) VALUES (30, 1, 'g1')",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO element_groups (id, sound_set_id, name) VALUES (40, 2, 'g2')",
+            [],
+        )
+        .unwrap();
+
+        conn.execute("INSERT INTO moods (id, name) VALUES (1, 'M')", [])
+            .unwrap();
+        conn.execute(
+            "INSERT INTO timelines (id, mood_id, name) VALUES (1, 1, 'T')",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO timeline_tracks (id, timeline_id, name) VALUES (1, 1, 'Trk')",
+            [],
+        )
+        .unwrap();
+
+        // Add elements to timeline
+        conn.execute("INSERT INTO timeline_elements (id, track_id, audio_element_id, start_time_ms, duration_ms) VALUES (1, 1, 10, 0, 1000)", []).unwrap();
+        conn.execute("INSERT INTO timeline_elements (id, track_id, audio_element_id, start_time_ms, duration_ms) VALUES (2, 1, 20, 1000, 1000)", []).unwrap();
+        conn.execute("INSERT INTO timeline_elements (id, track_id, element_group_id, start_time_ms, duration_ms) VALUES (3, 1, 30, 2000, 1000)", []).unwrap();
+        conn.execute("INSERT INTO timeline_elements (id, track_id, element_group_id, start_time_ms, duration_ms) VALUES (4, 1, 40, 3000, 1000)", []).unwrap();
+
+        // Verify get_track_elements availability
+        let results: Vec<(i64, bool)> = get_track_elements(&conn, 1)
+            .unwrap()
+            .into_iter()
+            .map(|element| (element.id, element.is_available))
+            .collect();
+
+        // Element 1 (S1 enabled) -> true
+        assert!(results.iter().find(|(id, _)| *id == 1).unwrap().1);
+        // Element 2 (S2 disabled) -> false
+        assert!(!results.iter().find(|(id, _)| *id == 2).unwrap().1);
+        // Element 3 (Group S1 enabled) -> true
+        assert!(results.iter().find(|(id, _)| *id == 3).unwrap().1);
+        // Element 4 (Group S2 disabled) -> false
+        assert!(!results.iter().find(|(id, _)| *id == 4).unwrap().1);
+    }
+}
@@ -0,0 +1,512 @@
+//! Crossfades between moods.
+//!
+//! A transition is looked up from the most to the least specific row: the exact
+//! mood pair, any mood into the target, the source into any mood, and finally
+//! the catch-all row with neither side set.
+
+use rusqlite::{Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use super::collect_rows;
+use super::markers::next_marker_position;
+use super::tempo::load_tempo_map;
+use super::timelines::{get_timeline_tracks, get_track_elements, FadeCurve};
+use crate::{AppError, AppResult};
+
+/// Crossfade used when no transition row applies.
+pub const DEFAULT_CROSSFADE_MS: i64 = 2000;
+pub const MAX_CROSSFADE_MS: i64 = 60_000;
+
+/// When the outgoing mood lets go.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitionSync {
+    /// Crossfade right away.
+    #[default]
+    Immediate,
+    /// Let each outgoing track play its current clip to the end.
+    FinishCurrentClip,
+    /// Wait for the next marker of the outgoing timeline, or go right away
+    /// when none is left.
+    NextMarker,
+    /// Wait for the next bar line of the outgoing timeline's tempo map, or go
+    /// right away when it has no tempo.
+    NextBar,
+}
+
+impl TransitionSync {
+    fn as_str(self) -> &'static str {
+        match self {
+            TransitionSync::Immediate => "immediate",
+            TransitionSync::FinishCurrentClip => "finish_current_clip",
+            TransitionSync::NextMarker => "next_marker",
+            TransitionSync::NextBar => "next_bar",
+        }
+    }
+
+    fn from_name(name: &str) -> Self {
+        match name {
+            "finish_current_clip" => TransitionSync::FinishCurrentClip,
+            "next_marker" => TransitionSync::NextMarker,
+            "next_bar" => TransitionSync::NextBar,
+            _ => TransitionSync::Immediate,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MoodTransition {
+    pub id: i64,
+    /// `None` applies to switches away from any mood.
+    pub from_mood_id: Option<i64>,
+    /// `None` applies to switches into any mood.
+    pub to_mood_id: Option<i64>,
+    pub crossfade_ms: i64,
+    pub curve: FadeCurve,
+    pub sync: TransitionSync,
+}
+
+/// Fade-out of one track of the outgoing timeline.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackFadeOut {
+    pub track_id: i64,
+    /// Position in the outgoing timeline where the fade starts.
+    pub start_ms: i64,
+    pub duration_ms: i64,
+}
+
+/// What to do, and when, to move from one mood to another. All times are
+/// positions in the outgoing timeline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransitionPlan {
+    pub from_mood_id: Option<i64>,
+    pub to_mood_id: i64,
+    /// Transition row the plan follows; `None` when built-in defaults apply.
+    pub transition_id: Option<i64>,
+    pub curve: FadeCurve,
+    pub sync: TransitionSync,
+    pub fade_outs: Vec<TrackFadeOut>,
+    /// Timeline of the incoming mood; `None` when it has none yet.
+    pub next_timeline_id: Option<i64>,
+    /// Where the incoming timeline starts, fading in over `fade_in_ms`.
+    pub next_timeline_start_ms: i64,
+    pub fade_in_ms: i64,
+}
+
+fn map_mood_transition(row: &Row<'_>) -> rusqlite::Result<MoodTransition> {
+    Ok(MoodTransition {
+        id: row.get(0)?,
+        from_mood_id: row.get(1)?,
+        to_mood_id: row.get(2)?,
+        crossfade_ms: row.get(3)?,
+        curve: FadeCurve::from_name(&row.get::<_, String>(4)?),
+        sync: TransitionSync::from_name(&row.get::<_, String>(5)?),
+    })
+}
+
+pub fn get_mood_transitions(conn: &Connection) -> AppResult<Vec<MoodTransition>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, from_mood_id, to_mood_id, crossfade_ms, curve, sync FROM mood_transitions
+         ORDER BY from_mood_id IS NULL, from_mood_id, to_mood_id IS NULL, to_mood_id",
+    )?;
+
+    collect_rows(&mut stmt, [], map_mood_transition)
+}
+
+/// Creates or replaces the transition for a mood pair, either side of which
+/// may be `None` to define a fallback.
+pub fn set_mood_transition(
+    conn: &Connection,
+    from_mood_id: Option<i64>,
+    to_mood_id: Option<i64>,
+    crossfade_ms: i64,
+    curve: FadeCurve,
+    sync: TransitionSync,
+) -> AppResult<MoodTransition> {
+    if !(0..=MAX_CROSSFADE_MS).contains(&crossfade_ms) {
+        return Err(AppError::ValidationFailed(format!(
+            "Crossfade must be between 0 and {} ms, got {}",
+            MAX_CROSSFADE_MS, crossfade_ms
+        )));
+    }
+
+    conn.execute(
+        "INSERT INTO mood_transitions (from_mood_id, to_mood_id, crossfade_ms, curve, sync)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT(IFNULL(from_mood_id, 0), IFNULL(to_mood_id, 0))
+         DO UPDATE SET crossfade_ms = excluded.crossfade_ms, curve = excluded.curve, sync = excluded.sync",
+        rusqlite::params![
+            from_mood_id,
+            to_mood_id,
+            crossfade_ms,
+            curve.as_str(),
+            sync.as_str()
+        ],
+    )?;
+
+    let id = conn.query_row(
+        "SELECT id FROM mood_transitions WHERE from_mood_id IS ?1 AND to_mood_id IS ?2",
+        rusqlite::params![from_mood_id, to_mood_id],
+        |row| row.get(0),
+    )?;
+
+    Ok(MoodTransition {
+        id,
+        from_mood_id,
+        to_mood_id,
+        crossfade_ms,
+        curve,
+        sync,
+    })
+}
+
+pub fn delete_mood_transition(conn: &Connection, id: i64) -> AppResult<()> {
+    conn.execute("DELETE FROM mood_transitions WHERE id = ?1", [id])?;
+
+    Ok(())
+}
+
+/// The most specific transition row for the pair, if any applies.
+pub fn resolve_mood_transition(
+    conn: &Connection,
+    from_mood_id: Option<i64>,
+    to_mood_id: i64,
+) -> AppResult<Option<MoodTransition>> {
+    let transition = conn
+        .query_row(
+            "SELECT id, from_mood_id, to_mood_id, crossfade_ms, curve, sync FROM mood_transitions
+             WHERE (from_mood_id IS NULL OR from_mood_id = ?1)
+               AND (to_mood_id IS NULL OR to_mood_id = ?2)
+             ORDER BY to_mood_id IS NULL, from_mood_id IS NULL
+             LIMIT 1",
+            rusqlite::params![from_mood_id, to_mood_id],
+            map_mood_transition,
+        )
+        .optional()?;
+
+    Ok(transition)
+}
+
+/// Plans the switch from `from_mood_id`, whose timeline is at `position_ms`,
+/// to `to_mood_id`. `from_mood_id` is `None` when nothing is playing yet.
+///
+/// `position_ms` is the playhead within the outgoing timeline, already wrapped
+/// when the timeline loops.
+pub fn plan_mood_transition(
+    conn: &Connection,
+    from_mood_id: Option<i64>,
+    to_mood_id: i64,
+    position_ms: i64,
+) -> AppResult<TransitionPlan> {
+    let transition = resolve_mood_transition(conn, from_mood_id, to_mood_id)?;
+    let (crossfade_ms, curve, sync) = match &transition {
+        Some(transition) => (transition.crossfade_ms, transition.curve, transition.sync),
+        None => (
+            DEFAULT_CROSSFADE_MS,
+            FadeCurve::EqualPower,
+            TransitionSync::Immediate,
+        ),
+    };
+
+    let mut fade_outs = Vec::new();
+    if let Some(timeline_id) = from_mood_id
+        .map(|mood_id| mood_timeline_id(conn, mood_id))
+        .transpose()?
+        .flatten()
+    {
+        let sync_point_ms = match sync {
+            TransitionSync::NextMarker => {
+                next_marker_position(conn, timeline_id, position_ms)?.unwrap_or(position_ms)
+            }
+            TransitionSync::NextBar => load_tempo_map(conn, timeline_id)?
+                .map(|tempo| tempo.next_bar_ms(position_ms as f64).round() as i64)
+                .unwrap_or(position_ms),
+            _ => position_ms,
+        };
+
+        for track in get_timeline_tracks(conn, timeline_id)? {
+            let sounding_until = match sync {
+                TransitionSync::Immediate
+                | TransitionSync::NextMarker
+                | TransitionSync::NextBar => None,
+                TransitionSync::FinishCurrentClip => get_track_elements(conn, track.id)?
+                    .iter()
+                    .filter(|element| element.is_available)
+                    .find(|element| {
+                        element.start_time_ms <= position_ms
+                            && position_ms < element.start_time_ms + element.duration_ms
+                    })
+                    .map(|element| element.start_time_ms + element.duration_ms),
+            };
+
+            // A finishing clip fades over its own tail, so it ends exactly with the clip.
+            let (start_ms, duration_ms) = match sounding_until {
+                Some(clip_end_ms) => {
+                    let start_ms = (clip_end_ms - crossfade_ms).max(position_ms);
+                    (start_ms, clip_end_ms - start_ms)
+                }
+                None => (sync_point_ms, crossfade_ms),
+            };
+            fade_outs.push(TrackFadeOut {
+                track_id: track.id,
+                start_ms,
+                duration_ms,
+            });
+        }
+    }
+
+    // The incoming mood fades in alongside the last track to let go.
+    let next_timeline_start_ms = fade_outs
+        .iter()
+        .map(|fade| fade.start_ms)
+        .max()
+        .unwrap_or(position_ms);
+
+    Ok(TransitionPlan {
+        from_mood_id,
+        to_mood_id,
+        transition_id: transition.map(|transition| transition.id),
+        curve,
+        sync,
+        fade_outs,
+        next_timeline_id: mood_timeline_id(conn, to_mood_id)?,
+        next_timeline_start_ms,
+        fade_in_ms: crossfade_ms,
+    })
+}
+
+fn mood_timeline_id(conn: &Connection, mood_id: i64) -> AppResult<Option<i64>> {
+    let timeline_id = conn
+        .query_row(
+            "SELECT id FROM timelines WHERE mood_id = ?1 ORDER BY order_index ASC LIMIT 1",
+            [mood_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(timeline_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::test_connection;
+
+    /// Mood 1 has tracks 10 (clip 0-5000) and 11 (clip 1000-2000); mood 2 has timeline 2.
+    fn seeded() -> Connection {
+        let conn = test_connection();
+        conn.execute_batch(
+            "INSERT INTO moods (id, name) VALUES (1, 'Tavern'), (2, 'Ambush'), (3, 'Calm');
+             INSERT INTO timelines (id, mood_id, name) VALUES (1, 1, 'T1'), (2, 2, 'T2');
+             INSERT INTO timeline_tracks (id, timeline_id, name, order_index) VALUES (10, 1, 'A', 0), (11, 1, 'B', 1);
+             INSERT INTO audio_elements (id, file_path, file_name) VALUES (100, 'p', 'a.ogg');
+             INSERT INTO timeline_elements (timeline_id, track_id, audio_element_id, start_time_ms, duration_ms)
+                 VALUES (1, 10, 100, 0, 5000), (1, 11, 100, 1000, 1000);",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn the_most_specific_transition_wins() {
+        let conn = seeded();
+        let catch_all = set_mood_transition(
+            &conn,
+            None,
+            None,
+            1000,
+            FadeCurve::Linear,
+            TransitionSync::Immediate,
+        )
+        .unwrap();
+        let into_ambush = set_mood_transition(
+            &conn,
+            None,
+            Some(2),
+            300,
+            FadeCurve::Linear,
+            TransitionSync::Immediate,
+        )
+        .unwrap();
+        let pair = set_mood_transition(
+            &conn,
+            Some(1),
+            Some(2),
+            4000,
+            FadeCurve::EqualPower,
+            TransitionSync::FinishCurrentClip,
+        )
+        .unwrap();
+
+        let resolve = |from, to| {
+            resolve_mood_transition(&conn, from, to)
+                .unwrap()
+                .unwrap()
+                .id
+        };
+        assert_eq!(resolve(Some(1), 2), pair.id);
+        assert_eq!(resolve(Some(3), 2), into_ambush.id);
+        assert_eq!(resolve(None, 2), into_ambush.id);
+        assert_eq!(resolve(Some(1), 3), catch_all.id);
+    }
+
+    #[test]
+    fn setting_a_pair_again_replaces_it() {
+        let conn = seeded();
+        let first = set_mood_transition(
+            &conn,
+            Some(1),
+            None,
+            1000,
+            FadeCurve::Linear,
+            TransitionSync::Immediate,
+        )
+        .unwrap();
+        let second = set_mood_transition(
+            &conn,
+            Some(1),
+            None,
+            2500,
+            FadeCurve::Exponential,
+            TransitionSync::Immediate,
+        )
+        .unwrap();
+
+        assert_eq!(first.id, second.id);
+        let stored = get_mood_transitions(&conn).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].crossfade_ms, 2500);
+        assert_eq!(stored[0].curve, FadeCurve::Exponential);
+
+        let too_long = set_mood_transition(
+            &conn,
+            None,
+            None,
+            120_000,
+            FadeCurve::Linear,
+            TransitionSync::Immediate,
+        );
+        assert_eq!(too_long.unwrap_err().code(), "ValidationFailed");
+    }
+
+    #[test]
+    fn immediate_plans_fade_everything_out_now() {
+        let conn = seeded();
+
+        let plan = plan_mood_transition(&conn, Some(1), 2, 1500).unwrap();
+
+        assert_eq!(plan.transition_id, None);
+        assert_eq!(plan.curve, FadeCurve::EqualPower);
+        assert_eq!(
+            plan.fade_outs,
+            [
+                TrackFadeOut {
+                    track_id: 10,
+                    start_ms: 1500,
+                    duration_ms: DEFAULT_CROSSFADE_MS
+                },
+                TrackFadeOut {
+                    track_id: 11,
+                    start_ms: 1500,
+                    duration_ms: DEFAULT_CROSSFADE_MS
+                },
+            ]
+        );
+        assert_eq!(plan.next_timeline_id, Some(2));
+        assert_eq!(plan.next_timeline_start_ms, 1500);
+    }
+
+    #[test]
+    fn finishing_clips_fade_over_their_tails() {
+        let conn = seeded();
+        set_mood_transition(
+            &conn,
+            Some(1),
+            Some(3),
+            1000,
+            FadeCurve::Linear,
+            TransitionSync::FinishCurrentClip,
+        )
+        .unwrap();
+
+        let plan = plan_mood_transition(&conn, Some(1), 3, 1800).unwrap();
+
+        assert_eq!(
+            plan.fade_outs,
+            [
+                TrackFadeOut {
+                    track_id: 10,
+                    start_ms: 4000,
+                    duration_ms: 1000
+                },
+                TrackFadeOut {
+                    track_id: 11,
+                    start_ms: 1800,
+                    duration_ms: 200
+                },
+            ]
+        );
+        assert_eq!(plan.next_timeline_id, None);
+        assert_eq!(plan.next_timeline_start_ms, 4000);
+        assert_eq!(plan.fade_in_ms, 1000);
+    }
+
+    #[test]
+    fn next_marker_transitions_wait_for_the_cue() {
+        let conn = seeded();
+        conn.execute(
+            "INSERT INTO timeline_markers (timeline_id, name, position_ms) VALUES (1, 'Chorus', 8000)",
+            [],
+        )
+        .unwrap();
+        set_mood_transition(
+            &conn,
+            None,
+            None,
+            500,
+            FadeCurve::Linear,
+            TransitionSync::NextMarker,
+        )
+        .unwrap();
+
+        let waiting = plan_mood_transition(&conn, Some(1), 2, 1500).unwrap();
+        assert!(waiting.fade_outs.iter().all(|fade| fade.start_ms == 8000));
+        assert_eq!(waiting.next_timeline_start_ms, 8000);
+
+        let past_last_marker = plan_mood_transition(&conn, Some(1), 2, 9000).unwrap();
+        assert_eq!(past_last_marker.next_timeline_start_ms, 9000);
+    }
+
+    #[test]
+    fn next_bar_transitions_wait_for_the_bar_line() {
+        let conn = seeded();
+        set_mood_transition(
+            &conn,
+            None,
+            None,
+            500,
+            FadeCurve::Linear,
+            TransitionSync::NextBar,
+        )
+        .unwrap();
+
+        let without_tempo = plan_mood_transition(&conn, Some(1), 2, 1500).unwrap();
+        assert_eq!(without_tempo.next_timeline_start_ms, 1500);
+
+        // 90 BPM in 3/4: 2000 ms bars.
+        crate::store::tempo::set_tempo_change(&conn, 1, 1, 90.0, 3, 4).unwrap();
+        let plan = plan_mood_transition(&conn, Some(1), 2, 2500).unwrap();
+        assert!(plan.fade_outs.iter().all(|fade| fade.start_ms == 4000));
+        assert_eq!(plan.next_timeline_start_ms, 4000);
+    }
+
+    #[test]
+    fn starting_from_silence_only_fades_in() {
+        let conn = seeded();
+
+        let plan = plan_mood_transition(&conn, None, 2, 0).unwrap();
+
+        assert!(plan.fade_outs.is_empty());
+        assert_eq!(plan.next_timeline_start_ms, 0);
+    }
+}
@@ -0,0 +1,513 @@
+//! Trash bin for sound sets, moods and audio elements.
+//!
+//! Deleting one of those only stamps `deleted_at`, so the timeline elements and
+//! group memberships that point at it stay in place, hidden from every listing.
+//! Restoring clears the stamp and they reappear as they were; emptying the
+//! trash (or the retention purge) removes the rows for good and lets the
+//! foreign key cascades clean up.
+
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use super::history;
+use super::timelines::ensure_no_overlap;
+use super::{collect_rows, in_savepoint};
+use crate::{AppError, AppResult};
+
+/// Days a trashed item is kept when the settings do not say otherwise.
+pub const DEFAULT_RETENTION_DAYS: u32 = 30;
+
+/// SQL condition that holds for a timeline element aliased `te` whose audio
+/// element or group is not in the trash, directly or through its sound set.
+pub(crate) const TIMELINE_ELEMENT_IS_VISIBLE: &str = "NOT EXISTS (
+        SELECT 1 FROM audio_elements hidden_ae
+        LEFT JOIN sound_sets hidden_ss ON hidden_ss.id = hidden_ae.sound_set_id
+        WHERE hidden_ae.id = te.audio_element_id
+          AND (hidden_ae.deleted_at IS NOT NULL OR hidden_ss.deleted_at IS NOT NULL)
+    )
+    AND NOT EXISTS (
+        SELECT 1 FROM element_groups hidden_eg
+        JOIN sound_sets hidden_ss ON hidden_ss.id = hidden_eg.sound_set_id
+        WHERE hidden_eg.id = te.element_group_id AND hidden_ss.deleted_at IS NOT NULL
+    )";
+
+/// Fails with `NotFound` unless the audio element exists and neither it nor
+/// its sound set is in the trash.
+pub(crate) fn ensure_live_audio_element(conn: &Connection, id: i64) -> AppResult<()> {
+    conn.query_row(
+        "SELECT ae.id FROM audio_elements ae
+         LEFT JOIN sound_sets ss ON ss.id = ae.sound_set_id
+         WHERE ae.id = ?1 AND ae.deleted_at IS NULL AND ss.deleted_at IS NULL",
+        [id],
+        |row| row.get::<_, i64>(0),
+    )
+    .optional()?
+    .ok_or_else(|| AppError::not_found("Audio element", id))?;
+
+    Ok(())
+}
+
+/// Fails with `NotFound` unless the element group exists and its sound set is
+/// not in the trash.
+pub(crate) fn ensure_live_element_group(conn: &Connection, id: i64) -> AppResult<()> {
+    conn.query_row(
+        "SELECT eg.id FROM element_groups eg
+         LEFT JOIN sound_sets ss ON ss.id = eg.sound_set_id
+         WHERE eg.id = ?1 AND ss.deleted_at IS NULL",
+        [id],
+        |row| row.get::<_, i64>(0),
+    )
+    .optional()?
+    .ok_or_else(|| AppError::not_found("Element group", id))?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrashKind {
+    SoundSet,
+    Mood,
+    AudioElement,
+}
+
+impl TrashKind {
+    pub(crate) fn table(self) -> &'static str {
+        match self {
+            TrashKind::SoundSet => "sound_sets",
+            TrashKind::Mood => "moods",
+            TrashKind::AudioElement => "audio_elements",
+        }
+    }
+
+    fn entity(self) -> &'static str {
+        match self {
+            TrashKind::SoundSet => "Sound set",
+            TrashKind::Mood => "Mood",
+            TrashKind::AudioElement => "Audio element",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashItem {
+    pub kind: TrashKind,
+    pub id: i64,
+    pub name: String,
+    pub deleted_at: String,
+}
+
+/// Which rows of a kind [`move_to_trash`] may touch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum TrashOwner {
+    /// Any live row with the id.
+    Any,
+    /// Only a row that belongs to no sound set, i.e. a global one-shot.
+    Global,
+}
+
+/// Stamps `deleted_at` on a live row; trashing twice is a no-op.
+pub(crate) fn move_to_trash(
+    conn: &Connection,
+    kind: TrashKind,
+    owner: TrashOwner,
+    id: i64,
+) -> AppResult<()> {
+    let owner_condition = match owner {
+        TrashOwner::Any => "",
+        TrashOwner::Global => " AND sound_set_id IS NULL",
+    };
+    conn.execute(
+        &format!(
+            "UPDATE {} SET deleted_at = CURRENT_TIMESTAMP WHERE id = ?1 AND deleted_at IS NULL{}",
+            kind.table(),
+            owner_condition
+        ),
+        [id],
+    )?;
+
+    Ok(())
+}
+
+/// Everything in the trash, most recently deleted first.
+pub fn list_trash(conn: &Connection) -> AppResult<Vec<TrashItem>> {
+    let mut stmt = conn.prepare(
+        "SELECT 'sound_set', id, name, deleted_at FROM sound_sets WHERE deleted_at IS NOT NULL
+         UNION ALL
+         SELECT 'mood', id, name, deleted_at FROM moods WHERE deleted_at IS NOT NULL
+         UNION ALL
+         SELECT 'audio_element', id, file_name, deleted_at FROM audio_elements WHERE deleted_at IS NOT NULL
+         ORDER BY 4 DESC, 2 DESC",
+    )?;
+
+    collect_rows(&mut stmt, [], |row| {
+        let kind = match row.get::<_, String>(0)?.as_str() {
+            "sound_set" => TrashKind::SoundSet,
+            "mood" => TrashKind::Mood,
+            _ => TrashKind::AudioElement,
+        };
+        Ok(TrashItem {
+            kind,
+            id: row.get(1)?,
+            name: row.get(2)?,
+            deleted_at: row.get(3)?,
+        })
+    })
+}
+
+/// Takes an item out of the trash together with everything that referenced it.
+///
+/// Fails with `Overlap` when one of the returning timeline elements would now
+/// collide with an element placed after the deletion.
+pub fn restore_from_trash(conn: &Connection, kind: TrashKind, id: i64) -> AppResult<()> {
+    in_savepoint(conn, |conn| {
+        let restored = conn.execute(
+            &format!(
+                "UPDATE {} SET deleted_at = NULL WHERE id = ?1 AND deleted_at IS NOT NULL",
+                kind.table()
+            ),
+            [id],
+        )?;
+        if restored == 0 {
+            return Err(AppError::NotFound(format!(
+                "{} {} is not in the trash",
+                kind.entity(),
+                id
+            )));
+        }
+
+        let dependents = match kind {
+            TrashKind::SoundSet => Some(
+                "te.audio_element_id IN (SELECT id FROM audio_elements WHERE sound_set_id = ?1)
+                 OR te.element_group_id IN (SELECT id FROM element_groups WHERE sound_set_id = ?1)",
+            ),
+            TrashKind::AudioElement => Some("te.audio_element_id = ?1"),
+            TrashKind::Mood => None,
+        };
+
+        if let Some(dependents) = dependents {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT te.id, te.track_id, te.start_time_ms, te.duration_ms
+                 FROM timeline_elements te
+                 WHERE ({}) AND {}",
+                dependents, TIMELINE_ELEMENT_IS_VISIBLE
+            ))?;
+            let returning = collect_rows(&mut stmt, [id], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })?;
+
+            for (element_id, track_id, start_time_ms, duration_ms) in returning {
+                ensure_no_overlap(conn, track_id, start_time_ms, duration_ms, Some(element_id))?;
+            }
+        }
+
+        Ok(())
+    })
+}
+
+/// The rows emptying the trash deletes, the trashed items and everything
+/// their removal cascades to, for journaling it as one edit.
+pub fn purge_scopes(conn: &Connection) -> AppResult<Vec<history::Scope>> {
+    history::cascade_scopes(
+        conn,
+        &[
+            (TrashKind::SoundSet.table(), "deleted_at IS NOT NULL"),
+            (TrashKind::Mood.table(), "deleted_at IS NOT NULL"),
+            (TrashKind::AudioElement.table(), "deleted_at IS NOT NULL"),
+        ],
+    )
+}
+
+/// Permanently deletes everything in the trash and returns how many items went.
+///
+/// Edits that touched a purged row, or a row the purge cascaded to, can no
+/// longer be undone, so they leave the edit history.
+pub fn empty_trash(conn: &Connection) -> AppResult<usize> {
+    purge(conn, "deleted_at IS NOT NULL", None)
+}
+
+/// Permanently deletes items that have been in the trash longer than `retention_days`.
+pub fn purge_expired_trash(conn: &Connection, retention_days: u32) -> AppResult<usize> {
+    purge(
+        conn,
+        "deleted_at <= datetime('now', '-' || ?1 || ' days')",
+        Some(retention_days),
+    )
+}
+
+fn purge(conn: &Connection, condition: &str, retention_days: Option<u32>) -> AppResult<usize> {
+    in_savepoint(conn, |conn| {
+        history::forget_removed_rows(conn, |conn| {
+            let mut purged = 0;
+            for kind in [
+                TrashKind::AudioElement,
+                TrashKind::Mood,
+                TrashKind::SoundSet,
+            ] {
+                let sql = format!("DELETE FROM {} WHERE {}", kind.table(), condition);
+                purged += match retention_days {
+                    Some(days) => conn.execute(&sql, [days])?,
+                    None => conn.execute(&sql, [])?,
+                };
+            }
+            Ok(purged)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::audio_elements::{delete_audio_element, get_audio_elements};
+    use crate::store::element_groups::{
+        add_element_to_group, get_element_groups, get_group_members,
+    };
+    use crate::store::moods::{delete_mood, get_moods};
+    use crate::store::sound_sets::{delete_sound_set, get_sound_sets};
+    use crate::store::tags::{list_tags, tag_items, TagTarget};
+    use crate::store::test_connection;
+    use crate::store::timelines::{
+        add_element_to_track, get_track_elements, update_element_time_and_duration,
+    };
+
+    /// Sound set 1 with element 10 placed on track 5 and grouped in group 20.
+    fn seeded() -> Connection {
+        let conn = test_connection();
+        conn.execute_batch(
+            "INSERT INTO sound_sets (id, name, description) VALUES (1, 'Forest', 'D');
+             INSERT INTO audio_elements (id, sound_set_id, file_path, file_name) VALUES (10, 1, 'p', 'owl.ogg');
+             INSERT INTO element_groups (id, sound_set_id, name) VALUES (20, 1, 'Birds');
+             INSERT INTO element_group_members (id, group_id, audio_element_id) VALUES (1, 20, 10);
+             INSERT INTO moods (id, name) VALUES (1, 'Night');
+             INSERT INTO timelines (id, mood_id, name) VALUES (1, 1, 'T');
+             INSERT INTO timeline_tracks (id, timeline_id, name) VALUES (5, 1, 'Trk');
+             INSERT INTO timeline_elements (id, timeline_id, track_id, audio_element_id, start_time_ms, duration_ms)
+                 VALUES (1, 1, 5, 10, 0, 1000);",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn trashed_elements_hide_their_dependents_until_restored() {
+        let conn = seeded();
+
+        delete_audio_element(&conn, 10).unwrap();
+
+        assert!(get_audio_elements(&conn, 1, None).unwrap().is_empty());
+        assert!(get_track_elements(&conn, 5).unwrap().is_empty());
+        assert!(get_group_members(&conn, 20).unwrap().is_empty());
+
+        let trash = list_trash(&conn).unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].kind, TrashKind::AudioElement);
+        assert_eq!(trash[0].name, "owl.ogg");
+
+        restore_from_trash(&conn, TrashKind::AudioElement, 10).unwrap();
+
+        assert_eq!(get_audio_elements(&conn, 1, None).unwrap().len(), 1);
+        assert_eq!(get_track_elements(&conn, 5).unwrap()[0].id, 1);
+        assert_eq!(get_group_members(&conn, 20).unwrap().len(), 1);
+        assert!(list_trash(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn restoring_fails_when_the_timeline_slot_was_reused() {
+        let conn = seeded();
+        delete_sound_set(&conn, 1).unwrap();
+        assert!(get_sound_sets(&conn).unwrap().is_empty());
+        assert!(get_track_elements(&conn, 5).unwrap().is_empty());
+
+        conn.execute(
+            "INSERT INTO audio_elements (id, file_path, file_name) VALUES (11, 'p', 'wind.ogg')",
+            [],
+        )
+        .unwrap();
+        add_element_to_track(&conn, 5, Some(11), None, 500, 1000, None).unwrap();
+
+        let error = restore_from_trash(&conn, TrashKind::SoundSet, 1).unwrap_err();
+        assert_eq!(error.code(), "Overlap");
+        assert_eq!(list_trash(&conn).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn restoring_something_not_in_the_trash_is_not_found() {
+        let conn = seeded();
+
+        let error = restore_from_trash(&conn, TrashKind::Mood, 1).unwrap_err();
+
+        assert_eq!(error.code(), "NotFound");
+    }
+
+    #[test]
+    fn trashed_items_cannot_be_placed_or_grouped() {
+        let conn = seeded();
+        conn.execute_batch(
+            "INSERT INTO audio_elements (id, file_path, file_name) VALUES (11, 'p', 'wind.ogg');
+             INSERT INTO element_groups (id, name) VALUES (21, 'Loose');",
+        )
+        .unwrap();
+        delete_audio_element(&conn, 11).unwrap();
+        delete_sound_set(&conn, 1).unwrap();
+
+        for error in [
+            add_element_to_track(&conn, 5, Some(11), None, 2000, 1000, None).unwrap_err(),
+            add_element_to_track(&conn, 5, None, Some(20), 2000, 1000, None).unwrap_err(),
+            add_element_to_group(&conn, 21, 11).unwrap_err(),
+            add_element_to_group(&conn, 20, 10).unwrap_err(),
+        ] {
+            assert_eq!(error.code(), "NotFound");
+        }
+
+        restore_from_trash(&conn, TrashKind::AudioElement, 11).unwrap();
+        add_element_to_track(&conn, 5, Some(11), None, 2000, 1000, None).unwrap();
+        add_element_to_group(&conn, 21, 11).unwrap();
+    }
+
+    #[test]
+    fn emptying_the_trash_deletes_for_good() {
+        let conn = seeded();
+        delete_mood(&conn, 1).unwrap();
+        delete_audio_element(&conn, 10).unwrap();
+
+        assert_eq!(empty_trash(&conn).unwrap(), 2);
+
+        assert!(list_trash(&conn).unwrap().is_empty());
+        assert!(get_moods(&conn).unwrap().is_empty());
+        let remaining: i64 = conn
+            .query_row("SELECT count(*) FROM timeline_elements", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(remaining, 0);
+    }
+
+    fn move_element(conn: &Connection, id: i64, start_time_ms: i64) {
+        history::record(
+            conn,
+            "Move element",
+            &[history::Scope::new("timeline_elements", "id = ?1", id)],
+            |conn| update_element_time_and_duration(conn, id, start_time_ms, 1000, None),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn purging_forgets_only_the_edits_that_touched_purged_rows() {
+        let conn = seeded();
+        move_element(&conn, 1, 2000);
+        delete_audio_element(&conn, 10).unwrap();
+        history::record(
+            &conn,
+            "Restore from trash",
+            &[history::Scope::new("audio_elements", "id = ?1", 10)],
+            |conn| restore_from_trash(conn, TrashKind::AudioElement, 10),
+        )
+        .unwrap();
+
+        history::undo(&conn).unwrap().unwrap();
+        assert_eq!(list_trash(&conn).unwrap().len(), 1);
+        history::redo(&conn).unwrap().unwrap();
+        assert!(list_trash(&conn).unwrap().is_empty());
+
+        // The mood takes the moved element with it; the restore stays undoable.
+        delete_mood(&conn, 1).unwrap();
+        empty_trash(&conn).unwrap();
+        let undone = history::undo(&conn).unwrap().unwrap();
+        assert_eq!(undone.label, "Restore from trash");
+        assert!(history::undo(&conn).unwrap().is_none());
+    }
+
+    #[test]
+    fn purging_unrelated_items_keeps_the_history() {
+        let conn = seeded();
+        move_element(&conn, 1, 2000);
+        conn.execute(
+            "INSERT INTO audio_elements (id, file_path, file_name, deleted_at)
+             VALUES (11, 'p', 'wind.ogg', datetime('now', '-31 days'))",
+            [],
+        )
+        .unwrap();
+
+        assert_eq!(
+            purge_expired_trash(&conn, DEFAULT_RETENTION_DAYS).unwrap(),
+            1
+        );
+
+        history::undo(&conn).unwrap().unwrap();
+        assert_eq!(get_track_elements(&conn, 5).unwrap()[0].start_time_ms, 0);
+    }
+
+    #[test]
+    fn emptying_the_trash_can_be_undone() {
+        let conn = seeded();
+        delete_sound_set(&conn, 1).unwrap();
+
+        history::record(
+            &conn,
+            "Empty trash",
+            &purge_scopes(&conn).unwrap(),
+            empty_trash,
+        )
+        .unwrap();
+        assert!(list_trash(&conn).unwrap().is_empty());
+
+        history::undo(&conn).unwrap().unwrap();
+        restore_from_trash(&conn, TrashKind::SoundSet, 1).unwrap();
+        assert_eq!(get_audio_elements(&conn, 1, None).unwrap().len(), 1);
+        assert_eq!(get_track_elements(&conn, 5).unwrap()[0].id, 1);
+        assert_eq!(get_group_members(&conn, 20).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn undoing_a_purge_brings_back_tags() {
+        let conn = seeded();
+        tag_items(&conn, TagTarget::AudioElement, &[10], &["night".into()]).unwrap();
+        tag_items(&conn, TagTarget::ElementGroup, &[20], &["birds".into()]).unwrap();
+        delete_sound_set(&conn, 1).unwrap();
+
+        history::record(
+            &conn,
+            "Empty trash",
+            &purge_scopes(&conn).unwrap(),
+            empty_trash,
+        )
+        .unwrap();
+        assert!(list_tags(&conn)
+            .unwrap()
+            .iter()
+            .all(|tag| tag.usage_count == 0));
+
+        history::undo(&conn).unwrap().unwrap();
+        restore_from_trash(&conn, TrashKind::SoundSet, 1).unwrap();
+        assert_eq!(
+            get_audio_elements(&conn, 1, None).unwrap()[0].tags,
+            ["night"]
+        );
+        assert_eq!(
+            get_element_groups(&conn, Some(1), None).unwrap()[0].tags,
+            ["birds"]
+        );
+    }
+
+    #[test]
+    fn purge_only_removes_items_past_retention() {
+        let conn = seeded();
+        delete_mood(&conn, 1).unwrap();
+        delete_audio_element(&conn, 10).unwrap();
+        conn.execute(
+            "UPDATE moods SET deleted_at = datetime('now', '-31 days') WHERE id = 1",
+            [],
+        )
+        .unwrap();
+
+        assert_eq!(
+            purge_expired_trash(&conn, DEFAULT_RETENTION_DAYS).unwrap(),
+            1
+        );
+
+        let trash = list_trash(&conn).unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].kind, TrashKind::AudioElement);
+    }
+}
@@ -17,6 +17,7 @@ import { useGlobalOneShotStore } from './features/sound-sets/stores/globalOneSho
 import { useSoundSetStore } from './features/sound-sets/stores/soundSetStore';
 import { useTimelineStore } from './features/sound-sets/stores/timelineStore';
 import { useToast } from './shared/hooks/useToast';
+import { getErrorMessage } from './shared/utils/commandError';
 
 export default function App() {
   const { loadSoundSets, selectedSoundSet, selectedMood, isLoading, soundSets, error } =
@@ -63,7 +64,7 @@ export default function App() {
           .getState()
           .removeElementFromGroup(memberId, sourceGroupId)
           .then(() => success('Removido do grupo'))
-          .catch(err => toastError('Erro ao remover: ' + String(err)));
+          .catch(err => toastError('Erro ao remover: ' + getErrorMessage(err)));
       }
       return;
     }
@@ -76,7 +77,7 @@ export default function App() {
           const { addElementToGroup } = useElementGroupStore.getState();
           addElementToGroup(groupId, elementId)
             .then(() => success('Adicionado ao grupo'))
-            .catch(err => toastError('Erro ao adicionar: ' + String(err)));
+            .catch(err => toastError('Erro ao adicionar: ' + getErrorMessage(err)));
         } else {
           toastError('Apenas áudios soltos podem ser inseridos no grupo.');
         }
@@ -115,10 +116,12 @@ export default function App() {
                   .then(() => {
                     success('Grupo criado');
                   })
-                  .catch(err => toastError('Erro ao adicionar elementos ao grupo: ' + String(err)));
+                  .catch(err =>
+                    toastError('Erro ao adicionar elementos ao grupo: ' + getErrorMessage(err))
+                  );
               }
             })
-            .catch(err => toastError('Erro ao criar grupo: ' + String(err)));
+            .catch(err => toastError('Erro ao criar grupo: ' + getErrorMessage(err)));
         }
         return;
       }
@@ -135,7 +138,7 @@ export default function App() {
           .getState()
           .removeElementFromGroup(memberId, sourceGroupId)
           .then(() => success('Removido do grupo'))
-          .catch(err => toastError('Erro ao remover: ' + String(err)));
+          .catch(err => toastError('Erro ao remover: ' + getErrorMessage(err)));
       }
       return;
     }
@@ -157,7 +160,7 @@ export default function App() {
 
         addElementToGroup(groupId, elementId)
           .then(() => success('Adicionado ao grupo'))
-          .catch(err => toastError('Failed to add element: ' + String(err)));
+          .catch(err => toastError('Failed to add element: ' + getErrorMessage(err)));
       } else {
         toastError('Cannot add a group inside another group.');
       }
@@ -244,7 +247,7 @@ export default function App() {
           );
         })
         .catch((err: unknown) => {
-          const message = err instanceof Error ? err.message : String(err);
+          const message = getErrorMessage(err);
           toastError('Falha ao adicionar à timeline: ' + message);
         });
     } else {
@@ -3,6 +3,7 @@ import { create } from 'zustand';
 
 import { useSettingsStore } from '../../settings/stores/settingsStore';
 import { useElementGroupStore } from '../../sound-sets/stores/elementGroupStore';
+import { getErrorMessage } from '../../../shared/utils/commandError';
 
 export interface AudioElement {
   id: number;
@@ -13,6 +14,21 @@ export interface AudioElement {
   channel_type: string;
   volume_db: number;
   created_at: string;
+  tags: string[];
+  notes: string;
+  rating: number | null;
+  duration_ms: number | null;
+  sample_rate: number | null;
+  channel_count: number | null;
+  codec: string | null;
+  file_size_bytes: number | null;
+  loudness_lufs: number | null;
+  loudness_range_lu: number | null;
+  true_peak_dbtp: number | null;
+  leading_silence_ms: number | null;
+  trailing_silence_ms: number | null;
+  loop_start_ms: number | null;
+  loop_end_ms: number | null;
 }
 
 interface DeviceAudioContext extends AudioContext {
@@ -222,7 +238,7 @@ export const useAudioEngineStore = create<AudioEngineState>((set, get) => ({
         discordSendInFlight = true;
         invoke('discord_send_audio', { pcmData: nextPcm })
           .catch(err => {
-            const message = String(err);
+            const message = getErrorMessage(err);
             const isTransientBridgeError =
               message.includes('Discord audio bridge is disconnected') ||
               message.includes('Discord audio bridge is not initialized') ||
@@ -3,6 +3,7 @@ import { useCallback, useRef } from 'react';
 import { invoke } from '@tauri-apps/api/core';
 
 import { useDiscordStore } from '../stores/discordStore';
+import { getErrorMessage } from '../../../shared/utils/commandError';
 
 export interface DiscordUser {
   id: string;
@@ -59,7 +60,7 @@ export function useDiscordConnection() {
         return true;
       } catch (err) {
         console.error('Discord validation error:', err);
-        setError(String(err));
+        setError(getErrorMessage(err));
         setBotUser(null);
         setGuilds([]);
         lastValidatedTokenRef.current = null;
@@ -82,7 +83,7 @@ export function useDiscordConnection() {
         setChannels(channelList);
       } catch (err) {
         console.error('Failed to load channels:', err);
-        setError(String(err));
+        setError(getErrorMessage(err));
         setChannels([]);
       }
     },
@@ -1,5 +1,6 @@
 import { invoke } from '@tauri-apps/api/core';
 import { create } from 'zustand';
+import { getErrorMessage } from '../../../shared/utils/commandError';
 
 export interface AppSettings {
   audio_file_strategy: 'reference' | 'copy';
@@ -8,6 +9,7 @@ export interface AppSettings {
   discord_bot_token: string;
   discord_guild_id: string;
   discord_channel_id: string;
+  trash_retention_days: number;
 }
 
 interface SettingsState {
@@ -28,6 +30,7 @@ export const useSettingsStore = create<SettingsState>(set => ({
     discord_bot_token: '',
     discord_guild_id: '',
     discord_channel_id: '',
+    trash_retention_days: 30,
   },
   isLoading: false,
   error: null,
@@ -38,7 +41,7 @@ export const useSettingsStore = create<SettingsState>(set => ({
       const settings = await invoke<AppSettings>('get_app_settings');
       set({ settings, isLoading: false });
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: getErrorMessage(error), isLoading: false });
     }
   },
 
@@ -48,7 +51,7 @@ export const useSettingsStore = create<SettingsState>(set => ({
       await invoke('update_app_settings', { settings });
       set({ settings, isLoading: false });
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: getErrorMessage(error), isLoading: false });
     }
   },
 
@@ -14,6 +14,7 @@ import { useToast } from '../../../shared/hooks/useToast';
 import { cn } from '../../../shared/utils/cn';
 import { useElementGroupStore } from '../stores/elementGroupStore';
 import { useSoundSetStore } from '../stores/soundSetStore';
+import { getErrorMessage } from '../../../shared/utils/commandError';
 
 interface SoundSetBrowserProps {
   isCollapsed?: boolean;
@@ -126,7 +127,7 @@ export function SoundSetBrowser({ isCollapsed = false }: SoundSetBrowserProps) {
       await importSoundSet(selected);
       success('SoundSet imported successfully!');
     } catch (err) {
-      error(`Failed to import SoundSet: ${String(err)}`);
+      error(`Failed to import SoundSet: ${getErrorMessage(err)}`);
     }
   };
 
@@ -142,7 +143,7 @@ export function SoundSetBrowser({ isCollapsed = false }: SoundSetBrowserProps) {
       await exportSoundSet(id, selected);
       success('SoundSet exported successfully!');
     } catch (err) {
-      error(`Failed to export SoundSet: ${String(err)}`);
+      error(`Failed to export SoundSet: ${getErrorMessage(err)}`);
     }
   };
 
@@ -1,18 +1,35 @@
 import { invoke } from '@tauri-apps/api/core';
 import { create } from 'zustand';
+import { getErrorMessage } from '../../../shared/utils/commandError';
 
 export interface ElementGroup {
   id: number;
   name: string;
   sound_set_id: number | null;
   created_at: string;
+  tags: string[];
+  notes: string;
+  rating: number | null;
+  playback_mode: PlaybackMode;
+  pitch_variation_semitones: number;
+  volume_variation_db: number;
 }
 
+export type PlaybackMode = 'sequential' | 'shuffle' | 'random_no_repeat' | 'weighted';
+
 export interface ElementGroupMember {
   id: number;
   group_id: number;
   audio_element_id: number;
   order_index: number;
+  weight: number;
+}
+
+export interface GroupPick {
+  member_id: number;
+  audio_element_id: number;
+  pitch_semitones: number;
+  volume_db: number;
 }
 
 interface ElementGroupState {
@@ -43,7 +60,7 @@ export const useElementGroupStore = create<ElementGroupState>(set => ({
       const groups = await invoke<ElementGroup[]>('get_all_available_element_groups');
       set({ groups, isLoading: false });
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: getErrorMessage(error), isLoading: false });
     }
   },
 
@@ -57,7 +74,7 @@ export const useElementGroupStore = create<ElementGroupState>(set => ({
       }));
       return newGroup;
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: getErrorMessage(error), isLoading: false });
       return null;
     }
   },
@@ -69,7 +86,7 @@ export const useElementGroupStore = create<ElementGroupState>(set => ({
         groups: state.groups.map(g => (g.id === id ? { ...g, name } : g)),
       }));
     } catch (error) {
-      set({ error: String(error) });
+      set({ error: getErrorMessage(error) });
     }
   },
 
@@ -87,7 +104,7 @@ export const useElementGroupStore = create<ElementGroupState>(set => ({
         };
       });
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: getErrorMessage(error), isLoading: false });
     }
   },
 
@@ -103,7 +120,7 @@ export const useElementGroupStore = create<ElementGroupState>(set => ({
         isLoading: false,
       }));
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: getErrorMessage(error), isLoading: false });
     }
   },
 
@@ -125,7 +142,7 @@ export const useElementGroupStore = create<ElementGroupState>(set => ({
         };
       });
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: getErrorMessage(error), isLoading: false });
     }
   },
 
@@ -144,7 +161,7 @@ export const useElementGroupStore = create<ElementGroupState>(set => ({
         };
       });
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: getErrorMessage(error), isLoading: false });
     }
   },
 }));
@@ -2,6 +2,7 @@ import { invoke } from '@tauri-apps/api/core';
 import { create } from 'zustand';
 
 import { AudioElement } from './soundSetStore';
+import { getErrorMessage } from '../../../shared/utils/commandError';
 
 interface GlobalOneShotState {
   globalOneShots: AudioElement[];
@@ -29,7 +30,7 @@ export const useGlobalOneShotStore = create<GlobalOneShotState>(set => ({
       const globalOneShots = await invoke<AudioElement[]>('get_global_oneshots');
       set({ globalOneShots, isLoading: false });
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: getErrorMessage(error), isLoading: false });
     }
   },
 
@@ -48,7 +49,7 @@ export const useGlobalOneShotStore = create<GlobalOneShotState>(set => ({
 
       return newOneShot;
     } catch (error) {
-      const message = String(error);
+      const message = getErrorMessage(error);
       set({ error: message, isLoading: false });
       throw error;
     }
@@ -63,7 +64,7 @@ export const useGlobalOneShotStore = create<GlobalOneShotState>(set => ({
         isLoading: false,
       }));
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: getErrorMessage(error), isLoading: false });
     }
   },
 
@@ -23,6 +23,15 @@ describe('soundSetStore', () => {
   });
 
   describe('Audio Channels', () => {
+    const compressor = {
+      enabled: false,
+      threshold_db: -18,
+      ratio: 4,
+      attack_ms: 10,
+      release_ms: 200,
+      makeup_db: 0,
+    };
+
     it('createChannel adds a new channel', async () => {
       const mockChannel = {
         id: 1,
@@ -59,6 +68,7 @@ describe('soundSetStore', () => {
             volume: 0.5,
             order_index: 0,
             created_at: '',
+            compressor,
           },
         ],
       });
@@ -90,6 +100,7 @@ describe('soundSetStore', () => {
             volume: 0.5,
             order_index: 0,
             created_at: '',
+            compressor,
           },
         ],
       });
@@ -115,6 +126,21 @@ describe('soundSetStore', () => {
             channel_type: 'ambient',
             volume_db: 0,
             created_at: '',
+            tags: [],
+            notes: '',
+            rating: null,
+            duration_ms: null,
+            sample_rate: null,
+            channel_count: null,
+            codec: null,
+            file_size_bytes: null,
+            loudness_lufs: null,
+            loudness_range_lu: null,
+            true_peak_dbtp: null,
+            leading_silence_ms: null,
+            trailing_silence_ms: null,
+            loop_start_ms: null,
+            loop_end_ms: null,
           },
         ],
       });
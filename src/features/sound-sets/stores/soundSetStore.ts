@@ -1,6 +1,7 @@
 import { invoke } from '@tauri-apps/api/core';
 import { create } from 'zustand';
 import { persist } from 'zustand/middleware';
+import { getErrorMessage } from '../../../shared/utils/commandError';
 
 export interface SoundSet {
   id: number;
@@ -18,6 +19,15 @@ export interface Mood {
   created_at: string;
 }
 
+export interface ChannelCompressor {
+  enabled: boolean;
+  threshold_db: number;
+  ratio: number;
+  attack_ms: number;
+  release_ms: number;
+  makeup_db: number;
+}
+
 export interface AudioChannel {
   id: number;
   sound_set_id: number;
@@ -26,6 +36,7 @@ export interface AudioChannel {
   volume: number;
   order_index: number;
   created_at: string;
+  compressor: ChannelCompressor;
 }
 
 export interface AudioElement {
@@ -37,6 +48,21 @@ export interface AudioElement {
   channel_type: string;
   volume_db: number;
   created_at: string;
+  tags: string[];
+  notes: string;
+  rating: number | null;
+  duration_ms: number | null;
+  sample_rate: number | null;
+  channel_count: number | null;
+  codec: string | null;
+  file_size_bytes: number | null;
+  loudness_lufs: number | null;
+  loudness_range_lu: number | null;
+  true_peak_dbtp: number | null;
+  leading_silence_ms: number | null;
+  trailing_silence_ms: number | null;
+  loop_start_ms: number | null;
+  loop_end_ms: number | null;
 }
 
 interface SoundSetState {
@@ -151,7 +177,7 @@ export const useSoundSetStore = create<SoundSetState>()(
           get().loadMoods();
         } catch (error) {
           console.error('Store: Failed to load soundsets:', error);
-          set({ error: String(error), isLoading: false });
+          set({ error: getErrorMessage(error), isLoading: false });
         }
       },
 
@@ -168,7 +194,7 @@ export const useSoundSetStore = create<SoundSetState>()(
           }));
           await get().seedDefaultChannels(newSoundSet.id);
         } catch (error) {
-          set({ error: String(error), isLoading: false });
+          set({ error: getErrorMessage(error), isLoading: false });
         }
       },
 
@@ -183,7 +209,7 @@ export const useSoundSetStore = create<SoundSetState>()(
             isLoading: false,
           }));
         } catch (error) {
-          set({ error: String(error), isLoading: false });
+          set({ error: getErrorMessage(error), isLoading: false });
         }
       },
 
@@ -198,7 +224,7 @@ export const useSoundSetStore = create<SoundSetState>()(
             isLoading: false,
           }));
         } catch (error) {
-          set({ error: String(error), isLoading: false });
+          set({ error: getErrorMessage(error), isLoading: false });
         }
       },
 
@@ -250,7 +276,7 @@ export const useSoundSetStore = create<SoundSetState>()(
           const moods = await invoke<Mood[]>('get_moods');
           set({ moods, isLoading: false });
         } catch (error) {
-          set({ error: String(error), isLoading: false });
+          set({ error: getErrorMessage(error), isLoading: false });
         }
       },
 
@@ -266,7 +292,7 @@ export const useSoundSetStore = create<SoundSetState>()(
             isLoading: false,
           }));
         } catch (error) {
-          set({ error: String(error), isLoading: false });
+          set({ error: getErrorMessage(error), isLoading: false });
         }
       },
 
@@ -280,7 +306,7 @@ export const useSoundSetStore = create<SoundSetState>()(
             isLoading: false,
           }));
         } catch (error) {
-          set({ error: String(error), isLoading: false });
+          set({ error: getErrorMessage(error), isLoading: false });
         }
       },
 
@@ -294,7 +320,7 @@ export const useSoundSetStore = create<SoundSetState>()(
           const audioElements = await invoke<AudioElement[]>('get_all_available_audio_elements');
           set({ audioElements, isLoading: false });
         } catch (error) {
-          set({ error: String(error), isLoading: false });
+          set({ error: getErrorMessage(error), isLoading: false });
         }
       },
 
@@ -315,7 +341,7 @@ export const useSoundSetStore = create<SoundSetState>()(
 
           return newElement;
         } catch (error) {
-          set({ error: String(error), isLoading: false });
+          set({ error: getErrorMessage(error), isLoading: false });
           throw error;
         }
       },
@@ -329,7 +355,7 @@ export const useSoundSetStore = create<SoundSetState>()(
             isLoading: false,
           }));
         } catch (error) {
-          set({ error: String(error), isLoading: false });
+          set({ error: getErrorMessage(error), isLoading: false });
         }
       },
 
@@ -344,7 +370,7 @@ export const useSoundSetStore = create<SoundSetState>()(
             isLoading: false,
           }));
         } catch (error) {
-          set({ error: String(error), isLoading: false });
+          set({ error: getErrorMessage(error), isLoading: false });
         }
       },
 
@@ -359,7 +385,7 @@ export const useSoundSetStore = create<SoundSetState>()(
             isLoading: false,
           }));
         } catch (error) {
-          set({ error: String(error), isLoading: false });
+          set({ error: getErrorMessage(error), isLoading: false });
         }
       },
 
@@ -369,7 +395,7 @@ export const useSoundSetStore = create<SoundSetState>()(
           const channels = await invoke<AudioChannel[]>('get_audio_channels', { soundSetId });
           set({ channels, isLoading: false });
         } catch (error) {
-          set({ error: String(error), isLoading: false });
+          set({ error: getErrorMessage(error), isLoading: false });
         }
       },
 
@@ -387,7 +413,7 @@ export const useSoundSetStore = create<SoundSetState>()(
             isLoading: false,
           }));
         } catch (error) {
-          set({ error: String(error), isLoading: false });
+          set({ error: getErrorMessage(error), isLoading: false });
         }
       },
 
@@ -400,7 +426,7 @@ export const useSoundSetStore = create<SoundSetState>()(
             isLoading: false,
           }));
         } catch (error) {
-          set({ error: String(error), isLoading: false });
+          set({ error: getErrorMessage(error), isLoading: false });
         }
       },
 
@@ -413,7 +439,7 @@ export const useSoundSetStore = create<SoundSetState>()(
             isLoading: false,
           }));
         } catch (error) {
-          set({ error: String(error), isLoading: false });
+          set({ error: getErrorMessage(error), isLoading: false });
         }
       },
 
@@ -425,7 +451,7 @@ export const useSoundSetStore = create<SoundSetState>()(
             await get().loadChannels(selectedSoundSet.id);
           }
         } catch (error) {
-          set({ error: String(error) });
+          set({ error: getErrorMessage(error) });
         }
       },
 
@@ -434,7 +460,7 @@ export const useSoundSetStore = create<SoundSetState>()(
           const channels = await invoke<AudioChannel[]>('seed_default_channels', { soundSetId });
           set({ channels });
         } catch (error) {
-          set({ error: String(error) });
+          set({ error: getErrorMessage(error) });
         }
       },
 
@@ -444,7 +470,7 @@ export const useSoundSetStore = create<SoundSetState>()(
           await invoke('export_sound_set', { soundSetId: id, destinationPath });
           set({ isLoading: false });
         } catch (error) {
-          set({ error: String(error), isLoading: false });
+          set({ error: getErrorMessage(error), isLoading: false });
           throw error;
         }
       },
@@ -456,7 +482,7 @@ export const useSoundSetStore = create<SoundSetState>()(
           await get().loadSoundSets();
           set({ isLoading: false });
         } catch (error) {
-          set({ error: String(error), isLoading: false });
+          set({ error: getErrorMessage(error), isLoading: false });
           throw error;
         }
       },
@@ -1,5 +1,6 @@
 import { invoke } from '@tauri-apps/api/core';
 import { create } from 'zustand';
+import { getErrorMessage } from '../../../shared/utils/commandError';
 
 export interface Timeline {
   id: number;
@@ -8,6 +9,44 @@ export interface Timeline {
   order_index: number;
   is_looping: boolean;
   created_at: string;
+  loop_marker_id: number | null;
+}
+
+export interface TimelineMarker {
+  id: number;
+  timeline_id: number;
+  name: string;
+  position_ms: number;
+  end_ms: number | null;
+  color: string | null;
+}
+
+export type SnapMode = 'bar' | 'beat' | 'half_beat' | 'quarter_beat';
+
+export interface TempoChange {
+  id: number;
+  timeline_id: number;
+  bar: number;
+  bpm: number;
+  beats_per_bar: number;
+  beat_unit: number;
+}
+
+export interface MusicalPosition {
+  bar: number;
+  beat: number;
+}
+
+export type ElementEdit =
+  | { kind: 'move'; id: number; start_time_ms: number; duration_ms: number }
+  | { kind: 'move_to_track'; id: number; track_id: number; start_time_ms: number }
+  | { kind: 'delete'; id: number };
+
+export interface TimelineTemplate {
+  id: number;
+  name: string;
+  track_count: number;
+  created_at: string;
 }
 
 export interface TimelineTrack {
@@ -27,8 +66,14 @@ export interface TimelineElement {
   start_time_ms: number;
   duration_ms: number;
   is_available: boolean;
+  source_offset_ms: number;
+  fade_in_ms: number;
+  fade_out_ms: number;
+  fade_curve: FadeCurve;
 }
 
+export type FadeCurve = 'linear' | 'equal_power' | 'exponential';
+
 interface TimelineElementLike {
   id: number;
   track_id?: number;
@@ -37,6 +82,10 @@ interface TimelineElementLike {
   start_time_ms?: number;
   duration_ms?: number;
   is_available?: boolean;
+  source_offset_ms?: number;
+  fade_in_ms?: number;
+  fade_out_ms?: number;
+  fade_curve?: FadeCurve;
   trackId?: number;
   audioElementId?: number | null;
   elementGroupId?: number | null;
@@ -59,6 +108,10 @@ const normalizeTimelineElement = (element: TimelineElementLike): TimelineElement
   start_time_ms: Number(element.start_time_ms ?? element.startTimeMs) || 0,
   duration_ms: Number(element.duration_ms ?? element.durationMs) || 0,
   is_available: element.is_available ?? element.isAvailable ?? true,
+  source_offset_ms: Number(element.source_offset_ms) || 0,
+  fade_in_ms: Number(element.fade_in_ms) || 0,
+  fade_out_ms: Number(element.fade_out_ms) || 0,
+  fade_curve: element.fade_curve ?? 'linear',
 });
 
 interface TimelineState {
@@ -97,6 +150,13 @@ interface TimelineState {
     startTimeMs: number,
     durationMs: number
   ) => Promise<void>;
+  updateElementFades: (
+    id: number,
+    sourceOffsetMs: number,
+    fadeInMs: number,
+    fadeOutMs: number,
+    fadeCurve: FadeCurve
+  ) => Promise<void>;
   deleteTimelineElement: (id: number) => Promise<void>;
 }
 
@@ -117,7 +177,7 @@ export const useTimelineStore = create<TimelineState>((set, get) => ({
       const timelines = await invoke<Timeline[]>('get_timelines', { moodId });
       set({ timelines, isLoading: false });
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: getErrorMessage(error), isLoading: false });
     }
   },
 
@@ -130,7 +190,7 @@ export const useTimelineStore = create<TimelineState>((set, get) => ({
         isLoading: false,
       }));
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: getErrorMessage(error), isLoading: false });
     }
   },
 
@@ -146,7 +206,7 @@ export const useTimelineStore = create<TimelineState>((set, get) => ({
         isLoading: false,
       }));
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: getErrorMessage(error), isLoading: false });
     }
   },
 
@@ -173,7 +233,7 @@ export const useTimelineStore = create<TimelineState>((set, get) => ({
             : state.tracks,
       }));
     } catch (error) {
-      set({ error: String(error) });
+      set({ error: getErrorMessage(error) });
     }
   },
 
@@ -183,7 +243,7 @@ export const useTimelineStore = create<TimelineState>((set, get) => ({
       const tracks = await invoke<TimelineTrack[]>('get_timeline_tracks', { timelineId });
       set({ tracks, isLoading: false });
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: getErrorMessage(error), isLoading: false });
     }
   },
 
@@ -196,7 +256,7 @@ export const useTimelineStore = create<TimelineState>((set, get) => ({
         isLoading: false,
       }));
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: getErrorMessage(error), isLoading: false });
     }
   },
 
@@ -210,7 +270,7 @@ export const useTimelineStore = create<TimelineState>((set, get) => ({
         isLoading: false,
       }));
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: getErrorMessage(error), isLoading: false });
     }
   },
 
@@ -221,7 +281,7 @@ export const useTimelineStore = create<TimelineState>((set, get) => ({
         tracks: state.tracks.map(t => (t.id === id ? { ...t, order_index: orderIndex } : t)),
       }));
     } catch (error) {
-      set({ error: String(error) });
+      set({ error: getErrorMessage(error) });
     }
   },
 
@@ -232,7 +292,7 @@ export const useTimelineStore = create<TimelineState>((set, get) => ({
         tracks: state.tracks.map(t => (t.id === id ? { ...t, is_looping: isLooping } : t)),
       }));
     } catch (error) {
-      set({ error: String(error) });
+      set({ error: getErrorMessage(error) });
     }
   },
 
@@ -247,7 +307,7 @@ export const useTimelineStore = create<TimelineState>((set, get) => ({
         return { elements: [...filtered, ...normalizedTrackElements], isLoading: false };
       });
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: getErrorMessage(error), isLoading: false });
     }
   },
 
@@ -267,7 +327,7 @@ export const useTimelineStore = create<TimelineState>((set, get) => ({
         isLoading: false,
       }));
     } catch (error) {
-      const message = String(error);
+      const message = getErrorMessage(error);
       set({ error: message, isLoading: false });
       if (error instanceof Error) {
         throw error;
@@ -288,7 +348,35 @@ export const useTimelineStore = create<TimelineState>((set, get) => ({
         ),
       }));
     } catch (error) {
-      set({ error: String(error) });
+      set({ error: getErrorMessage(error) });
+    }
+  },
+
+  updateElementFades: async (id, sourceOffsetMs, fadeInMs, fadeOutMs, fadeCurve) => {
+    set({ error: null });
+    try {
+      await invoke('update_element_fades', {
+        id,
+        sourceOffsetMs,
+        fadeInMs,
+        fadeOutMs,
+        fadeCurve,
+      });
+      set(state => ({
+        elements: state.elements.map(el =>
+          el.id === id
+            ? {
+                ...el,
+                source_offset_ms: sourceOffsetMs,
+                fade_in_ms: fadeInMs,
+                fade_out_ms: fadeOutMs,
+                fade_curve: fadeCurve,
+              }
+            : el
+        ),
+      }));
+    } catch (error) {
+      set({ error: getErrorMessage(error) });
     }
   },
 
@@ -301,7 +389,7 @@ export const useTimelineStore = create<TimelineState>((set, get) => ({
         isLoading: false,
       }));
     } catch (error) {
-      set({ error: String(error), isLoading: false });
+      set({ error: getErrorMessage(error), isLoading: false });
     }
   },
 }));
@@ -0,0 +1,35 @@
+export type CommandErrorCode =
+  | 'NotFound'
+  | 'Overlap'
+  | 'ValidationFailed'
+  | 'Io'
+  | 'Database'
+  | 'Sidecar'
+  | 'Network'
+  | 'ManifestInvalid'
+  | 'Internal';
+
+export interface CommandError {
+  code: CommandErrorCode;
+  message: string;
+  details: Record<string, unknown> | null;
+}
+
+export function isCommandError(error: unknown): error is CommandError {
+  return (
+    typeof error === 'object' &&
+    error !== null &&
+    typeof (error as CommandError).code === 'string' &&
+    typeof (error as CommandError).message === 'string'
+  );
+}
+
+export function getErrorMessage(error: unknown): string {
+  if (isCommandError(error)) {
+    return error.message;
+  }
+  if (error instanceof Error) {
+    return error.message;
+  }
+  return String(error);
+}